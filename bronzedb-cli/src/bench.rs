@@ -0,0 +1,451 @@
+use crate::histogram::Histogram;
+use bronzedb_client::{BronzeConnManager, Pool};
+use bronzedb_protocol::{MAX_KEY_LEN, MAX_VALUE_LEN};
+use bronzedb_util::status::StatusCode::IOError;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::Key;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+// writes sent at a time while loading the keys
+const LOAD_BATCH: u64 = 256;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Distribution {
+    Uniform,
+    Zipfian,
+    // each thread goes through its share of the keys in order, from the start again at the end
+    Sequential,
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        match name {
+            "uniform" => Ok(Distribution::Uniform),
+            "zipfian" => Ok(Distribution::Zipfian),
+            "sequential" => Ok(Distribution::Sequential),
+            _ => Err(format!("unknown distribution: {}", name)),
+        }
+    }
+}
+
+// Operations are picked at random in proportion to their weights. Keys are the numbers below
+// `keys`, zero-padded to `key_size` bytes.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    pub keys: u64,
+    pub key_size: usize,
+    pub value_size: usize,
+    pub reads: u64,
+    pub writes: u64,
+    pub scans: u64,
+    pub scan_length: usize,
+    pub distribution: Distribution,
+    // skew of the zipfian distribution, between 0 and 1
+    pub theta: f64,
+    pub threads: usize,
+    pub duration: Duration,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Self {
+            keys: 100_000,
+            key_size: 16,
+            value_size: 100,
+            reads: 50,
+            writes: 50,
+            scans: 0,
+            scan_length: 10,
+            distribution: Distribution::Uniform,
+            theta: 0.99,
+            threads: 4,
+            duration: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Workload {
+    pub fn check(&self) -> std::result::Result<(), String> {
+        if self.keys == 0 || self.threads == 0 {
+            return Err("there must be some keys and threads".to_owned());
+        }
+        if self.reads + self.writes + self.scans == 0 {
+            return Err("some operation must have a weight".to_owned());
+        }
+        let digits = (self.keys - 1).to_string().len();
+        if self.key_size < digits || self.key_size > MAX_KEY_LEN {
+            return Err(format!(
+                "keys take from {} to {} bytes",
+                digits, MAX_KEY_LEN
+            ));
+        }
+        if self.value_size > MAX_VALUE_LEN {
+            return Err(format!("values take at most {} bytes", MAX_VALUE_LEN));
+        }
+        if !(self.theta > 0.0 && self.theta < 1.0) {
+            return Err("theta must be between 0 and 1".to_owned());
+        }
+        Ok(())
+    }
+
+    pub fn key(&self, index: u64) -> Key {
+        format!("{:01$}", index, self.key_size).into_bytes().into()
+    }
+}
+
+// splitmix64, enough to pick keys and operations
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+
+    // in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Ranks drawn with the method of Gray et al., "Quickly Generating Billion-Record Synthetic
+// Databases", as YCSB does; rank 0 is the most frequent.
+pub struct Zipfian {
+    items: u64,
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    // Takes time in the number of items.
+    pub fn new(items: u64, theta: f64) -> Self {
+        let zeta = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let zetan = zeta(items);
+        let eta = (1.0 - (2.0 / items as f64).powf(1.0 - theta)) / (1.0 - zeta(2) / zetan);
+        Self {
+            items,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zetan,
+            eta,
+        }
+    }
+
+    pub fn sample(&self, rng: &mut Rng) -> u64 {
+        let u = rng.next_f64();
+        let uz = u * self.zetan;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.items - 1);
+        }
+        let rank = self.items as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha);
+        (rank as u64).min(self.items - 1)
+    }
+}
+
+// Picks the keys of a thread.
+pub struct Keys {
+    distribution: Distribution,
+    count: u64,
+    zipfian: Option<Arc<Zipfian>>,
+    next: u64,
+}
+
+impl Keys {
+    pub fn new(workload: &Workload, zipfian: Option<Arc<Zipfian>>, thread: usize) -> Self {
+        Self {
+            distribution: workload.distribution,
+            count: workload.keys,
+            zipfian,
+            next: workload.keys * thread as u64 / workload.threads as u64,
+        }
+    }
+
+    pub fn next(&mut self, rng: &mut Rng) -> u64 {
+        match self.distribution {
+            Distribution::Uniform => rng.below(self.count),
+            // the popular keys are spread over the key space rather than next to each other
+            Distribution::Zipfian => match self.zipfian {
+                Some(ref zipfian) => fnv(zipfian.sample(rng)) % self.count,
+                None => rng.below(self.count),
+            },
+            Distribution::Sequential => {
+                let key = self.next;
+                self.next = (self.next + 1) % self.count;
+                key
+            }
+        }
+    }
+}
+
+fn fnv(value: u64) -> u64 {
+    value
+        .to_le_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub reads: Histogram,
+    pub writes: Histogram,
+    pub scans: Histogram,
+    pub errors: u64,
+}
+
+impl Stats {
+    fn merge(&mut self, other: &Stats) {
+        self.reads.merge(&other.reads);
+        self.writes.merge(&other.writes);
+        self.scans.merge(&other.scans);
+        self.errors += other.errors;
+    }
+
+    pub fn total(&self) -> Histogram {
+        let mut total = self.reads.clone();
+        total.merge(&self.writes);
+        total.merge(&self.scans);
+        total
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Operation {
+    Read,
+    Write,
+    Scan,
+}
+
+fn seed(thread: usize) -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    nanos ^ fnv(thread as u64)
+}
+
+fn checkout(pool: &Pool<BronzeConnManager>) -> Result<r2d2::PooledConnection<BronzeConnManager>> {
+    pool.get()
+        .map_err(|err| Error::new(IOError, err.to_string()))
+}
+
+// Writes every key of the workload, the threads taking a share each, and counts them.
+pub fn load(pool: &Pool<BronzeConnManager>, workload: &Workload) -> Result<u64> {
+    let handles: Vec<_> = (0..workload.threads)
+        .map(|thread| {
+            let pool = pool.clone();
+            let workload = workload.clone();
+            spawn(move || -> Result<u64> {
+                let threads = workload.threads as u64;
+                let lower = workload.keys * thread as u64 / threads;
+                let upper = workload.keys * (thread as u64 + 1) / threads;
+                let mut rng = Rng::new(seed(thread));
+                let value = random_value(&mut rng, workload.value_size);
+                let mut conn = checkout(&pool)?;
+                let mut start = lower;
+                while start < upper {
+                    let end = upper.min(start + LOAD_BATCH);
+                    let batch = (start..end).map(|i| (workload.key(i), value.clone()));
+                    conn.set_batch(batch.collect())?;
+                    start = end;
+                }
+                Ok(upper - lower)
+            })
+        })
+        .collect();
+    let mut loaded = 0;
+    for handle in handles {
+        loaded += handle.join().expect("load thread panicked")?;
+    }
+    Ok(loaded)
+}
+
+fn random_value(rng: &mut Rng, size: usize) -> Vec<u8> {
+    (0..size).map(|_| b'a' + rng.below(26) as u8).collect()
+}
+
+// Runs the workload on connections from the pool until its duration is up, calling
+// `progress` about once a second with the operations done and the time taken so far.
+// Failed operations are counted, not timed.
+pub fn run(
+    pool: &Pool<BronzeConnManager>,
+    workload: &Workload,
+    mut progress: impl FnMut(u64, Duration),
+) -> Stats {
+    let zipfian = match workload.distribution {
+        Distribution::Zipfian => Some(Arc::new(Zipfian::new(workload.keys, workload.theta))),
+        _ => None,
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    let done = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+    let handles: Vec<_> = (0..workload.threads)
+        .map(|thread| {
+            let pool = pool.clone();
+            let workload = workload.clone();
+            let keys = Keys::new(&workload, zipfian.clone(), thread);
+            let stop = stop.clone();
+            let done = done.clone();
+            spawn(move || work(&pool, &workload, keys, thread, &stop, &done))
+        })
+        .collect();
+    while started.elapsed() < workload.duration {
+        sleep(PROGRESS_INTERVAL.min(workload.duration.saturating_sub(started.elapsed())));
+        progress(done.load(Ordering::Relaxed), started.elapsed());
+    }
+    stop.store(true, Ordering::Relaxed);
+    let mut stats = Stats::default();
+    for handle in handles {
+        stats.merge(&handle.join().expect("bench thread panicked"));
+    }
+    stats
+}
+
+fn work(
+    pool: &Pool<BronzeConnManager>,
+    workload: &Workload,
+    mut keys: Keys,
+    thread: usize,
+    stop: &AtomicBool,
+    done: &AtomicU64,
+) -> Stats {
+    let mut rng = Rng::new(seed(thread));
+    let value = random_value(&mut rng, workload.value_size);
+    let mut stats = Stats::default();
+    while !stop.load(Ordering::Relaxed) {
+        let key = workload.key(keys.next(&mut rng));
+        let pick = rng.below(workload.reads + workload.writes + workload.scans);
+        let operation = if pick < workload.reads {
+            Operation::Read
+        } else if pick < workload.reads + workload.writes {
+            Operation::Write
+        } else {
+            Operation::Scan
+        };
+        let started = Instant::now();
+        match operate(pool, operation, key, &value, workload.scan_length) {
+            Ok(()) => {
+                let histogram = match operation {
+                    Operation::Read => &mut stats.reads,
+                    Operation::Write => &mut stats.writes,
+                    Operation::Scan => &mut stats.scans,
+                };
+                histogram.record(started.elapsed());
+            }
+            Err(_) => stats.errors += 1,
+        }
+        done.fetch_add(1, Ordering::Relaxed);
+    }
+    stats
+}
+
+fn operate(
+    pool: &Pool<BronzeConnManager>,
+    operation: Operation,
+    key: Key,
+    value: &[u8],
+    scan_length: usize,
+) -> Result<()> {
+    let mut conn = checkout(pool)?;
+    match operation {
+        Operation::Read => conn.get(key).map(drop),
+        Operation::Write => conn.set(key, value.to_vec()),
+        // the rest of the scan is cancelled
+        Operation::Scan => conn
+            .scan(Some(key), None)?
+            .take(scan_length)
+            .try_for_each(|entry| entry.map(drop)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Distribution, Keys, Rng, Workload, Zipfian};
+    use std::sync::Arc;
+
+    #[test]
+    fn workloads() {
+        let workload = Workload {
+            keys: 1000,
+            key_size: 5,
+            ..Workload::default()
+        };
+        assert!(workload.check().is_ok());
+        assert_eq!(b"00042".to_vec(), workload.key(42).to_vec());
+        for invalid in &[
+            Workload {
+                key_size: 2,
+                ..workload.clone()
+            },
+            Workload {
+                reads: 0,
+                writes: 0,
+                ..workload.clone()
+            },
+            Workload {
+                theta: 1.0,
+                ..workload.clone()
+            },
+            Workload {
+                value_size: 1 << 20,
+                ..workload.clone()
+            },
+        ] {
+            assert!(invalid.check().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn distributions() {
+        let mut rng = Rng::new(42);
+        let zipfian = Zipfian::new(1000, 0.99);
+        let mut counts = vec![0; 1000];
+        for _ in 0..100_000 {
+            counts[zipfian.sample(&mut rng) as usize] += 1;
+        }
+        assert!(counts[0] > counts[1] && counts[1] > counts[10] && counts[10] > counts[500]);
+        assert!(counts[0] > 10_000, "{}", counts[0]);
+
+        let workload = Workload {
+            keys: 10,
+            threads: 2,
+            distribution: Distribution::Sequential,
+            ..Workload::default()
+        };
+        let mut keys = Keys::new(&workload, None, 1);
+        let picked: Vec<_> = (0..7).map(|_| keys.next(&mut rng)).collect();
+        assert_eq!(vec![5, 6, 7, 8, 9, 0, 1], picked);
+
+        let workload = Workload {
+            keys: 10,
+            distribution: Distribution::Zipfian,
+            ..Workload::default()
+        };
+        let mut keys = Keys::new(&workload, Some(Arc::new(Zipfian::new(10, 0.99))), 0);
+        assert!((0..1000).all(|_| keys.next(&mut rng) < 10));
+        assert!((0..1000).all(|_| rng.below(7) < 7));
+    }
+}
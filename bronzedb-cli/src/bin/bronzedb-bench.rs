@@ -0,0 +1,219 @@
+use bronzedb_cli::bench::{self, Stats, Workload};
+use bronzedb_cli::histogram::Histogram;
+use bronzedb_client::{BronzeConnManager, Credentials, Pool, Validation};
+use std::env;
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: bronzedb-bench [OPTIONS]
+
+options:
+  --addr ADDR              server address, or unix:///path/to/socket (127.0.0.1:8088)
+  --user USER --password PASSWORD
+  --threads N              threads sending requests (4)
+  --connections N          connections they share (as many as threads)
+  --duration SECONDS       (10)
+  --keys N                 number of distinct keys (100000)
+  --key-size BYTES         (16)
+  --value-size BYTES       (100)
+  --reads WEIGHT --writes WEIGHT --scans WEIGHT
+                           how often each operation runs (50, 50, 0)
+  --scan-length N          entries read by a scan (10)
+  --distribution uniform|zipfian|sequential
+                           how keys are picked (uniform)
+  --theta THETA            skew of the zipfian distribution (0.99)
+  --load                   write every key before the run";
+
+// rows of the latency histogram are as wide as their share of the operations
+const BAR_WIDTH: f64 = 40.0;
+
+struct Args {
+    addr: String,
+    user: Option<String>,
+    password: Option<String>,
+    connections: Option<u32>,
+    load: bool,
+    workload: Workload,
+}
+
+fn number<T: FromStr>(option: &str, value: String) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} needs a number, not {}", option, value))
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        addr: "127.0.0.1:8088".to_owned(),
+        user: None,
+        password: None,
+        connections: None,
+        load: false,
+        workload: Workload::default(),
+    };
+    let workload = &mut parsed.workload;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--addr" => parsed.addr = value()?,
+            "--user" => parsed.user = Some(value()?),
+            "--password" => parsed.password = Some(value()?),
+            "--threads" => workload.threads = number(&arg, value()?)?,
+            "--connections" => {
+                let connections = number(&arg, value()?)?;
+                if connections == 0 {
+                    return Err("--connections needs a positive number".to_owned());
+                }
+                parsed.connections = Some(connections)
+            }
+            "--duration" => {
+                let seconds: f64 = number(&arg, value()?)?;
+                workload.duration = Duration::try_from_secs_f64(seconds)
+                    .ok()
+                    .filter(|duration| !duration.is_zero())
+                    .ok_or("--duration needs a positive number")?
+            }
+            "--keys" => workload.keys = number(&arg, value()?)?,
+            "--key-size" => workload.key_size = number(&arg, value()?)?,
+            "--value-size" => workload.value_size = number(&arg, value()?)?,
+            "--reads" => workload.reads = number(&arg, value()?)?,
+            "--writes" => workload.writes = number(&arg, value()?)?,
+            "--scans" => workload.scans = number(&arg, value()?)?,
+            "--scan-length" => workload.scan_length = number(&arg, value()?)?,
+            "--distribution" => workload.distribution = value()?.parse()?,
+            "--theta" => workload.theta = number(&arg, value()?)?,
+            "--load" => parsed.load = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+    parsed.workload.check()?;
+    Ok(parsed)
+}
+
+fn main() {
+    let args = match parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let Args {
+        addr,
+        user,
+        password,
+        connections,
+        load,
+        workload,
+    } = args;
+    let connections = connections.unwrap_or(workload.threads as u32);
+    // a checkout is part of each operation, so it should not make a round trip
+    let mut manager = BronzeConnManager::new(addr.clone()).validation(Validation::State);
+    if let (Some(user), Some(password)) = (user, password) {
+        manager = manager.credentials(Credentials::Password { user, password });
+    }
+    let pool = Pool::builder()
+        .max_size(connections)
+        .connection_timeout(Duration::from_secs(5))
+        .build(manager)
+        .unwrap_or_else(|err| {
+            eprintln!("cannot connect to {}: {}", addr, err);
+            process::exit(1);
+        });
+
+    let total = workload.reads + workload.writes + workload.scans;
+    let share = |weight: u64| weight as f64 * 100.0 / total as f64;
+    println!(
+        "{} threads on {} connections to {}, {} keys of {} bytes ({:?}), values of {} bytes",
+        workload.threads,
+        connections,
+        addr,
+        workload.keys,
+        workload.key_size,
+        workload.distribution,
+        workload.value_size
+    );
+    println!(
+        "{:.0}% reads, {:.0}% writes, {:.0}% scans of {} entries, for {:.1?}\n",
+        share(workload.reads),
+        share(workload.writes),
+        share(workload.scans),
+        workload.scan_length,
+        workload.duration
+    );
+
+    if load {
+        let started = Instant::now();
+        match bench::load(&pool, &workload) {
+            Ok(loaded) => eprintln!("loaded {} keys in {:.2?}", loaded, started.elapsed()),
+            Err(err) => {
+                eprintln!("load failed: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    let mut last = (0, Duration::default());
+    let stats = bench::run(&pool, &workload, |done, elapsed| {
+        let rate = (done - last.0) as f64 / (elapsed - last.1).as_secs_f64();
+        eprintln!("[{:>4.0}s] {:.0} ops/s", elapsed.as_secs_f64(), rate);
+        last = (done, elapsed);
+    });
+    report(&stats, last.1.max(workload.duration));
+}
+
+fn report(stats: &Stats, elapsed: Duration) {
+    let total = stats.total();
+    println!(
+        "\n{:<10}{:>12}{:>12}{:>12}{:>12}{:>12}{:>12}{:>12}",
+        "operation", "count", "ops/s", "mean", "p50", "p99", "p999", "max"
+    );
+    let rows = [
+        ("read", &stats.reads),
+        ("write", &stats.writes),
+        ("scan", &stats.scans),
+        ("total", &total),
+    ];
+    for (name, histogram) in rows.iter().filter(|(_, histogram)| histogram.count() > 0) {
+        println!(
+            "{:<10}{:>12}{:>12.0}{:>12}{:>12}{:>12}{:>12}{:>12}",
+            name,
+            histogram.count(),
+            histogram.count() as f64 / elapsed.as_secs_f64(),
+            latency(histogram.mean()),
+            latency(histogram.percentile(0.5)),
+            latency(histogram.percentile(0.99)),
+            latency(histogram.percentile(0.999)),
+            latency(histogram.max()),
+        );
+    }
+    if stats.errors > 0 {
+        println!("{} operations failed", stats.errors);
+    }
+    if total.count() > 0 {
+        println!("\nlatency of all operations:");
+        histogram(&total);
+    }
+}
+
+fn latency(latency: Duration) -> String {
+    format!("{:.2?}", latency)
+}
+
+fn histogram(histogram: &Histogram) {
+    for (bound, count) in histogram.powers() {
+        let share = count as f64 / histogram.count() as f64;
+        println!(
+            "  < {:<10}{:>10}{:>7.2}% {}",
+            latency(bound),
+            count,
+            share * 100.0,
+            "#".repeat((share * BAR_WIDTH).round() as usize)
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use bronzedb_cli::editor::Editor;
+use bronzedb_cli::shell::{self, Command, HELP};
+use bronzedb_client::{BronzeConnManager, Client, Credentials};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: bronzedb-cli [OPTIONS] [FILE]
+
+options:
+  --addr ADDR              server address, or unix:///path/to/socket (127.0.0.1:8088)
+  --user USER --password PASSWORD
+  --timeout SECONDS        read and write timeout
+  -c, --command COMMAND    run the command and exit, may be repeated
+  --timing                 show how long commands take, on by default in a terminal
+
+Commands come from the options, FILE, or stdin, which is edited with history in a terminal.
+Run non-interactively, the first failing command stops the cli with status 1.";
+
+const HISTORY_FILE: &str = ".bronzedb_history";
+
+#[derive(Debug)]
+struct Args {
+    addr: String,
+    user: Option<String>,
+    password: Option<String>,
+    timeout: Option<Duration>,
+    commands: Vec<String>,
+    timing: bool,
+    file: Option<String>,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        addr: "127.0.0.1:8088".to_owned(),
+        user: None,
+        password: None,
+        timeout: None,
+        commands: Vec::new(),
+        timing: false,
+        file: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--addr" => parsed.addr = value()?,
+            "--user" => parsed.user = Some(value()?),
+            "--password" => parsed.password = Some(value()?),
+            "--timeout" => {
+                let timeout = value()?
+                    .parse()
+                    .ok()
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                    .filter(|timeout| !timeout.is_zero())
+                    .ok_or("--timeout needs a positive number of seconds")?;
+                parsed.timeout = Some(timeout)
+            }
+            "-c" | "--command" => parsed.commands.push(value()?),
+            "--timing" => parsed.timing = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if !arg.starts_with('-') && parsed.file.is_none() => parsed.file = Some(arg),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+enum Outcome {
+    Done,
+    Failed,
+    Quit,
+}
+
+struct Session {
+    client: Client,
+    timing: bool,
+}
+
+impl Session {
+    fn execute(&mut self, line: &str) -> Outcome {
+        let command = match Command::parse(line) {
+            Ok(Some(command)) => command,
+            Ok(None) => return Outcome::Done,
+            Err(message) => {
+                eprintln!("error: {}", message);
+                return Outcome::Failed;
+            }
+        };
+        match command {
+            Command::Timing(timing) => {
+                self.timing = timing.unwrap_or(!self.timing);
+                eprintln!("timing is {}", if self.timing { "on" } else { "off" });
+            }
+            Command::Help => println!("{}", HELP),
+            Command::Quit => return Outcome::Quit,
+            command => {
+                let started = Instant::now();
+                let result = shell::run(&mut self.client, command, &mut io::stdout().lock());
+                if self.timing {
+                    eprintln!("({:.2?})", started.elapsed());
+                }
+                if let Err(err) = result {
+                    eprintln!("error: {}", err);
+                    return Outcome::Failed;
+                }
+            }
+        }
+        Outcome::Done
+    }
+}
+
+fn main() {
+    let args = match parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let mut manager = BronzeConnManager::new(args.addr.clone());
+    if let (Some(user), Some(password)) = (args.user, args.password) {
+        manager = manager.credentials(Credentials::Password { user, password });
+    }
+    if let Some(timeout) = args.timeout {
+        manager = manager.read_timeout(timeout).write_timeout(timeout);
+    }
+    let mut session = Session {
+        client: Client::new(manager),
+        timing: args.timing,
+    };
+
+    let lines: Box<dyn Iterator<Item = io::Result<String>>> = if !args.commands.is_empty() {
+        Box::new(args.commands.into_iter().map(Ok))
+    } else if let Some(ref file) = args.file {
+        match File::open(file) {
+            Ok(file) => Box::new(BufReader::new(file).lines()),
+            Err(err) => {
+                eprintln!("cannot open {}: {}", file, err);
+                process::exit(2);
+            }
+        }
+    } else {
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        let mut editor = Editor::new(history);
+        if editor.is_terminal() {
+            interact(&mut session, &mut editor, &args.addr);
+            return;
+        }
+        Box::new(std::iter::from_fn(move || editor.read_line("").transpose()))
+    };
+    for (number, line) in lines.enumerate() {
+        let line = line.unwrap_or_else(|err| {
+            eprintln!("cannot read commands: {}", err);
+            process::exit(1);
+        });
+        match session.execute(&line) {
+            Outcome::Done => (),
+            Outcome::Quit => break,
+            Outcome::Failed => {
+                eprintln!("stopped at command {}", number + 1);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+// Failed commands are reported and the session goes on.
+fn interact(session: &mut Session, editor: &mut Editor, addr: &str) {
+    session.timing = true;
+    match session.client.ping() {
+        Ok(()) => eprintln!("connected to {}, type help for the commands", addr),
+        Err(err) => eprintln!("cannot reach {}: {}", addr, err),
+    }
+    let prompt = format!("{}> ", addr);
+    loop {
+        let line = match editor.read_line(&prompt) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("cannot read commands: {}", err);
+                process::exit(1);
+            }
+        };
+        editor.add(&line);
+        if let Outcome::Quit = session.execute(&line) {
+            break;
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use bronzedb_util::status::StatusCode::Corruption;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::Key;
+use std::fs;
+use std::io::ErrorKind;
+
+// How far an export or import got: the entries done, the bytes of the file they take and,
+// for an export, the last key. Saved next to the file, as `<file>.checkpoint`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checkpoint {
+    pub entries: u64,
+    pub offset: u64,
+    pub last: Option<Key>,
+}
+
+pub fn path(file: &str) -> String {
+    format!("{}.checkpoint", file)
+}
+
+impl Checkpoint {
+    pub fn load(path: &str) -> Result<Option<Self>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let invalid = || Error::new(Corruption, format!("invalid checkpoint {}", path));
+        let mut lines = text.lines();
+        let mut number = || -> Result<u64> {
+            lines
+                .next()
+                .and_then(|line| line.parse().ok())
+                .ok_or_else(invalid)
+        };
+        let entries = number()?;
+        let offset = number()?;
+        let last = match lines.next() {
+            Some("") | None => None,
+            Some(line) => Some(STANDARD.decode(line).map_err(|_| invalid())?.into()),
+        };
+        Ok(Some(Self {
+            entries,
+            offset,
+            last,
+        }))
+    }
+
+    // Replaces the checkpoint at once, so a crash leaves the old or the new one.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let last = self
+            .last
+            .as_ref()
+            .map(|key| STANDARD.encode(key.as_slice()))
+            .unwrap_or_default();
+        let temporary = format!("{}.tmp", path);
+        fs::write(
+            &temporary,
+            format!("{}\n{}\n{}\n", self.entries, self.offset, last),
+        )?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    pub fn remove(path: &str) -> Result<()> {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checkpoint;
+    use bronzedb_util::status::Result;
+
+    #[test]
+    fn save_and_load() -> Result<()> {
+        let path = std::env::temp_dir()
+            .join(format!("bronzedb-cli-{}.checkpoint", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert_eq!(None, Checkpoint::load(&path)?);
+        for last in &[None, Some(b"\xffkey".to_vec().into())] {
+            let checkpoint = Checkpoint {
+                entries: 42,
+                offset: 1024,
+                last: last.clone(),
+            };
+            checkpoint.save(&path)?;
+            assert_eq!(Some(checkpoint), Checkpoint::load(&path)?);
+        }
+        Checkpoint::remove(&path)?;
+        Checkpoint::remove(&path)?;
+        assert_eq!(None, Checkpoint::load(&path)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+const HISTORY_LEN: usize = 1000;
+
+// Reads lines from a terminal with emacs-like editing and a history browsed with the arrow
+// keys, or plain lines from anything else. The history is kept in `file` across sessions.
+pub struct Editor {
+    history: Vec<String>,
+    file: Option<PathBuf>,
+    terminal: bool,
+}
+
+impl Editor {
+    pub fn new(file: Option<PathBuf>) -> Self {
+        let mut history: Vec<String> = file
+            .as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|text| text.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+        history.drain(..history.len().saturating_sub(HISTORY_LEN));
+        Self {
+            history,
+            file,
+            terminal: cfg!(unix) && io::stdin().is_terminal() && io::stdout().is_terminal(),
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        self.terminal
+    }
+
+    // `None` at the end of the input.
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        if !self.terminal {
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let end = line.trim_end_matches(&['\r', '\n'][..]).len();
+            line.truncate(end);
+            return Ok(Some(line));
+        }
+        #[cfg(unix)]
+        let _raw = RawMode::enable()?;
+        self.edit(prompt, &mut io::stdin().lock(), &mut io::stdout().lock())
+    }
+
+    // Lines are appended to the file as they are added, so sessions running at once keep
+    // theirs.
+    pub fn add(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_owned());
+        if self.history.len() > HISTORY_LEN {
+            self.history.remove(0);
+        }
+        if let Some(ref file) = self.file {
+            let appended = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(file)
+                .and_then(|mut file| writeln!(file, "{}", line));
+            if appended.is_err() {
+                self.file = None;
+            }
+        }
+    }
+
+    fn edit(
+        &self,
+        prompt: &str,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> io::Result<Option<String>> {
+        let mut line = Line::default();
+        // the entry shown while browsing the history, and the line typed before
+        let mut browsing = self.history.len();
+        let mut typed = String::new();
+        refresh(output, prompt, &line)?;
+        loop {
+            let key = match read_key(input)? {
+                Some(key) => key,
+                None => return Ok(None),
+            };
+            match key {
+                Key::Enter => {
+                    output.write_all(b"\r\n")?;
+                    return Ok(Some(line.text()));
+                }
+                Key::Interrupt => {
+                    output.write_all(b"^C\r\n")?;
+                    return Ok(Some(String::new()));
+                }
+                Key::Eof if line.chars.is_empty() => {
+                    output.write_all(b"\r\n")?;
+                    return Ok(None);
+                }
+                Key::Up if browsing > 0 => {
+                    if browsing == self.history.len() {
+                        typed = line.text();
+                    }
+                    browsing -= 1;
+                    line = Line::new(&self.history[browsing]);
+                }
+                Key::Down if browsing < self.history.len() => {
+                    browsing += 1;
+                    line = Line::new(self.history.get(browsing).unwrap_or(&typed));
+                }
+                Key::Clear => output.write_all(b"\x1b[H\x1b[2J")?,
+                key => line.apply(key),
+            }
+            refresh(output, prompt, &line)?;
+        }
+    }
+}
+
+fn refresh(output: &mut impl Write, prompt: &str, line: &Line) -> io::Result<()> {
+    write!(output, "\r{}{}\x1b[K", prompt, line.text())?;
+    let back = line.chars.len() - line.cursor;
+    if back > 0 {
+        write!(output, "\x1b[{}D", back)?;
+    }
+    output.flush()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    KillEnd,
+    KillStart,
+    KillWord,
+    Clear,
+    Interrupt,
+    Eof,
+    Ignored,
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+// `None` at the end of the input.
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let byte = match read_byte(input)? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        1 => Key::Home,
+        2 => Key::Left,
+        3 => Key::Interrupt,
+        4 => Key::Eof,
+        5 => Key::End,
+        6 => Key::Right,
+        8 | 127 => Key::Backspace,
+        11 => Key::KillEnd,
+        12 => Key::Clear,
+        14 => Key::Down,
+        16 => Key::Up,
+        21 => Key::KillStart,
+        23 => Key::KillWord,
+        27 => read_escape(input)?,
+        0..=31 => Key::Ignored,
+        32..=126 => Key::Char(byte as char),
+        _ => {
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => return Ok(Some(Key::Ignored)),
+            };
+            let mut encoded = vec![byte; len];
+            input.read_exact(&mut encoded[1..])?;
+            match std::str::from_utf8(&encoded) {
+                Ok(text) => text.chars().next().map_or(Key::Ignored, Key::Char),
+                Err(_) => Key::Ignored,
+            }
+        }
+    };
+    Ok(Some(key))
+}
+
+// The arrow, home, end and delete keys send escape sequences.
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    match read_byte(input)? {
+        Some(b'[') | Some(b'O') => (),
+        _ => return Ok(Key::Ignored),
+    }
+    let key = match read_byte(input)? {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(digit @ b'0'..=b'9') => {
+            let mut code = vec![digit];
+            loop {
+                match read_byte(input)? {
+                    Some(b'~') => break,
+                    Some(byte) if code.len() < 8 => code.push(byte),
+                    _ => return Ok(Key::Ignored),
+                }
+            }
+            match code.as_slice() {
+                b"1" | b"7" => Key::Home,
+                b"4" | b"8" => Key::End,
+                b"3" => Key::Delete,
+                _ => Key::Ignored,
+            }
+        }
+        _ => Key::Ignored,
+    };
+    Ok(key)
+}
+
+#[derive(Debug, Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn new(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        Self {
+            cursor: chars.len(),
+            chars,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn apply(&mut self, key: Key) {
+        match key {
+            Key::Char(c) => {
+                self.chars.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete | Key::Eof if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = self.chars.len().min(self.cursor + 1),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.chars.len(),
+            Key::KillEnd => self.chars.truncate(self.cursor),
+            Key::KillStart => {
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::KillWord => {
+                let mut start = self.cursor;
+                while start > 0 && self.chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !self.chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.chars.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            _ => (),
+        }
+    }
+}
+
+// Turns off echo and line buffering of the terminal until dropped.
+#[cfg(unix)]
+struct RawMode(libc::termios);
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = termios;
+        termios.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+        termios.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawMode(original))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Editor;
+
+    fn edit(editor: &Editor, keys: &[u8]) -> Option<String> {
+        let mut output = Vec::new();
+        editor.edit("> ", &mut &keys[..], &mut output).unwrap()
+    }
+
+    #[test]
+    fn editing() {
+        let mut editor = Editor {
+            history: Vec::new(),
+            file: None,
+            terminal: false,
+        };
+        assert_eq!(
+            Some("aXbc".to_owned()),
+            edit(&editor, b"abc\x1b[D\x1b[DX\r")
+        );
+        assert_eq!(
+            Some("ñb".to_owned()),
+            edit(&editor, "añ\x01\x1b[3~\x05b\r".as_bytes())
+        );
+        assert_eq!(Some("set ".to_owned()), edit(&editor, b"set key\x17\r"));
+        assert_eq!(Some("b".to_owned()), edit(&editor, b"ab\x02\x15\r"));
+        assert_eq!(Some(String::new()), edit(&editor, b"get\x03"));
+        assert_eq!(None, edit(&editor, b"\x04"));
+        assert_eq!(None, edit(&editor, b"unfinished"));
+
+        editor.add("get a");
+        editor.add("get a");
+        editor.add("  ");
+        editor.add("get b");
+        assert_eq!(vec!["get a", "get b"], editor.history);
+        assert_eq!(
+            Some("get a".to_owned()),
+            edit(&editor, b"\x1b[A\x1b[A\x1b[A\r")
+        );
+        assert_eq!(Some("get b".to_owned()), edit(&editor, b"x\x10\x10\x0e\r"));
+        assert_eq!(
+            Some("x".to_owned()),
+            edit(&editor, b"x\x1b[A\x1b[B\x1b[B\r")
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use bronzedb_protocol::ext::WriteKVExt;
+use bronzedb_protocol::{MAX_KEY_LEN, MAX_VALUE_LEN};
+use bronzedb_util::status::StatusCode::Corruption;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::Entry;
+use byteorder::{BigEndian, ReadBytesExt};
+use serde_json::Value as Json;
+use std::convert::TryFrom;
+use std::io::{BufRead, Read, Write};
+use std::str::FromStr;
+
+const CSV_HEADER: &[u8] = b"key,value\n";
+
+// `Ndjson` has an object with base64 `key` and `value` on each line, `Csv` a `key,value`
+// header and quoted text fields, `Binary` the key and value with their lengths before them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Ndjson,
+    Csv,
+    Binary,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        match name {
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            "binary" => Ok(Format::Binary),
+            _ => Err(format!("unknown format: {}", name)),
+        }
+    }
+}
+
+fn corrupted(record: u64, message: impl std::fmt::Display) -> Error {
+    Error::new(Corruption, format!("record {}: {}", record, message))
+}
+
+pub struct EntryWriter<W: Write> {
+    format: Format,
+    writer: W,
+}
+
+impl<W: Write> EntryWriter<W> {
+    pub fn new(format: Format, writer: W) -> Self {
+        Self { format, writer }
+    }
+
+    // Comes before the first entry, if the format has one.
+    pub fn header(&mut self) -> Result<()> {
+        if self.format == Format::Csv {
+            self.writer.write_all(CSV_HEADER)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        match self.format {
+            Format::Ndjson => writeln!(
+                self.writer,
+                r#"{{"key":"{}","value":"{}"}}"#,
+                STANDARD.encode(key),
+                STANDARD.encode(value)
+            )?,
+            Format::Csv => {
+                write_field(&mut self.writer, key)?;
+                self.writer.write_all(b",")?;
+                write_field(&mut self.writer, value)?;
+                self.writer.write_all(b"\n")?;
+            }
+            Format::Binary => {
+                self.writer.write_key(key)?;
+                self.writer.write_value(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+}
+
+fn write_field(writer: &mut impl Write, field: &[u8]) -> Result<()> {
+    if std::str::from_utf8(field).is_err() {
+        return Err(Error::new(
+            Corruption,
+            "data is not valid utf-8, export as ndjson or binary",
+        ));
+    }
+    if field.iter().any(|byte| b",\"\r\n".contains(byte)) {
+        writer.write_all(b"\"")?;
+        for &byte in field {
+            if byte == b'"' {
+                writer.write_all(b"\"")?;
+            }
+            writer.write_all(&[byte])?;
+        }
+        writer.write_all(b"\"")?;
+    } else {
+        writer.write_all(field)?;
+    }
+    Ok(())
+}
+
+pub struct EntryReader<R: BufRead> {
+    format: Format,
+    reader: R,
+    // records read so far, the csv header included
+    records: u64,
+    line: Vec<u8>,
+}
+
+impl<R: BufRead> EntryReader<R> {
+    pub fn new(format: Format, reader: R) -> Self {
+        Self {
+            format,
+            reader,
+            records: 0,
+            line: Vec::new(),
+        }
+    }
+
+    fn read_entry(&mut self) -> Result<Option<Entry>> {
+        self.records += 1;
+        match self.format {
+            Format::Ndjson => loop {
+                self.line.clear();
+                if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                    return Ok(None);
+                }
+                if !self.line.iter().all(u8::is_ascii_whitespace) {
+                    return self.parse_json().map(Some);
+                }
+            },
+            Format::Csv => loop {
+                let fields = match self.read_record()? {
+                    Some(fields) => fields,
+                    None => return Ok(None),
+                };
+                if self.records == 1 && fields == [&b"key"[..], &b"value"[..]] {
+                    self.records += 1;
+                    continue;
+                }
+                return match <[Vec<u8>; 2]>::try_from(fields) {
+                    Ok([key, value]) => Ok(Some((key.into(), value))),
+                    Err(fields) => Err(corrupted(
+                        self.records,
+                        format!("{} fields instead of 2", fields.len()),
+                    )),
+                };
+            },
+            Format::Binary => {
+                let mut first = [0];
+                if self.reader.read(&mut first)? == 0 {
+                    return Ok(None);
+                }
+                let mut reader = (&first[..]).chain(&mut self.reader);
+                let key = read_field(&mut reader)?;
+                let value = read_field(&mut reader)?;
+                Ok(Some((key.into(), value)))
+            }
+        }
+    }
+
+    // Entries from a file may be too long for the protocol.
+    fn check(&self, entry: Option<Entry>) -> Result<Option<Entry>> {
+        if let Some((ref key, ref value)) = entry {
+            if key.len() > MAX_KEY_LEN {
+                return Err(corrupted(self.records, "key is too long"));
+            }
+            if value.len() > MAX_VALUE_LEN {
+                return Err(corrupted(self.records, "value is too long"));
+            }
+        }
+        Ok(entry)
+    }
+
+    fn parse_json(&self) -> Result<Entry> {
+        let object: Json =
+            serde_json::from_slice(&self.line).map_err(|err| corrupted(self.records, err))?;
+        let field = |name: &str| -> Result<Vec<u8>> {
+            let text = object[name]
+                .as_str()
+                .ok_or_else(|| corrupted(self.records, format!("no {} string", name)))?;
+            STANDARD
+                .decode(text)
+                .map_err(|err| corrupted(self.records, err))
+        };
+        Ok((field("key")?.into(), field("value")?))
+    }
+
+    // Quoted fields may hold commas, doubled quotes and line breaks.
+    fn read_record(&mut self) -> Result<Option<Vec<Vec<u8>>>> {
+        self.line.clear();
+        if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(None);
+        }
+        let mut fields = Vec::new();
+        let mut field = Vec::new();
+        let mut quoted = false;
+        let mut position = 0;
+        loop {
+            if position == self.line.len() {
+                if !quoted {
+                    break;
+                }
+                if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                    return Err(corrupted(self.records, "unterminated quote"));
+                }
+                continue;
+            }
+            let byte = self.line[position];
+            position += 1;
+            match (quoted, byte) {
+                (true, b'"') if self.line.get(position) == Some(&b'"') => {
+                    field.push(b'"');
+                    position += 1;
+                }
+                (true, b'"') => quoted = false,
+                (true, byte) => field.push(byte),
+                (false, b'"') if field.is_empty() => quoted = true,
+                (false, b',') => fields.push(std::mem::take(&mut field)),
+                (false, b'\r') | (false, b'\n') => (),
+                (false, byte) => field.push(byte),
+            }
+        }
+        fields.push(field);
+        Ok(Some(fields))
+    }
+}
+
+fn read_field(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut field = vec![0; reader.read_u16::<BigEndian>()? as usize];
+    reader.read_exact(&mut field)?;
+    Ok(field)
+}
+
+impl<R: BufRead> Iterator for EntryReader<R> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry()
+            .and_then(|entry| self.check(entry))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EntryReader, EntryWriter, Format};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine as _;
+    use bronzedb_protocol::MAX_KEY_LEN;
+    use bronzedb_util::status::{Result, StatusCode};
+    use bronzedb_util::types::Entry;
+
+    fn round_trip(format: Format, entries: &[(&[u8], &[u8])]) -> Result<Vec<Entry>> {
+        let mut data = Vec::new();
+        let mut writer = EntryWriter::new(format, &mut data);
+        writer.header()?;
+        for (key, value) in entries {
+            writer.write(key, value)?;
+        }
+        EntryReader::new(format, data.as_slice()).collect()
+    }
+
+    #[test]
+    fn formats() -> Result<()> {
+        let entries: &[(&[u8], &[u8])] = &[
+            (b"name", b"Hexi"),
+            (b"quote", b"say \"hi\", then\r\nleave"),
+            (b"key", b"value"),
+            (b"", b""),
+        ];
+        for format in &[Format::Ndjson, Format::Csv, Format::Binary] {
+            let read = round_trip(*format, entries)?;
+            assert_eq!(entries.len(), read.len(), "{:?}", format);
+            for ((key, value), (read_key, read_value)) in entries.iter().zip(&read) {
+                assert_eq!(*key, read_key.as_slice(), "{:?}", format);
+                assert_eq!(*value, read_value.as_slice(), "{:?}", format);
+            }
+        }
+        let binary: &[(&[u8], &[u8])] = &[(b"\xff\x00", b"\x80")];
+        assert_eq!(1, round_trip(Format::Ndjson, binary)?.len());
+        assert_eq!(1, round_trip(Format::Binary, binary)?.len());
+        assert!(round_trip(Format::Csv, binary).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn malformed() {
+        let inputs: &[(Format, &[u8])] = &[
+            (Format::Ndjson, b"{\"key\":\"a2V5\"}\n"),
+            (Format::Ndjson, b"{\"key\":\"!\",\"value\":\"\"}\n"),
+            (Format::Csv, b"a,b,c\n"),
+            (Format::Csv, b"\"a,b\n"),
+            (Format::Binary, b"\x00\x03ab"),
+        ];
+        for (format, input) in inputs {
+            let read = EntryReader::new(*format, *input).collect::<Result<Vec<_>>>();
+            assert!(read.is_err(), "{:?} {:?}", format, input);
+        }
+        let long = [0; MAX_KEY_LEN + 1];
+        let input = format!(r#"{{"key":"{}","value":""}}"#, STANDARD.encode(&long[..]));
+        assert!(EntryReader::new(Format::Ndjson, input.as_bytes())
+            .next()
+            .unwrap()
+            .is_err());
+        let err = EntryReader::new(Format::Csv, &b"key,value\nk,v\nk\n"[..])
+            .collect::<Result<Vec<_>>>()
+            .unwrap_err();
+        assert_eq!(StatusCode::Corruption, err.code);
+        assert!(err.message.starts_with("record 3:"), "{}", err.message);
+    }
+}
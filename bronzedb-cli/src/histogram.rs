@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+// values below 2^SUB_BITS get a bucket each, larger ones keep their SUB_BITS - 1 highest
+// bits after the first, which is within 1.6% of the value
+const SUB_BITS: u32 = 7;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const HALF: usize = SUB_BUCKETS / 2;
+const BUCKETS: usize = (64 - SUB_BITS as usize) * HALF + SUB_BUCKETS;
+
+// Latencies in nanoseconds, in buckets as wide as a small part of their values, so any
+// range is recorded in fixed space and percentiles are read off the buckets.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+}
+
+fn index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let shift = 64 - value.leading_zeros() - SUB_BITS;
+    shift as usize * HALF + (value >> shift) as usize
+}
+
+// The largest value of a bucket.
+fn highest(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = index / HALF - 1;
+    let top = (index - shift * HALF) as u128;
+    (((top + 1) << shift) - 1) as u64
+}
+
+impl Histogram {
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = latency.as_nanos().min(u64::MAX as u128) as u64;
+        self.counts[index(nanos)] += 1;
+        self.count += 1;
+        self.sum += nanos as u128;
+        self.min = self.min.min(nanos);
+        self.max = self.max.max(nanos);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::default(),
+            count => Duration::from_nanos((self.sum / count as u128) as u64),
+        }
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+
+    // The latency `quantile` of the values are at most, e.g. 0.99 for p99.
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::default();
+        }
+        let rank = ((quantile * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(highest(index).clamp(self.min, self.max));
+            }
+        }
+        self.max()
+    }
+
+    // The counts of values below each power of two of nanoseconds, up to the maximum.
+    pub fn powers(&self) -> Vec<(Duration, u64)> {
+        let mut powers: Vec<(Duration, u64)> = Vec::new();
+        for (index, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            // buckets do not straddle powers of two
+            let bound = (highest(index) as u128 + 1).next_power_of_two();
+            let bound = Duration::from_nanos(bound.min(u64::MAX as u128) as u64);
+            match powers.last_mut() {
+                Some((last, total)) if *last == bound => *total += count,
+                _ => powers.push((bound, count)),
+            }
+        }
+        powers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{highest, index, Histogram, BUCKETS};
+    use std::time::Duration;
+
+    #[test]
+    fn buckets() {
+        for value in (0..100_000).chain(vec![u64::MAX / 3, u64::MAX - 1, u64::MAX]) {
+            let index = index(value);
+            assert!(index < BUCKETS);
+            assert!(value <= highest(index), "{}", value);
+            assert!(index == 0 || value > highest(index - 1), "{}", value);
+        }
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut histogram = Histogram::default();
+        assert_eq!(Duration::default(), histogram.percentile(0.99));
+        for micros in 1..=1000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let mut other = Histogram::default();
+        other.record(Duration::from_secs(1));
+        histogram.merge(&other);
+        assert_eq!(1001, histogram.count());
+        for &(quantile, expected) in &[(0.5, 501_000.0), (0.99, 991_000.0), (0.999, 1_000_000.0)] {
+            let nanos = histogram.percentile(quantile).as_nanos() as f64;
+            assert!(
+                (nanos - expected).abs() / expected < 0.016,
+                "{} {}",
+                quantile,
+                nanos
+            );
+        }
+        assert_eq!(Duration::from_secs(1), histogram.percentile(1.0));
+        assert_eq!(Duration::from_secs(1), histogram.max());
+        let powers = histogram.powers();
+        assert_eq!(1001, powers.iter().map(|(_, count)| count).sum::<u64>());
+        assert_eq!(Duration::from_nanos(1 << 30), powers.last().unwrap().0);
+    }
+}
@@ -0,0 +1,8 @@
+pub mod bench;
+pub mod checkpoint;
+pub mod editor;
+pub mod format;
+pub mod histogram;
+pub mod literal;
+pub mod shell;
+pub mod transfer;
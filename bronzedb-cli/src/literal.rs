@@ -0,0 +1,188 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+const HEX_PREFIX: &str = "0x";
+const BASE64_PREFIX: &str = "b64:";
+
+// Splits a command line into its arguments, separated by whitespace. An argument is a word,
+// "double quoted" with the escapes \\ \" \n \r \t \0 and \xHH, 'single quoted' as it is,
+// 0x and hex digits, or b64: and base64.
+pub fn split(line: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let arg = match chars.peek() {
+            None => return Ok(args),
+            Some('"') => double_quoted(&mut chars)?,
+            Some('\'') => single_quoted(&mut chars)?,
+            Some(_) => word(&mut chars)?,
+        };
+        if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+            return Err("expected a space after the closing quote".to_owned());
+        }
+        args.push(arg);
+    }
+}
+
+fn double_quoted(chars: &mut Peekable<Chars>) -> Result<Vec<u8>, String> {
+    chars.next();
+    let mut arg = Vec::new();
+    loop {
+        let c = match chars.next() {
+            Some('"') => return Ok(arg),
+            Some('\\') => match chars.next() {
+                Some('x') => {
+                    let digits: String = chars.by_ref().take(2).collect();
+                    let byte = u8::from_str_radix(&digits, 16)
+                        .ok()
+                        .filter(|_| digits.len() == 2)
+                        .ok_or_else(|| format!("invalid escape: \\x{}", digits))?;
+                    arg.push(byte);
+                    continue;
+                }
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(c @ '\\') | Some(c @ '"') => c,
+                Some(c) => return Err(format!("invalid escape: \\{}", c)),
+                None => return Err("unterminated quote".to_owned()),
+            },
+            Some(c) => c,
+            None => return Err("unterminated quote".to_owned()),
+        };
+        let mut encoded = [0; 4];
+        arg.extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
+    }
+}
+
+fn single_quoted(chars: &mut Peekable<Chars>) -> Result<Vec<u8>, String> {
+    chars.next();
+    let mut arg = String::new();
+    loop {
+        match chars.next() {
+            Some('\'') => return Ok(arg.into_bytes()),
+            Some(c) => arg.push(c),
+            None => return Err("unterminated quote".to_owned()),
+        }
+    }
+}
+
+fn word(chars: &mut Peekable<Chars>) -> Result<Vec<u8>, String> {
+    let mut word = String::new();
+    while let Some(c) = chars.peek().filter(|c| !c.is_whitespace()) {
+        word.push(*c);
+        chars.next();
+    }
+    if let Some(digits) = word.strip_prefix(HEX_PREFIX) {
+        return hex(digits).ok_or_else(|| format!("invalid hex: {}", word));
+    }
+    if let Some(encoded) = word.strip_prefix(BASE64_PREFIX) {
+        return STANDARD
+            .decode(encoded)
+            .map_err(|err| format!("invalid base64 {}: {}", word, err));
+    }
+    Ok(word.into_bytes())
+}
+
+fn hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+// Shows data as a word when it reads as one, else double quoted, so it can be typed back.
+pub fn display(data: &[u8]) -> String {
+    let plain = std::str::from_utf8(data).ok().filter(|text| {
+        !text.is_empty()
+            && !text.starts_with(HEX_PREFIX)
+            && !text.starts_with(BASE64_PREFIX)
+            && !text.starts_with('\'')
+            && text
+                .chars()
+                .all(|c| !c.is_whitespace() && !c.is_control() && c != '"')
+    });
+    if let Some(text) = plain {
+        return text.to_owned();
+    }
+    let mut quoted = String::from("\"");
+    for chunk in data.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                c if c.is_control() => {
+                    let mut encoded = [0; 4];
+                    for byte in c.encode_utf8(&mut encoded).bytes() {
+                        write!(quoted, "\\x{:02x}", byte).unwrap();
+                    }
+                }
+                c => quoted.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(quoted, "\\x{:02x}", byte).unwrap();
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{display, split};
+
+    #[test]
+    fn literals() {
+        let args = split(r#"  set "a \"b\"\n\x00" 'it s' 0x00ff b64:aGk= naïve "#).unwrap();
+        let expected: Vec<&[u8]> = vec![
+            b"set",
+            b"a \"b\"\n\x00",
+            b"it s",
+            b"\x00\xff",
+            b"hi",
+            "naïve".as_bytes(),
+        ];
+        assert_eq!(expected, args.iter().map(Vec::as_slice).collect::<Vec<_>>());
+        assert!(split("   ").unwrap().is_empty());
+        for invalid in &[
+            r#""open"#, "'open", "'a''b'", r#""\q""#, r#""\x1""#, "0xabc", "b64:!!",
+        ] {
+            assert!(split(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let data: &[&[u8]] = &[
+            b"plain",
+            b"",
+            b"two words",
+            b"say \"hi\"\\",
+            b"\x00\xff\x80tail",
+            "naïve\u{85}".as_bytes(),
+            b"0x12",
+            b"b64:aGk=",
+            b"'quoted'",
+        ];
+        assert_eq!("plain", display(b"plain"));
+        assert_eq!(r#""\x00\xff""#, display(b"\x00\xff"));
+        for data in data {
+            let shown = display(data);
+            assert_eq!(vec![data.to_vec()], split(&shown).unwrap(), "{}", shown);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use bronzedb_cli::format::Format;
+use bronzedb_cli::transfer::{self, Job};
+use bronzedb_client::{BronzeConnManager, Credentials};
+use bronzedb_util::types::Key;
+use r2d2::ManageConnection;
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: bronzedb [--addr ADDR] [--user USER --password PASSWORD] COMMAND
+
+commands:
+  export [--format ndjson|csv|binary] [--from KEY] [--to KEY] [--output FILE] [--resume] [--batch N]
+  import [--format ndjson|csv|binary] [--input FILE] [--resume] [--batch N]
+
+Without a file, export writes to stdout and import reads stdin. An interrupted export or import
+of a file goes on from its last checkpoint with --resume.";
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Command {
+    Export,
+    Import,
+}
+
+#[derive(Debug)]
+struct Args {
+    addr: String,
+    user: Option<String>,
+    password: Option<String>,
+    lower: Option<Key>,
+    upper: Option<Key>,
+    job: Job,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<(Command, Args), String> {
+    let mut command = None;
+    let mut parsed = Args {
+        addr: "127.0.0.1:8088".to_owned(),
+        user: None,
+        password: None,
+        lower: None,
+        upper: None,
+        job: Job {
+            format: Format::Ndjson,
+            file: None,
+            resume: false,
+            batch: 256,
+        },
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--addr" => parsed.addr = value()?,
+            "--user" => parsed.user = Some(value()?),
+            "--password" => parsed.password = Some(value()?),
+            "--format" => parsed.job.format = value()?.parse()?,
+            "--from" => parsed.lower = Some(value()?.into_bytes().into()),
+            "--to" => parsed.upper = Some(value()?.into_bytes().into()),
+            "--output" | "--input" => parsed.job.file = Some(value()?),
+            "--batch" => {
+                parsed.job.batch = value()?
+                    .parse()
+                    .ok()
+                    .filter(|&batch| batch > 0)
+                    .ok_or("--batch needs a positive number")?
+            }
+            "--resume" => parsed.job.resume = true,
+            "-h" | "--help" => return Err(String::new()),
+            "export" if command.is_none() => command = Some(Command::Export),
+            "import" if command.is_none() => command = Some(Command::Import),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+    match command {
+        Some(command) => Ok((command, parsed)),
+        None => Err("no command".to_owned()),
+    }
+}
+
+fn main() {
+    let (command, args) = match parse(env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let Args {
+        addr,
+        user,
+        password,
+        lower,
+        upper,
+        job,
+    } = args;
+    let mut manager = BronzeConnManager::new(addr);
+    if let (Some(user), Some(password)) = (user, password) {
+        manager = manager.credentials(Credentials::Password { user, password });
+    }
+    let (name, verb) = match command {
+        Command::Export => ("export", "exported"),
+        Command::Import => ("import", "imported"),
+    };
+    let started = Instant::now();
+    let mut reported = started;
+    let progress = |entries: u64| {
+        if reported.elapsed() >= PROGRESS_INTERVAL {
+            reported = Instant::now();
+            eprintln!(
+                "{} {} entries, {:.0}/s",
+                verb,
+                entries,
+                entries as f64 / started.elapsed().as_secs_f64()
+            );
+        }
+    };
+    let result = manager.connect().and_then(|mut conn| match command {
+        Command::Export => transfer::export(&mut conn, &job, lower, upper, progress),
+        Command::Import => transfer::import(&mut conn, &job, progress),
+    });
+    match result {
+        Ok(entries) => eprintln!(
+            "{} {} entries in {:.1}s",
+            verb,
+            entries,
+            started.elapsed().as_secs_f64()
+        ),
+        Err(err) => {
+            eprintln!("{} failed: {}", name, err);
+            if job.file.is_some() {
+                eprintln!("run it again with --resume to go on from the last checkpoint");
+            }
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::literal::{display, split};
+use bronzedb_client::Client;
+use bronzedb_protocol::{MAX_KEY_LEN, MAX_VALUE_LEN};
+use bronzedb_util::status::Result;
+use bronzedb_util::types::{Key, Value};
+use std::io::Write;
+
+pub const HELP: &str = "commands:
+  get KEY
+  set KEY VALUE
+  delete KEY
+  exists KEY
+  scan [from KEY] [to KEY] [limit N]   keys between the bounds, both included
+  ping
+  timing [on|off]                      show how long commands take
+  help
+  quit
+
+Arguments are words, \"double quoted\" with \\\" \\\\ \\n \\r \\t \\0 and \\xHH escapes,
+'single quoted' as they are, 0x and hex digits, or b64: and base64.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Get(Key),
+    Set(Key, Value),
+    Delete(Key),
+    Exists(Key),
+    Scan {
+        lower: Option<Key>,
+        upper: Option<Key>,
+        limit: Option<usize>,
+    },
+    Ping,
+    // `None` toggles
+    Timing(Option<bool>),
+    Help,
+    Quit,
+}
+
+impl Command {
+    // `None` for a blank line.
+    pub fn parse(line: &str) -> std::result::Result<Option<Self>, String> {
+        let mut args = split(line)?.into_iter();
+        let name = match args.next() {
+            Some(name) => String::from_utf8_lossy(&name).to_lowercase(),
+            None => return Ok(None),
+        };
+        let command = match name.as_str() {
+            "get" => Command::Get(key(args.next())?),
+            "set" => {
+                let key = key(args.next())?;
+                let value = args.next().ok_or("set needs a key and a value")?;
+                if value.len() > MAX_VALUE_LEN {
+                    return Err(format!("values are at most {} bytes", MAX_VALUE_LEN));
+                }
+                Command::Set(key, value)
+            }
+            "delete" | "del" => Command::Delete(key(args.next())?),
+            "exists" => Command::Exists(key(args.next())?),
+            "scan" => {
+                let (mut lower, mut upper, mut limit) = (None, None, None);
+                while let Some(option) = args.next() {
+                    let mut value = || {
+                        args.next().ok_or_else(|| {
+                            format!("{} needs a value", String::from_utf8_lossy(&option))
+                        })
+                    };
+                    match option.as_slice() {
+                        b"from" => lower = Some(key(Some(value()?))?),
+                        b"to" => upper = Some(key(Some(value()?))?),
+                        b"limit" => {
+                            let number = value()?;
+                            limit = Some(
+                                std::str::from_utf8(&number)
+                                    .ok()
+                                    .and_then(|number| number.parse().ok())
+                                    .ok_or("limit needs a number")?,
+                            )
+                        }
+                        _ => {
+                            return Err(format!(
+                                "unexpected {}, scan takes from, to and limit",
+                                display(&option)
+                            ))
+                        }
+                    }
+                }
+                Command::Scan {
+                    lower,
+                    upper,
+                    limit,
+                }
+            }
+            "ping" => Command::Ping,
+            "timing" => match args.next().as_deref() {
+                None => Command::Timing(None),
+                Some(b"on") => Command::Timing(Some(true)),
+                Some(b"off") => Command::Timing(Some(false)),
+                Some(_) => return Err("timing takes on or off".to_owned()),
+            },
+            "help" | "?" => Command::Help,
+            "quit" | "exit" => Command::Quit,
+            _ => return Err(format!("unknown command {}, try help", name)),
+        };
+        match args.next() {
+            Some(arg) => Err(format!("unexpected argument {}", display(&arg))),
+            None => Ok(Some(command)),
+        }
+    }
+}
+
+fn key(arg: Option<Vec<u8>>) -> std::result::Result<Key, String> {
+    let key = arg.ok_or("missing key")?;
+    if key.len() > MAX_KEY_LEN {
+        return Err(format!("keys are at most {} bytes", MAX_KEY_LEN));
+    }
+    Ok(key.into())
+}
+
+// Runs a command on the server and writes what it answers, one line each, in the literals
+// commands take. Commands of the shell itself are not run.
+pub fn run(client: &mut Client, command: Command, output: &mut impl Write) -> Result<()> {
+    match command {
+        Command::Get(key) => match client.get(key)? {
+            Some(value) => writeln!(output, "{}", display(&value))?,
+            None => writeln!(output, "(nil)")?,
+        },
+        Command::Set(key, value) => {
+            client.set(key, value)?;
+            writeln!(output, "OK")?;
+        }
+        Command::Delete(key) => {
+            client.delete(key)?;
+            writeln!(output, "OK")?;
+        }
+        Command::Exists(key) => writeln!(output, "{}", client.exists(key)?)?,
+        Command::Scan {
+            lower,
+            upper,
+            limit,
+        } => {
+            // a scan dropped before its end is cancelled
+            let entries = client.scan(lower, upper)?;
+            for entry in entries.take(limit.unwrap_or(usize::MAX)) {
+                let (key, value) = entry?;
+                writeln!(output, "{} {}", display(&key), display(&value))?;
+            }
+        }
+        Command::Ping => {
+            client.ping()?;
+            writeln!(output, "PONG")?;
+        }
+        Command::Timing(_) | Command::Help | Command::Quit => (),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Command;
+
+    fn parse(line: &str) -> Command {
+        Command::parse(line).unwrap().unwrap()
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(None, Command::parse("  ").unwrap());
+        assert_eq!(
+            Command::Set(b"a key".to_vec().into(), b"\x00".to_vec()),
+            parse("SET 'a key' 0x00")
+        );
+        assert_eq!(Command::Delete(b"k".to_vec().into()), parse("del k"));
+        assert_eq!(
+            Command::Scan {
+                lower: None,
+                upper: None,
+                limit: None
+            },
+            parse("scan")
+        );
+        assert_eq!(
+            Command::Scan {
+                lower: Some(b"a".to_vec().into()),
+                upper: Some(b"z".to_vec().into()),
+                limit: Some(10)
+            },
+            parse("scan limit 10 to z from a")
+        );
+        assert_eq!(Command::Timing(Some(false)), parse("timing off"));
+        for invalid in &[
+            "get",
+            "get a b",
+            "set k",
+            "scan limit",
+            "scan limit ten",
+            "scan until z",
+            "frobnicate",
+            "timing maybe",
+        ] {
+            assert!(Command::parse(invalid).is_err(), "{}", invalid);
+        }
+        let long = format!("get 0x{}", "00".repeat(bronzedb_protocol::MAX_KEY_LEN + 1));
+        assert!(Command::parse(&long).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::checkpoint::{self, Checkpoint};
+use crate::format::{EntryReader, EntryWriter, Format};
+use bronzedb_client::Connection;
+use bronzedb_protocol::MAX_KEY_LEN;
+use bronzedb_util::status::StatusCode::UnknownAction;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::Key;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
+
+// Reads or writes `file`, or stdin and stdout without one. Progress is saved every `batch`
+// entries of a file, which are also the writes an import sends at a time.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub format: Format,
+    pub file: Option<String>,
+    pub resume: bool,
+    pub batch: usize,
+}
+
+impl Job {
+    fn start(&self) -> Result<Checkpoint> {
+        match self.file {
+            Some(ref file) if self.resume => {
+                Ok(Checkpoint::load(&checkpoint::path(file))?.unwrap_or_default())
+            }
+            None if self.resume => Err(Error::new(UnknownAction, "only a file can be resumed")),
+            _ => Ok(Checkpoint::default()),
+        }
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+        match self.file {
+            Some(ref file) => checkpoint.save(&checkpoint::path(file)),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&self) -> Result<()> {
+        match self.file {
+            Some(ref file) => Checkpoint::remove(&checkpoint::path(file)),
+            None => Ok(()),
+        }
+    }
+}
+
+// The smallest key after `key`, `None` if there is none.
+pub fn next_key(key: &[u8]) -> Option<Key> {
+    let mut next = key.to_vec();
+    if next.len() < MAX_KEY_LEN {
+        next.push(0);
+        return Some(next.into());
+    }
+    // no longer key starts with this one
+    while next.last() == Some(&u8::MAX) {
+        next.pop();
+    }
+    let last = next.pop()?;
+    next.push(last + 1);
+    Some(next.into())
+}
+
+// Scans the keys between the bounds into the file of the job and counts the entries.
+// Resuming drops whatever was written after the checkpoint and scans on from its last key.
+pub fn export<T: Read + Write>(
+    conn: &mut Connection<T>,
+    job: &Job,
+    lower: Option<Key>,
+    upper: Option<Key>,
+    progress: impl FnMut(u64),
+) -> Result<u64> {
+    let start = job.start()?;
+    let lower = match start.last {
+        Some(ref last) => match next_key(last) {
+            Some(next) => Some(next),
+            None => return finish(job, start.entries),
+        },
+        None => lower,
+    };
+    match job.file {
+        Some(ref file) => {
+            let mut output = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(file)?;
+            // nothing of a fresh export is kept
+            output.set_len(start.offset)?;
+            output.seek(SeekFrom::End(0))?;
+            let mut writer = EntryWriter::new(job.format, BufWriter::new(output));
+            let done = scan(conn, job, &mut writer, start, lower, upper, progress)?;
+            finish(job, done)
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut writer = EntryWriter::new(job.format, stdout.lock());
+            scan(conn, job, &mut writer, start, lower, upper, progress)
+        }
+    }
+}
+
+fn finish(job: &Job, entries: u64) -> Result<u64> {
+    job.finish()?;
+    Ok(entries)
+}
+
+fn scan<T: Read + Write, W: Write + Position>(
+    conn: &mut Connection<T>,
+    job: &Job,
+    writer: &mut EntryWriter<W>,
+    mut done: Checkpoint,
+    lower: Option<Key>,
+    upper: Option<Key>,
+    mut progress: impl FnMut(u64),
+) -> Result<u64> {
+    if done.offset == 0 {
+        writer.header()?;
+    }
+    for entry in conn.scan(lower, upper)? {
+        let (key, value) = entry?;
+        writer.write(&key, &value)?;
+        done.entries += 1;
+        done.last = Some(key);
+        if done.entries.is_multiple_of(job.batch as u64) {
+            done.offset = writer.get_mut().position()?;
+            job.save(&done)?;
+            progress(done.entries);
+        }
+    }
+    writer.get_mut().flush()?;
+    Ok(done.entries)
+}
+
+// Flushes the output and tells how much of it is written.
+trait Position: Write {
+    fn position(&mut self) -> Result<u64>;
+}
+
+impl Position for BufWriter<File> {
+    fn position(&mut self) -> Result<u64> {
+        self.flush()?;
+        Ok(self.get_mut().stream_position()?)
+    }
+}
+
+impl Position for io::StdoutLock<'_> {
+    fn position(&mut self) -> Result<u64> {
+        self.flush()?;
+        Ok(0)
+    }
+}
+
+// Writes the entries read from the file of the job in batches and counts them, passing
+// over the entries before the checkpoint when resuming. Keys missing from the file are kept.
+pub fn import<T: Read + Write>(
+    conn: &mut Connection<T>,
+    job: &Job,
+    progress: impl FnMut(u64),
+) -> Result<u64> {
+    let start = job.start()?;
+    let done = match job.file {
+        Some(ref file) => {
+            let input = BufReader::new(File::open(file)?);
+            load(conn, job, input, start, progress)?
+        }
+        None => {
+            let stdin = io::stdin();
+            load(conn, job, stdin.lock(), start, progress)?
+        }
+    };
+    finish(job, done)
+}
+
+fn load<T: Read + Write>(
+    conn: &mut Connection<T>,
+    job: &Job,
+    input: impl BufRead,
+    mut done: Checkpoint,
+    mut progress: impl FnMut(u64),
+) -> Result<u64> {
+    let mut entries = EntryReader::new(job.format, input);
+    for entry in entries.by_ref().take(done.entries as usize) {
+        entry?;
+    }
+    let mut batch = Vec::with_capacity(job.batch);
+    loop {
+        for entry in entries.by_ref().take(job.batch) {
+            batch.push(entry?);
+        }
+        if batch.is_empty() {
+            return Ok(done.entries);
+        }
+        done.entries += batch.len() as u64;
+        conn.set_batch(mem::take(&mut batch))?;
+        job.save(&done)?;
+        progress(done.entries);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::next_key;
+    use bronzedb_protocol::MAX_KEY_LEN;
+
+    #[test]
+    fn next_keys() {
+        assert_eq!(b"\x00".to_vec(), next_key(b"").unwrap().to_vec());
+        assert_eq!(b"ab\x00".to_vec(), next_key(b"ab").unwrap().to_vec());
+        let mut longest = vec![b'a'; MAX_KEY_LEN];
+        assert_eq!(
+            [vec![b'a'; MAX_KEY_LEN - 1], vec![b'b']].concat(),
+            next_key(&longest).unwrap().to_vec()
+        );
+        longest[MAX_KEY_LEN - 2..].copy_from_slice(b"\xff\xff");
+        assert_eq!(
+            [vec![b'a'; MAX_KEY_LEN - 3], vec![b'b']].concat(),
+            next_key(&longest).unwrap().to_vec()
+        );
+        assert!(next_key(&[u8::MAX; MAX_KEY_LEN]).is_none());
+    }
+}
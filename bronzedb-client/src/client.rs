@@ -0,0 +1,247 @@
+use super::connection::prefix_bounds;
+use super::{
+    BronzeConnManager, ClusterStatus, Connection, MigrationCommand, MigrationStatus, PartitionMap,
+    ReplicationStatus, Stream, Subscription,
+};
+use bronzedb_util::status::StatusCode::{Corruption, IOError, ServerBusy, Timeout};
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Entry, Key, Message, Value};
+use r2d2::ManageConnection;
+use std::io::{Read, Write};
+use std::thread::sleep;
+use std::time::Duration;
+
+// Waits `backoff` before the first retry, doubling up to `max_backoff` for later ones.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Retry {
+    pub attempts: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Retry {
+    pub fn never() -> Self {
+        Self {
+            attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn delay(&self, retried: u32) -> Duration {
+        self.backoff
+            .checked_mul(1 << retried.min(16))
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+// Errors that leave the connection unusable; the request can be tried again on a new one.
+fn transient(err: &Error) -> bool {
+    matches!(err.code, IOError | Timeout | Corruption | ServerBusy)
+}
+
+// A single connection that reconnects after transient errors.
+// Connecting is always retried; requests only when they are idempotent, or when the server
+// turned the connection down as busy before reading them.
+pub struct Client {
+    manager: BronzeConnManager,
+    retry: Retry,
+    conn: Option<Connection<Stream>>,
+}
+
+impl Client {
+    pub fn new(manager: BronzeConnManager) -> Self {
+        Self {
+            manager,
+            retry: Retry::default(),
+            conn: None,
+        }
+    }
+
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn set(&mut self, key: Key, value: Value) -> Result<()> {
+        self.call(false, |conn| conn.set(key.clone(), value.clone()))
+    }
+
+    pub fn delete(&mut self, key: Key) -> Result<()> {
+        self.call(false, |conn| conn.delete(key.clone()))
+    }
+
+    pub fn get(&mut self, key: Key) -> Result<Option<Value>> {
+        self.call(true, |conn| conn.get(key.clone()))
+    }
+
+    pub fn exists(&mut self, key: Key) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    pub fn ping(&mut self) -> Result<()> {
+        self.call(true, |conn| conn.ping())
+    }
+
+    pub fn replication_status(&mut self) -> Result<ReplicationStatus> {
+        self.call(true, |conn| conn.replication_status())
+    }
+
+    pub fn cluster_status(&mut self) -> Result<ClusterStatus> {
+        self.call(true, |conn| conn.cluster_status())
+    }
+
+    pub fn shard_map(&mut self) -> Result<PartitionMap> {
+        self.call(true, |conn| conn.shard_map())
+    }
+
+    pub fn migrate(&mut self, command: MigrationCommand) -> Result<MigrationStatus> {
+        let idempotent = command == MigrationCommand::Status;
+        self.call(idempotent, |conn| conn.migrate(command.clone()))
+    }
+
+    // Retried only before anything was written to `output`.
+    pub fn backup(&mut self, mut output: impl Write) -> Result<u64> {
+        self.call(false, |conn| conn.backup(&mut output))
+    }
+
+    pub fn backup_file(&mut self, path: impl Into<String>) -> Result<u64> {
+        let path = path.into();
+        self.call(true, |conn| conn.backup_file(path.clone()))
+    }
+
+    // Not retried once sent, as `input` is read on the way.
+    pub fn restore(&mut self, mut input: impl Read) -> Result<u64> {
+        self.call(false, |conn| conn.restore(&mut input))
+    }
+
+    // Restoring the same backup again writes the same values.
+    pub fn restore_file(&mut self, path: impl Into<String>) -> Result<u64> {
+        let path = path.into();
+        self.call(true, |conn| conn.restore_file(path.clone()))
+    }
+
+    // Not retried once sent, as the entries are consumed on the way.
+    pub fn ingest(&mut self, entries: impl IntoIterator<Item = Entry>) -> Result<u64> {
+        let mut entries = entries.into_iter();
+        self.call(false, |conn| conn.ingest(&mut entries))
+    }
+
+    // Only starting the scan is retried; errors while iterating are returned as they are.
+    pub fn scan(
+        &mut self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Box<dyn Iterator<Item = Result<Entry>> + '_>> {
+        self.call(true, |conn| {
+            conn.start_scan(lower_bound.clone(), upper_bound.clone())
+        })?;
+        let conn = self.conn.as_mut().expect("connection after a started scan");
+        Ok(conn.entries())
+    }
+
+    // Like `scan`, only starting the watch is retried.
+    pub fn watch(
+        &mut self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Subscription<'_, Stream>> {
+        self.call(true, |conn| {
+            conn.start_watch(lower_bound.clone(), upper_bound.clone())
+        })?;
+        let conn = self.conn.as_mut().expect("connection after a started watch");
+        Ok(conn.subscription())
+    }
+
+    pub fn watch_prefix(&mut self, prefix: Key) -> Result<Subscription<'_, Stream>> {
+        let (lower_bound, upper_bound) = prefix_bounds(prefix);
+        self.watch(lower_bound, upper_bound)
+    }
+
+    // Not retried once sent, like `set`, so subscribers do not get the message twice.
+    pub fn publish(&mut self, channel: impl Into<String>, payload: Value) -> Result<()> {
+        let channel = channel.into();
+        self.call(false, |conn| conn.publish(channel.clone(), payload.clone()))
+    }
+
+    pub fn subscribe(
+        &mut self,
+        channels: Vec<String>,
+        patterns: Vec<String>,
+    ) -> Result<Subscription<'_, Stream, Message>> {
+        self.call(true, |conn| {
+            conn.start_subscribe(channels.clone(), patterns.clone())
+        })?;
+        let conn = self.conn.as_mut().expect("connection after a started subscription");
+        Ok(conn.messages())
+    }
+
+    fn connection(&mut self) -> Result<&mut Connection<Stream>> {
+        if self.conn.as_ref().is_some_and(Connection::is_poisoned) {
+            self.conn = None;
+        }
+        match self.conn {
+            Some(ref mut conn) => Ok(conn),
+            None => Ok(self.conn.get_or_insert(self.manager.connect()?)),
+        }
+    }
+
+    fn call<R>(
+        &mut self,
+        idempotent: bool,
+        mut request: impl FnMut(&mut Connection<Stream>) -> Result<R>,
+    ) -> Result<R> {
+        let mut retried = 0;
+        loop {
+            let (err, sent) = match self.connection() {
+                Ok(conn) => match request(conn) {
+                    Ok(value) => return Ok(value),
+                    Err(err) => (err, true),
+                },
+                Err(err) => (err, false),
+            };
+            if !transient(&err) {
+                return Err(err);
+            }
+            let retryable = idempotent || !sent || err.code == ServerBusy;
+            if !retryable || retried + 1 >= self.retry.attempts {
+                return Err(err);
+            }
+            sleep(self.retry.delay(retried));
+            retried += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Retry;
+    use std::time::Duration;
+
+    #[test]
+    fn backoff() {
+        let retry = Retry {
+            attempts: 10,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        let delays: Vec<_> = (0..5).map(|retried| retry.delay(retried)).collect();
+        assert_eq!(
+            vec![100, 200, 400, 500, 500],
+            delays
+                .iter()
+                .map(|delay| delay.as_millis())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Duration::from_millis(500), retry.delay(u32::MAX));
+    }
+}
@@ -0,0 +1,248 @@
+use super::{BronzeConnManager, Client, PartitionMap, Retry, SCAN_WINDOW};
+use bronzedb_protocol::shard::successor;
+use bronzedb_util::status::StatusCode::{IOError, WrongShard};
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Entry, Key, Value};
+use std::collections::{HashMap, VecDeque};
+use std::thread::sleep;
+
+// How often a request is routed again after a server answered it with `WrongShard`.
+const MAX_REFRESHES: u32 = 3;
+
+type Manager = Box<dyn Fn(&str) -> BronzeConnManager + Send + Sync>;
+
+// Routes requests to the servers of a sharded deployment by their partition map.
+// The map is loaded from the seeds on first use and again whenever a server refuses a key
+// it no longer owns.
+pub struct ClusterClient {
+    seeds: Vec<String>,
+    manager: Manager,
+    retry: Retry,
+    map: Option<PartitionMap>,
+    clients: HashMap<String, Client>,
+}
+
+impl ClusterClient {
+    pub fn new(seeds: Vec<String>) -> Self {
+        Self {
+            seeds,
+            manager: Box::new(|addr| BronzeConnManager::new(addr)),
+            retry: Retry::default(),
+            map: None,
+            clients: HashMap::new(),
+        }
+    }
+
+    // Builds the connection manager of every server, to set options, credentials or timeouts.
+    pub fn manager(
+        mut self,
+        manager: impl Fn(&str) -> BronzeConnManager + Send + Sync + 'static,
+    ) -> Self {
+        self.manager = Box::new(manager);
+        self
+    }
+
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn partition_map(&mut self) -> Result<&PartitionMap> {
+        if self.map.is_none() {
+            self.refresh()?;
+        }
+        Ok(self.map.as_ref().expect("partition map after a refresh"))
+    }
+
+    // Loads the newest map known to the servers of the current map, or else to the seeds.
+    pub fn refresh(&mut self) -> Result<()> {
+        let mut addrs: Vec<String> = match self.map {
+            Some(ref map) => map
+                .shards()
+                .iter()
+                .map(|shard| shard.addr.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        addrs.extend(self.seeds.iter().cloned());
+        let mut newest: Option<PartitionMap> = None;
+        let mut last_err = Error::new(IOError, "no server to load the partition map from");
+        let mut asked = Vec::new();
+        for addr in addrs {
+            if asked.contains(&addr) {
+                continue;
+            }
+            match self.client(&addr).shard_map() {
+                Ok(map) => {
+                    let found = self
+                        .map
+                        .as_ref()
+                        .is_none_or(|current| map.version() > current.version());
+                    if newest
+                        .as_ref()
+                        .is_none_or(|newest| map.version() > newest.version())
+                    {
+                        newest = Some(map);
+                    }
+                    if found {
+                        break;
+                    }
+                }
+                Err(err) => last_err = err,
+            }
+            asked.push(addr);
+        }
+        match newest {
+            Some(map) => {
+                if self
+                    .map
+                    .as_ref()
+                    .is_none_or(|current| map.version() >= current.version())
+                {
+                    self.map = Some(map);
+                }
+                Ok(())
+            }
+            None => Err(last_err),
+        }
+    }
+
+    pub fn set(&mut self, key: Key, value: Value) -> Result<()> {
+        self.route(&key, |client| client.set(key.clone(), value.clone()))
+    }
+
+    pub fn delete(&mut self, key: Key) -> Result<()> {
+        self.route(&key, |client| client.delete(key.clone()))
+    }
+
+    pub fn get(&mut self, key: Key) -> Result<Option<Value>> {
+        self.route(&key, |client| client.get(key.clone()))
+    }
+
+    pub fn exists(&mut self, key: Key) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    // Scans the shards in key order, so the entries come out as from a single server.
+    // Every `SCAN_WINDOW` entries are a scan of their own, which lets the rest of the scan
+    // follow keys moving to other servers.
+    pub fn scan(
+        &mut self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Box<dyn Iterator<Item = Result<Entry>> + '_>> {
+        self.partition_map()?;
+        Ok(Box::new(Scan {
+            client: self,
+            lower: lower_bound,
+            upper: upper_bound,
+            page: VecDeque::new(),
+            done: false,
+        }))
+    }
+
+    fn client(&mut self, addr: &str) -> &mut Client {
+        let manager = &self.manager;
+        let retry = self.retry;
+        self.clients
+            .entry(addr.to_owned())
+            .or_insert_with(|| Client::new(manager(addr)).retry(retry))
+    }
+
+    fn route<R>(
+        &mut self,
+        key: &[u8],
+        mut request: impl FnMut(&mut Client) -> Result<R>,
+    ) -> Result<R> {
+        let mut refreshed = 0;
+        loop {
+            let addr = self.partition_map()?.owner(key).to_owned();
+            match request(self.client(&addr)) {
+                Err(ref err) if err.code == WrongShard && refreshed < MAX_REFRESHES => {
+                    // the keys may be moving; the new map reaches the servers soon after
+                    sleep(self.retry.delay(refreshed));
+                    self.refresh()?;
+                    refreshed += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+struct Scan<'a> {
+    client: &'a mut ClusterClient,
+    // the first key not scanned yet
+    lower: Option<Key>,
+    upper: Option<Key>,
+    page: VecDeque<Entry>,
+    done: bool,
+}
+
+impl Scan<'_> {
+    // Scans a page of the shard owning `lower`; it may be empty at the end of the shard.
+    fn fetch(&mut self) -> Result<()> {
+        let mut refreshed = 0;
+        loop {
+            let lower = self.lower.clone().unwrap_or_default();
+            if self.upper.as_ref().is_some_and(|upper| **upper < *lower) {
+                self.done = true;
+                return Ok(());
+            }
+            let map = self.client.partition_map()?;
+            let index = map.position(&lower);
+            let addr = map.shards()[index].addr.clone();
+            let next_shard = map.shards().get(index + 1).map(|next| next.lower.clone());
+            let (_, shard_upper) = map.bounds(index);
+            let last_shard = match (&shard_upper, &self.upper) {
+                (Some(shard_upper), Some(upper)) => **upper <= **shard_upper,
+                (Some(_), None) => false,
+                (None, _) => true,
+            };
+            let upper = if last_shard {
+                self.upper.clone()
+            } else {
+                shard_upper
+            };
+            let result = self
+                .client
+                .client(&addr)
+                .scan(Some(lower), upper)
+                .and_then(|entries| entries.take(SCAN_WINDOW).collect::<Result<Vec<_>>>());
+            let page = match result {
+                Err(ref err) if err.code == WrongShard && refreshed < MAX_REFRESHES => {
+                    sleep(self.client.retry.delay(refreshed));
+                    self.client.refresh()?;
+                    refreshed += 1;
+                    continue;
+                }
+                result => result?,
+            };
+            if page.len() == SCAN_WINDOW {
+                let (last, _) = &page[page.len() - 1];
+                self.lower = successor(last);
+                self.done = self.lower.is_none();
+            } else if last_shard {
+                self.done = true;
+            } else {
+                self.lower = next_shard;
+            }
+            self.page.extend(page);
+            return Ok(());
+        }
+    }
+}
+
+impl Iterator for Scan<'_> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.page.is_empty() && !self.done {
+            if let Err(err) = self.fetch() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+        self.page.pop_front().map(Ok)
+    }
+}
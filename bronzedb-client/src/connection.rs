@@ -1,23 +1,89 @@
-use bronzedb_protocol::request::Action::{Delete, Get, Ping, Scan, Set};
-use bronzedb_protocol::request::Request;
+use crate::subscription::Subscription;
+use bronzedb_protocol::backup::{BackupReader, BackupWriter};
+use bronzedb_protocol::frame::{Framed, Options};
+use bronzedb_protocol::raft::ClusterStatus;
+use bronzedb_protocol::replication::ReplicationStatus;
+use bronzedb_protocol::request::Action::{
+    self, Delete, Get, Ping, Publish, Scan, Set, Subscribe, Watch,
+};
+use bronzedb_protocol::request::{Credentials, Request};
 use bronzedb_protocol::response::Response::{self, *};
-use bronzedb_util::status::StatusCode::*;
+use bronzedb_protocol::shard::{MigrationCommand, MigrationStatus, PartitionMap};
+use bronzedb_protocol::{MAX_KEY_LEN, SCAN_WINDOW};
+use bronzedb_util::status::StatusCode::{self, *};
 use bronzedb_util::status::{Error, Result};
-use bronzedb_util::types::{Entry, Key, Value};
+use bronzedb_util::types::{Entry, Key, Message, Value};
 use std::io::{Read, Write};
 
 pub struct Connection<T: Read + Write> {
-    inner: T,
+    inner: Framed<T>,
+    poisoned: bool,
 }
 
 impl<T: Read + Write> Connection<T> {
     pub fn new(connection: T) -> Self {
-        Self { inner: connection }
+        Self {
+            inner: Framed::new(connection),
+            poisoned: false,
+        }
+    }
+
+    pub fn is_corrupted(&self) -> bool {
+        self.inner.is_corrupted()
+    }
+
+    // A poisoned connection failed on I/O, was closed by the server or left a scan it could
+    // not cancel in its stream, so its next response cannot be trusted.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned || self.inner.is_corrupted()
+    }
+
+    fn send(&mut self, request: Request, action: Action) -> Result<Response<'_>> {
+        if let Err(err) = request.write_to(&mut self.inner) {
+            self.poisoned = true;
+            return Err(err.into());
+        }
+        self.receive(action)
+    }
+
+    fn receive(&mut self, action: Action) -> Result<Response<'_>> {
+        let response = Response::read_from(&mut self.inner, action);
+        // the server closes the connection after these
+        if let Err(_)
+        | Ok(Status(UnknownAction))
+        | Ok(Status(EngineError))
+        | Ok(Status(Corruption))
+        | Ok(Status(ServerBusy)) = response
+        {
+            self.poisoned = true;
+        }
+        // replicas answer writes with the address of their primary
+        match response {
+            Ok(Response::Redirect(primary)) => Err(Error::new(StatusCode::Redirect, primary)),
+            response => response,
+        }
+    }
+
+    pub fn handshake(&mut self, options: Options) -> Result<Options> {
+        let accepted = match self.send(Request::Handshake(options), Action::Handshake)? {
+            Handshake(accepted) => accepted,
+            Status(status) => return Err(Error::new(status, "handshake error")),
+            _ => unreachable!(),
+        };
+        self.inner.set_options(accepted);
+        Ok(accepted)
+    }
+
+    pub fn auth(&mut self, credentials: Credentials) -> Result<()> {
+        match self.send(Request::Auth(credentials), Action::Auth)? {
+            Status(OK) => Ok(()),
+            Status(status) => Err(Error::new(status, "auth error")),
+            _ => unreachable!(),
+        }
     }
 
     pub fn set(&mut self, key: Key, value: Value) -> Result<()> {
-        Request::Set(key, value).write_to(&mut self.inner)?;
-        match Response::read_from(&mut self.inner, Set)? {
+        match self.send(Request::Set(key, value), Set)? {
             Status(status) => match status {
                 OK => Ok(()),
                 code => Err(Error::new(code, "set request error")),
@@ -26,9 +92,34 @@ impl<T: Read + Write> Connection<T> {
         }
     }
 
+    // Sends all the writes before reading their answers, saving a round trip for each. Every
+    // answer is read unless the connection fails; the first error is returned.
+    pub fn set_batch(&mut self, entries: Vec<Entry>) -> Result<()> {
+        let count = entries.len();
+        for (key, value) in entries {
+            if let Err(err) = Request::Set(key, value).write_to(&mut self.inner) {
+                self.poisoned = true;
+                return Err(err.into());
+            }
+        }
+        let mut failed = None;
+        for _ in 0..count {
+            let err = match self.receive(Set) {
+                Ok(Status(OK)) => continue,
+                Ok(Status(code)) => Error::new(code, "set request error"),
+                Ok(_) => unreachable!(),
+                Err(err) => err,
+            };
+            if self.poisoned {
+                return Err(err);
+            }
+            failed.get_or_insert(err);
+        }
+        failed.map_or(Ok(()), Err)
+    }
+
     pub fn delete(&mut self, key: Key) -> Result<()> {
-        Request::Delete(key).write_to(&mut self.inner)?;
-        match Response::read_from(&mut self.inner, Delete)? {
+        match self.send(Request::Delete(key), Delete)? {
             Status(status) => match status {
                 OK => Ok(()),
                 code => Err(Error::new(code, "delete request error")),
@@ -38,8 +129,7 @@ impl<T: Read + Write> Connection<T> {
     }
 
     pub fn get(&mut self, key: Key) -> Result<Option<Value>> {
-        Request::Get(key).write_to(&mut self.inner)?;
-        match Response::read_from(&mut self.inner, Get)? {
+        match self.send(Request::Get(key), Get)? {
             Status(status) => match status {
                 NotFound => Ok(None),
                 code => Err(Error::new(code, "get request error")),
@@ -49,34 +139,329 @@ impl<T: Read + Write> Connection<T> {
         }
     }
 
+    // Dropping the iterator early cancels the scan if the server supports it, reading at most
+    // the rest of a `SCAN_WINDOW`; otherwise the connection is poisoned.
     pub fn scan(
         &mut self,
         lower_bound: Option<Key>,
         upper_bound: Option<Key>,
     ) -> Result<Box<dyn Iterator<Item = Result<Entry>> + '_>> {
-        Request::Scan {
+        self.start_scan(lower_bound, upper_bound)?;
+        Ok(self.entries())
+    }
+
+    // Sends a scan request and reads its status, leaving the entries in the stream.
+    pub(crate) fn start_scan(
+        &mut self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<()> {
+        let request = Request::Scan {
             lower_bound,
             upper_bound,
-        }
-        .write_to(&mut self.inner)?;
-        match Response::read_from(&mut self.inner, Scan)? {
+        };
+        match self.send(request, Scan)? {
             Status(status) => Err(Error::new(status, "scan request error")),
-            Scanner(iter) => Ok(iter),
+            Scanner(_) => Ok(()),
+            _ => unreachable!(),
+        }
+    }
+
+    pub(crate) fn entries(&mut self) -> Box<dyn Iterator<Item = Result<Entry>> + '_> {
+        let window = if self.inner.options().cancel {
+            Some(SCAN_WINDOW)
+        } else {
+            None
+        };
+        Box::new(Entries {
+            stream: &mut self.inner,
+            poisoned: &mut self.poisoned,
+            window,
+            done: false,
+        })
+    }
+
+    // Keys between the bounds are watched, see `Subscription`.
+    // A read timeout should be longer than the pauses of a quiet watch, which are 500ms.
+    pub fn watch(
+        &mut self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Subscription<'_, T>> {
+        self.start_watch(lower_bound, upper_bound)?;
+        Ok(self.subscription())
+    }
+
+    pub fn watch_prefix(&mut self, prefix: Key) -> Result<Subscription<'_, T>> {
+        let (lower_bound, upper_bound) = prefix_bounds(prefix);
+        self.watch(lower_bound, upper_bound)
+    }
+
+    pub(crate) fn start_watch(
+        &mut self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<()> {
+        let request = Request::Watch {
+            lower_bound,
+            upper_bound,
+        };
+        match self.send(request, Watch)? {
+            Status(OK) => Ok(()),
+            Status(status) => Err(Error::new(status, "watch request error")),
             _ => unreachable!(),
         }
     }
 
+    pub(crate) fn subscription(&mut self) -> Subscription<'_, T> {
+        Subscription::new(&mut self.inner, &mut self.poisoned, Response::read_event)
+    }
+
+    // Delivered only to the subscribers connected at the time.
+    pub fn publish(&mut self, channel: impl Into<String>, payload: Value) -> Result<()> {
+        let request = Request::Publish {
+            channel: channel.into(),
+            payload,
+        };
+        match self.send(request, Publish)? {
+            Status(OK) => Ok(()),
+            Status(status) => Err(Error::new(status, "publish request error")),
+            _ => unreachable!(),
+        }
+    }
+
+    // Receives the messages published to any of `channels`, or to channels matching one of
+    // `patterns`, where `*` stands for any characters and `?` for one. See `Subscription`.
+    pub fn subscribe(
+        &mut self,
+        channels: Vec<String>,
+        patterns: Vec<String>,
+    ) -> Result<Subscription<'_, T, Message>> {
+        self.start_subscribe(channels, patterns)?;
+        Ok(self.messages())
+    }
+
+    pub(crate) fn start_subscribe(
+        &mut self,
+        channels: Vec<String>,
+        patterns: Vec<String>,
+    ) -> Result<()> {
+        match self.send(Request::Subscribe { channels, patterns }, Subscribe)? {
+            Status(OK) => Ok(()),
+            Status(status) => Err(Error::new(status, "subscribe request error")),
+            _ => unreachable!(),
+        }
+    }
+
+    pub(crate) fn messages(&mut self) -> Subscription<'_, T, Message> {
+        Subscription::new(&mut self.inner, &mut self.poisoned, Response::read_message)
+    }
+
     pub fn ping(&mut self) -> Result<()> {
-        Request::Ping.write_to(&mut self.inner)?;
-        match Response::read_from(&mut self.inner, Ping)? {
+        match self.send(Request::Ping, Ping)? {
             Status(OK) => Ok(()),
             Status(status) => Err(Error::new(status, "ping error")),
             _ => unreachable!(),
         }
     }
 
+    // The role of the server and, on a replica, how far it is behind its primary.
+    pub fn replication_status(&mut self) -> Result<ReplicationStatus> {
+        match self.send(Request::ReplicationStatus, Action::ReplicationStatus)? {
+            Replication(status) => Ok(status),
+            Status(status) => Err(Error::new(status, "replication status error")),
+            _ => unreachable!(),
+        }
+    }
+
+    // The role of a cluster member and the leader it knows.
+    pub fn cluster_status(&mut self) -> Result<ClusterStatus> {
+        match self.send(Request::ClusterStatus, Action::ClusterStatus)? {
+            Cluster(status) => Ok(status),
+            Status(status) => Err(Error::new(status, "cluster status error")),
+            _ => unreachable!(),
+        }
+    }
+
+    // The partition map known to a server of a sharded deployment.
+    pub fn shard_map(&mut self) -> Result<PartitionMap> {
+        match self.send(Request::ShardMap, Action::ShardMap)? {
+            Shards(map) => Ok(map),
+            Status(status) => Err(Error::new(status, "shard map error")),
+            _ => unreachable!(),
+        }
+    }
+
+    // Sent to the server that should take over the keys.
+    pub fn migrate(&mut self, command: MigrationCommand) -> Result<MigrationStatus> {
+        match self.send(Request::Migration(command), Action::Migration)? {
+            Migration(status) => Ok(status),
+            Status(status) => Err(Error::new(status, "migration error")),
+            _ => unreachable!(),
+        }
+    }
+
+    // Writes a consistent snapshot of the server to `output`, checked on the way.
+    pub fn backup(&mut self, output: impl Write) -> Result<u64> {
+        let entries = match self.send(Request::Backup, Action::Backup)? {
+            Backup(entries) => entries,
+            Status(status) => return Err(Error::new(status, "backup error")),
+            _ => unreachable!(),
+        };
+        let copied = copy(entries, output);
+        if copied.is_err() {
+            self.poisoned = true;
+        }
+        copied
+    }
+
+    // The server writes the backup to `path` on its own host.
+    pub fn backup_file(&mut self, path: impl Into<String>) -> Result<u64> {
+        match self.send(Request::BackupFile(path.into()), Action::BackupFile)? {
+            Count(count) => Ok(count),
+            Status(status) => Err(Error::new(status, "backup error")),
+            _ => unreachable!(),
+        }
+    }
+
+    // Sends the backup read from `input`, checking it as it goes. Keys the backup does not
+    // have are kept; the server keeps what it restored before a damaged part.
+    pub fn restore(&mut self, input: impl Read) -> Result<u64> {
+        let entries = BackupReader::new(input)?;
+        let sent = Request::Restore
+            .write_to(&mut self.inner)
+            .map_err(Into::into)
+            .and_then(|_| copy(entries, &mut self.inner));
+        if let Err(err) = sent {
+            self.poisoned = true;
+            return Err(err);
+        }
+        match self.receive(Action::Restore)? {
+            Count(count) => Ok(count),
+            Status(status) => Err(Error::new(status, "restore error")),
+            _ => unreachable!(),
+        }
+    }
+
+    // The server restores the backup at `path` on its own host, once it has checked all of it.
+    pub fn restore_file(&mut self, path: impl Into<String>) -> Result<u64> {
+        match self.send(Request::RestoreFile(path.into()), Action::RestoreFile)? {
+            Count(count) => Ok(count),
+            Status(status) => Err(Error::new(status, "restore error")),
+            _ => unreachable!(),
+        }
+    }
+
+    // Streams entries sorted by key, which the server loads into its engine in batches
+    // rather than one request each. Unsorted keys fail with `StatusCode::Corruption`.
+    pub fn ingest(&mut self, entries: impl IntoIterator<Item = Entry>) -> Result<u64> {
+        let sent = Request::Ingest
+            .write_to(&mut self.inner)
+            .map_err(Into::into)
+            .and_then(|_| copy(entries.into_iter().map(Ok), &mut self.inner));
+        if let Err(err) = sent {
+            self.poisoned = true;
+            return Err(err);
+        }
+        match self.receive(Action::Ingest)? {
+            Count(count) => Ok(count),
+            Status(status) => Err(Error::new(status, "ingest error")),
+            _ => unreachable!(),
+        }
+    }
+
     pub fn no_response(&mut self) -> Result<()> {
-        Request::NoResponse.write_to(&mut self.inner)?;
+        if let Err(err) = Request::NoResponse.write_to(&mut self.inner) {
+            self.poisoned = true;
+            return Err(err.into());
+        }
         Ok(())
     }
 }
+
+// Re-encodes the entries of a backup, so the copy has a trailer only if they all came.
+fn copy(entries: impl Iterator<Item = Result<Entry>>, output: impl Write) -> Result<u64> {
+    let mut writer = BackupWriter::new(output)?;
+    for entry in entries {
+        let (key, value) = entry?;
+        writer.write(&key, &value)?;
+    }
+    Ok(writer.finish()?)
+}
+
+// Bounds covering every key that starts with `prefix`.
+pub(crate) fn prefix_bounds(prefix: Key) -> (Option<Key>, Option<Key>) {
+    let mut upper = prefix.to_vec();
+    upper.resize(MAX_KEY_LEN.max(prefix.len()), u8::MAX);
+    (Some(prefix), Some(upper.into()))
+}
+
+struct Entries<'a, T: Read + Write> {
+    stream: &'a mut Framed<T>,
+    poisoned: &'a mut bool,
+    // entries left before the server waits for `More`, if the scan can be cancelled
+    window: Option<usize>,
+    done: bool,
+}
+
+impl<T: Read + Write> Entries<'_, T> {
+    fn next_entry(&mut self) -> Result<Option<Entry>> {
+        if self.window == Some(0) {
+            Request::More.write_to(&mut *self.stream)?;
+            self.window = Some(SCAN_WINDOW);
+        }
+        let entry = Response::read_entry(self.stream)?;
+        if let Some(ref mut left) = self.window {
+            *left -= 1;
+        }
+        Ok(entry)
+    }
+
+    fn cancel(&mut self) -> Result<()> {
+        let left = match self.window {
+            Some(left) => left,
+            None => return Err(Error::new(UnknownAction, "scan cannot be cancelled")),
+        };
+        for _ in 0..left {
+            if Response::read_entry(self.stream)?.is_none() {
+                return Ok(());
+            }
+        }
+        Request::Cancel.write_to(&mut *self.stream)?;
+        match Response::read_entry(self.stream)? {
+            None => Ok(()),
+            Some(_) => Err(Error::new(Corruption, "scan goes on after cancel")),
+        }
+    }
+}
+
+impl<T: Read + Write> Iterator for Entries<'_, T> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_entry() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            // the server closes the connection after a failed scan
+            Err(err) => {
+                self.done = true;
+                *self.poisoned = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl<T: Read + Write> Drop for Entries<'_, T> {
+    fn drop(&mut self) {
+        if !self.done && self.cancel().is_err() {
+            *self.poisoned = true;
+        }
+    }
+}
@@ -1,6 +1,25 @@
+pub use bronzedb_protocol::backup::{BackupReader, BackupWriter};
+pub use bronzedb_protocol::frame::{Compression, Options};
+pub use bronzedb_protocol::raft::{ClusterStatus, RaftRole};
+pub use bronzedb_protocol::replication::{ReplicaState, ReplicationStatus, Role};
+pub use bronzedb_protocol::request::Credentials;
+pub use bronzedb_protocol::shard::{
+    MigrationCommand, MigrationState, MigrationStatus, PartitionMap, Shard,
+};
+pub use bronzedb_protocol::SCAN_WINDOW;
 pub use r2d2::Pool;
+pub mod client;
+pub mod cluster;
 pub mod connection;
 pub mod manager;
+pub mod stream;
+pub mod subscription;
+#[cfg(feature = "tls")]
+pub mod tls;
 
+pub use client::{Client, Retry};
+pub use cluster::ClusterClient;
 pub use connection::Connection;
-pub use manager::BronzeConnManager;
+pub use manager::{BronzeConnManager, Validation};
+pub use stream::Stream;
+pub use subscription::Subscription;
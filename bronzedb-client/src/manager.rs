@@ -1,36 +1,197 @@
-use super::Connection;
-use bronzedb_util::status::Error;
-use std::net::TcpStream;
+use super::{Connection, Stream};
+use bronzedb_protocol::frame::{Compression, Options};
+use bronzedb_protocol::request::Credentials;
+use bronzedb_util::status::{Error, StatusCode};
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+#[cfg(feature = "tls")]
+use crate::tls::tls_err;
+#[cfg(feature = "tls")]
+use rustls::pki_types::ServerName;
+#[cfg(feature = "tls")]
+use rustls::{ClientConfig, ClientConnection, StreamOwned};
+#[cfg(feature = "tls")]
+use std::convert::TryFrom;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+#[cfg(unix)]
+const UNIX_SCHEME: &str = "unix://";
+
+// Servers older than the handshake refuse it and close the connection; unless other options
+// are asked for, the manager connects to them again without one.
+const DEFAULT_OPTIONS: Options = Options {
+    checksum: false,
+    compression: Compression::None,
+    cancel: true,
+};
+
+// How the pool checks a connection on checkout. Connections are always discarded once
+// poisoned; `Ping` additionally makes a round trip to detect a dead server.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Validation {
+    Ping,
+    State,
+}
 
 pub struct BronzeConnManager {
     db_addr: String,
+    options: Options,
+    credentials: Option<Credentials>,
+    validation: Validation,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    #[cfg(feature = "tls")]
+    tls: Option<(Arc<ClientConfig>, String)>,
 }
 
 impl BronzeConnManager {
+    // `addr` is either a tcp address or a unix socket path like `unix:///tmp/bronzedb.sock`.
     pub fn new(addr: impl Into<String>) -> Self {
         Self {
             db_addr: addr.into(),
+            options: DEFAULT_OPTIONS,
+            credentials: None,
+            validation: Validation::Ping,
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            #[cfg(feature = "tls")]
+            tls: None,
         }
     }
+
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    pub fn validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    // Requests whose responses take longer fail with `StatusCode::Timeout`.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: Arc<ClientConfig>, server_name: impl Into<String>) -> Self {
+        self.tls = Some((config, server_name.into()));
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    fn wrap(&self, mut stream: TcpStream) -> Result<Stream, Error> {
+        match self.tls {
+            Some((ref config, ref server_name)) => {
+                let name = ServerName::try_from(server_name.clone()).map_err(tls_err)?;
+                let mut conn = ClientConnection::new(config.clone(), name).map_err(tls_err)?;
+                while conn.is_handshaking() {
+                    conn.complete_io(&mut stream)?;
+                }
+                Ok(Stream::Tls(Box::new(StreamOwned::new(conn, stream))))
+            }
+            None => Ok(Stream::Tcp(stream)),
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn wrap(&self, stream: TcpStream) -> Result<Stream, Error> {
+        Ok(Stream::Tcp(stream))
+    }
+
+    fn open(&self) -> Result<Stream, Error> {
+        #[cfg(unix)]
+        if let Some(path) = self.db_addr.strip_prefix(UNIX_SCHEME) {
+            #[cfg(feature = "tls")]
+            if self.tls.is_some() {
+                return Err(tls_err("unix sockets are not supported"));
+            }
+            let stream = UnixStream::connect(path)?;
+            stream.set_read_timeout(self.read_timeout)?;
+            stream.set_write_timeout(self.write_timeout)?;
+            return Ok(Stream::Unix(stream));
+        }
+        let stream = match self.connect_timeout {
+            Some(timeout) => connect_timeout(&self.db_addr, timeout)?,
+            None => TcpStream::connect(&self.db_addr)?,
+        };
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+        self.wrap(stream)
+    }
+}
+
+fn connect_timeout(addr: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::InvalidInput, "no address to connect");
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
 }
 
 impl r2d2::ManageConnection for BronzeConnManager {
-    type Connection = Connection<TcpStream>;
+    type Connection = Connection<Stream>;
     type Error = Error;
 
     fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        let stream = TcpStream::connect(&self.db_addr)?;
-        Ok(Self::Connection::new(stream))
+        let mut conn = Self::Connection::new(self.open()?);
+        if self.options != Options::default() {
+            match conn.handshake(self.options) {
+                Ok(_) => (),
+                Err(err)
+                    if err.code == StatusCode::UnknownAction && self.options == DEFAULT_OPTIONS =>
+                {
+                    conn = Self::Connection::new(self.open()?)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if let Some(ref credentials) = self.credentials {
+            conn.auth(credentials.clone())?;
+        }
+        Ok(conn)
     }
 
     fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
-        conn.ping()
+        if conn.is_poisoned() {
+            return Err(Error::new(StatusCode::IOError, "connection is poisoned"));
+        }
+        match self.validation {
+            Validation::Ping => conn.ping(),
+            Validation::State => Ok(()),
+        }
     }
 
     fn has_broken(&self, conn: &mut Self::Connection) -> bool {
-        match conn.no_response() {
-            Ok(()) => false,
-            Err(_) => true,
-        }
+        conn.is_poisoned()
     }
 }
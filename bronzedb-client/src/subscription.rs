@@ -0,0 +1,95 @@
+use bronzedb_protocol::frame::Framed;
+use bronzedb_protocol::request::Request;
+use bronzedb_protocol::response::Streamed;
+use bronzedb_util::status::Result;
+use bronzedb_util::types::Event;
+use std::io::{Read, Write};
+
+// Blocks for the next change to the watched keys, or message to the subscribed channels.
+// The server pauses the stream when it has been quiet for a while or sent many items, this
+// resumes it transparently. Dropping it cancels the stream, reading the items sent until
+// the next pause.
+pub struct Subscription<'a, T: Read + Write, I = Event> {
+    stream: &'a mut Framed<T>,
+    poisoned: &'a mut bool,
+    read: fn(&mut dyn Read) -> Result<Streamed<I>>,
+    done: bool,
+}
+
+impl<'a, T: Read + Write, I> Subscription<'a, T, I> {
+    pub(crate) fn new(
+        stream: &'a mut Framed<T>,
+        poisoned: &'a mut bool,
+        read: fn(&mut dyn Read) -> Result<Streamed<I>>,
+    ) -> Self {
+        Self {
+            stream,
+            poisoned,
+            read,
+            done: false,
+        }
+    }
+
+    pub fn cancel(mut self) -> Result<()> {
+        self.done = true;
+        let result = self.stop();
+        if result.is_err() {
+            *self.poisoned = true;
+        }
+        result
+    }
+
+    fn next_item(&mut self) -> Result<Option<I>> {
+        loop {
+            match (self.read)(self.stream)? {
+                Streamed::Item(item) => return Ok(Some(item)),
+                Streamed::Paused => {
+                    Request::More.write_to(&mut *self.stream)?;
+                }
+                Streamed::Complete => return Ok(None),
+            }
+        }
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        loop {
+            match (self.read)(self.stream)? {
+                Streamed::Item(_) => (),
+                Streamed::Paused => {
+                    Request::Cancel.write_to(&mut *self.stream)?;
+                }
+                Streamed::Complete => return Ok(()),
+            }
+        }
+    }
+}
+
+impl<T: Read + Write, I> Iterator for Subscription<'_, T, I> {
+    type Item = Result<I>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_item() {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                *self.poisoned = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl<T: Read + Write, I> Drop for Subscription<'_, T, I> {
+    fn drop(&mut self) {
+        if !self.done && self.stop().is_err() {
+            *self.poisoned = true;
+        }
+    }
+}
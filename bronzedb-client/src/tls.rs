@@ -0,0 +1,45 @@
+use bronzedb_util::status::StatusCode::IOError;
+use bronzedb_util::status::{Error, Result};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ClientConfig, RootCertStore};
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::Arc;
+
+pub(crate) fn tls_err(err: impl Display) -> Error {
+    Error::new(IOError, format!("tls error: {}", err))
+}
+
+fn load_certs(path: impl AsRef<Path>) -> Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path.as_ref())
+        .map_err(tls_err)?
+        .collect::<std::result::Result<_, _>>()
+        .map_err(tls_err)
+}
+
+// `identity` is the (cert, key) pair presented to servers that require client certificates.
+pub fn client_config(
+    ca: impl AsRef<Path>,
+    identity: Option<(impl AsRef<Path>, impl AsRef<Path>)>,
+) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca)? {
+        roots.add(cert).map_err(tls_err)?;
+    }
+    let builder =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(tls_err)?
+            .with_root_certificates(roots);
+    let config = match identity {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(
+                load_certs(cert)?,
+                PrivateKeyDer::from_pem_file(key.as_ref()).map_err(tls_err)?,
+            )
+            .map_err(tls_err)?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
@@ -1,16 +1,70 @@
 use bronzedb_util::status::Error;
-use bronzedb_util::types::{Entry, Key, Value};
+use bronzedb_util::types::{Entry, Event, Key, Value};
+use std::sync::mpsc::Receiver;
 
 pub trait Engine {
     type Error: Into<Error>;
     fn set(&mut self, key: Key, value: Value) -> Result<(), Self::Error>;
     fn get(&self, key: Key) -> Result<Option<Value>, Self::Error>;
     fn delete(&mut self, key: Key) -> Result<(), Self::Error>;
+    // Yields the entries between the bounds, both included, in key order.
     fn scan(
         &self,
         lower_bound: Option<Key>,
         upper_bound: Option<Key>,
     ) -> Result<Box<dyn Scanner + '_>, Self::Error>;
+
+    // Sends the changes to keys between the bounds made after the call, in the order they
+    // were applied, until the receiver is dropped. `None` if the engine cannot watch keys.
+    fn watch(
+        &self,
+        _lower_bound: Option<Key>,
+        _upper_bound: Option<Key>,
+    ) -> Result<Option<Receiver<Event>>, Self::Error> {
+        Ok(None)
+    }
+
+    // Yields every entry as it was at the time of the call, in key order, while writes go
+    // on. `None` if the engine cannot take snapshots.
+    fn snapshot(&self) -> Result<Option<Box<dyn Scanner + '_>>, Self::Error> {
+        Ok(None)
+    }
+
+    // Sets the key to `value`, or deletes it for `None`, if its value is `expected`, `None`
+    // standing for a missing key, with no other write in between; returns whether it did.
+    // `None` if the engine cannot compare and set.
+    fn compare_and_set(
+        &mut self,
+        _key: Key,
+        _expected: Option<Value>,
+        _value: Option<Value>,
+    ) -> Result<Option<bool>, Self::Error> {
+        Ok(None)
+    }
+
+    // Writes a batch of entries sorted by key, as `set` would. Engines may build their
+    // storage from it directly instead.
+    fn ingest(&mut self, entries: Vec<Entry>) -> Result<(), Self::Error> {
+        for (key, value) in entries {
+            self.set(key, value)?;
+        }
+        Ok(())
+    }
+
+    // Persists buffered writes.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    // Whether flushed writes are still there after a restart.
+    fn durable(&self) -> bool {
+        false
+    }
+
+    // Called once before the server exits, after the last request is served.
+    fn close(&mut self) -> Result<(), Self::Error> {
+        self.flush()
+    }
 }
 
 pub trait Scanner {
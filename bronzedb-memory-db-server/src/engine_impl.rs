@@ -1,21 +1,55 @@
 use bronzedb_engine::{Engine, Scanner};
 use bronzedb_util::status::{Error, StatusCode};
-use bronzedb_util::types::{Entry, EntryRef, Key, Value};
+use bronzedb_util::types::{Entry, EntryRef, Event, Key, Value};
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::sync::PoisonError;
-use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::sync::{PoisonError, RwLockWriteGuard};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct EngineImpl {
     inner: Arc<RwLock<HashMap<Key, Value>>>,
+    watchers: Arc<Mutex<Vec<Watcher>>>,
+}
+
+struct Watcher {
+    lower_bound: Option<Key>,
+    upper_bound: Option<Key>,
+    sender: Sender<Event>,
+}
+
+impl Watcher {
+    fn watches(&self, key: &Key) -> bool {
+        self.lower_bound.as_ref().is_none_or(|lower| key >= lower)
+            && self.upper_bound.as_ref().is_none_or(|upper| key <= upper)
+    }
 }
 
 impl EngineImpl {
     pub fn new() -> Self {
-        Self {
-            inner: Arc::new(RwLock::new(HashMap::new())),
-        }
+        Self::default()
+    }
+
+    // Takes the write guard so watchers get the changes in the order they are applied.
+    fn notify(
+        &self,
+        _guard: &RwLockWriteGuard<'_, HashMap<Key, Value>>,
+        key: &Key,
+        value: Option<&Value>,
+    ) -> Result<(), EngineError> {
+        // dropped receivers are removed on the next change they would get
+        self.watchers.lock()?.retain(|watcher| {
+            if !watcher.watches(key) {
+                return true;
+            }
+            let event = match value {
+                Some(value) => Event::Set(key.clone(), value.clone()),
+                None => Event::Delete(key.clone()),
+            };
+            watcher.sender.send(event).is_ok()
+        });
+        Ok(())
     }
 }
 
@@ -49,19 +83,41 @@ impl std::error::Error for EngineError {}
 impl Engine for EngineImpl {
     type Error = EngineError;
     fn set(&mut self, key: Key, value: Vec<u8>) -> Result<(), Self::Error> {
-        self.inner.write()?.insert(key, value);
+        let mut guard = self.inner.write()?;
+        self.notify(&guard, &key, Some(&value))?;
+        guard.insert(key, value);
         Ok(())
     }
 
     fn get(&self, key: Key) -> Result<Option<Value>, Self::Error> {
-        Ok(self.inner.read()?.get(&key).map(|value| value.clone()))
+        Ok(self.inner.read()?.get(&key).cloned())
     }
 
     fn delete(&mut self, key: Key) -> Result<(), Self::Error> {
-        self.inner.write()?.remove(&key);
+        let mut guard = self.inner.write()?;
+        self.notify(&guard, &key, None)?;
+        guard.remove(&key);
         Ok(())
     }
 
+    fn compare_and_set(
+        &mut self,
+        key: Key,
+        expected: Option<Value>,
+        value: Option<Value>,
+    ) -> Result<Option<bool>, Self::Error> {
+        let mut guard = self.inner.write()?;
+        if guard.get(&key) != expected.as_ref() {
+            return Ok(Some(false));
+        }
+        self.notify(&guard, &key, value.as_ref())?;
+        match value {
+            Some(value) => guard.insert(key, value),
+            None => guard.remove(&key),
+        };
+        Ok(Some(true))
+    }
+
     fn scan(
         &self,
         lower_bound: Option<Key>,
@@ -73,6 +129,45 @@ impl Engine for EngineImpl {
             upper_bound,
         )))
     }
+
+    fn watch(
+        &self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Option<Receiver<Event>>, Self::Error> {
+        let (sender, receiver) = channel();
+        self.watchers.lock()?.push(Watcher {
+            lower_bound,
+            upper_bound,
+            sender,
+        });
+        Ok(Some(receiver))
+    }
+
+    // A copy, so writes only wait for the entries to be cloned.
+    fn snapshot(&self) -> Result<Option<Box<dyn Scanner + '_>>, Self::Error> {
+        let mut entries: Vec<Entry> = self
+            .inner
+            .read()?
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.as_slice().cmp(b.as_slice()));
+        Ok(Some(Box::new(SnapshotScanner {
+            entries: entries.into_iter(),
+        })))
+    }
+
+    // One write lock for the whole batch.
+    fn ingest(&mut self, entries: Vec<Entry>) -> Result<(), Self::Error> {
+        let mut guard = self.inner.write()?;
+        guard.reserve(entries.len());
+        for (key, value) in entries {
+            self.notify(&guard, &key, Some(&value))?;
+            guard.insert(key, value);
+        }
+        Ok(())
+    }
 }
 
 struct GuardScanner<'a> {
@@ -96,7 +191,7 @@ impl<'a> GuardScanner<'a> {
 }
 
 impl Scanner for GuardScanner<'_> {
-    fn iter(&mut self) -> Box<Iterator<Item = Result<Entry, Error>> + '_> {
+    fn iter(&mut self) -> Box<dyn Iterator<Item = Result<Entry, Error>> + '_> {
         let mut entries: Box<dyn Iterator<Item = EntryRef>> = Box::new(self.guard.iter());
         if let Some(lower_key) = self.lower_bound.as_ref() {
             entries = Box::new(entries.filter(move |(key, _)| *key >= lower_key))
@@ -104,6 +199,22 @@ impl Scanner for GuardScanner<'_> {
         if let Some(upper_key) = self.upper_bound.as_ref() {
             entries = Box::new(entries.filter(move |(key, _)| *key <= upper_key))
         }
-        Box::new(entries.map(|(key, value)| Ok((key.clone(), value.clone()))))
+        let mut entries: Vec<_> = entries.collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.as_slice().cmp(b.as_slice()));
+        Box::new(
+            entries
+                .into_iter()
+                .map(|(key, value)| Ok((key.clone(), value.clone()))),
+        )
+    }
+}
+
+struct SnapshotScanner {
+    entries: std::vec::IntoIter<Entry>,
+}
+
+impl Scanner for SnapshotScanner {
+    fn iter(&mut self) -> Box<dyn Iterator<Item = Result<Entry, Error>> + '_> {
+        Box::new(self.entries.by_ref().map(Ok))
     }
 }
@@ -0,0 +1,190 @@
+use crate::ext::{ReadKVExt, WriteKVExt};
+use bronzedb_util::status::StatusCode::Corruption;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::Entry;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crc32c::crc32c_append;
+use std::io::{self, Read, Write};
+
+// A backup is `MAGIC | VERSION | (ENTRY | key | value)* | END | count: u64 | crc32c`, with
+// the entries in key order and the checksum over all of them. Nothing in it depends on
+// the engine, so any engine restores the backup of another.
+const MAGIC: &[u8; 8] = b"BRONZEDB";
+const VERSION: u8 = 1;
+const END: u8 = 0;
+const ENTRY: u8 = 1;
+
+pub struct BackupWriter<W: Write> {
+    writer: W,
+    count: u64,
+    crc: u32,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> BackupWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_u8(VERSION)?;
+        Ok(Self {
+            writer,
+            count: 0,
+            crc: 0,
+            buf: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.buf.clear();
+        self.buf.write_u8(ENTRY)?;
+        self.buf.write_key(key)?;
+        self.buf.write_value(value)?;
+        self.crc = crc32c_append(self.crc, &self.buf);
+        self.count += 1;
+        self.writer.write_all(&self.buf)
+    }
+
+    // Writes the trailer; a backup without it does not restore.
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.writer.write_u8(END)?;
+        self.writer.write_u64::<BigEndian>(self.count)?;
+        self.writer.write_u32::<BigEndian>(self.crc)?;
+        self.writer.flush()?;
+        Ok(self.count)
+    }
+}
+
+// Yields the entries of a backup, and an error instead of the last one if the trailer
+// does not match them. It reads no further than the trailer.
+pub struct BackupReader<R: Read> {
+    reader: R,
+    count: u64,
+    crc: u32,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> BackupReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(Corruption, "not a bronzedb backup"));
+        }
+        match reader.read_u8()? {
+            VERSION => Ok(Self {
+                reader,
+                count: 0,
+                crc: 0,
+                buf: Vec::new(),
+                done: false,
+            }),
+            version => Err(Error::new(
+                Corruption,
+                format!("unknown backup version: {}", version),
+            )),
+        }
+    }
+
+    fn read_entry(&mut self) -> Result<Option<Entry>> {
+        match self.reader.read_u8()? {
+            ENTRY => {
+                let key = self.reader.read_key()?;
+                let value = self.reader.read_value()?;
+                self.buf.clear();
+                self.buf.write_u8(ENTRY)?;
+                self.buf.write_key(&key)?;
+                self.buf.write_value(&value)?;
+                self.crc = crc32c_append(self.crc, &self.buf);
+                self.count += 1;
+                Ok(Some((key.into(), value)))
+            }
+            END => {
+                let count = self.reader.read_u64::<BigEndian>()?;
+                let crc = self.reader.read_u32::<BigEndian>()?;
+                if count != self.count || crc != self.crc {
+                    return Err(Error::new(Corruption, "backup checksum mismatch"));
+                }
+                Ok(None)
+            }
+            kind => Err(Error::new(
+                Corruption,
+                format!("unknown backup item: {}", kind),
+            )),
+        }
+    }
+}
+
+impl<R: Read> Iterator for BackupReader<R> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.read_entry();
+        if !matches!(entry, Ok(Some(_))) {
+            self.done = true;
+        }
+        entry.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackupReader, BackupWriter};
+    use bronzedb_util::status::{Result, StatusCode};
+    use bronzedb_util::types::Entry;
+    use speculate::speculate;
+    use std::io::Cursor;
+
+    fn backup(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut writer = BackupWriter::new(&mut data).unwrap();
+        for (key, value) in entries {
+            writer.write(key.as_bytes(), value.as_bytes()).unwrap();
+        }
+        assert_eq!(entries.len() as u64, writer.finish().unwrap());
+        data
+    }
+
+    speculate! {
+        describe "backup" {
+            it "round trip" {
+                let mut data = backup(&[("a", "1"), ("b", ""), ("c", "3")]);
+                // bytes after the trailer are left to the caller
+                data.push(0xff);
+                let mut cursor = Cursor::new(data);
+                let mut reader = BackupReader::new(&mut cursor).unwrap();
+                let entries = reader.by_ref().collect::<Result<Vec<Entry>>>().unwrap();
+                assert_eq!(3, entries.len());
+                assert!(reader.next().is_none());
+                assert_eq!(b"b", entries[1].0.as_slice());
+                assert_eq!(b"3".to_vec(), entries[2].1);
+                assert_eq!(cursor.position() + 1, cursor.get_ref().len() as u64);
+
+                let empty = backup(&[]);
+                assert!(BackupReader::new(empty.as_slice()).unwrap().next().is_none());
+            }
+
+            it "corrupted" {
+                let mut data = backup(&[("a", "1"), ("b", "2")]);
+                // the value of the last entry, before the 13 bytes of the trailer
+                let value = data.len() - 14;
+                data[value] ^= 1;
+                let err = BackupReader::new(data.as_slice())
+                    .unwrap()
+                    .collect::<Result<Vec<_>>>()
+                    .unwrap_err();
+                assert_eq!(StatusCode::Corruption, err.code);
+
+                let truncated = &backup(&[("a", "1")])[..12];
+                assert!(BackupReader::new(truncated)
+                    .unwrap()
+                    .collect::<Result<Vec<_>>>()
+                    .is_err());
+                let err = BackupReader::new(&b"BRONZEDC\x01"[..]).err().unwrap();
+                assert_eq!(StatusCode::Corruption, err.code);
+            }
+        }
+    }
+}
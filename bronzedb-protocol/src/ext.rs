@@ -33,8 +33,7 @@ impl<T: Read + ?Sized> ReadKVExt for T {
     fn read_key(&mut self) -> io::Result<Vec<u8>> {
         let key_len = self.read_u16::<BigEndian>()? as usize;
         debug_assert!(key_len <= MAX_KEY_LEN);
-        let mut key = Vec::with_capacity(key_len);
-        unsafe { key.set_len(key_len) };
+        let mut key = vec![0; key_len];
         self.read_exact(key.as_mut_slice())?;
         Ok(key)
     }
@@ -42,8 +41,7 @@ impl<T: Read + ?Sized> ReadKVExt for T {
     fn read_value(&mut self) -> io::Result<Value> {
         let value_len = self.read_u16::<BigEndian>()? as usize;
         debug_assert!(value_len <= MAX_VALUE_LEN);
-        let mut value = Vec::with_capacity(value_len);
-        unsafe { value.set_len(value_len) };
+        let mut value = vec![0; value_len];
         self.read_exact(value.as_mut_slice())?;
         Ok(value)
     }
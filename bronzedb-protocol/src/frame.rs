@@ -0,0 +1,511 @@
+use bronzedb_util::status::{Error, StatusCode};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crc32c::crc32c;
+use std::io::{self, ErrorKind, Read, Write};
+
+const MAX_FRAME_LEN: usize = 1 << 16;
+const BATCH_LEN: usize = 1 << 15;
+const COMPRESS_THRESHOLD: usize = 128;
+const ZSTD_LEVEL: i32 = 1;
+const CHECKSUM_FLAG: u8 = 1;
+const COMPRESSION_SHIFT: u8 = 1;
+const COMPRESSION_MASK: u8 = 0b11;
+const CANCEL_FLAG: u8 = 1 << 3;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2,
+}
+
+impl From<u8> for Compression {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Compression::Lz4,
+            2 => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Options {
+    pub checksum: bool,
+    pub compression: Compression,
+    // scans pause every `SCAN_WINDOW` entries, so the client can cancel them
+    pub cancel: bool,
+}
+
+impl Options {
+    pub fn framed(&self) -> bool {
+        self.checksum || self.compression != Compression::None
+    }
+
+    // Server side: checksums need both ends to enable them, while any compression
+    // requested by the client is accepted unless the server disables compression.
+    // Cancellable scans also need both ends.
+    pub fn negotiate(&self, requested: Options) -> Self {
+        Self {
+            checksum: self.checksum && requested.checksum,
+            compression: match self.compression {
+                Compression::None => Compression::None,
+                _ => requested.compression,
+            },
+            cancel: self.cancel && requested.cancel,
+        }
+    }
+}
+
+impl From<u8> for Options {
+    fn from(flags: u8) -> Self {
+        Self {
+            checksum: flags & CHECKSUM_FLAG != 0,
+            compression: ((flags >> COMPRESSION_SHIFT) & COMPRESSION_MASK).into(),
+            cancel: flags & CANCEL_FLAG != 0,
+        }
+    }
+}
+
+impl From<Options> for u8 {
+    fn from(options: Options) -> Self {
+        let mut flags = (options.compression as u8) << COMPRESSION_SHIFT;
+        if options.checksum {
+            flags |= CHECKSUM_FLAG;
+        }
+        if options.cancel {
+            flags |= CANCEL_FLAG;
+        }
+        flags
+    }
+}
+
+// Once options are negotiated, data is sent as `len | payload | crc32c(payload)` frames,
+// the checksum only if enabled. With compression, the payload starts with the codec
+// that was actually used, small or incompressible frames are kept raw.
+// `flush` marks the end of a message, long scans are split into batches of `BATCH_LEN`.
+pub struct Framed<T> {
+    inner: T,
+    options: Options,
+    corrupted: bool,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    write_buf: Vec<u8>,
+}
+
+impl<T> Framed<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            options: Options::default(),
+            corrupted: false,
+            read_buf: Vec::new(),
+            read_pos: 0,
+            write_buf: Vec::new(),
+        }
+    }
+
+    pub fn options(&self) -> Options {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: Options) {
+        self.options = options;
+    }
+
+    pub fn is_corrupted(&self) -> bool {
+        self.corrupted
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: Read> Framed<T> {
+    fn corruption(&mut self, message: &str) -> io::Error {
+        self.corrupted = true;
+        io::Error::new(
+            ErrorKind::InvalidData,
+            Error::new(StatusCode::Corruption, message),
+        )
+    }
+
+    fn read_frame(&mut self) -> io::Result<()> {
+        let frame_len = self.inner.read_u32::<BigEndian>()? as usize;
+        if frame_len > MAX_FRAME_LEN {
+            return Err(self.corruption("frame too long"));
+        }
+        self.read_buf.resize(frame_len, 0);
+        self.read_pos = 0;
+        let checksum_enabled = self.options.checksum;
+        let checksum = match self.inner.read_exact(&mut self.read_buf).and_then(|_| {
+            if checksum_enabled {
+                self.inner.read_u32::<BigEndian>().map(Some)
+            } else {
+                Ok(None)
+            }
+        }) {
+            Ok(checksum) => checksum,
+            Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => {
+                return Err(self.corruption("truncated frame"))
+            }
+            Err(err) => return Err(err),
+        };
+        if checksum.is_some_and(|checksum| checksum != crc32c(&self.read_buf)) {
+            return Err(self.corruption("checksum mismatch"));
+        }
+        if self.options.compression != Compression::None {
+            self.decompress()?;
+        }
+        Ok(())
+    }
+
+    fn decompress(&mut self) -> io::Result<()> {
+        let (codec, body) = match self.read_buf.split_first() {
+            Some((codec, body)) => (Compression::from(*codec), body),
+            None => return Err(self.corruption("empty frame")),
+        };
+        let data = match codec {
+            Compression::None => {
+                self.read_pos = 1;
+                return Ok(());
+            }
+            Compression::Lz4 => decompress_lz4(body),
+            Compression::Zstd => zstd::bulk::decompress(body, MAX_FRAME_LEN).ok(),
+        };
+        match data {
+            Some(data) => {
+                self.read_buf = data;
+                Ok(())
+            }
+            None => Err(self.corruption("cannot decompress frame")),
+        }
+    }
+}
+
+fn decompress_lz4(mut body: &[u8]) -> Option<Vec<u8>> {
+    let raw_len = body.read_u32::<BigEndian>().ok()? as usize;
+    if raw_len > MAX_FRAME_LEN {
+        return None;
+    }
+    lz4_flex::block::decompress(body, raw_len).ok()
+}
+
+fn compress(compression: Compression, data: &[u8]) -> Vec<u8> {
+    let compressed = match compression {
+        _ if data.len() < COMPRESS_THRESHOLD => None,
+        Compression::Lz4 => {
+            let mut body = (data.len() as u32).to_be_bytes().to_vec();
+            body.extend_from_slice(&lz4_flex::block::compress(data));
+            Some(body)
+        }
+        Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL).ok(),
+        Compression::None => None,
+    };
+    let (codec, body) = match compressed {
+        Some(ref body) if body.len() < data.len() => (compression, body.as_slice()),
+        _ => (Compression::None, data),
+    };
+    let mut payload = Vec::with_capacity(body.len() + 1);
+    payload.push(codec as u8);
+    payload.extend_from_slice(body);
+    payload
+}
+
+impl<T: Read> Read for Framed<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.options.framed() {
+            return self.inner.read(buf);
+        }
+        if self.corrupted {
+            return Err(self.corruption("stream corrupted"));
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.read_pos == self.read_buf.len() {
+            self.read_frame()?;
+        }
+        let size = (&self.read_buf[self.read_pos..]).read(buf)?;
+        self.read_pos += size;
+        Ok(size)
+    }
+}
+
+impl<T: Write> Framed<T> {
+    fn write_frame(&mut self) -> io::Result<()> {
+        if self.write_buf.is_empty() {
+            return Ok(());
+        }
+        debug_assert!(self.write_buf.len() <= MAX_FRAME_LEN);
+        let compressed;
+        let payload = match self.options.compression {
+            Compression::None => &self.write_buf,
+            compression => {
+                compressed = compress(compression, &self.write_buf);
+                &compressed
+            }
+        };
+        let mut frame = Vec::with_capacity(payload.len() + 8);
+        frame.write_u32::<BigEndian>(payload.len() as u32)?;
+        frame.extend_from_slice(payload);
+        if self.options.checksum {
+            frame.write_u32::<BigEndian>(crc32c(payload))?;
+        }
+        self.write_buf.clear();
+        self.inner.write_all(&frame)
+    }
+}
+
+impl<T: Write> Write for Framed<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.options.framed() {
+            return self.inner.write(buf);
+        }
+        self.write_buf.extend_from_slice(buf);
+        if self.write_buf.len() >= BATCH_LEN {
+            self.write_frame()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.options.framed() {
+            self.write_frame()?;
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compression, Framed, Options, BATCH_LEN};
+    use crate::request::Action::Scan;
+    use crate::request::Request;
+    use crate::response::Response;
+    use bronzedb_util::status::{Error, StatusCode};
+    use bronzedb_util::types::Entry;
+    use speculate::speculate;
+    use std::io::{Cursor, Read};
+
+    const CHECKSUM: Options = Options {
+        checksum: true,
+        compression: Compression::None,
+        cancel: false,
+    };
+
+    fn framed_stream(data: Vec<u8>, options: Options) -> Framed<Cursor<Vec<u8>>> {
+        let mut stream = Framed::new(Cursor::new(data));
+        stream.set_options(options);
+        stream
+    }
+
+    fn send(requests: Vec<Request>, options: Options) -> Vec<u8> {
+        let mut stream = framed_stream(Vec::new(), options);
+        for request in requests {
+            request.write_to(&mut stream).unwrap();
+        }
+        stream.get_ref().get_ref().clone()
+    }
+
+    fn json_value(id: usize) -> Vec<u8> {
+        format!(
+            r#"{{"id":{},"name":"bronze","tags":["database","kv","database","kv"]}}"#,
+            id
+        )
+        .repeat(8)
+        .into_bytes()
+    }
+
+    speculate! {
+        describe "frame" {
+            it "options flags" {
+                for compression in [Compression::None, Compression::Lz4, Compression::Zstd] {
+                    let options = Options {
+                        checksum: true,
+                        compression,
+                        cancel: true,
+                    };
+                    assert_eq!(options, Options::from(u8::from(options)));
+                }
+                assert_eq!(Options::default(), Options::from(0));
+            }
+
+            it "negotiate" {
+                let server = Options {
+                    checksum: false,
+                    compression: Compression::Lz4,
+                    cancel: true,
+                };
+                let requested = Options {
+                    checksum: true,
+                    compression: Compression::Zstd,
+                    cancel: true,
+                };
+                assert_eq!(
+                    Options {
+                        checksum: false,
+                        compression: Compression::Zstd,
+                        cancel: true,
+                    },
+                    server.negotiate(requested)
+                );
+                assert_eq!(Options::default(), Options::default().negotiate(requested));
+            }
+
+            it "round trip" {
+                let buffer = send(
+                    vec![
+                        Request::Set(b"name"[..].to_vec().into(), b"Hexi"[..].to_vec()),
+                        Request::Get(b"name"[..].to_vec().into()),
+                    ],
+                    CHECKSUM,
+                );
+                assert_eq!(2 * 8 + 1 + 6 + 6 + 1 + 6, buffer.len());
+                let mut stream = framed_stream(buffer, CHECKSUM);
+                match Request::read_from(&mut stream).unwrap() {
+                    Request::Set(key, value) => {
+                        assert_eq!(b"name", key.as_slice());
+                        assert_eq!(b"Hexi", value.as_slice());
+                    }
+                    _ => panic!("expect set"),
+                }
+                assert!(matches!(
+                    Request::read_from(&mut stream).unwrap(),
+                    Request::Get(_)
+                ));
+            }
+
+            it "bit flipped" {
+                let mut buffer = send(vec![Request::Get(b"name"[..].to_vec().into())], CHECKSUM);
+                buffer[6] ^= 1;
+                let mut stream = framed_stream(buffer, CHECKSUM);
+                let err: Error = Request::read_from(&mut stream).err().unwrap().into();
+                assert_eq!(StatusCode::Corruption, err.code);
+                assert!(stream.is_corrupted());
+            }
+
+            it "truncated" {
+                let mut buffer = send(vec![Request::Get(b"name"[..].to_vec().into())], CHECKSUM);
+                buffer.truncate(buffer.len() - 2);
+                let err: Error = Request::read_from(&mut framed_stream(buffer, CHECKSUM))
+                    .err()
+                    .unwrap()
+                    .into();
+                assert_eq!(StatusCode::Corruption, err.code);
+            }
+
+            it "plain pass through" {
+                let mut stream = Framed::new(Cursor::new(Vec::new()));
+                Request::Ping.write_to(&mut stream).unwrap();
+                assert_eq!(&[1u8][..], stream.get_ref().get_ref().as_slice());
+                let mut data = Vec::new();
+                Framed::new(Cursor::new(vec![1u8, 2]))
+                    .read_to_end(&mut data)
+                    .unwrap();
+                assert_eq!(vec![1u8, 2], data);
+            }
+        }
+    }
+
+    macro_rules! assert_compressed_set {
+        ($compression:expr) => {
+            let options = Options {
+                checksum: false,
+                compression: $compression,
+                cancel: false,
+            };
+            let value = json_value(0);
+            let buffer = send(
+                vec![Request::Set(b"doc"[..].to_vec().into(), value.clone())],
+                options,
+            );
+            assert!(buffer.len() < value.len());
+            match Request::read_from(&mut framed_stream(buffer, options)).unwrap() {
+                Request::Set(_, new_value) => assert_eq!(value, new_value),
+                _ => panic!("expect set"),
+            }
+        };
+    }
+
+    speculate! {
+        describe "compression" {
+            it "lz4 set" {
+                assert_compressed_set!(Compression::Lz4);
+            }
+
+            it "zstd set" {
+                assert_compressed_set!(Compression::Zstd);
+            }
+
+            it "small frame kept raw" {
+                let options = Options {
+                    checksum: true,
+                    compression: Compression::Zstd,
+                    cancel: false,
+                };
+                let buffer = send(vec![Request::Ping], options);
+                assert_eq!(4 + 2 + 4, buffer.len());
+                assert!(matches!(
+                    Request::read_from(&mut framed_stream(buffer, options)).unwrap(),
+                    Request::Ping
+                ));
+            }
+
+            it "compressed scan batches" {
+                let options = Options {
+                    checksum: true,
+                    compression: Compression::Lz4,
+                    cancel: false,
+                };
+                let origin_data: Vec<Entry> = (0..200)
+                    .map(|id| (format!("doc{:03}", id).into_bytes().into(), json_value(id)))
+                    .collect();
+                let raw_len = origin_data
+                    .iter()
+                    .fold(0, |size, (key, value)| size + 5 + key.len() + value.len());
+                assert!(raw_len > 2 * BATCH_LEN);
+
+                let mut stream = framed_stream(Vec::new(), options);
+                Response::Scanner(Box::new(origin_data.iter().map(|entry| Ok(entry.clone()))))
+                    .write_to(&mut stream)
+                    .unwrap();
+                let buffer = stream.get_ref().get_ref().clone();
+                assert!(buffer.len() < raw_len / 4);
+
+                let mut stream = framed_stream(buffer, options);
+                match Response::read_from(&mut stream, Scan).unwrap() {
+                    Response::Scanner(iter) => {
+                        let transferred_data = iter.map(|ret| ret.unwrap()).collect::<Vec<Entry>>();
+                        assert_eq!(origin_data, transferred_data);
+                    }
+                    _ => panic!("expect scanner"),
+                };
+            }
+
+            it "corrupted compressed frame" {
+                let options = Options {
+                    checksum: false,
+                    compression: Compression::Lz4,
+                    cancel: false,
+                };
+                let mut buffer = send(
+                    vec![Request::Set(b"doc"[..].to_vec().into(), json_value(0))],
+                    options,
+                );
+                buffer[5] = 0xff;
+                let err: Error = Request::read_from(&mut framed_stream(buffer, options))
+                    .err()
+                    .unwrap()
+                    .into();
+                assert_eq!(StatusCode::Corruption, err.code);
+            }
+        }
+    }
+}
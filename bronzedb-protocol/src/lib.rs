@@ -4,11 +4,18 @@ extern crate matches;
 #[cfg(test)]
 extern crate speculate;
 
-const MAX_KEY_LEN: usize = 1 << 8;
-const MAX_VALUE_LEN: usize = 1 << 12;
-const MIN_KEY: &'static [u8] = b"";
-const MAX_KEY: &'static [u8] = &[0xff; MAX_KEY_LEN];
+pub const MAX_KEY_LEN: usize = 1 << 8;
+pub const MAX_VALUE_LEN: usize = 1 << 12;
+// Entries a cancellable scan sends before waiting for the client.
+pub const SCAN_WINDOW: usize = 1 << 8;
+const MIN_KEY: &[u8] = b"";
+const MAX_KEY: &[u8] = &[0xff; MAX_KEY_LEN];
 
+pub mod backup;
 pub mod ext;
+pub mod frame;
+pub mod raft;
+pub mod replication;
 pub mod request;
 pub mod response;
+pub mod shard;
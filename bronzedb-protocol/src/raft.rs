@@ -0,0 +1,389 @@
+use crate::ext::{ReadKVExt, WriteKVExt};
+use bronzedb_util::status::StatusCode::Corruption;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::Event;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+
+const VOTE: u8 = 0;
+const VOTED: u8 = 1;
+const APPEND: u8 = 2;
+const APPENDED: u8 = 3;
+const SNAPSHOT: u8 = 4;
+
+const NOOP_ENTRY: u8 = 0;
+const SET_ENTRY: u8 = 1;
+const DELETE_ENTRY: u8 = 2;
+
+// A write replicated by a cluster; leaders start their term with an entry without one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub term: u64,
+    pub event: Option<Event>,
+}
+
+impl LogEntry {
+    pub fn write_to(&self, writer: &mut dyn Write) -> io::Result<usize> {
+        writer.write_u64::<BigEndian>(self.term)?;
+        Ok(9 + match self.event {
+            None => {
+                writer.write_u8(NOOP_ENTRY)?;
+                0
+            }
+            Some(Event::Set(ref key, ref value)) => {
+                writer.write_u8(SET_ENTRY)?;
+                writer.write_key(key)? + writer.write_value(value)?
+            }
+            Some(Event::Delete(ref key)) => {
+                writer.write_u8(DELETE_ENTRY)?;
+                writer.write_key(key)?
+            }
+        })
+    }
+
+    pub fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let term = reader.read_u64::<BigEndian>()?;
+        let event = match reader.read_u8()? {
+            NOOP_ENTRY => None,
+            SET_ENTRY => Some(Event::Set(reader.read_key()?.into(), reader.read_value()?)),
+            DELETE_ENTRY => Some(Event::Delete(reader.read_key()?.into())),
+            kind => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown entry kind: {}", kind),
+                ))
+            }
+        };
+        Ok(Self { term, event })
+    }
+}
+
+// The messages between the members of a cluster; `Voted` answers `Vote`, and `Appended`
+// answers `Append` and `Snapshot`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RaftMessage {
+    Vote {
+        term: u64,
+        candidate: u64,
+        last_index: u64,
+        last_term: u64,
+    },
+    Voted {
+        term: u64,
+        granted: bool,
+    },
+    Append {
+        term: u64,
+        leader: u64,
+        prev_index: u64,
+        prev_term: u64,
+        entries: Vec<LogEntry>,
+        commit: u64,
+    },
+    // `index` is the last entry matching the leader's log, or a guess where it is on failure
+    Appended {
+        term: u64,
+        success: bool,
+        index: u64,
+    },
+    // A part of the snapshot of the leader's engine up to the entry at `index`, sent to
+    // members missing entries the leader compacted; `data` starts at `offset` in the snapshot.
+    Snapshot {
+        term: u64,
+        leader: u64,
+        index: u64,
+        last_term: u64,
+        offset: u64,
+        data: Vec<u8>,
+        done: bool,
+    },
+}
+
+// Read and written like a `Request`, which carries them.
+impl RaftMessage {
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<usize> {
+        let mut counter = 1;
+        match *self {
+            RaftMessage::Vote {
+                term,
+                candidate,
+                last_index,
+                last_term,
+            } => {
+                writer.write_u8(VOTE)?;
+                for field in &[term, candidate, last_index, last_term] {
+                    writer.write_u64::<BigEndian>(*field)?;
+                }
+                counter += 32;
+            }
+            RaftMessage::Voted { term, granted } => {
+                writer.write_u8(VOTED)?;
+                writer.write_u64::<BigEndian>(term)?;
+                writer.write_u8(granted as u8)?;
+                counter += 9;
+            }
+            RaftMessage::Append {
+                term,
+                leader,
+                prev_index,
+                prev_term,
+                ref entries,
+                commit,
+            } => {
+                writer.write_u8(APPEND)?;
+                for field in &[term, leader, prev_index, prev_term, commit] {
+                    writer.write_u64::<BigEndian>(*field)?;
+                }
+                writer.write_u32::<BigEndian>(entries.len() as u32)?;
+                counter += 44;
+                for entry in entries {
+                    counter += entry.write_to(&mut writer)?;
+                }
+            }
+            RaftMessage::Appended {
+                term,
+                success,
+                index,
+            } => {
+                writer.write_u8(APPENDED)?;
+                writer.write_u64::<BigEndian>(term)?;
+                writer.write_u8(success as u8)?;
+                writer.write_u64::<BigEndian>(index)?;
+                counter += 17;
+            }
+            RaftMessage::Snapshot {
+                term,
+                leader,
+                index,
+                last_term,
+                offset,
+                ref data,
+                done,
+            } => {
+                writer.write_u8(SNAPSHOT)?;
+                for field in &[term, leader, index, last_term, offset] {
+                    writer.write_u64::<BigEndian>(*field)?;
+                }
+                writer.write_u8(done as u8)?;
+                writer.write_u32::<BigEndian>(data.len() as u32)?;
+                writer.write_all(data)?;
+                counter += 45 + data.len();
+            }
+        }
+        Ok(counter)
+    }
+
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        match reader.read_u8()? {
+            VOTE => Ok(RaftMessage::Vote {
+                term: reader.read_u64::<BigEndian>()?,
+                candidate: reader.read_u64::<BigEndian>()?,
+                last_index: reader.read_u64::<BigEndian>()?,
+                last_term: reader.read_u64::<BigEndian>()?,
+            }),
+            VOTED => Ok(RaftMessage::Voted {
+                term: reader.read_u64::<BigEndian>()?,
+                granted: reader.read_u8()? != 0,
+            }),
+            APPEND => {
+                let term = reader.read_u64::<BigEndian>()?;
+                let leader = reader.read_u64::<BigEndian>()?;
+                let prev_index = reader.read_u64::<BigEndian>()?;
+                let prev_term = reader.read_u64::<BigEndian>()?;
+                let commit = reader.read_u64::<BigEndian>()?;
+                let len = reader.read_u32::<BigEndian>()?;
+                let entries = (0..len)
+                    .map(|_| LogEntry::read_from(&mut reader))
+                    .collect::<io::Result<_>>()?;
+                Ok(RaftMessage::Append {
+                    term,
+                    leader,
+                    prev_index,
+                    prev_term,
+                    entries,
+                    commit,
+                })
+            }
+            APPENDED => Ok(RaftMessage::Appended {
+                term: reader.read_u64::<BigEndian>()?,
+                success: reader.read_u8()? != 0,
+                index: reader.read_u64::<BigEndian>()?,
+            }),
+            SNAPSHOT => {
+                let term = reader.read_u64::<BigEndian>()?;
+                let leader = reader.read_u64::<BigEndian>()?;
+                let index = reader.read_u64::<BigEndian>()?;
+                let last_term = reader.read_u64::<BigEndian>()?;
+                let offset = reader.read_u64::<BigEndian>()?;
+                let done = reader.read_u8()? != 0;
+                let mut data = vec![0; reader.read_u32::<BigEndian>()? as usize];
+                reader.read_exact(&mut data)?;
+                Ok(RaftMessage::Snapshot {
+                    term,
+                    leader,
+                    index,
+                    last_term,
+                    offset,
+                    data,
+                    done,
+                })
+            }
+            kind => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown raft message: {}", kind),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RaftRole {
+    Follower = 0,
+    Candidate = 1,
+    Leader = 2,
+}
+
+// `commit` is the last entry known to be replicated to a majority, `applied` the last one
+// applied to the engine of the member.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterStatus {
+    pub id: u64,
+    pub role: RaftRole,
+    pub term: u64,
+    // the address of the leader, if the member knows one
+    pub leader: Option<String>,
+    pub commit: u64,
+    pub applied: u64,
+    pub members: u32,
+}
+
+impl ClusterStatus {
+    pub fn write_to(&self, mut writer: impl Write) -> Result<usize> {
+        writer.write_u64::<BigEndian>(self.id)?;
+        writer.write_u8(self.role as u8)?;
+        writer.write_u64::<BigEndian>(self.term)?;
+        let leader = self.leader.as_ref().map_or("", String::as_str);
+        let counter = 17 + writer.write_key(leader.as_bytes())?;
+        writer.write_u64::<BigEndian>(self.commit)?;
+        writer.write_u64::<BigEndian>(self.applied)?;
+        writer.write_u32::<BigEndian>(self.members)?;
+        Ok(counter + 20)
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let id = reader.read_u64::<BigEndian>()?;
+        let role = match reader.read_u8()? {
+            0 => RaftRole::Follower,
+            1 => RaftRole::Candidate,
+            2 => RaftRole::Leader,
+            role => return Err(Error::new(Corruption, format!("unknown role: {}", role))),
+        };
+        let term = reader.read_u64::<BigEndian>()?;
+        let leader = String::from_utf8(reader.read_key()?)
+            .map_err(|err| Error::new(Corruption, err.to_string()))?;
+        Ok(Self {
+            id,
+            role,
+            term,
+            leader: if leader.is_empty() {
+                None
+            } else {
+                Some(leader)
+            },
+            commit: reader.read_u64::<BigEndian>()?,
+            applied: reader.read_u64::<BigEndian>()?,
+            members: reader.read_u32::<BigEndian>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClusterStatus, LogEntry, RaftMessage, RaftRole};
+    use bronzedb_util::types::Event;
+    use speculate::speculate;
+    use std::io::Cursor;
+
+    speculate! {
+        describe "raft" {
+            it "messages" {
+                let messages = vec![
+                    RaftMessage::Vote {
+                        term: 3,
+                        candidate: 2,
+                        last_index: 10,
+                        last_term: 2,
+                    },
+                    RaftMessage::Voted {
+                        term: 3,
+                        granted: true,
+                    },
+                    RaftMessage::Append {
+                        term: 3,
+                        leader: 2,
+                        prev_index: 10,
+                        prev_term: 2,
+                        entries: vec![
+                            LogEntry {
+                                term: 3,
+                                event: None,
+                            },
+                            LogEntry {
+                                term: 3,
+                                event: Some(Event::Set(b"name"[..].to_vec().into(), b"Hexi".to_vec())),
+                            },
+                            LogEntry {
+                                term: 3,
+                                event: Some(Event::Delete(b"name"[..].to_vec().into())),
+                            },
+                        ],
+                        commit: 10,
+                    },
+                    RaftMessage::Appended {
+                        term: 3,
+                        success: false,
+                        index: 9,
+                    },
+                    RaftMessage::Snapshot {
+                        term: 3,
+                        leader: 2,
+                        index: 10,
+                        last_term: 2,
+                        offset: 4096,
+                        data: b"entries".to_vec(),
+                        done: true,
+                    },
+                ];
+                let mut buffer = Vec::new();
+                let mut size = 0;
+                for message in &messages {
+                    size += message.write_to(&mut buffer).unwrap();
+                }
+                assert_eq!(buffer.len(), size);
+                let mut reader = Cursor::new(buffer);
+                for message in messages {
+                    assert_eq!(message, RaftMessage::read_from(&mut reader).unwrap());
+                }
+            }
+
+            it "status" {
+                let status = ClusterStatus {
+                    id: 1,
+                    role: RaftRole::Follower,
+                    term: 4,
+                    leader: Some("127.0.0.1:8088".to_owned()),
+                    commit: 12,
+                    applied: 11,
+                    members: 3,
+                };
+                let mut buffer = Vec::new();
+                let size = status.write_to(&mut buffer).unwrap();
+                assert_eq!(buffer.len(), size);
+                assert_eq!(
+                    status,
+                    ClusterStatus::read_from(Cursor::new(buffer)).unwrap()
+                );
+            }
+        }
+    }
+}
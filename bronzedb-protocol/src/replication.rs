@@ -0,0 +1,213 @@
+use crate::ext::{ReadKVExt, WriteKVExt};
+use bronzedb_util::status::StatusCode::Corruption;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Event, Key, Value};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+const RESET: u8 = 0;
+const ENTRY: u8 = 1;
+const SET_CHANGE: u8 = 2;
+const DELETE_CHANGE: u8 = 3;
+const HEAD: u8 = 4;
+
+// Changes are numbered from 1 in the order the primary applied them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplicationItem {
+    // A snapshot follows: the replica drops its data, loads the entries and has then applied
+    // every change up to `head`.
+    Reset { head: u64 },
+    Entry(Key, Value),
+    Change { seq: u64, event: Event },
+    // the last change of the primary, sent while there are no new ones
+    Head(u64),
+}
+
+impl ReplicationItem {
+    pub fn write_to(self, mut writer: impl Write) -> Result<usize> {
+        Ok(match self {
+            ReplicationItem::Reset { head } => {
+                writer.write_u8(RESET)?;
+                writer.write_u64::<BigEndian>(head)?;
+                9
+            }
+            ReplicationItem::Entry(key, value) => {
+                writer.write_u8(ENTRY)?;
+                1 + writer.write_key(&key)? + writer.write_value(&value)?
+            }
+            ReplicationItem::Change {
+                seq,
+                event: Event::Set(key, value),
+            } => {
+                writer.write_u8(SET_CHANGE)?;
+                writer.write_u64::<BigEndian>(seq)?;
+                9 + writer.write_key(&key)? + writer.write_value(&value)?
+            }
+            ReplicationItem::Change {
+                seq,
+                event: Event::Delete(key),
+            } => {
+                writer.write_u8(DELETE_CHANGE)?;
+                writer.write_u64::<BigEndian>(seq)?;
+                9 + writer.write_key(&key)?
+            }
+            ReplicationItem::Head(head) => {
+                writer.write_u8(HEAD)?;
+                writer.write_u64::<BigEndian>(head)?;
+                9
+            }
+        })
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        match reader.read_u8()? {
+            RESET => Ok(ReplicationItem::Reset {
+                head: reader.read_u64::<BigEndian>()?,
+            }),
+            ENTRY => Ok(ReplicationItem::Entry(
+                reader.read_key()?.into(),
+                reader.read_value()?,
+            )),
+            SET_CHANGE => Ok(ReplicationItem::Change {
+                seq: reader.read_u64::<BigEndian>()?,
+                event: Event::Set(reader.read_key()?.into(), reader.read_value()?),
+            }),
+            DELETE_CHANGE => Ok(ReplicationItem::Change {
+                seq: reader.read_u64::<BigEndian>()?,
+                event: Event::Delete(reader.read_key()?.into()),
+            }),
+            HEAD => Ok(ReplicationItem::Head(reader.read_u64::<BigEndian>()?)),
+            kind => Err(Error::new(
+                Corruption,
+                format!("unknown replication item: {}", kind),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Role {
+    Standalone = 0,
+    Primary = 1,
+    Replica = 2,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReplicaState {
+    Connecting = 0,
+    Syncing = 1,
+    Streaming = 2,
+}
+
+// `head` is the last change of the primary, as far as a replica knows, and `applied` the
+// last one applied locally. `replicas` counts the replicas streaming from a primary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicationStatus {
+    pub role: Role,
+    pub state: ReplicaState,
+    pub primary: Option<String>,
+    pub head: u64,
+    pub applied: u64,
+    pub replicas: u32,
+}
+
+impl ReplicationStatus {
+    pub fn lag(&self) -> u64 {
+        self.head.saturating_sub(self.applied)
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> Result<usize> {
+        writer.write_u8(self.role as u8)?;
+        writer.write_u8(self.state as u8)?;
+        let primary = self.primary.as_ref().map_or("", String::as_str);
+        let counter = 2 + writer.write_key(primary.as_bytes())?;
+        writer.write_u64::<BigEndian>(self.head)?;
+        writer.write_u64::<BigEndian>(self.applied)?;
+        writer.write_u32::<BigEndian>(self.replicas)?;
+        Ok(counter + 20)
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let role = match reader.read_u8()? {
+            0 => Role::Standalone,
+            1 => Role::Primary,
+            2 => Role::Replica,
+            role => return Err(Error::new(Corruption, format!("unknown role: {}", role))),
+        };
+        let state = match reader.read_u8()? {
+            0 => ReplicaState::Connecting,
+            1 => ReplicaState::Syncing,
+            2 => ReplicaState::Streaming,
+            state => return Err(Error::new(Corruption, format!("unknown state: {}", state))),
+        };
+        let primary = String::from_utf8(reader.read_key()?)
+            .map_err(|err| Error::new(Corruption, err.to_string()))?;
+        Ok(Self {
+            role,
+            state,
+            primary: if primary.is_empty() {
+                None
+            } else {
+                Some(primary)
+            },
+            head: reader.read_u64::<BigEndian>()?,
+            applied: reader.read_u64::<BigEndian>()?,
+            replicas: reader.read_u32::<BigEndian>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReplicaState, ReplicationItem, ReplicationStatus, Role};
+    use bronzedb_util::types::Event;
+    use speculate::speculate;
+    use std::io::Cursor;
+
+    speculate! {
+        describe "replication" {
+            it "items" {
+                let items = vec![
+                    ReplicationItem::Reset { head: 42 },
+                    ReplicationItem::Entry(b"name"[..].to_vec().into(), b"Hexi".to_vec()),
+                    ReplicationItem::Change {
+                        seq: 43,
+                        event: Event::Set(b"name"[..].to_vec().into(), b"Lee".to_vec()),
+                    },
+                    ReplicationItem::Change {
+                        seq: 44,
+                        event: Event::Delete(b"name"[..].to_vec().into()),
+                    },
+                    ReplicationItem::Head(44),
+                ];
+                let mut buffer = Vec::new();
+                for item in items.clone() {
+                    item.write_to(&mut buffer).unwrap();
+                }
+                let mut reader = Cursor::new(buffer);
+                for item in items {
+                    assert_eq!(item, ReplicationItem::read_from(&mut reader).unwrap());
+                }
+            }
+
+            it "status" {
+                let status = ReplicationStatus {
+                    role: Role::Replica,
+                    state: ReplicaState::Streaming,
+                    primary: Some("127.0.0.1:8088".to_owned()),
+                    head: 10,
+                    applied: 7,
+                    replicas: 0,
+                };
+                let mut buffer = Vec::new();
+                let size = status.write_to(&mut buffer).unwrap();
+                assert_eq!(buffer.len(), size);
+                assert_eq!(3, status.lag());
+                assert_eq!(
+                    status,
+                    ReplicationStatus::read_from(Cursor::new(buffer)).unwrap()
+                );
+            }
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use crate::ext::{ReadKVExt, WriteKVExt};
+use crate::frame::Options;
+use crate::raft::RaftMessage;
+use crate::shard::{read_upper, write_upper, MigrationCommand, PartitionMap};
 use crate::{MAX_KEY, MIN_KEY};
 use bronzedb_util::types::{Key, Value};
-use byteorder::{ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::io::{self, Read, Write};
 use std::ops::Deref;
-use std::u8::MAX;
 
 #[derive(Debug, Copy, Clone)]
 pub enum Action {
@@ -14,7 +16,27 @@ pub enum Action {
     Get = 3,
     Delete = 4,
     Scan = 5,
-    Unknown = MAX as isize,
+    Handshake = 6,
+    Auth = 7,
+    More = 8,
+    Cancel = 9,
+    Watch = 10,
+    Publish = 11,
+    Subscribe = 12,
+    Replicate = 13,
+    ReplicationStatus = 14,
+    Raft = 15,
+    ClusterStatus = 16,
+    ShardMap = 17,
+    Migration = 18,
+    Transfer = 19,
+    UpdateShardMap = 20,
+    Backup = 21,
+    BackupFile = 22,
+    Restore = 23,
+    RestoreFile = 24,
+    Ingest = 25,
+    Unknown = u8::MAX as isize,
 }
 
 impl From<u8> for Action {
@@ -26,6 +48,26 @@ impl From<u8> for Action {
             3 => Action::Get,
             4 => Action::Delete,
             5 => Action::Scan,
+            6 => Action::Handshake,
+            7 => Action::Auth,
+            8 => Action::More,
+            9 => Action::Cancel,
+            10 => Action::Watch,
+            11 => Action::Publish,
+            12 => Action::Subscribe,
+            13 => Action::Replicate,
+            14 => Action::ReplicationStatus,
+            15 => Action::Raft,
+            16 => Action::ClusterStatus,
+            17 => Action::ShardMap,
+            18 => Action::Migration,
+            19 => Action::Transfer,
+            20 => Action::UpdateShardMap,
+            21 => Action::Backup,
+            22 => Action::BackupFile,
+            23 => Action::Restore,
+            24 => Action::RestoreFile,
+            25 => Action::Ingest,
             _ => Action::Unknown,
         }
     }
@@ -41,9 +83,120 @@ pub enum Request {
         lower_bound: Option<Key>,
         upper_bound: Option<Key>,
     },
+    Handshake(Options),
+    Auth(Credentials),
+    Watch {
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    },
+    Publish {
+        channel: String,
+        payload: Value,
+    },
+    // patterns may contain `*` and `?` wildcards
+    Subscribe {
+        channels: Vec<String>,
+        patterns: Vec<String>,
+    },
+    // streams changes after `after` from a primary, or a full snapshot first when `after`
+    // is None or no longer in its change log
+    Replicate {
+        after: Option<u64>,
+    },
+    ReplicationStatus,
+    // sent between the members of a cluster
+    Raft(RaftMessage),
+    ClusterStatus,
+    // the partition map of a sharded deployment
+    ShardMap,
+    Migration(MigrationCommand),
+    // streams the keys from `lower` up to `upper` and the changes to them to `target`, which
+    // takes them over, see `MigrationCommand`
+    Transfer {
+        lower: Key,
+        upper: Option<Key>,
+        target: String,
+    },
+    // a newer partition map, passed on by the server that took over a range
+    UpdateShardMap(PartitionMap),
+    // a consistent snapshot of every entry, in the format of `backup`
+    Backup,
+    // writes the backup to a file at the path on the server
+    BackupFile(String),
+    // followed by a backup, whose entries are written over those of the server
+    Restore,
+    RestoreFile(String),
+    // followed by entries in the format of `backup`, sorted by key, which are loaded into the
+    // engine in batches
+    Ingest,
+    // answers a paused scan, watch, subscription or replication
+    More,
+    Cancel,
     Unknown,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    Password { user: String, password: String },
+    Token(String),
+}
+
+const PASSWORD: u8 = 0;
+const TOKEN: u8 = 1;
+
+fn into_string(data: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_bounds(
+    mut writer: impl Write,
+    lower_bound: Option<Key>,
+    upper_bound: Option<Key>,
+) -> io::Result<usize> {
+    let lower_key = match lower_bound.as_ref() {
+        Some(key) => key.deref(),
+        None => MIN_KEY,
+    };
+    let upper_key = match upper_bound.as_ref() {
+        Some(key) => key.deref(),
+        None => MAX_KEY,
+    };
+    Ok(writer.write_key(lower_key)? + writer.write_key(upper_key)?)
+}
+
+fn write_names(mut writer: impl Write, names: &[String]) -> io::Result<usize> {
+    writer.write_u16::<BigEndian>(names.len() as u16)?;
+    let mut counter = 2;
+    for name in names {
+        counter += writer.write_key(name.as_bytes())?;
+    }
+    Ok(counter)
+}
+
+fn read_names(mut reader: impl Read) -> io::Result<Vec<String>> {
+    let len = reader.read_u16::<BigEndian>()?;
+    (0..len)
+        .map(|_| into_string(reader.read_key()?))
+        .collect()
+}
+
+fn read_bounds(mut reader: impl Read) -> io::Result<(Option<Key>, Option<Key>)> {
+    let lower_bound = reader.read_key()?;
+    let upper_bound = reader.read_key()?;
+    Ok((
+        if lower_bound.as_slice() == MIN_KEY {
+            None
+        } else {
+            Some(lower_bound.into())
+        },
+        if upper_bound.as_slice() == MAX_KEY {
+            None
+        } else {
+            Some(upper_bound.into())
+        },
+    ))
+}
+
 impl Request {
     pub fn write_to(self, mut writer: impl Write) -> io::Result<usize> {
         let mut counter = 1usize; // for Action
@@ -69,22 +222,107 @@ impl Request {
                 upper_bound,
             } => {
                 writer.write_u8(Action::Scan as u8)?;
-                let lower_key = match lower_bound.as_ref() {
-                    Some(key) => key.deref(),
-                    None => MIN_KEY,
-                };
-                let upper_key = match upper_bound.as_ref() {
-                    Some(key) => key.deref(),
-                    None => MAX_KEY,
-                };
-                counter += writer.write_key(&lower_key)?;
-                counter += writer.write_key(&upper_key)?;
+                counter += write_bounds(&mut writer, lower_bound, upper_bound)?;
+            }
+
+            Request::Watch {
+                lower_bound,
+                upper_bound,
+            } => {
+                writer.write_u8(Action::Watch as u8)?;
+                counter += write_bounds(&mut writer, lower_bound, upper_bound)?;
+            }
+
+            Request::Publish { channel, payload } => {
+                writer.write_u8(Action::Publish as u8)?;
+                counter += writer.write_key(channel.as_bytes())?;
+                counter += writer.write_value(&payload)?;
+            }
+
+            Request::Subscribe { channels, patterns } => {
+                writer.write_u8(Action::Subscribe as u8)?;
+                counter += write_names(&mut writer, &channels)?;
+                counter += write_names(&mut writer, &patterns)?;
+            }
+
+            Request::Replicate { after } => {
+                writer.write_u8(Action::Replicate as u8)?;
+                writer.write_u8(after.is_some() as u8)?;
+                writer.write_u64::<BigEndian>(after.unwrap_or_default())?;
+                counter += 9;
+            }
+
+            Request::Raft(message) => {
+                writer.write_u8(Action::Raft as u8)?;
+                counter += message.write_to(&mut writer)?;
+            }
+
+            Request::Migration(command) => {
+                writer.write_u8(Action::Migration as u8)?;
+                counter += command.write_to(&mut writer)?;
+            }
+
+            Request::Transfer {
+                lower,
+                upper,
+                target,
+            } => {
+                writer.write_u8(Action::Transfer as u8)?;
+                counter += writer.write_key(&lower)?;
+                counter += write_upper(&mut writer, &upper)?;
+                counter += writer.write_key(target.as_bytes())?;
+            }
+
+            Request::UpdateShardMap(map) => {
+                writer.write_u8(Action::UpdateShardMap as u8)?;
+                counter += map.write_to(&mut writer)?;
+            }
+
+            Request::BackupFile(path) => {
+                writer.write_u8(Action::BackupFile as u8)?;
+                counter += writer.write_key(path.as_bytes())?;
+            }
+
+            Request::RestoreFile(path) => {
+                writer.write_u8(Action::RestoreFile as u8)?;
+                counter += writer.write_key(path.as_bytes())?;
+            }
+
+            Request::Handshake(options) => {
+                writer.write_u8(Action::Handshake as u8)?;
+                writer.write_u8(options.into())?;
+                counter += 1;
+            }
+
+            Request::Auth(credentials) => {
+                writer.write_u8(Action::Auth as u8)?;
+                counter += 1;
+                match credentials {
+                    Credentials::Password { user, password } => {
+                        writer.write_u8(PASSWORD)?;
+                        counter += writer.write_key(user.as_bytes())?;
+                        counter += writer.write_value(password.as_bytes())?;
+                    }
+                    Credentials::Token(token) => {
+                        writer.write_u8(TOKEN)?;
+                        counter += writer.write_value(token.as_bytes())?;
+                    }
+                }
             }
 
             Request::Ping => writer.write_u8(Action::Ping as u8)?,
             Request::NoResponse => writer.write_u8(Action::NoResponse as u8)?,
+            Request::ReplicationStatus => writer.write_u8(Action::ReplicationStatus as u8)?,
+            Request::ClusterStatus => writer.write_u8(Action::ClusterStatus as u8)?,
+            Request::ShardMap => writer.write_u8(Action::ShardMap as u8)?,
+            Request::Backup => writer.write_u8(Action::Backup as u8)?,
+            Request::Restore => writer.write_u8(Action::Restore as u8)?,
+            Request::Ingest => writer.write_u8(Action::Ingest as u8)?,
+            Request::More => writer.write_u8(Action::More as u8)?,
+            Request::Cancel => writer.write_u8(Action::Cancel as u8)?,
             Request::Unknown => panic!("cannot send Request::Unknown"),
         }
+        writer.flush()?;
         Ok(counter)
     }
 }
@@ -100,23 +338,72 @@ impl Request {
             Action::Get => Ok(Request::Get(reader.read_key()?.into())),
             Action::Delete => Ok(Request::Delete(reader.read_key()?.into())),
             Action::Scan => {
-                let lower_bound = reader.read_key()?;
-                let upper_bound = reader.read_key()?;
+                let (lower_bound, upper_bound) = read_bounds(&mut reader)?;
                 Ok(Request::Scan {
-                    lower_bound: if lower_bound.as_slice() == MIN_KEY {
-                        None
-                    } else {
-                        Some(lower_bound.into())
-                    },
-                    upper_bound: if upper_bound.as_slice() == MAX_KEY {
-                        None
-                    } else {
-                        Some(upper_bound.into())
-                    },
+                    lower_bound,
+                    upper_bound,
+                })
+            }
+            Action::Watch => {
+                let (lower_bound, upper_bound) = read_bounds(&mut reader)?;
+                Ok(Request::Watch {
+                    lower_bound,
+                    upper_bound,
                 })
             }
+            Action::Publish => Ok(Request::Publish {
+                channel: into_string(reader.read_key()?)?,
+                payload: reader.read_value()?,
+            }),
+            Action::Subscribe => Ok(Request::Subscribe {
+                channels: read_names(&mut reader)?,
+                patterns: read_names(&mut reader)?,
+            }),
+            Action::Replicate => {
+                let resume = reader.read_u8()? != 0;
+                let after = reader.read_u64::<BigEndian>()?;
+                Ok(Request::Replicate {
+                    after: if resume { Some(after) } else { None },
+                })
+            }
+            Action::Raft => Ok(Request::Raft(RaftMessage::read_from(&mut reader)?)),
+            Action::Migration => Ok(Request::Migration(MigrationCommand::read_from(
+                &mut reader,
+            )?)),
+            Action::Transfer => Ok(Request::Transfer {
+                lower: reader.read_key()?.into(),
+                upper: read_upper(&mut reader)?,
+                target: into_string(reader.read_key()?)?,
+            }),
+            Action::UpdateShardMap => PartitionMap::read_from(&mut reader)
+                .map(Request::UpdateShardMap)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+            Action::BackupFile => Ok(Request::BackupFile(into_string(reader.read_key()?)?)),
+            Action::RestoreFile => Ok(Request::RestoreFile(into_string(reader.read_key()?)?)),
+            Action::Handshake => Ok(Request::Handshake(reader.read_u8()?.into())),
+            Action::Auth => match reader.read_u8()? {
+                PASSWORD => Ok(Request::Auth(Credentials::Password {
+                    user: into_string(reader.read_key()?)?,
+                    password: into_string(reader.read_value()?)?,
+                })),
+                TOKEN => Ok(Request::Auth(Credentials::Token(into_string(
+                    reader.read_value()?,
+                )?))),
+                kind => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown credentials kind: {}", kind),
+                )),
+            },
             Action::Ping => Ok(Request::Ping),
             Action::NoResponse => Ok(Request::NoResponse),
+            Action::ReplicationStatus => Ok(Request::ReplicationStatus),
+            Action::ClusterStatus => Ok(Request::ClusterStatus),
+            Action::ShardMap => Ok(Request::ShardMap),
+            Action::Backup => Ok(Request::Backup),
+            Action::Restore => Ok(Request::Restore),
+            Action::Ingest => Ok(Request::Ingest),
+            Action::More => Ok(Request::More),
+            Action::Cancel => Ok(Request::Cancel),
             Action::Unknown => Ok(Request::Unknown),
         }
     }
@@ -124,9 +411,9 @@ impl Request {
 
 #[cfg(test)]
 mod tests {
-    use super::Request;
+    use super::{Credentials, Request};
+    use crate::frame::{Compression, Options};
     use crate::{MAX_KEY, MAX_KEY_LEN, MAX_VALUE_LEN, MIN_KEY};
-    use matches::matches;
     use speculate::speculate;
     use std::io::Cursor;
     use bronzedb_util::status::Result;
@@ -239,6 +526,104 @@ mod tests {
         }
     }
 
+    speculate! {
+        it "handshake" {
+            let options = Options {
+                checksum: true,
+                compression: Compression::Lz4,
+                cancel: true,
+            };
+            let (new_request, bytes) = Request::Handshake(options).transfer_move().unwrap();
+            assert_eq!(2, bytes);
+            assert!(matches!(new_request, Request::Handshake(_)));
+            if let Request::Handshake(new_options) = new_request {
+                assert_eq!(options, new_options);
+            }
+        }
+
+        it "auth" {
+            let credentials = Credentials::Password {
+                user: "admin".into(),
+                password: "secret".into(),
+            };
+            let (new_request, bytes) = Request::Auth(credentials.clone())
+                .transfer_move()
+                .unwrap();
+            assert_eq!(2 + 7 + 8, bytes);
+            assert!(matches!(&new_request, Request::Auth(new_credentials) if *new_credentials == credentials));
+
+            let credentials = Credentials::Token("token".into());
+            let (new_request, bytes) = Request::Auth(credentials.clone())
+                .transfer_move()
+                .unwrap();
+            assert_eq!(2 + 7, bytes);
+            assert!(matches!(&new_request, Request::Auth(new_credentials) if *new_credentials == credentials));
+        }
+
+        it "subscribe" {
+            let channels = vec!["news".to_owned(), "jobs".to_owned()];
+            let patterns = vec!["user:*".to_owned()];
+            let (new_request, bytes) = Request::Subscribe {
+                channels: channels.clone(),
+                patterns: patterns.clone(),
+            }
+            .transfer_move()
+            .unwrap();
+            assert_eq!(1 + (2 + 6 + 6) + (2 + 8), bytes);
+            assert!(matches!(
+                &new_request,
+                Request::Subscribe { channels: new_channels, patterns: new_patterns }
+                    if *new_channels == channels && *new_patterns == patterns
+            ));
+        }
+
+        it "replicate" {
+            for after in [None, Some(0), Some(42)] {
+                let (new_request, bytes) = Request::Replicate { after }.transfer_move().unwrap();
+                assert_eq!(10, bytes);
+                assert!(
+                    matches!(new_request, Request::Replicate { after: new_after } if new_after == after)
+                );
+            }
+        }
+
+        it "transfer" {
+            let request = Request::Transfer {
+                lower: b"m".to_vec().into(),
+                upper: Some(b"p".to_vec().into()),
+                target: "127.0.0.1:8089".to_owned(),
+            };
+            let (new_request, bytes) = request.transfer_move().unwrap();
+            assert_eq!(1 + 3 + 4 + 16, bytes);
+            match new_request {
+                Request::Transfer {
+                    lower,
+                    upper,
+                    target,
+                } => {
+                    assert_eq!(b"m", lower.as_slice());
+                    assert_eq!(b"p", upper.unwrap().as_slice());
+                    assert_eq!("127.0.0.1:8089", target);
+                }
+                _ => panic!("expected a transfer"),
+            }
+        }
+
+        it "backup" {
+            let (new_request, bytes) = Request::Backup.transfer_move().unwrap();
+            assert_eq!(1, bytes);
+            assert!(matches!(new_request, Request::Backup));
+            let (new_request, bytes) = Request::RestoreFile("/tmp/backup".to_owned())
+                .transfer_move()
+                .unwrap();
+            assert_eq!(1 + 2 + 11, bytes);
+            assert!(matches!(new_request, Request::RestoreFile(path) if path == "/tmp/backup"));
+            let (new_request, bytes) = Request::Ingest.transfer_move().unwrap();
+            assert_eq!(1, bytes);
+            assert!(matches!(new_request, Request::Ingest));
+        }
+    }
+
     macro_rules! assert_scan {
         () => {
             let (new_request, bytes) = Request::Scan {
@@ -254,8 +639,8 @@ mod tests {
                 upper_bound,
             } = new_request
             {
-                assert!(matches!(lower_bound, None));
-                assert!(matches!(upper_bound, None));
+                assert!(lower_bound.is_none());
+                assert!(upper_bound.is_none());
             }
         };
 
@@ -296,7 +681,7 @@ mod tests {
                 } = new_request
                 {
                     assert!(matches!(lower_bound, Some(ref _key)));
-                    assert!(matches!(upper_bound, None));
+                    assert!(upper_bound.is_none());
                     assert_eq!(&$any_bound[..], lower_bound.unwrap().as_slice());
                 }
             }
@@ -314,7 +699,7 @@ mod tests {
                     upper_bound,
                 } = new_request
                 {
-                    assert!(matches!(lower_bound, None));
+                    assert!(lower_bound.is_none());
                     assert!(matches!(upper_bound, Some(ref _key)));
                     assert_eq!(&$any_bound[..], upper_bound.unwrap().as_slice());
                 }
@@ -323,25 +708,24 @@ mod tests {
     }
 
     speculate! {
-        use std::u8::MAX;
-
+        
         describe "scan with two bounds" {
             it "normal" {
                 assert_scan!(b"last_name", b"name");
             }
 
             it "max length" {
-                assert_scan!([1; MAX_KEY_LEN], [MAX - 1; MAX_KEY_LEN]);
+                assert_scan!([1; MAX_KEY_LEN], [u8::MAX - 1; MAX_KEY_LEN]);
             }
 
             #[should_panic] // should be (None, None)
             it "max range" {
-                assert_scan!(b"", [MAX; MAX_KEY_LEN]);
+                assert_scan!(b"", [u8::MAX; MAX_KEY_LEN]);
             }
 
             #[should_panic]
             it "overflow" {
-                assert_scan!([0; MAX_KEY_LEN + 1], [MAX; MAX_KEY_LEN + 1]);
+                assert_scan!([0; MAX_KEY_LEN + 1], [u8::MAX; MAX_KEY_LEN + 1]);
             }
         }
 
@@ -361,7 +745,7 @@ mod tests {
 
             #[should_panic] // should be (None, None)
             it "max upper_bound" {
-                assert_scan!([MAX; MAX_KEY_LEN]);
+                assert_scan!([u8::MAX; MAX_KEY_LEN]);
             }
 
             #[should_panic]
@@ -1,15 +1,36 @@
 use super::request::Action::{self, *};
+use crate::backup::{BackupReader, BackupWriter};
 use crate::ext::{ReadKVExt, WriteKVExt};
+use crate::frame::Options;
+use crate::raft::{ClusterStatus, RaftMessage};
+use crate::replication::{ReplicationItem, ReplicationStatus};
+use crate::shard::{MigrationStatus, PartitionMap};
+use crate::SCAN_WINDOW;
 use bronzedb_util::status::StatusCode::{self, *};
 use bronzedb_util::status::{Error, Result};
-use bronzedb_util::types::{Entry, Value};
-use byteorder::{ReadBytesExt, WriteBytesExt};
+use bronzedb_util::types::{Entry, Event, Message, Value};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::io::{Read, Write};
 
+const SET_EVENT: u8 = 0;
+const DELETE_EVENT: u8 = 1;
+
 pub enum Response<'a> {
     Status(StatusCode),
     SingleValue(Value),
     Scanner(Box<dyn Iterator<Item = Result<Entry>> + 'a>),
+    Handshake(Options),
+    // a replica refusing a write, with the address of its primary
+    Redirect(String),
+    Replication(ReplicationStatus),
+    Raft(RaftMessage),
+    Cluster(ClusterStatus),
+    Shards(PartitionMap),
+    Migration(MigrationStatus),
+    // a snapshot in the format of `backup`; an error cuts it short of the trailer
+    Backup(Box<dyn Iterator<Item = Result<Entry>> + 'a>),
+    // the number of entries a backup or a restore went through
+    Count(u64),
 }
 
 impl<'a> Response<'a> {
@@ -31,6 +52,7 @@ impl<'a> Response<'a> {
                         }
                         Err(err) => {
                             writer.write_u8(err.code as u8)?;
+                            writer.flush()?;
                             Err(err)?;
                         }
                     }
@@ -38,27 +60,282 @@ impl<'a> Response<'a> {
                 writer.write_u8(Complete as u8)?;
                 counter += 1;
             }
+            Response::Handshake(options) => {
+                writer.write_u8(OK as u8)?;
+                writer.write_u8(options.into())?;
+                counter += 1;
+            }
+            Response::Redirect(primary) => {
+                writer.write_u8(Redirect as u8)?;
+                counter += writer.write_key(primary.as_bytes())?;
+            }
+            Response::Replication(status) => {
+                writer.write_u8(OK as u8)?;
+                counter += status.write_to(&mut writer)?;
+            }
+            Response::Raft(message) => {
+                writer.write_u8(OK as u8)?;
+                counter += message.write_to(&mut writer)?;
+            }
+            Response::Cluster(status) => {
+                writer.write_u8(OK as u8)?;
+                counter += status.write_to(&mut writer)?;
+            }
+            Response::Shards(map) => {
+                writer.write_u8(OK as u8)?;
+                counter += map.write_to(&mut writer)?;
+            }
+            Response::Migration(status) => {
+                writer.write_u8(OK as u8)?;
+                counter += status.write_to(&mut writer)?;
+            }
+            Response::Backup(iter) => {
+                writer.write_u8(OK as u8)?;
+                let mut backup = BackupWriter::new(&mut writer)?;
+                for result in iter {
+                    let (key, value) = result?;
+                    backup.write(&key, &value)?;
+                }
+                backup.finish()?;
+            }
+            Response::Count(count) => {
+                writer.write_u8(OK as u8)?;
+                writer.write_u64::<BigEndian>(count)?;
+                counter += 8;
+            }
+        }
+        writer.flush()?;
+        Ok(counter)
+    }
+
+    // Writes a scan that waits for `More` or `Cancel` after every `SCAN_WINDOW` entries,
+    // calling `paused` first; a cancelled scan ends with `Complete` at once.
+    pub fn write_cancellable<S: Read + Write>(
+        iter: impl Iterator<Item = Result<Entry>>,
+        mut stream: S,
+        mut paused: impl FnMut(),
+    ) -> Result<usize> {
+        stream.write_u8(OK as u8)?;
+        let mut counter = 2usize; // for OK and Complete
+        let mut sent = 0usize;
+        for result in iter {
+            match result {
+                Ok((key, value)) => {
+                    stream.write_u8(OK as u8)?;
+                    counter += 1 + stream.write_key(&key)? + stream.write_value(&value)?;
+                }
+                Err(err) => {
+                    stream.write_u8(err.code as u8)?;
+                    stream.flush()?;
+                    Err(err)?;
+                }
+            }
+            sent += 1;
+            if sent.is_multiple_of(SCAN_WINDOW) {
+                stream.flush()?;
+                paused();
+                match stream.read_u8()?.into() {
+                    More => (),
+                    Cancel => break,
+                    action => Err(Error::new(
+                        UnknownAction,
+                        format!("unexpected {:?} in a scan", action),
+                    ))?,
+                }
+            }
         }
+        stream.write_u8(Complete as u8)?;
+        stream.flush()?;
         Ok(counter)
     }
 
+    // Writes the events of a watch as they come, see `write_stream`.
+    pub fn write_events<S: Read + Write>(
+        events: impl Iterator<Item = Option<Event>>,
+        stream: S,
+        paused: impl FnMut(),
+    ) -> Result<usize> {
+        write_stream(events, stream, paused, |stream, event| {
+            Ok(match event {
+                Event::Set(key, value) => {
+                    stream.write_u8(SET_EVENT)?;
+                    1 + stream.write_key(&key)? + stream.write_value(&value)?
+                }
+                Event::Delete(key) => {
+                    stream.write_u8(DELETE_EVENT)?;
+                    1 + stream.write_key(&key)?
+                }
+            })
+        })
+    }
+
+    // Writes the messages of a subscription as they come, see `write_stream`.
+    pub fn write_messages<S: Read + Write>(
+        messages: impl Iterator<Item = Option<Message>>,
+        stream: S,
+        paused: impl FnMut(),
+    ) -> Result<usize> {
+        write_stream(messages, stream, paused, |stream, message| {
+            Ok(stream.write_key(message.channel.as_bytes())?
+                + stream.write_value(&message.payload)?)
+        })
+    }
+
+    // Writes a snapshot or the changes of a primary, see `write_stream`. An error aborts the
+    // stream without `Complete`, so a replica cannot take it for a full snapshot.
+    pub fn write_replication<S: Read + Write>(
+        items: impl Iterator<Item = Option<Result<ReplicationItem>>>,
+        stream: S,
+        paused: impl FnMut(),
+    ) -> Result<usize> {
+        write_stream(items, stream, paused, |stream, item| item?.write_to(stream))
+    }
+
+    // Reads the next message of a watch.
+    pub fn read_event(reader: &mut dyn Read) -> Result<Streamed<Event>> {
+        read_streamed(reader, |reader| match reader.read_u8()? {
+            SET_EVENT => Ok(Event::Set(reader.read_key()?.into(), reader.read_value()?)),
+            DELETE_EVENT => Ok(Event::Delete(reader.read_key()?.into())),
+            kind => Err(Error::new(
+                Corruption,
+                format!("unknown event kind: {}", kind),
+            )),
+        })
+    }
+
+    // Reads the next message of a subscription.
+    pub fn read_message(reader: &mut dyn Read) -> Result<Streamed<Message>> {
+        read_streamed(reader, |reader| {
+            let channel = String::from_utf8(reader.read_key()?)
+                .map_err(|err| Error::new(Corruption, err.to_string()))?;
+            Ok(Message {
+                channel,
+                payload: reader.read_value()?,
+            })
+        })
+    }
+
+    // Reads the next message of a replication stream.
+    pub fn read_replication(reader: &mut dyn Read) -> Result<Streamed<ReplicationItem>> {
+        read_streamed(reader, |reader| ReplicationItem::read_from(reader))
+    }
+
+    // Reads the next entry of a scan, `None` once it is complete.
+    pub fn read_entry(reader: &mut dyn Read) -> Result<Option<Entry>> {
+        match reader.read_u8()?.into() {
+            OK => Ok(Some((reader.read_key()?.into(), reader.read_value()?))),
+            Complete => Ok(None),
+            code => Err(Error::new(code, "some error")),
+        }
+    }
+
+    // Reads the entries of a scan whose status has already been read.
+    pub fn entries(reader: &'a mut dyn Read) -> Box<dyn Iterator<Item = Result<Entry>> + 'a> {
+        Box::new(ReaderIter::new(reader))
+    }
+
     pub fn read_from(reader: &'a mut dyn Read, request_action: Action) -> Result<Self> {
         match reader.read_u8()?.into() {
             OK => match request_action {
                 Get => Ok(Response::SingleValue(reader.read_value()?)),
-                Delete | Set | Ping => Ok(Response::Status(OK)),
-                Scan => Ok(Response::Scanner(Box::new(ReaderIter::new(reader)))),
+                Delete | Set | Ping | Auth | Watch | Publish | Subscribe | Replicate | Transfer
+                | UpdateShardMap => Ok(Response::Status(OK)),
+                Action::ReplicationStatus => {
+                    Ok(Response::Replication(ReplicationStatus::read_from(reader)?))
+                }
+                Action::Raft => Ok(Response::Raft(RaftMessage::read_from(reader)?)),
+                Action::ClusterStatus => Ok(Response::Cluster(ClusterStatus::read_from(reader)?)),
+                Action::ShardMap => Ok(Response::Shards(PartitionMap::read_from(reader)?)),
+                Action::Migration => Ok(Response::Migration(MigrationStatus::read_from(reader)?)),
+                Scan => Ok(Response::Scanner(Self::entries(reader))),
+                Backup => Ok(Response::Backup(Box::new(BackupReader::new(reader)?))),
+                BackupFile | Restore | RestoreFile | Ingest => {
+                    Ok(Response::Count(reader.read_u64::<BigEndian>()?))
+                }
+                More | Cancel => Err(Error::new(
+                    UnknownAction,
+                    format!("no response to {:?}", request_action),
+                )),
+                Handshake => Ok(Response::Handshake(reader.read_u8()?.into())),
                 Unknown => Err(Error::new(
                     UnknownAction,
                     format!("unknown action: {:?}", request_action),
                 )),
                 NoResponse => unreachable!(),
             },
+            Redirect => {
+                let primary = String::from_utf8(reader.read_key()?)
+                    .map_err(|err| Error::new(Corruption, err.to_string()))?;
+                Ok(Response::Redirect(primary))
+            }
             code => Ok(Response::Status(code)),
         }
     }
 }
 
+// A message of a stream that goes on until the client cancels it.
+pub enum Streamed<I> {
+    Item(I),
+    // the server waits for `More` or `Cancel`
+    Paused,
+    Complete,
+}
+
+// Writes items as they come. `None` stands for a while without items, after which the
+// server pauses like after `SCAN_WINDOW` items: it sends `Paused` and waits for `More` or
+// `Cancel`, calling `paused` first. Ends with `Complete` once cancelled or when `items`
+// runs out.
+fn write_stream<S: Read + Write, I>(
+    items: impl Iterator<Item = Option<I>>,
+    mut stream: S,
+    mut paused: impl FnMut(),
+    mut write_item: impl FnMut(&mut S, I) -> Result<usize>,
+) -> Result<usize> {
+    stream.write_u8(OK as u8)?;
+    stream.flush()?;
+    let mut counter = 2usize; // for OK and Complete
+    let mut sent = 0usize;
+    for item in items {
+        if let Some(item) = item {
+            stream.write_u8(OK as u8)?;
+            counter += 1 + write_item(&mut stream, item)?;
+            sent += 1;
+            if sent < SCAN_WINDOW {
+                stream.flush()?;
+                continue;
+            }
+        }
+        sent = 0;
+        stream.write_u8(Paused as u8)?;
+        stream.flush()?;
+        counter += 1;
+        paused();
+        match stream.read_u8()?.into() {
+            More => (),
+            Cancel => break,
+            action => Err(Error::new(
+                UnknownAction,
+                format!("unexpected {:?} in a stream", action),
+            ))?,
+        }
+    }
+    stream.write_u8(Complete as u8)?;
+    stream.flush()?;
+    Ok(counter)
+}
+
+fn read_streamed<I>(
+    reader: &mut dyn Read,
+    read_item: impl FnOnce(&mut dyn Read) -> Result<I>,
+) -> Result<Streamed<I>> {
+    match reader.read_u8()?.into() {
+        OK => Ok(Streamed::Item(read_item(reader)?)),
+        Paused => Ok(Streamed::Paused),
+        Complete => Ok(Streamed::Complete),
+        code => Err(Error::new(code, "stream error")),
+    }
+}
+
 struct ReaderIter<'a> {
     reader: &'a mut dyn Read,
     complete: bool,
@@ -81,25 +358,15 @@ impl Iterator for ReaderIter<'_> {
         if self.complete || self.err_occurred {
             return None;
         }
-        match self.read_entry() {
-            Ok(entry) => Some(Ok(entry)),
-            Err(ref err) if err.code == Complete => None,
-            Err(err) => Some(Err(err)),
-        }
-    }
-}
-
-impl ReaderIter<'_> {
-    fn read_entry(&mut self) -> Result<Entry> {
-        match self.reader.read_u8()?.into() {
-            OK => Ok((self.reader.read_key()?.into(), self.reader.read_value()?)),
-            Complete => {
+        match Response::read_entry(self.reader) {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
                 self.complete = true;
-                Err(Error::new(Complete, "complete"))
+                None
             }
-            code => {
+            Err(err) => {
                 self.err_occurred = true;
-                Err(Error::new(code, "some error"))
+                Some(Err(err))
             }
         }
     }
@@ -108,14 +375,15 @@ impl ReaderIter<'_> {
 #[cfg(test)]
 mod tests {
     use super::Response::{self, *};
-    use crate::request::Action::*;
-    use crate::{MAX_KEY_LEN, MAX_VALUE_LEN};
-    use matches::matches;
+    use super::Streamed;
+    use crate::frame::{Compression, Options};
+    use crate::request::Action::{self, *};
+    use crate::{MAX_KEY_LEN, MAX_VALUE_LEN, SCAN_WINDOW};
     use speculate::speculate;
-    use std::io::Cursor;
+    use std::io::{self, Cursor, Read, Write};
     use bronzedb_util::status::StatusCode::{self, *};
     use bronzedb_util::status::{Error, Result};
-    use bronzedb_util::types::Entry;
+    use bronzedb_util::types::{Entry, Event};
 
     macro_rules! transfer_move {
         ($new_resp:ident, $origin_resp:expr, $size:expr, $action:expr) => {
@@ -219,6 +487,26 @@ mod tests {
         }
     }
 
+    speculate! {
+        it "handshake ok" {
+            let options = Options {
+                checksum: true,
+                compression: Compression::Zstd,
+                cancel: true,
+            };
+            transfer_move!(
+                new_resp,
+                Response::Handshake(options),
+                2usize,
+                Action::Handshake
+            );
+            assert!(matches!(new_resp, Response::Handshake(_)));
+            if let Response::Handshake(new_options) = new_resp {
+                assert_eq!(options, new_options);
+            }
+        }
+    }
+
     #[test]
     fn scan_ok() {
         let origin_data: Vec<Entry> = vec![
@@ -266,7 +554,154 @@ mod tests {
                 &iter.next().unwrap().unwrap()
             );
             assert!(matches!(iter.next().unwrap(), Err(_ref)));
-            assert!(matches!(iter.next(), None));
+            assert!(iter.next().is_none());
+        }
+    }
+
+    speculate! {
+        it "backup ok" {
+            let origin_data: Vec<Result<Entry>> = vec![
+                Ok((b"first_name"[..].to_vec().into(), b"Hexi"[..].into())),
+                Ok((b"last_name"[..].to_vec().into(), b"Lee"[..].into())),
+            ];
+            let mut buffer = Vec::new();
+            Response::Backup(Box::new(origin_data.clone().into_iter()))
+                .write_to(&mut buffer)
+                .unwrap();
+            let mut reader = Cursor::new(buffer);
+            let new_resp = Response::read_from(&mut reader, Action::Backup).unwrap();
+            assert!(matches!(new_resp, Response::Backup(_)));
+            if let Response::Backup(iter) = new_resp {
+                let entries = iter.collect::<Result<Vec<Entry>>>().unwrap();
+                assert_eq!(origin_data[1].as_ref().unwrap(), &entries[1]);
+                assert_eq!(2, entries.len());
+            }
+
+            // an error leaves the backup without its trailer
+            let origin_data: Vec<Result<Entry>> = vec![
+                Ok((b"first_name"[..].to_vec().into(), b"Hexi"[..].into())),
+                Err(Error::new(EngineError, "some error")),
+            ];
+            transfer_err!(
+                new_resp,
+                Response::Backup(Box::new(origin_data.into_iter())),
+                Action::Backup
+            );
+            if let Response::Backup(iter) = new_resp {
+                assert!(iter.collect::<Result<Vec<Entry>>>().is_err());
+            }
+
+            transfer_move!(new_resp, Count(42), 9usize, Action::RestoreFile);
+            assert!(matches!(new_resp, Count(42)));
+        }
+    }
+
+    // Client messages are read from `input`, responses go to `output`.
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn cancellable_scan(input: Vec<Action>, len: usize) -> (Vec<Entry>, usize) {
+        let origin_data: Vec<Entry> = (0..len)
+            .map(|i| (i.to_string().into_bytes().into(), b"value"[..].into()))
+            .collect();
+        let mut stream = Duplex {
+            input: Cursor::new(input.into_iter().map(|action| action as u8).collect()),
+            output: Vec::new(),
+        };
+        let mut pauses = 0;
+        Response::write_cancellable(
+            origin_data.iter().map(|entry| Ok(entry.clone())),
+            &mut stream,
+            || pauses += 1,
+        )
+        .unwrap();
+        let mut reader = Cursor::new(stream.output);
+        let entries = match Response::read_from(&mut reader, Scan).unwrap() {
+            Scanner(iter) => iter.map(|ret| ret.unwrap()).collect(),
+            _ => panic!("not a scanner"),
+        };
+        (entries, pauses)
+    }
+
+    speculate! {
+        describe "stream" {
+            it "cancel scan" {
+                let (entries, pauses) = cancellable_scan(vec![More, More], 2 * SCAN_WINDOW + 1);
+                assert_eq!(2 * SCAN_WINDOW + 1, entries.len());
+                assert_eq!(2, pauses);
+
+                let (entries, pauses) = cancellable_scan(vec![Cancel], 2 * SCAN_WINDOW + 1);
+                assert_eq!(SCAN_WINDOW, entries.len());
+                assert_eq!(1, pauses);
+
+                // a scan of exactly one window still waits before `Complete`
+                let (entries, pauses) = cancellable_scan(vec![More], SCAN_WINDOW);
+                assert_eq!(SCAN_WINDOW, entries.len());
+                assert_eq!(1, pauses);
+            }
+
+            it "watch" {
+                let set = Event::Set(b"name"[..].to_vec().into(), b"Hexi"[..].into());
+                let delete = Event::Delete(b"name"[..].to_vec().into());
+                let events = vec![
+                    Some(set.clone()),
+                    None,
+                    Some(delete.clone()),
+                    None,
+                    Some(set),
+                ];
+                let mut stream = Duplex {
+                    input: Cursor::new(vec![More as u8, Cancel as u8]),
+                    output: Vec::new(),
+                };
+                let mut pauses = 0;
+                Response::write_events(events.into_iter(), &mut stream, || pauses += 1).unwrap();
+                assert_eq!(2, pauses);
+
+                let mut reader = Cursor::new(stream.output);
+                assert!(matches!(
+                    Response::read_from(&mut reader, Watch).unwrap(),
+                    Status(StatusCode::OK)
+                ));
+                assert!(matches!(
+                    Response::read_event(&mut reader).unwrap(),
+                    Streamed::Item(Event::Set(..))
+                ));
+                assert!(matches!(
+                    Response::read_event(&mut reader).unwrap(),
+                    Streamed::Paused
+                ));
+                match Response::read_event(&mut reader).unwrap() {
+                    Streamed::Item(event) => assert_eq!(delete, event),
+                    _ => panic!("not an event"),
+                }
+                assert!(matches!(
+                    Response::read_event(&mut reader).unwrap(),
+                    Streamed::Paused
+                ));
+                assert!(matches!(
+                    Response::read_event(&mut reader).unwrap(),
+                    Streamed::Complete
+                ));
+            }
         }
     }
 }
@@ -0,0 +1,484 @@
+use crate::ext::{ReadKVExt, WriteKVExt};
+use crate::MAX_KEY_LEN;
+use bronzedb_util::status::StatusCode::{Corruption, WrongShard};
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::Key;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+
+const START: u8 = 0;
+const THROTTLE: u8 = 1;
+const ABORT: u8 = 2;
+const STATUS: u8 = 3;
+
+// The keys from `lower` up to the `lower` of the next shard, served by the node at `addr`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shard {
+    pub lower: Key,
+    pub addr: String,
+}
+
+// Splits the keys into ranges ordered by their lower keys, the first one starting at the
+// empty key. Every change of the map must raise `version`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionMap {
+    version: u64,
+    shards: Vec<Shard>,
+}
+
+impl PartitionMap {
+    pub fn new(version: u64, mut shards: Vec<Shard>) -> Result<Self> {
+        shards.sort_by(|a, b| a.lower.as_slice().cmp(b.lower.as_slice()));
+        if shards.first().is_none_or(|shard| !shard.lower.is_empty()) {
+            return Err(Error::new(
+                Corruption,
+                "the first shard must start at the empty key",
+            ));
+        }
+        if shards.windows(2).any(|pair| pair[0].lower == pair[1].lower) {
+            return Err(Error::new(
+                Corruption,
+                "shards must not start at the same key",
+            ));
+        }
+        Ok(Self { version, shards })
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn shards(&self) -> &[Shard] {
+        &self.shards
+    }
+
+    // The index of the shard owning `key`.
+    pub fn position(&self, key: &[u8]) -> usize {
+        self.shards
+            .partition_point(|shard| shard.lower.as_slice() <= key)
+            - 1
+    }
+
+    pub fn owner(&self, key: &[u8]) -> &str {
+        &self.shards[self.position(key)].addr
+    }
+
+    // The first and last keys of a shard, like the bounds of a scan.
+    pub fn bounds(&self, index: usize) -> (Option<Key>, Option<Key>) {
+        let lower = &self.shards[index].lower;
+        let lower = if lower.is_empty() {
+            None
+        } else {
+            Some(lower.clone())
+        };
+        let upper = self
+            .shards
+            .get(index + 1)
+            .map(|next| predecessor(&next.lower));
+        (lower, upper)
+    }
+
+    // The next version, with the keys from `lower` up to `upper`, or to the end of their
+    // shard, moved to `addr`. Neighbouring shards of the same server are merged.
+    pub fn split(&self, lower: &[u8], upper: Option<&[u8]>, addr: &str) -> Result<Self> {
+        let index = self.position(lower);
+        let shard = &self.shards[index];
+        let end = self.shards.get(index + 1).map(|next| next.lower.as_slice());
+        if upper.is_some_and(|upper| upper <= lower || end.is_some_and(|end| upper > end)) {
+            return Err(Error::new(WrongShard, "the keys must be in a single shard"));
+        }
+        let mut shards = self.shards[..index].to_vec();
+        if shard.lower.as_slice() < lower {
+            shards.push(shard.clone());
+        }
+        shards.push(Shard {
+            lower: lower.to_vec().into(),
+            addr: addr.to_owned(),
+        });
+        if let Some(upper) = upper.filter(|&upper| Some(upper) != end) {
+            shards.push(Shard {
+                lower: upper.to_vec().into(),
+                addr: shard.addr.clone(),
+            });
+        }
+        shards.extend_from_slice(&self.shards[index + 1..]);
+        shards.dedup_by(|next, previous| next.addr == previous.addr);
+        Self::new(self.version + 1, shards)
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<usize> {
+        writer.write_u64::<BigEndian>(self.version)?;
+        writer.write_u32::<BigEndian>(self.shards.len() as u32)?;
+        let mut counter = 12;
+        for shard in &self.shards {
+            counter += writer.write_key(&shard.lower)?;
+            counter += writer.write_key(shard.addr.as_bytes())?;
+        }
+        Ok(counter)
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let version = reader.read_u64::<BigEndian>()?;
+        let len = reader.read_u32::<BigEndian>()?;
+        let shards = (0..len)
+            .map(|_| {
+                let lower = reader.read_key()?.into();
+                let addr = String::from_utf8(reader.read_key()?)
+                    .map_err(|err| Error::new(Corruption, err.to_string()))?;
+                Ok(Shard { lower, addr })
+            })
+            .collect::<Result<_>>()?;
+        Self::new(version, shards)
+    }
+}
+
+// The greatest key before a non-empty `key`.
+pub fn predecessor(key: &[u8]) -> Key {
+    let mut key = key.to_vec();
+    match key.pop() {
+        Some(0) | None => (),
+        Some(last) => {
+            key.push(last - 1);
+            key.resize(MAX_KEY_LEN.max(key.len()), u8::MAX);
+        }
+    }
+    key.into()
+}
+
+// The least key after `key`, if there is one.
+pub fn successor(key: &[u8]) -> Option<Key> {
+    let mut key = key.to_vec();
+    if key.len() < MAX_KEY_LEN {
+        key.push(0);
+        return Some(key.into());
+    }
+    while let Some(last) = key.pop() {
+        if last < u8::MAX {
+            key.push(last + 1);
+            return Some(key.into());
+        }
+    }
+    None
+}
+
+// The end of a range moved between servers, `None` for the end of its shard.
+pub(crate) fn write_upper(mut writer: impl Write, upper: &Option<Key>) -> io::Result<usize> {
+    writer.write_u8(upper.is_some() as u8)?;
+    let upper = upper.as_ref().map_or(&[][..], |upper| upper.as_slice());
+    Ok(1 + writer.write_key(upper)?)
+}
+
+pub(crate) fn read_upper(mut reader: impl Read) -> io::Result<Option<Key>> {
+    let bounded = reader.read_u8()? != 0;
+    let upper = reader.read_key()?;
+    Ok(if bounded { Some(upper.into()) } else { None })
+}
+
+// Sent to the server that should take over a range of keys.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationCommand {
+    // moves the keys from `lower` up to `upper`, or to the end of their shard, copying at
+    // most `rate` entries a second, or as fast as possible if `rate` is 0
+    Start {
+        lower: Key,
+        upper: Option<Key>,
+        rate: u32,
+    },
+    Throttle(u32),
+    // gives up a migration that has not handed over yet
+    Abort,
+    Status,
+}
+
+impl MigrationCommand {
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<usize> {
+        Ok(1 + match *self {
+            MigrationCommand::Start {
+                ref lower,
+                ref upper,
+                rate,
+            } => {
+                writer.write_u8(START)?;
+                let counter = writer.write_key(lower)? + write_upper(&mut writer, upper)?;
+                writer.write_u32::<BigEndian>(rate)?;
+                counter + 4
+            }
+            MigrationCommand::Throttle(rate) => {
+                writer.write_u8(THROTTLE)?;
+                writer.write_u32::<BigEndian>(rate)?;
+                4
+            }
+            MigrationCommand::Abort => {
+                writer.write_u8(ABORT)?;
+                0
+            }
+            MigrationCommand::Status => {
+                writer.write_u8(STATUS)?;
+                0
+            }
+        })
+    }
+
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        match reader.read_u8()? {
+            START => Ok(MigrationCommand::Start {
+                lower: reader.read_key()?.into(),
+                upper: read_upper(&mut reader)?,
+                rate: reader.read_u32::<BigEndian>()?,
+            }),
+            THROTTLE => Ok(MigrationCommand::Throttle(reader.read_u32::<BigEndian>()?)),
+            ABORT => Ok(MigrationCommand::Abort),
+            STATUS => Ok(MigrationCommand::Status),
+            kind => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown migration command: {}", kind),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum MigrationState {
+    #[default]
+    Idle = 0,
+    Copying = 1,
+    CatchingUp = 2,
+    Done = 3,
+    Failed = 4,
+}
+
+// The last migration of a server: `copied` entries of the range, then `changes` made to it
+// since the copy started.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MigrationStatus {
+    pub state: MigrationState,
+    pub source: String,
+    pub lower: Key,
+    pub upper: Option<Key>,
+    pub copied: u64,
+    pub changes: u64,
+    pub rate: u32,
+    pub error: Option<String>,
+}
+
+impl MigrationStatus {
+    pub fn write_to(&self, mut writer: impl Write) -> Result<usize> {
+        writer.write_u8(self.state as u8)?;
+        let mut counter = 1 + writer.write_key(self.source.as_bytes())?;
+        counter += writer.write_key(&self.lower)?;
+        counter += write_upper(&mut writer, &self.upper)?;
+        writer.write_u64::<BigEndian>(self.copied)?;
+        writer.write_u64::<BigEndian>(self.changes)?;
+        writer.write_u32::<BigEndian>(self.rate)?;
+        let error = self.error.as_ref().map_or("", String::as_str);
+        Ok(counter + 20 + writer.write_value(error.as_bytes())?)
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let state = match reader.read_u8()? {
+            0 => MigrationState::Idle,
+            1 => MigrationState::Copying,
+            2 => MigrationState::CatchingUp,
+            3 => MigrationState::Done,
+            4 => MigrationState::Failed,
+            state => return Err(Error::new(Corruption, format!("unknown state: {}", state))),
+        };
+        let into_string =
+            |data| String::from_utf8(data).map_err(|err| Error::new(Corruption, err.to_string()));
+        let source = into_string(reader.read_key()?)?;
+        let lower = reader.read_key()?.into();
+        let upper = read_upper(&mut reader)?;
+        let copied = reader.read_u64::<BigEndian>()?;
+        let changes = reader.read_u64::<BigEndian>()?;
+        let rate = reader.read_u32::<BigEndian>()?;
+        let error = into_string(reader.read_value()?)?;
+        Ok(Self {
+            state,
+            source,
+            lower,
+            upper,
+            copied,
+            changes,
+            rate,
+            error: if error.is_empty() { None } else { Some(error) },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        predecessor, successor, MigrationCommand, MigrationState, MigrationStatus, PartitionMap,
+        Shard,
+    };
+    use crate::MAX_KEY_LEN;
+    use speculate::speculate;
+    use std::io::Cursor;
+
+    fn map() -> PartitionMap {
+        let shard = |lower: &[u8], addr: &str| Shard {
+            lower: lower.to_vec().into(),
+            addr: addr.to_owned(),
+        };
+        PartitionMap::new(
+            3,
+            vec![shard(b"m", "b"), shard(b"", "a"), shard(b"t\0", "c")],
+        )
+        .unwrap()
+    }
+
+    speculate! {
+        describe "shard" {
+            it "route" {
+                let map = map();
+                assert_eq!("a", map.owner(b""));
+                assert_eq!("a", map.owner(b"lzzz"));
+                assert_eq!("b", map.owner(b"m"));
+                assert_eq!("b", map.owner(b"t"));
+                assert_eq!("c", map.owner(b"t\0"));
+                assert_eq!("c", map.owner(&[u8::MAX; MAX_KEY_LEN]));
+                let (lower, upper) = map.bounds(0);
+                assert!(lower.is_none());
+                assert_eq!(
+                    upper.unwrap().as_slice(),
+                    &[&b"l"[..], &[u8::MAX; MAX_KEY_LEN - 1]].concat()[..]
+                );
+                let (lower, upper) = map.bounds(1);
+                assert_eq!(b"m", lower.unwrap().as_slice());
+                assert_eq!(b"t", upper.unwrap().as_slice());
+                assert!(map.bounds(2).1.is_none());
+            }
+
+            it "neighbours" {
+                assert_eq!(b"a\0", successor(b"a").unwrap().as_slice());
+                let mut key = vec![b'a'; MAX_KEY_LEN];
+                key[MAX_KEY_LEN - 1] = u8::MAX;
+                assert_eq!(
+                    &key[..MAX_KEY_LEN - 2],
+                    &successor(&key).unwrap()[..MAX_KEY_LEN - 2]
+                );
+                assert_eq!(b'b', successor(&key).unwrap()[MAX_KEY_LEN - 2]);
+                assert!(successor(&[u8::MAX; MAX_KEY_LEN]).is_none());
+                assert_eq!(b"a", predecessor(b"a\0").as_slice());
+            }
+
+            it "invalid" {
+                let shard = |lower: &[u8]| Shard {
+                    lower: lower.to_vec().into(),
+                    addr: "a".to_owned(),
+                };
+                assert!(PartitionMap::new(1, vec![]).is_err());
+                assert!(PartitionMap::new(1, vec![shard(b"a")]).is_err());
+                assert!(PartitionMap::new(1, vec![shard(b""), shard(b"a"), shard(b"a")]).is_err());
+            }
+
+            it "split" {
+                let map = map();
+                let owners = |map: &PartitionMap| {
+                    map.shards()
+                        .iter()
+                        .map(|shard| {
+                            (
+                                String::from_utf8(shard.lower.to_vec()).unwrap(),
+                                shard.addr.clone(),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                };
+                let moved = map.split(b"o", Some(b"r"), "d").unwrap();
+                assert_eq!(4, moved.version());
+                assert_eq!(
+                    vec![
+                        ("".to_owned(), "a".to_owned()),
+                        ("m".to_owned(), "b".to_owned()),
+                        ("o".to_owned(), "d".to_owned()),
+                        ("r".to_owned(), "b".to_owned()),
+                        ("t\0".to_owned(), "c".to_owned()),
+                    ],
+                    owners(&moved)
+                );
+                // the rest of the shard, merged with the next one
+                let moved = map.split(b"o", None, "c").unwrap();
+                assert_eq!(
+                    vec![
+                        ("".to_owned(), "a".to_owned()),
+                        ("m".to_owned(), "b".to_owned()),
+                        ("o".to_owned(), "c".to_owned()),
+                    ],
+                    owners(&moved)
+                );
+                let moved = map.split(b"m", Some(b"t\0"), "a").unwrap();
+                assert_eq!(
+                    vec![
+                        ("".to_owned(), "a".to_owned()),
+                        ("t\0".to_owned(), "c".to_owned()),
+                    ],
+                    owners(&moved)
+                );
+                assert!(map.split(b"o", Some(b"u"), "d").is_err());
+                assert!(map.split(b"o", Some(b"n"), "d").is_err());
+            }
+
+            it "migration" {
+                let commands = vec![
+                    MigrationCommand::Start {
+                        lower: b"m"[..].to_vec().into(),
+                        upper: None,
+                        rate: 1000,
+                    },
+                    MigrationCommand::Start {
+                        lower: b"m"[..].to_vec().into(),
+                        upper: Some(b"p"[..].to_vec().into()),
+                        rate: 0,
+                    },
+                    MigrationCommand::Throttle(10),
+                    MigrationCommand::Abort,
+                    MigrationCommand::Status,
+                ];
+                let mut buffer = Vec::new();
+                let mut size = 0;
+                for command in &commands {
+                    size += command.write_to(&mut buffer).unwrap();
+                }
+                assert_eq!(buffer.len(), size);
+                let mut reader = Cursor::new(buffer);
+                for command in commands {
+                    assert_eq!(command, MigrationCommand::read_from(&mut reader).unwrap());
+                }
+
+                let status = MigrationStatus {
+                    state: MigrationState::Failed,
+                    source: "127.0.0.1:8088".to_owned(),
+                    lower: b"m"[..].to_vec().into(),
+                    upper: Some(b"p"[..].to_vec().into()),
+                    copied: 300,
+                    changes: 12,
+                    rate: 1000,
+                    error: Some("migration aborted".to_owned()),
+                };
+                let mut buffer = Vec::new();
+                let size = status.write_to(&mut buffer).unwrap();
+                assert_eq!(buffer.len(), size);
+                assert_eq!(
+                    status,
+                    MigrationStatus::read_from(Cursor::new(buffer)).unwrap()
+                );
+                let idle = MigrationStatus::default();
+                let mut buffer = Vec::new();
+                idle.write_to(&mut buffer).unwrap();
+                assert_eq!(
+                    idle,
+                    MigrationStatus::read_from(Cursor::new(buffer)).unwrap()
+                );
+            }
+
+            it "encode" {
+                let map = map();
+                let mut buffer = Vec::new();
+                let size = map.write_to(&mut buffer).unwrap();
+                assert_eq!(buffer.len(), size);
+                assert_eq!(map, PartitionMap::read_from(Cursor::new(buffer)).unwrap());
+            }
+        }
+    }
+}
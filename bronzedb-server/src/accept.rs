@@ -0,0 +1,96 @@
+use crate::limit::{Limits, Slot, WorkerPool};
+use crate::listener::Listener;
+use crate::shutdown::{Shutdown, Tracked};
+use bronzedb_util::status::Result;
+use log::{info, warn};
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+// How long a listener waits after a failed accept, doubling while accepts keep failing.
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+// Accepts connections until the shutdown handle is triggered, handing each one to `handle`
+// on its own thread or a worker; `reject` turns away clients over the limits. Every protocol
+// of a server is served this way, so they share its limits and shutdown.
+pub(crate) fn serve<L, H, R>(
+    listener: L,
+    limits: Limits,
+    shutdown: &Shutdown,
+    handle: H,
+    reject: R,
+) -> Result<()>
+where
+    L: Listener,
+    H: Fn(L::Stream, String, &Tracked) + Send + Sync + 'static,
+    R: Fn(L::Stream, &str),
+{
+    let handle = Arc::new(handle);
+    let connections = Arc::new(AtomicUsize::new(0));
+    let pool = limits.workers.map(|workers| {
+        let handle = handle.clone();
+        WorkerPool::new(
+            workers,
+            limits.queue_size,
+            move |(stream, addr, _slot, tracked): (L::Stream, String, Slot, Tracked)| {
+                handle(stream, addr, &tracked)
+            },
+        )
+    });
+    shutdown.register(listener.waker()?);
+    let mut backoff = MIN_ACCEPT_BACKOFF;
+    loop {
+        let accepted = listener.accept();
+        if shutdown.is_triggered() {
+            info!("stop accepting connections");
+            break Ok(());
+        }
+        // running out of descriptors or a peer hanging up early should not end the server
+        let (stream, addr) = match accepted {
+            Ok(accepted) => {
+                backoff = MIN_ACCEPT_BACKOFF;
+                accepted
+            }
+            Err(err) => {
+                warn!("accept failed: {}", err);
+                sleep(backoff);
+                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                continue;
+            }
+        };
+        let slot = match Slot::acquire(&connections, limits.max_connections) {
+            Some(slot) => slot,
+            None => {
+                reject(stream, &addr);
+                continue;
+            }
+        };
+        let tracked = match shutdown.track(&stream) {
+            Ok(tracked) => tracked,
+            Err(err) => {
+                warn!("{} from {}", err, addr);
+                continue;
+            }
+        };
+        info!("establish connection from {}", addr);
+        match pool {
+            Some(ref pool) => {
+                if let Err((stream, addr, slot, tracked)) =
+                    pool.submit((stream, addr, slot, tracked))
+                {
+                    drop((slot, tracked));
+                    reject(stream, &addr);
+                }
+            }
+            None => {
+                let handle = handle.clone();
+                spawn(move || {
+                    let _slot = slot;
+                    handle(stream, addr, &tracked)
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use bronzedb_protocol::request::Credentials;
+use bronzedb_protocol::request::Request::{self, *};
+use bronzedb_util::status::Result;
+use bronzedb_util::types::Entry;
+use ring::digest::SHA256_OUTPUT_LEN;
+use ring::pbkdf2::{self, PBKDF2_HMAC_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::num::NonZeroU32;
+use subtle::ConstantTimeEq;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read,
+    Write,
+    Delete,
+    Scan,
+}
+
+// Passwords are stored salted as `iterations$salt$hash` of PBKDF2-HMAC-SHA256, as printed by
+// `hash_password`. Tokens should be long random strings, so they are stored as hex encoded
+// sha256 digests, e.g. `echo -n token | sha256sum`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    #[serde(default)]
+    pub password_pbkdf2: Option<String>,
+    #[serde(default)]
+    pub token_sha256: Option<String>,
+    #[serde(default = "all_permissions")]
+    pub permissions: Vec<Permission>,
+    // An empty list allows every key.
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+}
+
+fn all_permissions() -> Vec<Permission> {
+    vec![
+        Permission::Read,
+        Permission::Write,
+        Permission::Delete,
+        Permission::Scan,
+    ]
+}
+
+const ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    hex(&Sha256::digest(secret.as_bytes()))
+}
+
+// Hashes the password with a new random salt, so equal passwords are stored differently.
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .expect("no random source for the salt");
+    let mut hash = [0; SHA256_OUTPUT_LEN];
+    let iterations = NonZeroU32::new(ITERATIONS).unwrap();
+    pbkdf2::derive(
+        PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.as_bytes(),
+        &mut hash,
+    );
+    format!("{}${}${}", ITERATIONS, hex(&salt), hex(&hash))
+}
+
+// Both checks take the same time whichever byte of the secret differs.
+fn password_matches(stored: &Option<String>, password: &str) -> bool {
+    let verified = stored.as_ref().and_then(|stored| {
+        let mut parts = stored.split('$');
+        let iterations = parts.next()?.parse().ok().and_then(NonZeroU32::new)?;
+        let salt = unhex(parts.next()?)?;
+        let hash = unhex(parts.next()?)?;
+        let verified = pbkdf2::verify(
+            PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            password.as_bytes(),
+            &hash,
+        );
+        Some(verified.is_ok())
+    });
+    verified.unwrap_or(false)
+}
+
+fn token_matches(digest: &Option<String>, token: &str) -> bool {
+    match digest.as_deref().and_then(unhex) {
+        Some(digest) => digest.ct_eq(&Sha256::digest(token.as_bytes())).into(),
+        None => false,
+    }
+}
+
+impl User {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            password_pbkdf2: None,
+            token_sha256: None,
+            permissions: all_permissions(),
+            prefixes: Vec::new(),
+        }
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password_pbkdf2 = Some(hash_password(password));
+        self
+    }
+
+    pub fn token(mut self, token: &str) -> Self {
+        self.token_sha256 = Some(hash_secret(token));
+        self
+    }
+
+    pub fn permissions(mut self, permissions: Vec<Permission>) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    pub fn prefixes(mut self, prefixes: Vec<String>) -> Self {
+        self.prefixes = prefixes;
+        self
+    }
+
+    pub fn verify(&self, credentials: &Credentials) -> bool {
+        match credentials {
+            Credentials::Password { user, password } => {
+                *user == self.name && password_matches(&self.password_pbkdf2, password)
+            }
+            Credentials::Token(token) => token_matches(&self.token_sha256, token),
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    pub fn allows_key(&self, key: &[u8]) -> bool {
+        self.prefixes.is_empty()
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| key.starts_with(prefix.as_bytes()))
+    }
+}
+
+pub fn authenticate<'a>(users: &'a [User], credentials: &Credentials) -> Option<&'a User> {
+    users.iter().find(|user| user.verify(credentials))
+}
+
+// Authentication is disabled when no user is configured.
+pub(crate) fn permitted(
+    users: &[User],
+    session: Option<&User>,
+    permission: Permission,
+    key: Option<&[u8]>,
+) -> bool {
+    if users.is_empty() {
+        return true;
+    }
+    match session {
+        Some(user) => user.allows(permission) && key.is_none_or(|key| user.allows_key(key)),
+        None => false,
+    }
+}
+
+pub(crate) fn authorized(users: &[User], session: Option<&User>, request: &Request) -> bool {
+    match request {
+        Get(key) => permitted(users, session, Permission::Read, Some(key)),
+        Set(key, _) => permitted(users, session, Permission::Write, Some(key)),
+        Delete(key) => permitted(users, session, Permission::Delete, Some(key)),
+        Scan { .. } | Watch { .. } | Replicate { .. } => {
+            permitted(users, session, Permission::Scan, None)
+        }
+        // channels are not keys, so key prefixes do not restrict them
+        Publish { .. } => permitted(users, session, Permission::Write, None),
+        Subscribe { .. } => permitted(users, session, Permission::Read, None),
+        // members of a cluster and shards taking over keys write any key, backups read or
+        // write all of them and may reach the files of the server
+        Raft(_)
+        | Migration(_)
+        | Transfer { .. }
+        | UpdateShardMap(_)
+        | Backup
+        | BackupFile(_)
+        | Restore
+        | RestoreFile(_)
+        | Ingest => {
+            permitted(users, session, Permission::Write, None)
+                && permitted(users, session, Permission::Delete, None)
+                && session.is_none_or(|user| user.prefixes.is_empty())
+        }
+        Ping | NoResponse | Handshake(_) | Auth(_) | ReplicationStatus | ClusterStatus
+        | ShardMap | More | Cancel | Unknown => true,
+    }
+}
+
+pub(crate) fn key_visible(session: Option<&User>, key: &[u8]) -> bool {
+    session.is_none_or(|user| user.allows_key(key))
+}
+
+// Drops the entries a user is not allowed to see.
+pub(crate) fn visible<'a, I>(
+    session: Option<&'a User>,
+    iter: I,
+) -> Box<dyn Iterator<Item = Result<Entry>> + 'a>
+where
+    I: Iterator<Item = Result<Entry>> + 'a,
+{
+    match session {
+        Some(user) if !user.prefixes.is_empty() => {
+            Box::new(iter.filter(move |result| match result {
+                Ok((key, _)) => user.allows_key(key),
+                Err(_) => true,
+            }))
+        }
+        _ => Box::new(iter),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{authenticate, authorized, hash_password, hash_secret, Permission, User};
+    use bronzedb_protocol::request::Credentials;
+    use bronzedb_protocol::request::Request;
+
+    fn users() -> Vec<User> {
+        vec![
+            User::new("admin").password("secret"),
+            User::new("reader")
+                .token("token")
+                .permissions(vec![Permission::Read])
+                .prefixes(vec!["public:".into()]),
+        ]
+    }
+
+    #[test]
+    fn hash() {
+        assert_eq!(
+            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b",
+            hash_secret("secret")
+        );
+        let stored = hash_password("secret");
+        assert_ne!(stored, hash_password("secret"));
+        let user = User {
+            password_pbkdf2: Some(stored),
+            ..User::new("admin")
+        };
+        let password = |password: &str| Credentials::Password {
+            user: "admin".into(),
+            password: password.into(),
+        };
+        assert!(user.verify(&password("secret")));
+        assert!(!user.verify(&password("secreT")));
+    }
+
+    #[test]
+    fn authenticate_credentials() {
+        let users = users();
+        let admin = Credentials::Password {
+            user: "admin".into(),
+            password: "secret".into(),
+        };
+        assert_eq!("admin", authenticate(&users, &admin).unwrap().name);
+        let wrong = Credentials::Password {
+            user: "admin".into(),
+            password: "token".into(),
+        };
+        assert!(authenticate(&users, &wrong).is_none());
+        let token = Credentials::Token("token".into());
+        assert_eq!("reader", authenticate(&users, &token).unwrap().name);
+    }
+
+    #[test]
+    fn authorize_requests() {
+        let users = users();
+        let get = |key: &[u8]| Request::Get(key.to_vec().into());
+        assert!(authorized(&[], None, &get(b"key")));
+        assert!(!authorized(&users, None, &get(b"key")));
+        assert!(authorized(&users, None, &Request::Ping));
+        assert!(authorized(&users, Some(&users[0]), &get(b"key")));
+        assert!(authorized(&users, Some(&users[1]), &get(b"public:key")));
+        assert!(!authorized(&users, Some(&users[1]), &get(b"key")));
+        let set = Request::Set(b"public:key".to_vec().into(), b"value".to_vec());
+        assert!(!authorized(&users, Some(&users[1]), &set));
+        let scan = Request::Scan {
+            lower_bound: None,
+            upper_bound: None,
+        };
+        assert!(!authorized(&users, Some(&users[1]), &scan));
+    }
+}
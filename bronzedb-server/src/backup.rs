@@ -0,0 +1,127 @@
+use crate::shard::{self, Sharding};
+use bronzedb_engine::Engine;
+use bronzedb_protocol::backup::{BackupReader, BackupWriter};
+use bronzedb_util::status::StatusCode::{Corruption, PermissionDenied};
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Entry, Key};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+
+// entries handed to `Engine::ingest` at a time
+const INGEST_BATCH: usize = 1024;
+
+// Places a path sent by a client in the backup directory; paths that could leave it, absolute
+// ones or with `..`, are refused, and so is any path without a backup directory.
+pub(crate) fn resolve(dir: Option<&Path>, path: &str) -> Result<PathBuf> {
+    let dir = dir.ok_or_else(|| Error::new(PermissionDenied, "no backup directory is set"))?;
+    let inside = Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if path.is_empty() || !inside {
+        return Err(Error::new(
+            PermissionDenied,
+            format!("{} is not a path inside the backup directory", path),
+        ));
+    }
+    Ok(dir.join(path))
+}
+
+// Writes the entries to a backup at `path`, through a temporary file so that a backup cut
+// short does not replace an older one.
+pub(crate) fn save(entries: impl Iterator<Item = Result<Entry>>, path: &Path) -> Result<u64> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    match write(entries, &temporary) {
+        Ok(count) => {
+            fs::rename(&temporary, path)?;
+            Ok(count)
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&temporary);
+            Err(err)
+        }
+    }
+}
+
+fn write(entries: impl Iterator<Item = Result<Entry>>, path: &Path) -> Result<u64> {
+    let mut writer = BackupWriter::new(BufWriter::new(File::create(path)?))?;
+    for entry in entries {
+        let (key, value) = entry?;
+        writer.write(&key, &value)?;
+    }
+    Ok(writer.finish()?)
+}
+
+// Opens the backup at `path` once it has been read through, so a damaged file is not
+// restored in part.
+pub(crate) fn open(path: &Path) -> Result<BackupReader<BufReader<File>>> {
+    let reader = BackupReader::new(BufReader::new(File::open(path)?))?;
+    for entry in reader {
+        entry?;
+    }
+    BackupReader::new(BufReader::new(File::open(path)?))
+}
+
+// Writes the entries of a backup over those of the engine and counts them. Keys missing
+// from the backup are kept, and keys served by other shards are skipped.
+// After a failed write the rest of the backup is still read, so a stream stays in step;
+// the outer error is the backup's own, after which it is not.
+pub(crate) fn load<T: Engine>(
+    engine: &mut T,
+    sharding: Option<&Sharding>,
+    entries: impl Iterator<Item = Result<Entry>>,
+) -> Result<Result<u64>> {
+    let mut count = 0;
+    let mut failed: Option<Error> = None;
+    for entry in entries {
+        let (key, value) = entry?;
+        if failed.is_some() {
+            continue;
+        }
+        match shard::write(sharding, key, |key| engine.set(key, value)) {
+            Some(Ok(())) => count += 1,
+            Some(Err(err)) => failed = Some(err.into()),
+            None => (),
+        }
+    }
+    Ok(failed.map_or(Ok(count), Err))
+}
+
+// Loads entries sorted by key into the engine in batches, then flushes it, and counts them.
+// Errors are dealt with as in `load`; keys out of order are an error of the stream.
+pub(crate) fn ingest<T: Engine>(
+    engine: &mut T,
+    sharding: Option<&Sharding>,
+    entries: impl Iterator<Item = Result<Entry>>,
+) -> Result<Result<u64>> {
+    let mut count = 0;
+    let mut failed: Option<Error> = None;
+    let mut last: Option<Key> = None;
+    let mut batch = Vec::with_capacity(INGEST_BATCH);
+    for entry in entries {
+        let (key, value) = entry?;
+        if last.as_ref().is_some_and(|last| key <= *last) {
+            return Err(Error::new(Corruption, "keys are not sorted"));
+        }
+        last = Some(key.clone());
+        if failed.is_some() {
+            continue;
+        }
+        shard::write(sharding, key, |key| batch.push((key, value)));
+        if batch.len() == INGEST_BATCH {
+            count += batch.len() as u64;
+            if let Err(err) = engine.ingest(mem::take(&mut batch)) {
+                failed = Some(err.into());
+            }
+        }
+    }
+    if failed.is_none() {
+        count += batch.len() as u64;
+        let ingested = engine.ingest(batch).and_then(|_| engine.flush());
+        failed = ingested.err().map(Into::into);
+    }
+    Ok(failed.map_or(Ok(count), Err))
+}
@@ -0,0 +1,770 @@
+use crate::accept;
+use crate::auth::{self, Permission, User};
+use crate::limit::Limits;
+use crate::listener::{Listener, Socket};
+use crate::shard::{self, Sharding};
+use crate::shutdown::{Shutdown, Tracked};
+use crate::timeout::{Clock, Timed, Timeouts};
+use base64::alphabet::URL_SAFE;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig, STANDARD};
+use base64::engine::DecodePaddingMode;
+use base64::Engine as _;
+use bronzedb_engine::Engine;
+use bronzedb_protocol::request::Credentials;
+use bronzedb_protocol::{MAX_KEY_LEN, MAX_VALUE_LEN};
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Entry, Key};
+use log::{info, warn};
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::Arc;
+
+const MAX_BODY_LEN: usize = MAX_VALUE_LEN * 2;
+const MAX_LINE_LEN: u64 = 1 << 13;
+const MAX_HEADERS: usize = 100;
+
+// Decodes both the standard and the url safe alphabet, with or without padding.
+const DECODER: GeneralPurpose = GeneralPurpose::new(
+    &URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+// Serves a JSON gateway over HTTP/1.1: `GET/PUT/DELETE /kv/{key}` and `GET /scan` as NDJSON.
+pub struct HttpServer<T: Engine> {
+    engine: T,
+    users: Arc<Vec<User>>,
+    limits: Limits,
+    timeouts: Timeouts,
+    shutdown: Shutdown,
+    sharding: Option<Sharding>,
+}
+
+impl<T: Engine + Clone + Sync + Send + 'static> HttpServer<T> {
+    pub fn new(engine: T) -> Self {
+        Self {
+            engine,
+            users: Arc::new(Vec::new()),
+            limits: Limits::default(),
+            timeouts: Timeouts::default(),
+            shutdown: Shutdown::new(),
+            sharding: None,
+        }
+    }
+
+    pub fn users(mut self, users: Vec<User>) -> Self {
+        self.users = Arc::new(users);
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    // Requests for keys of other shards, or scans over more than this one, fail with 421.
+    pub fn sharding(mut self, sharding: Sharding) -> Self {
+        self.sharding = Some(sharding);
+        self
+    }
+
+    // Returns once the shutdown handle is triggered.
+    pub fn serve<L: Listener>(&mut self, listener: L) -> Result<()> {
+        let engine = self.engine.clone();
+        let users = self.users.clone();
+        let timeouts = self.timeouts;
+        let sharding = self.sharding.clone();
+        let handle = move |stream, addr: String, tracked: &Tracked| {
+            let clock = Clock::new(tracked);
+            let result = Timed::new(stream, timeouts, clock.clone())
+                .map_err(Error::from)
+                .and_then(|stream| {
+                    handle_client(stream, engine.clone(), &users, sharding.as_ref(), &clock)
+                });
+            if let Err(err) = result {
+                warn!("{} from {}", err, addr);
+            }
+            info!("close http connection from {}", addr);
+        };
+        accept::serve(listener, self.limits, &self.shutdown, handle, reject)
+    }
+}
+
+fn reject(mut stream: impl Write, addr: &str) {
+    warn!("server is busy, reject http connection from {}", addr);
+    let body = json!({ "error": "server is busy" }).to_string();
+    let _ = write_head(
+        &mut stream,
+        503,
+        "application/json",
+        Some(body.len()),
+        false,
+    )
+    .and_then(|_| stream.write_all(body.as_bytes()));
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Encoding {
+    Utf8,
+    Base64,
+}
+
+impl Encoding {
+    fn encode(self, data: &[u8]) -> std::result::Result<String, Failure> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(data.to_vec()).map_err(|_| {
+                Failure::new(422, "data is not valid utf-8, retry with encoding=base64")
+            }),
+            Encoding::Base64 => Ok(STANDARD.encode(data)),
+        }
+    }
+
+    fn decode(self, data: &[u8]) -> std::result::Result<Vec<u8>, Failure> {
+        match self {
+            Encoding::Utf8 => Ok(data.to_vec()),
+            Encoding::Base64 => {
+                let normalized: Vec<u8> = data
+                    .iter()
+                    .filter(|c| !c.is_ascii_whitespace())
+                    .map(|c| match c {
+                        b'+' => b'-',
+                        b'/' => b'_',
+                        c => *c,
+                    })
+                    .collect();
+                DECODER
+                    .decode(normalized)
+                    .map_err(|err| Failure::new(400, format!("invalid base64: {}", err)))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Failure {
+    status: u16,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl<E: Into<Error>> From<E> for Failure {
+    fn from(err: E) -> Self {
+        Failure::new(500, err.into().to_string())
+    }
+}
+
+enum Outcome {
+    Json(u16, Json),
+    NoContent,
+    Scan {
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+        limit: usize,
+        encoding: Encoding,
+    },
+}
+
+// A request with its whole body; bodies are small enough to read before answering.
+struct Request {
+    method: String,
+    target: String,
+    http10: bool,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // HTTP/1.0 connections are closed after every response, which also ends its scans.
+    fn keep_alive(&self) -> bool {
+        !self.http10
+            && !self
+                .header("Connection")
+                .is_some_and(|value| value.eq_ignore_ascii_case("close"))
+    }
+}
+
+// Malformed requests are answered before the connection is closed, as the rest of one cannot
+// be told apart from the next request; a failed read just closes it.
+enum ReadError {
+    Io(io::Error),
+    Malformed(Failure),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> Self {
+        ReadError::Io(err)
+    }
+}
+
+fn malformed(status: u16, message: &str) -> ReadError {
+    ReadError::Malformed(Failure::new(status, message))
+}
+
+fn read_line(reader: &mut impl BufRead) -> std::result::Result<Option<String>, ReadError> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return Err(malformed(431, "request line or header is too long"));
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| malformed(400, "request head is not valid utf-8"))
+}
+
+// `None` once the client closed the connection between requests.
+fn read_request<R: Read>(
+    reader: &mut BufReader<R>,
+    writer: &mut impl Write,
+) -> std::result::Result<Option<Request>, ReadError> {
+    let line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(malformed(400, "malformed request line")),
+    };
+    let http10 = match version {
+        "HTTP/1.0" => true,
+        "HTTP/1.1" => false,
+        _ => return Err(malformed(505, "only HTTP/1.0 and HTTP/1.1 are supported")),
+    };
+    let mut request = Request {
+        method: method.to_owned(),
+        target: target.to_owned(),
+        http10,
+        headers: Vec::new(),
+        body: Vec::new(),
+    };
+    loop {
+        let line = read_line(reader)?.ok_or_else(|| malformed(400, "incomplete request"))?;
+        if line.is_empty() {
+            break;
+        }
+        if request.headers.len() == MAX_HEADERS {
+            return Err(malformed(431, "too many headers"));
+        }
+        let (field, value) = line
+            .split_once(':')
+            .ok_or_else(|| malformed(400, "malformed header"))?;
+        request
+            .headers
+            .push((field.trim().to_owned(), value.trim().to_owned()));
+    }
+    let chunked = match request.header("Transfer-Encoding") {
+        None => false,
+        Some(coding) if coding.eq_ignore_ascii_case("chunked") => true,
+        Some(_) => return Err(malformed(501, "unsupported transfer encoding")),
+    };
+    let length = match request.header("Content-Length") {
+        _ if chunked => None,
+        Some(length) => Some(
+            length
+                .parse::<usize>()
+                .map_err(|_| malformed(400, "invalid content length"))?,
+        ),
+        None => None,
+    };
+    if length.is_some_and(|length| length > MAX_BODY_LEN) {
+        return Err(malformed(413, "value is too long"));
+    }
+    if (chunked || length.is_some_and(|length| length > 0))
+        && !request.http10
+        && request
+            .header("Expect")
+            .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+    {
+        writer
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .and_then(|_| writer.flush())?;
+    }
+    request.body = match length {
+        Some(length) => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            body
+        }
+        None if chunked => read_chunked(reader)?,
+        None => Vec::new(),
+    };
+    Ok(Some(request))
+}
+
+fn read_chunked(reader: &mut impl BufRead) -> std::result::Result<Vec<u8>, ReadError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or_else(|| malformed(400, "incomplete body"))?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size =
+            usize::from_str_radix(size, 16).map_err(|_| malformed(400, "invalid chunk size"))?;
+        if size == 0 {
+            // skip the trailers
+            while !read_line(reader)?
+                .ok_or_else(|| malformed(400, "incomplete body"))?
+                .is_empty()
+            {}
+            return Ok(body);
+        }
+        if body.len() + size > MAX_BODY_LEN {
+            return Err(malformed(413, "value is too long"));
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        if !read_line(reader)?.is_some_and(|line| line.is_empty()) {
+            return Err(malformed(400, "malformed chunk"));
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        421 => "Misdirected Request",
+        422 => "Unprocessable Entity",
+        431 => "Request Header Fields Too Large",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Internal Server Error",
+    }
+}
+
+// Without a length the body is chunked, or ends with the connection when it is not kept alive.
+fn write_head(
+    writer: &mut impl Write,
+    status: u16,
+    content_type: &str,
+    length: Option<usize>,
+    keep_alive: bool,
+) -> io::Result<()> {
+    write!(writer, "HTTP/1.1 {} {}\r\n", status, reason(status))?;
+    if status != 204 {
+        write!(writer, "Content-Type: {}\r\n", content_type)?;
+        match length {
+            Some(length) => write!(writer, "Content-Length: {}\r\n", length)?,
+            None if keep_alive => writer.write_all(b"Transfer-Encoding: chunked\r\n")?,
+            None => (),
+        }
+    }
+    if status == 401 {
+        writer.write_all(b"WWW-Authenticate: Basic realm=\"bronzedb\"\r\n")?;
+    }
+    if !keep_alive {
+        writer.write_all(b"Connection: close\r\n")?;
+    }
+    writer.write_all(b"\r\n")
+}
+
+fn write_json(writer: &mut impl Write, status: u16, body: &Json, keep_alive: bool) -> Result<()> {
+    let body = body.to_string();
+    write_head(
+        writer,
+        status,
+        "application/json",
+        Some(body.len()),
+        keep_alive,
+    )?;
+    writer.write_all(body.as_bytes())?;
+    Ok(())
+}
+
+// Frames every write as a chunk.
+struct Chunked<W: Write>(W);
+
+impl<W: Write> Write for Chunked<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            write!(self.0, "{:x}\r\n", buf.len())?;
+            self.0.write_all(buf)?;
+            self.0.write_all(b"\r\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn credentials(request: &Request) -> Option<Credentials> {
+    let value = request.header("Authorization")?;
+    if let Some(token) = value.strip_prefix("Bearer ") {
+        return Some(Credentials::Token(token.trim().to_owned()));
+    }
+    let decoded = STANDARD.decode(value.strip_prefix("Basic ")?.trim()).ok()?;
+    let (user, password) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+    Some(Credentials::Password {
+        user: user.to_owned(),
+        password: password.to_owned(),
+    })
+}
+
+fn session<'a>(
+    users: &'a [User],
+    request: &Request,
+) -> std::result::Result<Option<&'a User>, Failure> {
+    if users.is_empty() {
+        return Ok(None);
+    }
+    match credentials(request).and_then(|credentials| auth::authenticate(users, &credentials)) {
+        Some(user) => Ok(Some(user)),
+        None => Err(Failure::new(401, "authentication required")),
+    }
+}
+
+fn check(
+    users: &[User],
+    session: Option<&User>,
+    permission: Permission,
+    key: Option<&[u8]>,
+) -> std::result::Result<(), Failure> {
+    if auth::permitted(users, session, permission, key) {
+        Ok(())
+    } else {
+        Err(Failure::new(403, "permission denied"))
+    }
+}
+
+fn wrong_shard() -> Failure {
+    Failure::new(421, "the key is served by another shard")
+}
+
+fn key(data: Vec<u8>) -> std::result::Result<Key, Failure> {
+    if data.len() > MAX_KEY_LEN {
+        return Err(Failure::new(400, "key is too long"));
+    }
+    Ok(data.into())
+}
+
+fn handle_client<T: Engine, S: Socket>(
+    stream: Timed<S>,
+    mut engine: T,
+    users: &[User],
+    sharding: Option<&Sharding>,
+    clock: &Clock,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        clock.next(!reader.buffer().is_empty());
+        let request = match read_request(&mut reader, &mut writer) {
+            Ok(Some(request)) => request,
+            Ok(None) => break Ok(()),
+            Err(ReadError::Io(err)) => break Err(err.into()),
+            Err(ReadError::Malformed(failure)) => {
+                write_json(
+                    &mut writer,
+                    failure.status,
+                    &json!({ "error": failure.message }),
+                    false,
+                )?;
+                writer.flush()?;
+                break Ok(());
+            }
+        };
+        let keep_alive = request.keep_alive();
+        handle_request(
+            &request,
+            &mut writer,
+            &mut engine,
+            users,
+            sharding,
+            keep_alive,
+        )?;
+        writer.flush()?;
+        if !keep_alive {
+            break Ok(());
+        }
+    }
+}
+
+fn handle_request<T: Engine>(
+    request: &Request,
+    writer: &mut impl Write,
+    engine: &mut T,
+    users: &[User],
+    sharding: Option<&Sharding>,
+    keep_alive: bool,
+) -> Result<()> {
+    let outcome = session(users, request)
+        .and_then(|session| Ok((session, route(request, engine, users, session, sharding)?)));
+    match outcome {
+        Ok((_, Outcome::Json(status, body))) => write_json(writer, status, &body, keep_alive),
+        Ok((_, Outcome::NoContent)) => Ok(write_head(writer, 204, "", Some(0), keep_alive)?),
+        Ok((
+            session,
+            Outcome::Scan {
+                lower_bound,
+                upper_bound,
+                limit,
+                encoding,
+            },
+        )) => {
+            let mut scanner = match engine.scan(lower_bound, upper_bound) {
+                Ok(scanner) => scanner,
+                Err(err) => {
+                    let failure = Failure::from(err);
+                    let body = json!({ "error": failure.message });
+                    return write_json(writer, failure.status, &body, keep_alive);
+                }
+            };
+            let mut lines = Lines {
+                entries: auth::visible(session, scanner.iter()),
+                encoding,
+                remaining: limit,
+                buf: Vec::new(),
+                pos: 0,
+                done: false,
+            };
+            write_head(writer, 200, "application/x-ndjson", None, keep_alive)?;
+            if keep_alive {
+                io::copy(&mut lines, &mut Chunked(&mut *writer))?;
+                writer.write_all(b"0\r\n\r\n")?;
+            } else {
+                io::copy(&mut lines, writer)?;
+            }
+            Ok(())
+        }
+        Err(failure) => write_json(
+            writer,
+            failure.status,
+            &json!({ "error": failure.message }),
+            keep_alive,
+        ),
+    }
+}
+
+fn route<T: Engine>(
+    request: &Request,
+    engine: &mut T,
+    users: &[User],
+    session: Option<&User>,
+    sharding: Option<&Sharding>,
+) -> std::result::Result<Outcome, Failure> {
+    let (path, query) = request
+        .target
+        .split_once('?')
+        .unwrap_or((&request.target, ""));
+    let params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let encoding = match params.get("encoding").map(String::as_str) {
+        None | Some("utf8") => Encoding::Utf8,
+        Some("base64") => Encoding::Base64,
+        Some(other) => return Err(Failure::new(400, format!("unknown encoding {}", other))),
+    };
+
+    if let Some(raw_key) = path.strip_prefix("/kv/") {
+        let raw_key: Vec<u8> = percent_encoding::percent_decode_str(raw_key).collect();
+        let key = key(encoding.decode(&raw_key)?)?;
+        if sharding.is_some_and(|sharding| !sharding.owns(&key)) {
+            return Err(wrong_shard());
+        }
+        return match request.method.as_str() {
+            "GET" => {
+                check(users, session, Permission::Read, Some(&key))?;
+                match engine.get(key.clone())? {
+                    Some(value) => Ok(Outcome::Json(
+                        200,
+                        json!({
+                            "key": encoding.encode(&key)?,
+                            "value": encoding.encode(&value)?,
+                        }),
+                    )),
+                    None => Err(Failure::new(404, "not found")),
+                }
+            }
+            "PUT" => {
+                check(users, session, Permission::Write, Some(&key))?;
+                let value = encoding.decode(&request.body)?;
+                if value.len() > MAX_VALUE_LEN {
+                    return Err(Failure::new(413, "value is too long"));
+                }
+                shard::write(sharding, key, |key| engine.set(key, value))
+                    .ok_or_else(wrong_shard)??;
+                Ok(Outcome::NoContent)
+            }
+            "DELETE" => {
+                check(users, session, Permission::Delete, Some(&key))?;
+                shard::write(sharding, key, |key| engine.delete(key)).ok_or_else(wrong_shard)??;
+                Ok(Outcome::NoContent)
+            }
+            _ => Err(Failure::new(405, "method not allowed")),
+        };
+    }
+
+    match (request.method.as_str(), path) {
+        ("GET", "/scan") => {
+            check(users, session, Permission::Scan, None)?;
+            let bound = |name: &str| match params.get(name) {
+                Some(bound) if !bound.is_empty() => {
+                    key(encoding.decode(bound.as_bytes())?).map(Some)
+                }
+                _ => Ok(None),
+            };
+            let limit = match params.get("limit") {
+                Some(limit) => limit
+                    .parse()
+                    .map_err(|_| Failure::new(400, "invalid limit"))?,
+                None => usize::MAX,
+            };
+            let (lower_bound, upper_bound) = (bound("lower")?, bound("upper")?);
+            if sharding.is_some_and(|sharding| {
+                !sharding.owns_range(lower_bound.as_ref(), upper_bound.as_ref())
+            }) {
+                return Err(Failure::new(421, "the range spans more than this shard"));
+            }
+            Ok(Outcome::Scan {
+                lower_bound,
+                upper_bound,
+                limit,
+                encoding,
+            })
+        }
+        (_, "/scan") => Err(Failure::new(405, "method not allowed")),
+        _ => Err(Failure::new(404, "no such endpoint")),
+    }
+}
+
+// Streams entries as newline delimited JSON; an error ends the stream with an `error` object.
+struct Lines<'a> {
+    entries: Box<dyn Iterator<Item = Result<Entry>> + 'a>,
+    encoding: Encoding,
+    remaining: usize,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl Lines<'_> {
+    fn next_line(&mut self) -> Option<Json> {
+        if self.done || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let line = match self.entries.next()? {
+            Ok((key, value)) => self
+                .encoding
+                .encode(&key)
+                .and_then(|key| Ok(json!({ "key": key, "value": self.encoding.encode(&value)? }))),
+            Err(err) => Err(err.into()),
+        };
+        Some(line.unwrap_or_else(|failure| {
+            self.done = true;
+            json!({ "error": failure.message })
+        }))
+    }
+}
+
+impl Read for Lines<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            let line = match self.next_line() {
+                Some(line) => line,
+                None => return Ok(0),
+            };
+            self.buf = line.to_string().into_bytes();
+            self.buf.push(b'\n');
+            self.pos = 0;
+        }
+        let len = buf.len().min(self.buf.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, Lines};
+    use bronzedb_util::status::{Error, StatusCode};
+    use std::io::Read;
+
+    #[test]
+    fn base64() {
+        let data = b"\xfb\xff\x00key".to_vec();
+        let encoded = Encoding::Base64.encode(&data).unwrap();
+        assert_eq!("+/8Aa2V5", encoded);
+        assert_eq!(data, Encoding::Base64.decode(encoded.as_bytes()).unwrap());
+        assert_eq!(data, Encoding::Base64.decode(b"-_8Aa2V5\n").unwrap());
+        assert_eq!(b"k".to_vec(), Encoding::Base64.decode(b"aw").unwrap());
+        assert_eq!(400, Encoding::Base64.decode(b"a").unwrap_err().status);
+        assert_eq!(422, Encoding::Utf8.encode(&data).unwrap_err().status);
+    }
+
+    #[test]
+    fn ndjson() {
+        let entries = vec![
+            Ok((b"a".to_vec().into(), b"1".to_vec())),
+            Ok((b"b".to_vec().into(), b"2".to_vec())),
+            Err(Error::new(StatusCode::EngineError, "broken")),
+            Ok((b"c".to_vec().into(), b"3".to_vec())),
+        ];
+        let read = |limit| {
+            let mut lines = Lines {
+                entries: Box::new(entries.clone().into_iter()),
+                encoding: Encoding::Utf8,
+                remaining: limit,
+                buf: Vec::new(),
+                pos: 0,
+                done: false,
+            };
+            let mut output = String::new();
+            lines.read_to_string(&mut output).unwrap();
+            output
+        };
+        assert_eq!("{\"key\":\"a\",\"value\":\"1\"}\n", read(1));
+        assert_eq!(
+            concat!(
+                "{\"key\":\"a\",\"value\":\"1\"}\n",
+                "{\"key\":\"b\",\"value\":\"2\"}\n",
+                "{\"error\":\"EngineError: broken\"}\n",
+            ),
+            read(usize::MAX)
+        );
+    }
+}
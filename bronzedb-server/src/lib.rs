@@ -1,39 +1,249 @@
 use bronzedb_engine::Engine;
+use bronzedb_protocol::backup::BackupReader;
+use bronzedb_protocol::frame::{Compression, Framed, Options};
+use bronzedb_protocol::replication::ReplicationItem;
 use bronzedb_protocol::request::Request::{self, *};
 use bronzedb_protocol::response::Response;
-use bronzedb_util::status::StatusCode::*;
+use bronzedb_util::status::StatusCode::{self, *};
 use bronzedb_util::status::{Error, Result};
-use log::info;
-use std::io::ErrorKind;
-use std::net::{TcpListener, TcpStream};
-use std::thread::spawn;
+use log::{info, warn};
+use shutdown::Tracked;
+use std::io::{ErrorKind, Read, Write};
+use std::iter;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+use timeout::{Clock, Timed};
+
+#[cfg(feature = "tls")]
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+pub use auth::{Permission, User};
+pub use http::HttpServer;
+pub use limit::Limits;
+pub use listener::{Listener, Socket};
+pub use memcached::MemcachedServer;
+pub use pubsub::Broker;
+pub use raft::{Cluster, ClusterConfig, Clustered, Member};
+pub use replication::{ChangeLog, Logged, ReadOnly, Replica, Replication, ReplicationConfig};
+pub use resp::RespServer;
+pub use run::run;
+pub use shard::{ShardConfig, Sharding, ShardingConfig};
+pub use shutdown::Shutdown;
+pub use timeout::Timeouts;
+
+mod accept;
+pub mod auth;
+mod backup;
+pub mod http;
+pub mod limit;
+pub mod listener;
+pub mod memcached;
+pub mod pubsub;
+pub mod raft;
+pub mod replication;
+pub mod resp;
+pub mod run;
+pub mod shard;
+pub mod shutdown;
+pub mod timeout;
+#[cfg(feature = "tls")]
+pub mod tls;
+
+// How long a watch or subscription goes quiet before it pauses, so the client can cancel it.
+const HEARTBEAT: Duration = Duration::from_millis(500);
 
 pub struct Server<T: Engine> {
     engine: T,
+    options: Options,
+    users: Arc<Vec<User>>,
+    limits: Limits,
+    shutdown: Shutdown,
+    timeouts: Timeouts,
+    broker: Broker,
+    replication: Replication,
+    cluster: Option<Cluster>,
+    sharding: Option<Sharding>,
+    backup_dir: Option<PathBuf>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<ServerConfig>>,
 }
 
 impl<T: Engine + Clone + Sync + Send + 'static> Server<T> {
     pub fn new(engine: T) -> Self {
-        Self { engine }
-    }
-
-    pub fn serve(&mut self, listener: TcpListener) -> Result<()> {
-        for stream in listener.incoming() {
-            let stream = stream?;
-            info!("establish connection from {}", stream.peer_addr()?);
-            let engine = self.engine.clone();
-            spawn(move || {
-                let addr = stream.peer_addr().unwrap();
-                handle_client(stream, engine).unwrap();
-                info!("close connection from {}", addr);
-            });
+        Self {
+            engine,
+            options: Options {
+                checksum: true,
+                compression: Compression::Lz4,
+                cancel: true,
+            },
+            users: Arc::new(Vec::new()),
+            limits: Limits::default(),
+            shutdown: Shutdown::new(),
+            timeouts: Timeouts::default(),
+            broker: Broker::new(),
+            replication: Replication::Standalone,
+            cluster: None,
+            sharding: None,
+            backup_dir: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    // Every client must authenticate once any user is configured.
+    pub fn users(mut self, users: Vec<User>) -> Self {
+        self.users = Arc::new(users);
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: Arc<ServerConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    // `BackupFile` and `RestoreFile` take paths relative to `dir`, without `..`; they are
+    // refused unless it is set.
+    pub fn backup_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.backup_dir = Some(dir.into());
+        self
+    }
+
+    // Servers sharing a broker deliver messages to each other's subscribers.
+    pub fn broker(mut self, broker: Broker) -> Self {
+        self.broker = broker;
+        self
+    }
+
+    // A primary streams the changes of its log to replicas, a replica redirects writes.
+    pub fn replication(mut self, replication: Replication) -> Self {
+        self.replication = replication;
+        self
+    }
+
+    // The engine of a cluster member must be wrapped in `Clustered` with the same cluster.
+    pub fn cluster(mut self, cluster: Cluster) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    // Requests for keys of shards at other addresses are refused with `WrongShard`.
+    pub fn sharding(mut self, sharding: Sharding) -> Self {
+        self.sharding = Some(sharding);
+        self
+    }
+
+    // Returns once the shutdown handle is triggered.
+    pub fn serve<L: Listener>(&mut self, listener: L) -> Result<()> {
+        let handler = self.handler::<L::Stream>();
+        accept::serve(
+            listener,
+            self.limits,
+            &self.shutdown,
+            handler,
+            |stream, addr| self.reject(stream, addr),
+        )
+    }
+
+    fn handler<S: Socket>(&self) -> impl Fn(S, String, &Tracked) + Send + Sync {
+        let engine = self.engine.clone();
+        let options = self.options;
+        let timeouts = self.timeouts;
+        let shared = Shared {
+            users: self.users.clone(),
+            broker: self.broker.clone(),
+            replication: self.replication.clone(),
+            cluster: self.cluster.clone(),
+            sharding: self.sharding.clone(),
+            backup_dir: self.backup_dir.clone(),
+        };
+        #[cfg(feature = "tls")]
+        let tls = self.tls.clone();
+        move |stream, addr, tracked: &Tracked| {
+            let engine = engine.clone();
+            let clock = Clock::new(tracked);
+            let result = Timed::new(stream, timeouts, clock.clone())
+                .map_err(Error::from)
+                .and_then(|stream| {
+                    #[cfg(feature = "tls")]
+                    if let Some(ref config) = tls {
+                        return ServerConnection::new(config.clone())
+                            .map_err(|err| Error::new(IOError, format!("tls error: {}", err)))
+                            .and_then(|conn| {
+                                handle_client(
+                                    StreamOwned::new(conn, stream),
+                                    engine,
+                                    options,
+                                    &shared,
+                                    &addr,
+                                    &clock,
+                                )
+                            });
+                    }
+                    handle_client(stream, engine, options, &shared, &addr, &clock)
+                });
+            if let Err(err) = result {
+                warn!("{} from {}", err, addr);
+            }
+            info!("close connection from {}", addr);
+        }
+    }
+
+    // TLS clients are closed without the status, which cannot be sent before a handshake.
+    fn reject(&self, mut stream: impl Write, addr: &str) {
+        warn!("server is busy, reject connection from {}", addr);
+        #[cfg(feature = "tls")]
+        if self.tls.is_some() {
+            return;
         }
-        Ok(())
+        let _ = Response::Status(ServerBusy).write_to(&mut stream);
     }
 }
 
+// State of a server shared by its connections.
+struct Shared {
+    users: Arc<Vec<User>>,
+    broker: Broker,
+    replication: Replication,
+    cluster: Option<Cluster>,
+    sharding: Option<Sharding>,
+    backup_dir: Option<PathBuf>,
+}
+
+// Yields `None` whenever `HEARTBEAT` passes without an item.
+fn heartbeats<I>(receiver: &Receiver<I>) -> impl Iterator<Item = Option<I>> + '_ {
+    iter::from_fn(move || match receiver.recv_timeout(HEARTBEAT) {
+        Ok(item) => Some(Some(item)),
+        Err(RecvTimeoutError::Timeout) => Some(None),
+        Err(RecvTimeoutError::Disconnected) => None,
+    })
+}
+
 fn deal_engine_err<T, E: Into<Error>>(
-    stream_ref: &mut TcpStream,
+    stream_ref: &mut impl Write,
     result: std::result::Result<T, E>,
 ) -> Result<T> {
     match result {
@@ -45,39 +255,443 @@ fn deal_engine_err<T, E: Into<Error>>(
     }
 }
 
-fn handle_client<T: Engine>(mut stream: TcpStream, mut engine: T) -> Result<()> {
+// Writes a cluster member cannot take now are refused without closing the connection.
+fn deal_write_err<E: Into<Error>>(
+    stream_ref: &mut impl Write,
+    result: std::result::Result<(), E>,
+) -> Result<()> {
+    match result.map_err(Into::into) {
+        Ok(()) => Response::Status(OK).write_to(stream_ref).map(drop),
+        Err(err) if err.code == Redirect => Response::Redirect(err.message)
+            .write_to(stream_ref)
+            .map(drop),
+        Err(err) if err.code == Unavailable => {
+            Response::Status(Unavailable).write_to(stream_ref).map(drop)
+        }
+        Err(err) => {
+            Response::Status(EngineError).write_to(stream_ref)?;
+            Err(err)
+        }
+    }
+}
+
+// Reads a cluster member cannot serve now are refused like writes; `None` once answered.
+fn deal_read_err<T, E: Into<Error>>(
+    stream_ref: &mut impl Write,
+    result: std::result::Result<T, E>,
+) -> Result<Option<T>> {
+    match result.map_err(Into::into) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.code == Redirect => Response::Redirect(err.message)
+            .write_to(stream_ref)
+            .map(|_| None),
+        Err(err) if err.code == Unavailable => Response::Status(Unavailable)
+            .write_to(stream_ref)
+            .map(|_| None),
+        Err(err) => {
+            Response::Status(EngineError).write_to(stream_ref)?;
+            Err(err)
+        }
+    }
+}
+
+// Like `deal_write_err`; a replica answers with its primary, as its engine only refuses.
+fn deal_restore_err(
+    stream_ref: &mut impl Write,
+    replication: &Replication,
+    result: Result<u64>,
+) -> Result<()> {
+    match result {
+        Ok(count) => Response::Count(count).write_to(stream_ref).map(drop),
+        Err(err) if err.code == Redirect => {
+            let primary = replication.primary().map_or(err.message, str::to_owned);
+            Response::Redirect(primary).write_to(stream_ref).map(drop)
+        }
+        Err(err) if err.code == Unavailable => {
+            Response::Status(Unavailable).write_to(stream_ref).map(drop)
+        }
+        Err(err) => {
+            Response::Status(EngineError).write_to(stream_ref)?;
+            Err(err)
+        }
+    }
+}
+
+// The payload of a refused restore or ingest is read and dropped first, so the next request
+// on the connection is read from its first byte.
+fn refuse<S: Read + Write>(
+    stream_ref: &mut S,
+    request: &Request,
+    status: StatusCode,
+) -> Result<()> {
+    if let Restore | Ingest = request {
+        let discarded = BackupReader::new(&mut *stream_ref)
+            .and_then(|mut entries| entries.try_for_each(|entry| entry.map(drop)));
+        if let Err(err) = discarded {
+            Response::Status(err.code).write_to(stream_ref)?;
+            return Err(err);
+        }
+    }
+    Response::Status(status).write_to(stream_ref).map(drop)
+}
+
+fn handle_client<T: Engine + Clone + Send + 'static, S: Read + Write>(
+    stream: S,
+    mut engine: T,
+    options: Options,
+    shared: &Shared,
+    addr: &str,
+    clock: &Clock,
+) -> Result<()> {
+    let Shared {
+        users,
+        broker,
+        replication,
+        cluster,
+        sharding,
+        backup_dir,
+    } = shared;
+    let mut stream = Framed::new(stream);
+    let mut session = None;
     loop {
+        clock.idle();
         match Request::read_from(&mut stream) {
+            Ok(ref request) if !auth::authorized(users, session, request) => {
+                refuse(&mut stream, request, PermissionDenied)?;
+            }
+            Ok(ref request) if shard::misrouted(sharding.as_ref(), request) => {
+                refuse(&mut stream, request, WrongShard)?;
+            }
             Ok(request) => match request {
+                Set(..) | Delete(_) if replication.primary().is_some() => {
+                    let primary = replication.primary().unwrap_or_default().to_owned();
+                    Response::Redirect(primary).write_to(&mut stream)?;
+                }
                 Get(key) => {
-                    let value = deal_engine_err(&mut stream, engine.get(key.into()))?;
+                    let value = match deal_read_err(&mut stream, engine.get(key))? {
+                        Some(value) => value,
+                        None => continue,
+                    };
                     match value {
                         Some(data) => Response::SingleValue(data).write_to(&mut stream)?,
                         None => Response::Status(NotFound).write_to(&mut stream)?,
                     };
                 }
                 Set(key, value) => {
-                    deal_engine_err(&mut stream, engine.set(key.into(), value))?;
-                    Response::Status(OK).write_to(&mut stream)?;
+                    let written =
+                        shard::write(sharding.as_ref(), key, |key| engine.set(key, value));
+                    match written {
+                        Some(result) => deal_write_err(&mut stream, result)?,
+                        None => {
+                            Response::Status(WrongShard).write_to(&mut stream)?;
+                        }
+                    }
                 }
-
                 Delete(key) => {
-                    deal_engine_err(&mut stream, engine.delete(key.into()))?;
-                    Response::Status(OK).write_to(&mut stream)?;
+                    let written = shard::write(sharding.as_ref(), key, |key| engine.delete(key));
+                    match written {
+                        Some(result) => deal_write_err(&mut stream, result)?,
+                        None => {
+                            Response::Status(WrongShard).write_to(&mut stream)?;
+                        }
+                    }
                 }
                 Scan {
                     lower_bound,
                     upper_bound,
                 } => {
-                    let mut scanner =
-                        deal_engine_err(&mut stream, engine.scan(lower_bound, upper_bound))?;
-                    Response::Scanner(scanner.iter()).write_to(&mut stream)?;
+                    let scanned = engine.scan(lower_bound, upper_bound);
+                    let mut scanner = match deal_read_err(&mut stream, scanned)? {
+                        Some(scanner) => scanner,
+                        None => continue,
+                    };
+                    let entries = auth::visible(session, scanner.iter());
+                    if stream.options().cancel {
+                        // waiting for the client is not part of the request
+                        Response::write_cancellable(entries, &mut stream, || clock.pause())?;
+                    } else {
+                        Response::Scanner(entries).write_to(&mut stream)?;
+                    }
                 }
 
+                Watch {
+                    lower_bound,
+                    upper_bound,
+                } => {
+                    let watched = engine.watch(lower_bound, upper_bound);
+                    let receiver = match deal_engine_err(&mut stream, watched)? {
+                        Some(receiver) => receiver,
+                        None => {
+                            Response::Status(UnknownAction).write_to(&mut stream)?;
+                            break Err(Error::new(UnknownAction, "engine cannot watch keys"));
+                        }
+                    };
+                    let events = heartbeats(&receiver).filter(|event| {
+                        event
+                            .as_ref()
+                            .is_none_or(|event| auth::key_visible(session, event.key()))
+                    });
+                    Response::write_events(events, &mut stream, || clock.idle())?;
+                }
+
+                Publish { channel, payload } => {
+                    broker.publish(&channel, payload);
+                    Response::Status(OK).write_to(&mut stream)?;
+                }
+
+                Subscribe { channels, patterns } => {
+                    let subscription = broker.subscribe(channels, patterns);
+                    let messages = heartbeats(&subscription.receiver);
+                    Response::write_messages(messages, &mut stream, || clock.idle())?;
+                }
+
+                Replicate { after } => {
+                    let log = match replication {
+                        Replication::Primary(log) => log,
+                        _ => {
+                            Response::Status(UnknownAction).write_to(&mut stream)?;
+                            break Err(Error::new(UnknownAction, "server is not a primary"));
+                        }
+                    };
+                    let _follower = log.follow();
+                    match after.filter(|&after| log.contains(after)) {
+                        Some(after) => {
+                            let changes = log.changes(after).filter(|item| match item {
+                                Some(Ok(ReplicationItem::Change { event, .. })) => {
+                                    auth::key_visible(session, event.key())
+                                }
+                                _ => true,
+                            });
+                            Response::write_replication(changes, &mut stream, || clock.idle())?;
+                        }
+                        None => {
+                            // changes made during the scan are sent again after it
+                            let head = log.head();
+                            let mut scanner =
+                                deal_engine_err(&mut stream, engine.scan(None, None))?;
+                            let entries = auth::visible(session, scanner.iter()).map(|entry| {
+                                Some(entry.map(|(key, value)| ReplicationItem::Entry(key, value)))
+                            });
+                            let items = iter::once(Some(Ok(ReplicationItem::Reset { head })))
+                                .chain(entries);
+                            Response::write_replication(items, &mut stream, || clock.idle())?;
+                        }
+                    }
+                }
+
+                ReplicationStatus => {
+                    Response::Replication(replication.status()).write_to(&mut stream)?;
+                }
+
+                Raft(message) => match cluster {
+                    Some(cluster) => {
+                        match cluster.handle(message) {
+                            Ok(answer) => Response::Raft(answer).write_to(&mut stream)?,
+                            Err(err) => Response::Status(err.code).write_to(&mut stream)?,
+                        };
+                    }
+                    None => {
+                        Response::Status(UnknownAction).write_to(&mut stream)?;
+                        break Err(Error::new(UnknownAction, "server is not in a cluster"));
+                    }
+                },
+
+                ClusterStatus => match cluster {
+                    Some(cluster) => {
+                        Response::Cluster(cluster.status()).write_to(&mut stream)?;
+                    }
+                    None => {
+                        Response::Status(UnknownAction).write_to(&mut stream)?;
+                        break Err(Error::new(UnknownAction, "server is not in a cluster"));
+                    }
+                },
+
+                ShardMap => match sharding {
+                    Some(sharding) => {
+                        Response::Shards(sharding.map()).write_to(&mut stream)?;
+                    }
+                    None => {
+                        Response::Status(UnknownAction).write_to(&mut stream)?;
+                        break Err(Error::new(UnknownAction, "server is not sharded"));
+                    }
+                },
+
+                Migration(command) => match sharding {
+                    Some(sharding) => {
+                        match sharding.migrate(command, engine.clone()) {
+                            Ok(status) => Response::Migration(status).write_to(&mut stream)?,
+                            Err(err) => Response::Status(err.code).write_to(&mut stream)?,
+                        };
+                    }
+                    None => {
+                        Response::Status(UnknownAction).write_to(&mut stream)?;
+                        break Err(Error::new(UnknownAction, "server is not sharded"));
+                    }
+                },
+
+                Transfer {
+                    lower,
+                    upper,
+                    target,
+                } => match sharding {
+                    Some(sharding) => {
+                        sharding.transfer(
+                            &mut engine,
+                            &mut stream,
+                            lower,
+                            upper,
+                            &target,
+                            || clock.pause(),
+                        )?;
+                    }
+                    None => {
+                        Response::Status(UnknownAction).write_to(&mut stream)?;
+                        break Err(Error::new(UnknownAction, "server is not sharded"));
+                    }
+                },
+
+                UpdateShardMap(map) => match sharding {
+                    Some(sharding) => {
+                        let status = match sharding.update(map) {
+                            Ok(_) => OK,
+                            Err(err) => {
+                                warn!("cannot update the partition map: {}", err);
+                                err.code
+                            }
+                        };
+                        Response::Status(status).write_to(&mut stream)?;
+                    }
+                    None => {
+                        Response::Status(UnknownAction).write_to(&mut stream)?;
+                        break Err(Error::new(UnknownAction, "server is not sharded"));
+                    }
+                },
+
+                Backup => {
+                    let snapshot = match deal_read_err(&mut stream, engine.snapshot())? {
+                        Some(snapshot) => snapshot,
+                        None => continue,
+                    };
+                    let mut snapshot = match snapshot {
+                        Some(snapshot) => snapshot,
+                        None => {
+                            Response::Status(UnknownAction).write_to(&mut stream)?;
+                            break Err(Error::new(UnknownAction, "engine cannot take snapshots"));
+                        }
+                    };
+                    Response::Backup(snapshot.iter()).write_to(&mut stream)?;
+                }
+
+                BackupFile(path) => {
+                    let path = match backup::resolve(backup_dir.as_deref(), &path) {
+                        Ok(path) => path,
+                        Err(err) => {
+                            warn!("backup to {} refused: {}", path, err.message);
+                            Response::Status(err.code).write_to(&mut stream)?;
+                            continue;
+                        }
+                    };
+                    let snapshot = match deal_read_err(&mut stream, engine.snapshot())? {
+                        Some(snapshot) => snapshot,
+                        None => continue,
+                    };
+                    let mut snapshot = match snapshot {
+                        Some(snapshot) => snapshot,
+                        None => {
+                            Response::Status(UnknownAction).write_to(&mut stream)?;
+                            break Err(Error::new(UnknownAction, "engine cannot take snapshots"));
+                        }
+                    };
+                    match backup::save(snapshot.iter(), &path) {
+                        Ok(count) => Response::Count(count).write_to(&mut stream)?,
+                        Err(err) => {
+                            warn!("backup to {} failed: {}", path.display(), err.message);
+                            Response::Status(err.code).write_to(&mut stream)?
+                        }
+                    };
+                }
+
+                Restore => {
+                    let entries = match BackupReader::new(&mut stream) {
+                        Ok(entries) => entries,
+                        Err(err) => {
+                            Response::Status(err.code).write_to(&mut stream)?;
+                            break Err(err);
+                        }
+                    };
+                    let loaded = backup::load(&mut engine, sharding.as_ref(), entries);
+                    match loaded {
+                        Ok(restored) => deal_restore_err(&mut stream, replication, restored)?,
+                        // the rest of the backup is still on the connection
+                        Err(err) => {
+                            Response::Status(err.code).write_to(&mut stream)?;
+                            break Err(err);
+                        }
+                    }
+                }
+
+                Ingest => {
+                    let entries = match BackupReader::new(&mut stream) {
+                        Ok(entries) => entries,
+                        Err(err) => {
+                            Response::Status(err.code).write_to(&mut stream)?;
+                            break Err(err);
+                        }
+                    };
+                    let loaded = backup::ingest(&mut engine, sharding.as_ref(), entries);
+                    match loaded {
+                        Ok(ingested) => deal_restore_err(&mut stream, replication, ingested)?,
+                        Err(err) => {
+                            Response::Status(err.code).write_to(&mut stream)?;
+                            break Err(err);
+                        }
+                    }
+                }
+
+                RestoreFile(path) => match backup::resolve(backup_dir.as_deref(), &path)
+                    .and_then(|path| backup::open(&path))
+                {
+                    Ok(entries) => {
+                        let loaded = backup::load(&mut engine, sharding.as_ref(), entries);
+                        match loaded {
+                            Ok(restored) => deal_restore_err(&mut stream, replication, restored)?,
+                            Err(err) => {
+                                Response::Status(err.code).write_to(&mut stream)?;
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        warn!("restore from {} failed: {}", path, err.message);
+                        Response::Status(err.code).write_to(&mut stream)?;
+                    }
+                },
+
+                Handshake(requested) => {
+                    let accepted = options.negotiate(requested);
+                    Response::Handshake(accepted).write_to(&mut stream)?;
+                    stream.set_options(accepted);
+                }
+
+                Auth(credentials) => match auth::authenticate(users, &credentials) {
+                    Some(user) => {
+                        info!("{} authenticated as {}", addr, user.name);
+                        session = Some(user);
+                        Response::Status(OK).write_to(&mut stream)?;
+                    }
+                    None if users.is_empty() => {
+                        Response::Status(OK).write_to(&mut stream)?;
+                    }
+                    None => {
+                        warn!("authentication failed from {}", addr);
+                        Response::Status(PermissionDenied).write_to(&mut stream)?;
+                    }
+                },
+
                 Ping => {
                     Response::Status(OK).write_to(&mut stream)?;
                 }
-                NoResponse => continue,
+                // only meaningful while a scan is paused
+                NoResponse | More | Cancel => continue,
                 Unknown => {
                     Response::Status(UnknownAction).write_to(&mut stream)?;
                     break Err(Error::new(UnknownAction, "unknown action"));
@@ -85,7 +699,15 @@ fn handle_client<T: Engine>(mut stream: TcpStream, mut engine: T) -> Result<()>
             },
 
             Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => break Ok(()), // shutdown
-            Err(err) => break Err(err.into()),
+            Err(err) => {
+                let err: Error = err.into();
+                if err.code == Corruption {
+                    warn!("{} from {}", err, addr);
+                    let _ = Response::Status(Corruption).write_to(&mut stream);
+                    break Ok(());
+                }
+                break Err(err);
+            }
         }
     }
 }
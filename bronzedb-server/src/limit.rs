@@ -0,0 +1,135 @@
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::Builder;
+
+const DEFAULT_QUEUE_SIZE: usize = 128;
+
+// Without `workers` every connection gets its own thread; with it, connections wait in a
+// queue of `queue_size` for a free worker. Clients beyond either limit are rejected with
+// `ServerBusy`.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    pub max_connections: Option<usize>,
+    pub workers: Option<usize>,
+    pub queue_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            workers: None,
+            queue_size: DEFAULT_QUEUE_SIZE,
+        }
+    }
+}
+
+// Holds one of the `max_connections` slots until dropped.
+pub(crate) struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    pub(crate) fn acquire(connections: &Arc<AtomicUsize>, max: Option<usize>) -> Option<Self> {
+        let current = connections.fetch_add(1, Ordering::SeqCst);
+        let slot = Slot(connections.clone());
+        match max {
+            Some(max) if current >= max => None,
+            _ => Some(slot),
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub(crate) struct WorkerPool<T> {
+    sender: SyncSender<T>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    pub(crate) fn new(
+        workers: usize,
+        queue_size: usize,
+        handler: impl Fn(T) + Send + Sync + 'static,
+    ) -> Self {
+        let (sender, receiver) = sync_channel::<T>(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+        for id in 0..workers {
+            let receiver = receiver.clone();
+            let handler = handler.clone();
+            Builder::new()
+                .name(format!("bronzedb-worker-{}", id))
+                .spawn(move || loop {
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => {
+                            if catch_unwind(AssertUnwindSafe(|| handler(job))).is_err() {
+                                warn!("worker {} recovered from a panic", id);
+                            }
+                        }
+                        // the pool is dropped
+                        Err(_) => break,
+                    }
+                })
+                .expect("fail to spawn worker");
+        }
+        Self { sender }
+    }
+
+    // Gives the job back when the queue is full.
+    pub(crate) fn submit(&self, job: T) -> Result<(), T> {
+        self.sender.try_send(job).map_err(|err| match err {
+            TrySendError::Full(job) | TrySendError::Disconnected(job) => job,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Slot, WorkerPool};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, Barrier};
+
+    #[test]
+    fn slots() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let first = Slot::acquire(&connections, Some(2)).unwrap();
+        let _second = Slot::acquire(&connections, Some(2)).unwrap();
+        assert!(Slot::acquire(&connections, Some(2)).is_none());
+        drop(first);
+        assert!(Slot::acquire(&connections, Some(2)).is_some());
+        assert!(Slot::acquire(&connections, None).is_some());
+    }
+
+    #[test]
+    fn bounded_queue() {
+        let barrier = Arc::new(Barrier::new(2));
+        let (sender, receiver) = channel();
+        let pool = {
+            let barrier = barrier.clone();
+            WorkerPool::new(1, 1, move |job: u32| {
+                if job == 0 {
+                    barrier.wait();
+                    barrier.wait();
+                }
+                sender.send(job).unwrap();
+            })
+        };
+        pool.submit(0).unwrap();
+        // the only worker is busy with job 0
+        barrier.wait();
+        pool.submit(1).unwrap();
+        assert_eq!(Err(2), pool.submit(2));
+        barrier.wait();
+        assert_eq!(vec![0, 1], receiver.iter().take(2).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,143 @@
+use std::io::{self, Read, Write};
+use std::net::{self, Ipv4Addr, Ipv6Addr, TcpListener, TcpStream};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+
+pub type Closer = Box<dyn Fn(net::Shutdown) + Send + Sync>;
+pub type Waker = Box<dyn Fn() + Send + Sync>;
+
+// Anything the server can accept client streams from.
+pub trait Listener {
+    type Stream: Socket;
+
+    // Returns the accepted stream and a printable peer address.
+    fn accept(&self) -> io::Result<(Self::Stream, String)>;
+
+    // Returns a handle that unblocks a pending `accept` from another thread.
+    fn waker(&self) -> io::Result<Waker>;
+}
+
+pub trait Socket: Read + Write + Send + 'static {
+    // Returns a handle that shuts the socket down from another thread.
+    fn closer(&self) -> io::Result<Closer>;
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    // Returns another handle to the socket, so reads and writes can be buffered apart.
+    fn try_clone(&self) -> io::Result<Self>
+    where
+        Self: Sized;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn accept(&self) -> io::Result<(TcpStream, String)> {
+        let (stream, addr) = TcpListener::accept(self)?;
+        // responses are written in several small pieces
+        let _ = stream.set_nodelay(true);
+        Ok((stream, addr.to_string()))
+    }
+
+    fn waker(&self) -> io::Result<Waker> {
+        let mut addr = self.local_addr()?;
+        if addr.ip().is_unspecified() {
+            match addr {
+                net::SocketAddr::V4(_) => addr.set_ip(Ipv4Addr::LOCALHOST.into()),
+                net::SocketAddr::V6(_) => addr.set_ip(Ipv6Addr::LOCALHOST.into()),
+            }
+        }
+        Ok(Box::new(move || {
+            let _ = TcpStream::connect(addr);
+        }))
+    }
+}
+
+impl Socket for TcpStream {
+    fn closer(&self) -> io::Result<Closer> {
+        let stream = self.try_clone()?;
+        Ok(Box::new(move |how| {
+            let _ = stream.shutdown(how);
+        }))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+}
+
+// Unix peers are usually unnamed, so they are reported by the listening path.
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    fn accept(&self) -> io::Result<(UnixStream, String)> {
+        let (stream, _) = UnixListener::accept(self)?;
+        let addr = match self.local_addr()?.as_pathname() {
+            Some(path) => format!("unix://{}", path.display()),
+            None => "unix socket".to_owned(),
+        };
+        Ok((stream, addr))
+    }
+
+    fn waker(&self) -> io::Result<Waker> {
+        let path = self.local_addr()?.as_pathname().map(Path::to_path_buf);
+        Ok(Box::new(move || {
+            if let Some(ref path) = path {
+                let _ = UnixStream::connect(path);
+            }
+        }))
+    }
+}
+
+#[cfg(unix)]
+impl Socket for UnixStream {
+    fn closer(&self) -> io::Result<Closer> {
+        let stream = self.try_clone()?;
+        Ok(Box::new(move |how| {
+            let _ = stream.shutdown(how);
+        }))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+}
+
+// Binds a unix socket, replacing the socket file a previous process left behind.
+#[cfg(unix)]
+pub fn bind_unix(path: impl AsRef<Path>) -> io::Result<UnixListener> {
+    let path = path.as_ref();
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+    }
+    UnixListener::bind(path)
+}
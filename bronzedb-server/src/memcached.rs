@@ -0,0 +1,373 @@
+use crate::accept;
+use crate::auth::{self, Permission, User};
+use crate::limit::Limits;
+use crate::listener::{Listener, Socket};
+use crate::shard::{self, Sharding};
+use crate::shutdown::{Shutdown, Tracked};
+use crate::timeout::{Clock, Timed, Timeouts};
+use bronzedb_engine::Engine;
+use bronzedb_protocol::request::Credentials;
+use bronzedb_protocol::MAX_VALUE_LEN;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Key, Value};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::sync::Arc;
+
+mod binary;
+mod text;
+
+const MAX_KEY_LEN: usize = 250;
+// Items with flags are stored as `FLAGGED`, the flags and the data; others as the data alone,
+// so values stay shared with the other protocols.
+const FLAGGED: &[u8; 8] = b"\xff\x00mcitem";
+const FLAGS_LEN: usize = 4;
+
+// Serves the memcached text and binary protocols on top of any engine.
+// Items do not expire, so a store with an expiration time is refused; cas uniques are
+// digests of the stored value. All commands but `get` and `set` need an engine that can
+// compare and set.
+pub struct MemcachedServer<T: Engine> {
+    engine: T,
+    users: Arc<Vec<User>>,
+    limits: Limits,
+    timeouts: Timeouts,
+    shutdown: Shutdown,
+    sharding: Option<Sharding>,
+}
+
+impl<T: Engine + Clone + Sync + Send + 'static> MemcachedServer<T> {
+    pub fn new(engine: T) -> Self {
+        Self {
+            engine,
+            users: Arc::new(Vec::new()),
+            limits: Limits::default(),
+            timeouts: Timeouts::default(),
+            shutdown: Shutdown::new(),
+            sharding: None,
+        }
+    }
+
+    // Only binary connections can authenticate, via SASL PLAIN; with users configured, text
+    // connections are refused once they send their first command.
+    pub fn users(mut self, users: Vec<User>) -> Self {
+        self.users = Arc::new(users);
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    // Commands on keys of other shards fail with `SERVER_ERROR`, or `Not my vbucket` over
+    // the binary protocol.
+    pub fn sharding(mut self, sharding: Sharding) -> Self {
+        self.sharding = Some(sharding);
+        self
+    }
+
+    // Returns once the shutdown handle is triggered.
+    pub fn serve<L: Listener>(&mut self, listener: L) -> Result<()> {
+        let engine = self.engine.clone();
+        let users = self.users.clone();
+        let timeouts = self.timeouts;
+        let sharding = self.sharding.clone();
+        let handle = move |stream, addr: String, tracked: &Tracked| {
+            let clock = Clock::new(tracked);
+            let result = Timed::new(stream, timeouts, clock.clone())
+                .map_err(Error::from)
+                .and_then(|stream| {
+                    let session = Session {
+                        engine: engine.clone(),
+                        users: &users,
+                        user: None,
+                        sharding: sharding.as_ref(),
+                        clock: &clock,
+                    };
+                    handle_client(stream, session)
+                });
+            if let Err(err) = result {
+                warn!("{} from {}", err, addr);
+            }
+            info!("close memcached connection from {}", addr);
+        };
+        accept::serve(listener, self.limits, &self.shutdown, handle, reject)
+    }
+}
+
+// Binary clients read the text error as a malformed response and give up as well.
+fn reject(mut stream: impl Write, addr: &str) {
+    warn!("server is busy, reject memcached connection from {}", addr);
+    let _ = stream.write_all(b"SERVER_ERROR too many open connections\r\n");
+}
+
+fn handle_client<T: Engine, S: Socket>(stream: Timed<S>, mut session: Session<T>) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    session.clock.idle();
+    match reader.fill_buf()?.first() {
+        None => Ok(()),
+        Some(&binary::REQUEST_MAGIC) => binary::serve(&mut reader, &mut writer, &mut session),
+        Some(_) if !session.users.is_empty() => {
+            writer
+                .write_all(b"CLIENT_ERROR authentication required, use the binary protocol\r\n")?;
+            writer.flush().map_err(Into::into)
+        }
+        Some(_) => text::serve(&mut reader, &mut writer, &mut session),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Mode {
+    Set,
+    Add,
+    Replace,
+    Cas(u64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Outcome {
+    // with the cas unique of the stored item
+    Stored(u64),
+    NotStored,
+    Exists,
+    NotFound,
+}
+
+#[derive(Debug)]
+enum Failure {
+    Unauthenticated,
+    Denied,
+    InvalidKey,
+    TooLarge,
+    Expiring,
+    NonNumeric,
+    Unsupported,
+    WrongShard,
+    Engine(Error),
+}
+
+impl<E: Into<Error>> From<E> for Failure {
+    fn from(err: E) -> Self {
+        Failure::Engine(err.into())
+    }
+}
+
+fn cas_unique(value: &[u8]) -> u64 {
+    let digest = Sha256::digest(value);
+    u64::from_be_bytes(digest[..8].try_into().unwrap()).max(1)
+}
+
+struct Item {
+    flags: u32,
+    data: Value,
+    cas: u64,
+}
+
+impl Item {
+    fn decode(value: Value) -> Self {
+        let cas = cas_unique(&value);
+        match value.strip_prefix(&FLAGGED[..]) {
+            Some(rest) if rest.len() >= FLAGS_LEN => Self {
+                flags: u32::from_be_bytes(rest[..FLAGS_LEN].try_into().unwrap()),
+                data: rest[FLAGS_LEN..].to_vec(),
+                cas,
+            },
+            _ => Self {
+                flags: 0,
+                data: value,
+                cas,
+            },
+        }
+    }
+}
+
+// Data that happens to start like a flagged item is stored flagged as well.
+fn encode(flags: u32, data: Value) -> Value {
+    if flags == 0 && !data.starts_with(FLAGGED) {
+        return data;
+    }
+    let mut value = Vec::with_capacity(FLAGGED.len() + FLAGS_LEN + data.len());
+    value.extend_from_slice(FLAGGED);
+    value.extend_from_slice(&flags.to_be_bytes());
+    value.extend_from_slice(&data);
+    value
+}
+
+fn parse_counter(value: &[u8]) -> Option<u64> {
+    std::str::from_utf8(value).ok()?.trim_end().parse().ok()
+}
+
+struct Session<'a, T: Engine> {
+    engine: T,
+    users: &'a [User],
+    user: Option<&'a User>,
+    sharding: Option<&'a Sharding>,
+    clock: &'a Clock,
+}
+
+impl<'a, T: Engine> Session<'a, T> {
+    fn authenticate(&mut self, credentials: Credentials) -> bool {
+        if self.users.is_empty() {
+            return true;
+        }
+        self.user = auth::authenticate(self.users, &credentials);
+        self.user.is_some()
+    }
+
+    fn check(&self, permission: Permission, key: &[u8]) -> std::result::Result<(), Failure> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(Failure::InvalidKey);
+        }
+        match (
+            auth::permitted(self.users, self.user, permission, Some(key)),
+            self.user,
+        ) {
+            (true, _) => (),
+            (false, None) => return Err(Failure::Unauthenticated),
+            (false, Some(_)) => return Err(Failure::Denied),
+        }
+        match self.sharding {
+            Some(sharding) if !sharding.owns(key) => Err(Failure::WrongShard),
+            _ => Ok(()),
+        }
+    }
+
+    // Writes through the partition map, so a handover waits for the write.
+    fn write<R>(
+        &mut self,
+        key: &[u8],
+        write: impl FnOnce(&mut T, Key) -> std::result::Result<R, T::Error>,
+    ) -> std::result::Result<R, Failure> {
+        let engine = &mut self.engine;
+        Ok(
+            shard::write(self.sharding, key.to_vec().into(), |key| write(engine, key))
+                .ok_or(Failure::WrongShard)??,
+        )
+    }
+
+    // Read-modify-write commands swap the value they read, so they retry or fail once another
+    // connection changed it in between.
+    fn swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<Value>,
+        value: Option<Value>,
+    ) -> std::result::Result<bool, Failure> {
+        self.write(key, |engine, key| {
+            engine.compare_and_set(key, expected, value)
+        })?
+        .ok_or(Failure::Unsupported)
+    }
+
+    fn current(&self, key: &[u8]) -> std::result::Result<Option<Value>, Failure> {
+        Ok(self.engine.get(key.to_vec().into())?)
+    }
+
+    fn get(&self, key: &[u8]) -> std::result::Result<Option<Item>, Failure> {
+        self.check(Permission::Read, key)?;
+        Ok(self.engine.get(key.to_vec().into())?.map(Item::decode))
+    }
+
+    fn store(
+        &mut self,
+        mode: Mode,
+        key: &[u8],
+        flags: u32,
+        exptime: i64,
+        data: Value,
+    ) -> std::result::Result<Outcome, Failure> {
+        self.check(Permission::Write, key)?;
+        if exptime != 0 {
+            return Err(Failure::Expiring);
+        }
+        let value = encode(flags, data);
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Failure::TooLarge);
+        }
+        let cas = cas_unique(&value);
+        if mode == Mode::Set {
+            self.write(key, |engine, key| engine.set(key, value))?;
+            return Ok(Outcome::Stored(cas));
+        }
+        if mode == Mode::Add {
+            return match self.swap(key, None, Some(value))? {
+                true => Ok(Outcome::Stored(cas)),
+                false => Ok(Outcome::NotStored),
+            };
+        }
+        loop {
+            let current = match (mode, self.current(key)?) {
+                (Mode::Cas(_), None) => return Ok(Outcome::NotFound),
+                (_, None) => return Ok(Outcome::NotStored),
+                (Mode::Cas(unique), Some(current)) if cas_unique(&current) != unique => {
+                    return Ok(Outcome::Exists)
+                }
+                (_, Some(current)) => current,
+            };
+            if self.swap(key, Some(current), Some(value.clone()))? {
+                return Ok(Outcome::Stored(cas));
+            }
+        }
+    }
+
+    fn delete(&mut self, key: &[u8]) -> std::result::Result<bool, Failure> {
+        self.check(Permission::Delete, key)?;
+        loop {
+            let current = match self.current(key)? {
+                Some(current) => current,
+                None => return Ok(false),
+            };
+            if self.swap(key, Some(current), None)? {
+                return Ok(true);
+            }
+        }
+    }
+
+    // Returns the counter and the cas unique of the stored item, None when the key is
+    // missing and no initial value is given. The flags of the item are kept.
+    fn arithmetic(
+        &mut self,
+        key: &[u8],
+        delta: u64,
+        incr: bool,
+        initial: Option<u64>,
+    ) -> std::result::Result<Option<(u64, u64)>, Failure> {
+        self.check(Permission::Write, key)?;
+        loop {
+            let current = self.current(key)?;
+            let (flags, next) = match current.clone().map(Item::decode) {
+                Some(item) => {
+                    let counter = parse_counter(&item.data).ok_or(Failure::NonNumeric)?;
+                    if incr {
+                        (item.flags, counter.wrapping_add(delta))
+                    } else {
+                        (item.flags, counter.saturating_sub(delta))
+                    }
+                }
+                None => match initial {
+                    Some(initial) => (0, initial),
+                    None => return Ok(None),
+                },
+            };
+            let value = encode(flags, next.to_string().into_bytes());
+            let cas = cas_unique(&value);
+            if self.swap(key, current, Some(value))? {
+                return Ok(Some((next, cas)));
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use super::{Failure, Mode, Outcome, Session};
+use bronzedb_engine::Engine;
+use bronzedb_protocol::request::Credentials;
+use bronzedb_protocol::MAX_VALUE_LEN;
+use bronzedb_util::status::Result;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+pub(super) const REQUEST_MAGIC: u8 = 0x80;
+const RESPONSE_MAGIC: u8 = 0x81;
+const HEADER_LEN: usize = 24;
+const MAX_BODY_LEN: usize = MAX_VALUE_LEN + 1024;
+
+const GET: u8 = 0x00;
+const SET: u8 = 0x01;
+const ADD: u8 = 0x02;
+const REPLACE: u8 = 0x03;
+const DELETE: u8 = 0x04;
+const INCREMENT: u8 = 0x05;
+const DECREMENT: u8 = 0x06;
+const QUIT: u8 = 0x07;
+const GETQ: u8 = 0x09;
+const NOOP: u8 = 0x0a;
+const VERSION: u8 = 0x0b;
+const GETK: u8 = 0x0c;
+const GETKQ: u8 = 0x0d;
+const SETQ: u8 = 0x11;
+const ADDQ: u8 = 0x12;
+const REPLACEQ: u8 = 0x13;
+const DELETEQ: u8 = 0x14;
+const INCREMENTQ: u8 = 0x15;
+const DECREMENTQ: u8 = 0x16;
+const QUITQ: u8 = 0x17;
+const SASL_LIST_MECHS: u8 = 0x20;
+const SASL_AUTH: u8 = 0x21;
+
+const KEY_NOT_FOUND: u16 = 0x0001;
+const KEY_EXISTS: u16 = 0x0002;
+const VALUE_TOO_LARGE: u16 = 0x0003;
+const INVALID_ARGUMENTS: u16 = 0x0004;
+const NON_NUMERIC: u16 = 0x0006;
+const NOT_MY_VBUCKET: u16 = 0x0007;
+const AUTH_ERROR: u16 = 0x0020;
+const UNKNOWN_COMMAND: u16 = 0x0081;
+const NOT_SUPPORTED: u16 = 0x0083;
+const INTERNAL_ERROR: u16 = 0x0084;
+
+// an incr/decr expiration of all ones means "do not create a missing counter"
+const NO_CREATE: u32 = 0xffff_ffff;
+
+struct Header {
+    opcode: u8,
+    key_len: usize,
+    extras_len: usize,
+    body_len: usize,
+    opaque: u32,
+    cas: u64,
+}
+
+#[derive(Default)]
+struct Reply<'a> {
+    status: u16,
+    cas: u64,
+    extras: &'a [u8],
+    key: &'a [u8],
+    value: &'a [u8],
+}
+
+impl Reply<'_> {
+    fn status(status: u16, message: &'static str) -> Reply<'static> {
+        Reply {
+            status,
+            value: message.as_bytes(),
+            ..Reply::default()
+        }
+    }
+}
+
+fn failure_reply(failure: &Failure) -> Reply<'static> {
+    match failure {
+        Failure::Unauthenticated | Failure::Denied => Reply::status(AUTH_ERROR, "Auth failure."),
+        Failure::InvalidKey => Reply::status(INVALID_ARGUMENTS, "Invalid arguments"),
+        Failure::TooLarge => Reply::status(VALUE_TOO_LARGE, "Too large."),
+        Failure::Expiring => Reply::status(NOT_SUPPORTED, "Items do not expire"),
+        Failure::NonNumeric => Reply::status(
+            NON_NUMERIC,
+            "Non-numeric server-side value for incr or decr",
+        ),
+        Failure::Unsupported => Reply::status(NOT_SUPPORTED, "Not supported"),
+        Failure::WrongShard => Reply::status(NOT_MY_VBUCKET, "Not my vbucket"),
+        Failure::Engine(_) => Reply::status(INTERNAL_ERROR, "Internal error"),
+    }
+}
+
+fn read_header(reader: &mut impl BufRead) -> io::Result<Option<Header>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut header = [0; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    let mut header = &header[..];
+    if header.read_u8()? != REQUEST_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid request magic",
+        ));
+    }
+    let opcode = header.read_u8()?;
+    let key_len = header.read_u16::<BigEndian>()? as usize;
+    let extras_len = header.read_u8()? as usize;
+    let _data_type = header.read_u8()?;
+    let _vbucket = header.read_u16::<BigEndian>()?;
+    let body_len = header.read_u32::<BigEndian>()? as usize;
+    let opaque = header.read_u32::<BigEndian>()?;
+    let cas = header.read_u64::<BigEndian>()?;
+    Ok(Some(Header {
+        opcode,
+        key_len,
+        extras_len,
+        body_len,
+        opaque,
+        cas,
+    }))
+}
+
+fn write_reply(writer: &mut impl Write, header: &Header, reply: &Reply) -> io::Result<()> {
+    writer.write_u8(RESPONSE_MAGIC)?;
+    writer.write_u8(header.opcode)?;
+    writer.write_u16::<BigEndian>(reply.key.len() as u16)?;
+    writer.write_u8(reply.extras.len() as u8)?;
+    writer.write_u8(0)?;
+    writer.write_u16::<BigEndian>(reply.status)?;
+    writer.write_u32::<BigEndian>(
+        (reply.extras.len() + reply.key.len() + reply.value.len()) as u32,
+    )?;
+    writer.write_u32::<BigEndian>(header.opaque)?;
+    writer.write_u64::<BigEndian>(reply.cas)?;
+    writer.write_all(reply.extras)?;
+    writer.write_all(reply.key)?;
+    writer.write_all(reply.value)
+}
+
+pub(super) fn serve<T: Engine>(
+    reader: &mut BufReader<impl Read>,
+    writer: &mut impl Write,
+    session: &mut Session<T>,
+) -> Result<()> {
+    loop {
+        session.clock.next(!reader.buffer().is_empty());
+        let header = match read_header(reader)? {
+            Some(header) => header,
+            None => break Ok(()),
+        };
+        if header.body_len > MAX_BODY_LEN {
+            io::copy(&mut reader.take(header.body_len as u64), &mut io::sink())?;
+            write_reply(writer, &header, &failure_reply(&Failure::TooLarge))?;
+            writer.flush()?;
+            continue;
+        }
+        let mut body = vec![0; header.body_len];
+        reader.read_exact(&mut body)?;
+        if header.extras_len + header.key_len > body.len() {
+            write_reply(
+                writer,
+                &header,
+                &Reply::status(INVALID_ARGUMENTS, "Invalid arguments"),
+            )?;
+            writer.flush()?;
+            break Ok(());
+        }
+        let (extras, rest) = body.split_at(header.extras_len);
+        let (key, value) = rest.split_at(header.key_len);
+        let quit = execute(writer, session, &header, extras, key, value)?;
+        if quit || reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+        if quit {
+            break Ok(());
+        }
+    }
+}
+
+// Returns true when the connection should be closed.
+fn execute<T: Engine>(
+    writer: &mut impl Write,
+    session: &mut Session<T>,
+    header: &Header,
+    extras: &[u8],
+    key: &[u8],
+    value: &[u8],
+) -> io::Result<bool> {
+    let quiet = matches!(
+        header.opcode,
+        GETQ | GETKQ | SETQ | ADDQ | REPLACEQ | DELETEQ | INCREMENTQ | DECREMENTQ | QUITQ
+    );
+    let respond = |writer: &mut _, reply: &Reply| write_reply(writer, header, reply);
+    match header.opcode {
+        GET | GETQ | GETK | GETKQ => {
+            let with_key = matches!(header.opcode, GETK | GETKQ);
+            let response_key = if with_key { key } else { &[][..] };
+            match session.get(key) {
+                Ok(Some(item)) => respond(
+                    writer,
+                    &Reply {
+                        cas: item.cas,
+                        extras: &item.flags.to_be_bytes(),
+                        key: response_key,
+                        value: &item.data,
+                        ..Reply::default()
+                    },
+                )?,
+                Ok(None) if quiet => (),
+                Ok(None) => respond(
+                    writer,
+                    &Reply {
+                        key: response_key,
+                        ..Reply::status(KEY_NOT_FOUND, "Not found")
+                    },
+                )?,
+                Err(failure) => respond(writer, &failure_reply(&failure))?,
+            }
+        }
+        SET | SETQ | ADD | ADDQ | REPLACE | REPLACEQ => {
+            if extras.len() != 8 {
+                respond(
+                    writer,
+                    &Reply::status(INVALID_ARGUMENTS, "Invalid arguments"),
+                )?;
+                return Ok(false);
+            }
+            let mode = match header.opcode {
+                ADD | ADDQ => Mode::Add,
+                _ if header.cas != 0 => Mode::Cas(header.cas),
+                REPLACE | REPLACEQ => Mode::Replace,
+                _ => Mode::Set,
+            };
+            let mut extras = extras;
+            let flags = extras.read_u32::<BigEndian>()?;
+            let exptime = extras.read_u32::<BigEndian>()?;
+            let stored = session.store(mode, key, flags, exptime.into(), value.to_vec());
+            let reply = match stored {
+                Ok(Outcome::Stored(_)) if quiet => return Ok(false),
+                Ok(Outcome::Stored(cas)) => Reply {
+                    cas,
+                    ..Reply::default()
+                },
+                Ok(Outcome::NotStored) if mode == Mode::Add => {
+                    Reply::status(KEY_EXISTS, "Data exists for key.")
+                }
+                Ok(Outcome::Exists) => Reply::status(KEY_EXISTS, "Data exists for key."),
+                Ok(Outcome::NotStored) | Ok(Outcome::NotFound) => {
+                    Reply::status(KEY_NOT_FOUND, "Not found")
+                }
+                Err(failure) => failure_reply(&failure),
+            };
+            respond(writer, &reply)?;
+        }
+        DELETE | DELETEQ => match session.delete(key) {
+            Ok(true) if quiet => (),
+            Ok(true) => respond(writer, &Reply::default())?,
+            Ok(false) => respond(writer, &Reply::status(KEY_NOT_FOUND, "Not found"))?,
+            Err(failure) => respond(writer, &failure_reply(&failure))?,
+        },
+        INCREMENT | INCREMENTQ | DECREMENT | DECREMENTQ => {
+            if extras.len() != 20 {
+                respond(
+                    writer,
+                    &Reply::status(INVALID_ARGUMENTS, "Invalid arguments"),
+                )?;
+                return Ok(false);
+            }
+            let mut extras = extras;
+            let delta = extras.read_u64::<BigEndian>()?;
+            let initial = extras.read_u64::<BigEndian>()?;
+            let initial = match extras.read_u32::<BigEndian>()? {
+                NO_CREATE => None,
+                0 => Some(initial),
+                _ => {
+                    respond(writer, &failure_reply(&Failure::Expiring))?;
+                    return Ok(false);
+                }
+            };
+            let incr = matches!(header.opcode, INCREMENT | INCREMENTQ);
+            match session.arithmetic(key, delta, incr, initial) {
+                Ok(Some(_)) if quiet => (),
+                Ok(Some((counter, cas))) => respond(
+                    writer,
+                    &Reply {
+                        cas,
+                        value: &counter.to_be_bytes(),
+                        ..Reply::default()
+                    },
+                )?,
+                Ok(None) => respond(writer, &Reply::status(KEY_NOT_FOUND, "Not found"))?,
+                Err(failure) => respond(writer, &failure_reply(&failure))?,
+            }
+        }
+        QUIT | QUITQ => {
+            if !quiet {
+                respond(writer, &Reply::default())?;
+            }
+            return Ok(true);
+        }
+        NOOP => respond(writer, &Reply::default())?,
+        VERSION => respond(
+            writer,
+            &Reply {
+                value: env!("CARGO_PKG_VERSION").as_bytes(),
+                ..Reply::default()
+            },
+        )?,
+        SASL_LIST_MECHS => respond(
+            writer,
+            &Reply {
+                value: b"PLAIN",
+                ..Reply::default()
+            },
+        )?,
+        SASL_AUTH => {
+            // PLAIN: [authzid] NUL authcid NUL passwd
+            let mut fields = value.split(|c| *c == 0).rev();
+            let credentials = match (key, fields.next(), fields.next()) {
+                (b"PLAIN", Some(password), Some(user)) => Some(Credentials::Password {
+                    user: String::from_utf8_lossy(user).into_owned(),
+                    password: String::from_utf8_lossy(password).into_owned(),
+                }),
+                _ => None,
+            };
+            let authenticated = match credentials {
+                Some(credentials) => session.authenticate(credentials),
+                None => false,
+            };
+            if authenticated {
+                respond(
+                    writer,
+                    &Reply {
+                        value: b"Authenticated",
+                        ..Reply::default()
+                    },
+                )?
+            } else {
+                respond(writer, &Reply::status(AUTH_ERROR, "Auth failure."))?
+            }
+        }
+        _ => respond(writer, &Reply::status(UNKNOWN_COMMAND, "Unknown command"))?,
+    }
+    Ok(false)
+}
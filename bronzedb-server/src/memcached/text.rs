@@ -0,0 +1,202 @@
+use super::{Failure, Mode, Outcome, Session};
+use bronzedb_engine::Engine;
+use bronzedb_protocol::MAX_VALUE_LEN;
+use bronzedb_util::status::Result;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+const MAX_LINE_LEN: u64 = 2048;
+
+enum Flow {
+    Continue,
+    Quit,
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    let read = reader
+        .by_ref()
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "line is too long or truncated",
+        ));
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse<N: std::str::FromStr>(data: &[u8]) -> Option<N> {
+    std::str::from_utf8(data).ok()?.parse().ok()
+}
+
+fn failure_line(failure: Failure) -> String {
+    match failure {
+        Failure::Unauthenticated => "CLIENT_ERROR authentication required".to_owned(),
+        Failure::Denied => "CLIENT_ERROR permission denied".to_owned(),
+        Failure::InvalidKey => "CLIENT_ERROR bad command line format".to_owned(),
+        Failure::TooLarge => "SERVER_ERROR object too large for cache".to_owned(),
+        Failure::Expiring => "CLIENT_ERROR items do not expire, exptime must be 0".to_owned(),
+        Failure::Unsupported => "SERVER_ERROR the engine cannot compare and set".to_owned(),
+        Failure::WrongShard => "SERVER_ERROR the key is served by another shard".to_owned(),
+        Failure::NonNumeric => {
+            "CLIENT_ERROR cannot increment or decrement non-numeric value".to_owned()
+        }
+        Failure::Engine(err) => format!("SERVER_ERROR {}", err),
+    }
+}
+
+pub(super) fn serve<T: Engine>(
+    reader: &mut BufReader<impl Read>,
+    writer: &mut impl Write,
+    session: &mut Session<T>,
+) -> Result<()> {
+    loop {
+        session.clock.next(!reader.buffer().is_empty());
+        let line = match read_line(reader) {
+            Ok(Some(line)) => line,
+            Ok(None) => break Ok(()),
+            Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {
+                writer.write_all(b"CLIENT_ERROR line is too long\r\n")?;
+                writer.flush()?;
+                break Ok(());
+            }
+            Err(err) => break Err(err.into()),
+        };
+        let args: Vec<&[u8]> = line
+            .split(|c| *c == b' ')
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        let flow = execute(reader, writer, session, &args)?;
+        if let Flow::Quit = flow {
+            writer.flush()?;
+            break Ok(());
+        }
+        // flush once the pipelined commands are drained
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+}
+
+fn reply(writer: &mut impl Write, noreply: bool, line: &str) -> io::Result<Flow> {
+    if !noreply {
+        write!(writer, "{}\r\n", line)?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn execute<T: Engine>(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    session: &mut Session<T>,
+    args: &[&[u8]],
+) -> io::Result<Flow> {
+    let (command, args) = match args.split_first() {
+        Some(split) => split,
+        None => return reply(writer, false, "ERROR"),
+    };
+    let noreply = args.last() == Some(&&b"noreply"[..]);
+    match *command {
+        b"get" | b"gets" => {
+            if args.is_empty() {
+                return reply(writer, false, "ERROR");
+            }
+            for key in args {
+                match session.get(key) {
+                    Ok(Some(item)) => {
+                        write!(writer, "VALUE ")?;
+                        writer.write_all(key)?;
+                        write!(writer, " {} {}", item.flags, item.data.len())?;
+                        if *command == b"gets" {
+                            write!(writer, " {}", item.cas)?;
+                        }
+                        writer.write_all(b"\r\n")?;
+                        writer.write_all(&item.data)?;
+                        writer.write_all(b"\r\n")?;
+                    }
+                    Ok(None) => (),
+                    Err(failure) => return reply(writer, false, &failure_line(failure)),
+                }
+            }
+            reply(writer, false, "END")
+        }
+        b"set" | b"add" | b"replace" | b"cas" => {
+            let fields = if *command == b"cas" { 5 } else { 4 };
+            if args.len() != fields && !(args.len() == fields + 1 && noreply) {
+                return reply(writer, false, "CLIENT_ERROR bad command line format");
+            }
+            let header = (
+                parse::<u32>(args[1]),
+                parse::<i64>(args[2]),
+                parse::<usize>(args[3]),
+            );
+            let (flags, exptime, len) = match header {
+                (Some(flags), Some(exptime), Some(len)) => (flags, exptime, len),
+                _ => return reply(writer, false, "CLIENT_ERROR bad command line format"),
+            };
+            if len > MAX_VALUE_LEN {
+                io::copy(&mut reader.take(len as u64 + 2), &mut io::sink())?;
+                return reply(writer, noreply, &failure_line(Failure::TooLarge));
+            }
+            let mut data = vec![0; len + 2];
+            reader.read_exact(&mut data)?;
+            if !data.ends_with(b"\r\n") {
+                return reply(writer, false, "CLIENT_ERROR bad data chunk");
+            }
+            data.truncate(len);
+            let mode = match *command {
+                b"add" => Mode::Add,
+                b"replace" => Mode::Replace,
+                b"cas" => match parse(args[4]) {
+                    Some(unique) => Mode::Cas(unique),
+                    None => return reply(writer, false, "CLIENT_ERROR bad command line format"),
+                },
+                _ => Mode::Set,
+            };
+            let line = match session.store(mode, args[0], flags, exptime, data) {
+                Ok(Outcome::Stored(_)) => "STORED".to_owned(),
+                Ok(Outcome::NotStored) => "NOT_STORED".to_owned(),
+                Ok(Outcome::Exists) => "EXISTS".to_owned(),
+                Ok(Outcome::NotFound) => "NOT_FOUND".to_owned(),
+                Err(failure) => failure_line(failure),
+            };
+            reply(writer, noreply, &line)
+        }
+        // a legacy `delete <key> 0` is still accepted
+        b"delete" if !args.is_empty() && args.len() <= 3 => {
+            let line = match session.delete(args[0]) {
+                Ok(true) => "DELETED".to_owned(),
+                Ok(false) => "NOT_FOUND".to_owned(),
+                Err(failure) => failure_line(failure),
+            };
+            reply(writer, noreply, &line)
+        }
+        b"incr" | b"decr" if args.len() == 2 || (args.len() == 3 && noreply) => {
+            let delta = match parse(args[1]) {
+                Some(delta) => delta,
+                None => return reply(writer, false, "CLIENT_ERROR invalid numeric delta argument"),
+            };
+            let line = match session.arithmetic(args[0], delta, *command == b"incr", None) {
+                Ok(Some((counter, _))) => counter.to_string(),
+                Ok(None) => "NOT_FOUND".to_owned(),
+                Err(failure) => failure_line(failure),
+            };
+            reply(writer, noreply, &line)
+        }
+        b"version" => reply(
+            writer,
+            false,
+            concat!("VERSION ", env!("CARGO_PKG_VERSION")),
+        ),
+        b"quit" => Ok(Flow::Quit),
+        _ => reply(writer, false, "ERROR"),
+    }
+}
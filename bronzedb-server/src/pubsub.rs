@@ -0,0 +1,173 @@
+use bronzedb_util::types::{Message, Value};
+use std::collections::HashMap;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+// Messages a subscriber has not read yet; newer ones are dropped for it.
+const SUBSCRIBER_QUEUE: usize = 1024;
+
+// Fans messages out to the subscribers connected when they are published, shared by every
+// server it is given to.
+#[derive(Clone, Default)]
+pub struct Broker {
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    subscribers: HashMap<u64, Subscriber>,
+}
+
+struct Subscriber {
+    channels: Vec<String>,
+    patterns: Vec<String>,
+    sender: SyncSender<Message>,
+}
+
+impl Subscriber {
+    fn listens(&self, channel: &str) -> bool {
+        self.channels.iter().any(|name| name == channel)
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| matches(pattern.as_bytes(), channel.as_bytes()))
+    }
+}
+
+impl Broker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns the number of subscribers the message was queued for.
+    pub fn publish(&self, channel: &str, payload: Value) -> usize {
+        let mut delivered = 0;
+        self.state().subscribers.retain(|_, subscriber| {
+            if !subscriber.listens(channel) {
+                return true;
+            }
+            let message = Message {
+                channel: channel.to_owned(),
+                payload: payload.clone(),
+            };
+            match subscriber.sender.try_send(message) {
+                Ok(()) => delivered += 1,
+                Err(TrySendError::Full(_)) => (),
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+            true
+        });
+        delivered
+    }
+
+    pub(crate) fn subscribe(&self, channels: Vec<String>, patterns: Vec<String>) -> Subscription {
+        let (sender, receiver) = sync_channel(SUBSCRIBER_QUEUE);
+        let mut state = self.state();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.subscribers.insert(
+            id,
+            Subscriber {
+                channels,
+                patterns,
+                sender,
+            },
+        );
+        Subscription {
+            id,
+            broker: self.clone(),
+            receiver,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Receives messages until dropped.
+pub(crate) struct Subscription {
+    id: u64,
+    broker: Broker,
+    pub(crate) receiver: Receiver<Message>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.broker.state().subscribers.remove(&self.id);
+    }
+}
+
+// Glob matching, `*` stands for any bytes and `?` for a single one.
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // the last `*` and the name position it was tried at
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&byte) if byte == b'?' || byte == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches, Broker};
+
+    #[test]
+    fn glob() {
+        for (pattern, name, expected) in [
+            ("news", "news", true),
+            ("news", "new", false),
+            ("news.*", "news.sport", true),
+            ("news.*", "news.", true),
+            ("news.*", "news", false),
+            ("*.sport", "news.sport", true),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("a*b*c", "axxbyyc", true),
+            ("a*b*c", "axxbyy", false),
+            ("*", "", true),
+        ] {
+            assert_eq!(
+                expected,
+                matches(pattern.as_bytes(), name.as_bytes()),
+                "{} {}",
+                pattern,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn fanout() {
+        let broker = Broker::new();
+        let news = broker.subscribe(vec!["news".to_owned()], Vec::new());
+        let all = broker.subscribe(Vec::new(), vec!["*".to_owned()]);
+        assert_eq!(2, broker.publish("news", b"hello".to_vec()));
+        assert_eq!(1, broker.publish("jobs", b"hiring".to_vec()));
+        assert_eq!(b"hello".to_vec(), news.receiver.recv().unwrap().payload);
+        assert!(news.receiver.try_recv().is_err());
+        let channels: Vec<_> = all.receiver.try_iter().map(|msg| msg.channel).collect();
+        assert_eq!(vec!["news", "jobs"], channels);
+        drop(all);
+        assert_eq!(1, broker.publish("news", Vec::new()));
+    }
+}
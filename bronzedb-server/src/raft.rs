@@ -0,0 +1,1325 @@
+use crate::replication::{clear, connect, credentials};
+use bronzedb_engine::{Engine, Scanner};
+use bronzedb_protocol::frame::Framed;
+use bronzedb_protocol::raft::{ClusterStatus, LogEntry, RaftMessage, RaftRole};
+use bronzedb_protocol::request::{Action, Credentials, Request};
+use bronzedb_protocol::response::Response;
+use bronzedb_util::status::StatusCode::*;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Event, Key, Value};
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{sleep, Builder};
+use std::time::{Duration, Instant};
+use storage::{Storage, RECEIVED, WRITTEN};
+
+mod storage;
+
+// Entries sent to a follower at once.
+const MAX_APPEND: usize = 256;
+// Bytes of a snapshot sent to a follower at once.
+const SNAPSHOT_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Member {
+    pub id: u64,
+    // where the other members and clients reach the member with the native protocol
+    pub addr: String,
+}
+
+// Timeouts are in milliseconds. Members wait between `election_timeout` and twice as long
+// for a leader before they stand for election; leaders send heartbeats every `heartbeat`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterConfig {
+    pub id: u64,
+    // every member of the cluster, including this one
+    pub members: Vec<Member>,
+    #[serde(default = "default_election_timeout")]
+    pub election_timeout: u64,
+    #[serde(default = "default_heartbeat")]
+    pub heartbeat: u64,
+    // how long a write waits to be committed before it fails with `Unavailable`
+    #[serde(default = "default_write_timeout")]
+    pub write_timeout: u64,
+    // credentials to authenticate with the other members, if they have users
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+    // the directory the member keeps its term, vote, log and snapshot in
+    #[serde(default = "default_path")]
+    pub path: String,
+    // applied entries kept in the log before they are compacted into a snapshot
+    #[serde(default = "default_log_size")]
+    pub log_size: u64,
+    // reads see every write committed before them: the leader checks it still leads first,
+    // followers redirect them to it
+    #[serde(default)]
+    pub linearizable_reads: bool,
+}
+
+fn default_election_timeout() -> u64 {
+    1000
+}
+
+fn default_heartbeat() -> u64 {
+    100
+}
+
+fn default_write_timeout() -> u64 {
+    5000
+}
+
+fn default_path() -> String {
+    "raft".into()
+}
+
+fn default_log_size() -> u64 {
+    10_000
+}
+
+impl ClusterConfig {
+    pub fn new(id: u64, members: Vec<Member>) -> Self {
+        Self {
+            id,
+            members,
+            election_timeout: default_election_timeout(),
+            heartbeat: default_heartbeat(),
+            write_timeout: default_write_timeout(),
+            user: None,
+            password: None,
+            token: None,
+            path: default_path(),
+            log_size: default_log_size(),
+            linearizable_reads: false,
+        }
+    }
+}
+
+struct Progress {
+    // the next entry to send
+    next: u64,
+    // the last entry known to match the leader's log
+    matched: u64,
+    sent: Option<Instant>,
+    // when the last message the member answered in this term was sent
+    acked: Option<Instant>,
+    // how much of the snapshot the member has, while it is sent one
+    snapshot: Option<u64>,
+}
+
+struct State {
+    term: u64,
+    voted_for: Option<u64>,
+    role: RaftRole,
+    leader: Option<u64>,
+    // the last entry in the snapshot and its term; the entry with index `i` after it is
+    // `log[i - start - 1]`
+    start: u64,
+    start_term: u64,
+    log: Vec<LogEntry>,
+    commit: u64,
+    applied: u64,
+    deadline: Instant,
+    votes: HashSet<u64>,
+    asked: HashSet<u64>,
+    peers: HashMap<u64, Progress>,
+    // the snapshot being received from the leader, by its last entry
+    receiving: Option<u64>,
+    // the last entry on disk, and whether a proposal is syncing the log
+    synced: u64,
+    syncing: bool,
+    storage: Storage,
+}
+
+// Changes to the term, the vote and the log are stored before they are used, but for the
+// entries a leader writes: it sends them to the others while they are synced.
+impl State {
+    fn last_index(&self) -> u64 {
+        self.start + self.log.len() as u64
+    }
+
+    // Entries in the snapshot are only known by the term of the last one.
+    fn term_at(&self, index: u64) -> u64 {
+        match index.checked_sub(self.start + 1) {
+            Some(offset) => self.log[offset as usize].term,
+            None => self.start_term,
+        }
+    }
+
+    // The entries after `from` up to `to`.
+    fn entries(&self, from: u64, to: u64) -> &[LogEntry] {
+        &self.log[(from - self.start) as usize..(to - self.start) as usize]
+    }
+
+    fn step_down(&mut self, term: u64) -> Result<()> {
+        self.role = RaftRole::Follower;
+        if term > self.term {
+            self.term = term;
+            self.voted_for = None;
+            self.leader = None;
+            self.storage.save_vote(term, None)?;
+        }
+        Ok(())
+    }
+
+    fn vote(&mut self, candidate: u64) -> Result<()> {
+        self.voted_for = Some(candidate);
+        self.storage.save_vote(self.term, self.voted_for)
+    }
+
+    fn append(&mut self, entries: Vec<LogEntry>) -> Result<()> {
+        self.storage.append(&entries)?;
+        self.log.extend(entries);
+        self.synced = self.last_index();
+        Ok(())
+    }
+
+    fn write(&mut self, entries: Vec<LogEntry>) -> Result<()> {
+        self.storage.write(&entries)?;
+        self.log.extend(entries);
+        Ok(())
+    }
+
+    // Drops the entries from `index` on.
+    fn truncate(&mut self, index: u64) -> Result<()> {
+        let len = (index - self.start - 1) as usize;
+        self.storage.truncate(len)?;
+        self.log.truncate(len);
+        self.synced = self.synced.min(self.last_index());
+        Ok(())
+    }
+
+    // Replaces the entries up to `index` with the snapshot in the file `name`, and the
+    // entries after it as well unless the entry at `index` matches.
+    fn compact(&mut self, name: &str, index: u64, term: u64) -> Result<()> {
+        let log = if index <= self.last_index() && self.term_at(index) == term {
+            self.log[(index - self.start) as usize..].to_vec()
+        } else {
+            Vec::new()
+        };
+        self.storage.install(name, index, term, log.clone())?;
+        self.log = log;
+        self.start = index;
+        self.start_term = term;
+        self.synced = self.last_index();
+        Ok(())
+    }
+}
+
+struct Node {
+    id: u64,
+    members: Vec<Member>,
+    election_timeout: Duration,
+    heartbeat: Duration,
+    write_timeout: Duration,
+    log_size: u64,
+    linearizable_reads: bool,
+    dir: PathBuf,
+    credentials: Option<Credentials>,
+    state: Mutex<State>,
+    changed: Condvar,
+    stopped: AtomicBool,
+}
+
+// A member of a cluster that replicates every write through a Raft log before applying it.
+// The term, the vote and the log are kept in `path`; applied entries beyond `log_size` are
+// compacted into a snapshot of the engine, which is sent to members missing them.
+#[derive(Clone)]
+pub struct Cluster {
+    node: Arc<Node>,
+}
+
+impl Cluster {
+    pub fn new(config: ClusterConfig) -> Result<Self> {
+        if !config.members.iter().any(|member| member.id == config.id) {
+            return Err(Error::new(
+                IOError,
+                format!("member {} is not in the cluster", config.id),
+            ));
+        }
+        let election_timeout = Duration::from_millis(config.election_timeout.max(1));
+        let credentials = credentials(&config.user, &config.password, &config.token);
+        let (storage, stored) = Storage::open(&config.path)?;
+        let synced = stored.start + stored.log.len() as u64;
+        Ok(Self {
+            node: Arc::new(Node {
+                id: config.id,
+                election_timeout,
+                heartbeat: Duration::from_millis(config.heartbeat.max(1)),
+                write_timeout: Duration::from_millis(config.write_timeout),
+                log_size: config.log_size.max(1),
+                linearizable_reads: config.linearizable_reads,
+                dir: storage.dir().to_path_buf(),
+                credentials,
+                state: Mutex::new(State {
+                    term: stored.term,
+                    voted_for: stored.voted_for,
+                    role: RaftRole::Follower,
+                    leader: None,
+                    start: stored.start,
+                    start_term: stored.start_term,
+                    log: stored.log,
+                    // the snapshot holds applied entries only
+                    commit: stored.start,
+                    applied: 0,
+                    deadline: Instant::now() + jitter(election_timeout),
+                    votes: HashSet::new(),
+                    asked: HashSet::new(),
+                    peers: HashMap::new(),
+                    receiving: None,
+                    synced,
+                    syncing: false,
+                    storage,
+                }),
+                members: config.members,
+                changed: Condvar::new(),
+                stopped: AtomicBool::new(false),
+            }),
+        })
+    }
+
+    // Committed writes are applied to `engine`. A durable engine resumes from the last entry
+    // applied to it, others are rebuilt from the snapshot and the log.
+    pub fn start<T: Engine + Send + 'static>(&self, mut engine: T) -> Result<()> {
+        if engine.durable() {
+            let mut state = self.node.lock();
+            let applied = state.storage.applied()?;
+            if applied >= state.start && applied <= state.last_index() {
+                state.applied = applied;
+                state.commit = state.commit.max(applied);
+            }
+        }
+        let node = self.node.clone();
+        spawn("bronzedb-raft", move || node.elect())?;
+        let node = self.node.clone();
+        spawn("bronzedb-raft-apply", move || node.apply(&mut engine))?;
+        for member in &self.node.members {
+            if member.id != self.node.id {
+                let node = self.node.clone();
+                let member = member.clone();
+                spawn("bronzedb-raft-peer", move || node.replicate(&member))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Stops taking part in the cluster, which goes on without this member if a majority is
+    // left. Stopped members refuse messages from the others with `Unavailable`.
+    pub fn stop(&self) {
+        self.node.stopped.store(true, Ordering::SeqCst);
+        self.node.changed.notify_all();
+    }
+
+    pub fn status(&self) -> ClusterStatus {
+        let state = self.node.lock();
+        ClusterStatus {
+            id: self.node.id,
+            role: state.role,
+            term: state.term,
+            leader: state.leader.and_then(|id| self.node.addr(id)),
+            commit: state.commit,
+            applied: state.applied,
+            members: self.node.members.len() as u32,
+        }
+    }
+
+    // Returns once the write is applied to the engine of this member. Members other than
+    // the leader fail with `Redirect` and the address of the leader, or with `Unavailable`
+    // while there is none.
+    pub fn propose(&self, event: Event) -> Result<()> {
+        self.node.propose(event)
+    }
+
+    // With `linearizable_reads`, returns once the engine of this member applied every write
+    // committed before the call; members other than the leader fail like `propose`.
+    pub fn read_index(&self) -> Result<()> {
+        match self.node.linearizable_reads {
+            true => self.node.read_index(),
+            false => Ok(()),
+        }
+    }
+
+    pub(crate) fn handle(&self, message: RaftMessage) -> Result<RaftMessage> {
+        if self.node.stopped() {
+            return Err(Error::new(Unavailable, "member is stopped"));
+        }
+        self.node.handle(message)
+    }
+}
+
+fn spawn(name: &str, f: impl FnOnce() + Send + 'static) -> Result<()> {
+    Builder::new().name(name.into()).spawn(f)?;
+    Ok(())
+}
+
+// A random duration between `timeout` and twice as long.
+fn jitter(timeout: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    timeout + Duration::from_nanos(random % (timeout.as_nanos() as u64).max(1))
+}
+
+impl Node {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    fn addr(&self, id: u64) -> Option<String> {
+        self.members
+            .iter()
+            .find(|member| member.id == id)
+            .map(|member| member.addr.clone())
+    }
+
+    fn majority(&self) -> usize {
+        self.members.len() / 2 + 1
+    }
+
+    fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    fn elect(&self) {
+        while !self.stopped() {
+            sleep(self.heartbeat.min(self.election_timeout / 10));
+            let mut state = self.lock();
+            if state.role == RaftRole::Leader || Instant::now() < state.deadline {
+                continue;
+            }
+            state.term += 1;
+            state.role = RaftRole::Candidate;
+            state.leader = None;
+            state.votes = Some(self.id).into_iter().collect();
+            state.asked.clear();
+            state.deadline = Instant::now() + jitter(self.election_timeout);
+            if let Err(err) = state.vote(self.id) {
+                warn!("member {} cannot store its vote: {}", self.id, err);
+                state.role = RaftRole::Follower;
+                continue;
+            }
+            info!(
+                "member {} stands for election in term {}",
+                self.id, state.term
+            );
+            if state.votes.len() >= self.majority() {
+                self.lead(&mut state);
+            }
+            self.changed.notify_all();
+        }
+    }
+
+    fn lead(&self, state: &mut State) {
+        info!("member {} leads term {}", self.id, state.term);
+        state.role = RaftRole::Leader;
+        state.leader = Some(self.id);
+        let next = state.last_index() + 1;
+        state.peers = self
+            .members
+            .iter()
+            .filter(|member| member.id != self.id)
+            .map(|member| {
+                let progress = Progress {
+                    next,
+                    matched: 0,
+                    sent: None,
+                    acked: None,
+                    snapshot: None,
+                };
+                (member.id, progress)
+            })
+            .collect();
+        // entries of earlier terms are committed along with the first one of this term
+        let term = state.term;
+        if let Err(err) = state.append(vec![LogEntry { term, event: None }]) {
+            warn!("member {} cannot store its log: {}", self.id, err);
+            state.role = RaftRole::Follower;
+            state.leader = None;
+            return;
+        }
+        self.advance_commit(state);
+    }
+
+    // Commits the last entry of this term that a majority has on disk.
+    fn advance_commit(&self, state: &mut State) {
+        for index in (state.commit + 1..=state.last_index()).rev() {
+            if state.term_at(index) != state.term {
+                break;
+            }
+            let replicas = usize::from(state.synced >= index)
+                + state
+                    .peers
+                    .values()
+                    .filter(|progress| progress.matched >= index)
+                    .count();
+            if replicas >= self.majority() {
+                state.commit = index;
+                self.changed.notify_all();
+                break;
+            }
+        }
+    }
+
+    fn redirect(&self, state: &State) -> Error {
+        match state.leader.filter(|&id| id != self.id) {
+            Some(id) => Error::new(Redirect, self.addr(id).unwrap_or_default()),
+            None => Error::new(Unavailable, "no leader is elected"),
+        }
+    }
+
+    fn propose(&self, event: Event) -> Result<()> {
+        let deadline = Instant::now() + self.write_timeout;
+        let mut state = self.lock();
+        if state.role != RaftRole::Leader {
+            return Err(self.redirect(&state));
+        }
+        let term = state.term;
+        state.write(vec![LogEntry {
+            term,
+            event: Some(event),
+        }])?;
+        let index = state.last_index();
+        self.changed.notify_all();
+        loop {
+            if state.synced < index && !state.syncing && state.term == term {
+                state = self.sync(state, term)?;
+                continue;
+            }
+            if state.applied >= index {
+                return match index > state.start {
+                    true if state.term_at(index) == term => Ok(()),
+                    // the entry is compacted, and only a new term may have overwritten it
+                    false if state.term == term => Ok(()),
+                    false => Err(Error::new(
+                        Unavailable,
+                        "the write is applied unless a new leader overwrote it",
+                    )),
+                    true => Err(Error::new(
+                        Unavailable,
+                        "the write was overwritten by a new leader",
+                    )),
+                };
+            }
+            let now = Instant::now();
+            if state.term != term || now >= deadline || self.stopped() {
+                return Err(Error::new(
+                    Unavailable,
+                    "the write is not committed yet, it may or may not be applied",
+                ));
+            }
+            state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    // Takes the commit index once a majority answered heartbeats sent after the call, so no
+    // other leader can have committed more (ReadIndex), then waits until it is applied.
+    fn read_index(&self) -> Result<()> {
+        let called = Instant::now();
+        let deadline = called + self.write_timeout;
+        let mut state = self.lock();
+        if state.role != RaftRole::Leader {
+            return Err(self.redirect(&state));
+        }
+        let term = state.term;
+        // heartbeats go out at once
+        for progress in state.peers.values_mut() {
+            progress.sent = None;
+        }
+        self.changed.notify_all();
+        let mut index = None;
+        loop {
+            if state.term != term {
+                return Err(self.redirect(&state));
+            }
+            if index.is_none() {
+                let acked = 1 + state
+                    .peers
+                    .values()
+                    .filter(|progress| progress.acked.is_some_and(|acked| acked >= called))
+                    .count();
+                // the commit index is behind until an entry of this term is committed
+                if acked >= self.majority() && state.term_at(state.commit) == term {
+                    index = Some(state.commit);
+                }
+            }
+            if index.is_some_and(|index| state.applied >= index) {
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline || self.stopped() {
+                return Err(Error::new(
+                    Unavailable,
+                    "the leader cannot confirm it still leads",
+                ));
+            }
+            state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    // Syncs the log without the lock, so the entries proposed meanwhile are synced together
+    // by the next proposal.
+    fn sync<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State>,
+        term: u64,
+    ) -> Result<MutexGuard<'a, State>> {
+        state.syncing = true;
+        let last_index = state.last_index();
+        let log = state.storage.log();
+        drop(state);
+        let synced = log.and_then(|log| Ok(log.sync_data()?));
+        let mut state = self.lock();
+        state.syncing = false;
+        self.changed.notify_all();
+        synced?;
+        // a new term may have replaced the entries
+        if state.term == term {
+            state.synced = state.synced.max(last_index);
+            self.advance_commit(&mut state);
+        }
+        Ok(state)
+    }
+
+    fn handle(&self, message: RaftMessage) -> Result<RaftMessage> {
+        let mut state = self.lock();
+        match message {
+            RaftMessage::Vote {
+                term,
+                candidate,
+                last_index,
+                last_term,
+            } => {
+                if term > state.term {
+                    state.step_down(term)?;
+                }
+                let up_to_date = (last_term, last_index)
+                    >= (state.term_at(state.last_index()), state.last_index());
+                let granted = term == state.term
+                    && state.voted_for.is_none_or(|id| id == candidate)
+                    && up_to_date;
+                if granted {
+                    state.vote(candidate)?;
+                    state.deadline = Instant::now() + jitter(self.election_timeout);
+                }
+                Ok(RaftMessage::Voted {
+                    term: state.term,
+                    granted,
+                })
+            }
+            RaftMessage::Append {
+                term,
+                leader,
+                mut prev_index,
+                prev_term,
+                mut entries,
+                commit,
+            } => {
+                if term < state.term {
+                    return Ok(RaftMessage::Appended {
+                        term: state.term,
+                        success: false,
+                        index: 0,
+                    });
+                }
+                state.step_down(term)?;
+                state.leader = Some(leader);
+                state.deadline = Instant::now() + jitter(self.election_timeout);
+                let matched = prev_index + entries.len() as u64;
+                // entries in the snapshot are committed, so they match the leader's
+                if prev_index < state.start {
+                    let skipped = ((state.start - prev_index) as usize).min(entries.len());
+                    entries.drain(..skipped);
+                    prev_index += skipped as u64;
+                } else if prev_index > state.last_index() || state.term_at(prev_index) != prev_term
+                {
+                    return Ok(RaftMessage::Appended {
+                        term,
+                        success: false,
+                        index: prev_index.saturating_sub(1).min(state.last_index()),
+                    });
+                }
+                let mut new = Vec::new();
+                for (index, entry) in (prev_index + 1..).zip(entries) {
+                    if new.is_empty() && index <= state.last_index() {
+                        if state.term_at(index) == entry.term {
+                            continue;
+                        }
+                        state.truncate(index)?;
+                    }
+                    new.push(entry);
+                }
+                state.append(new)?;
+                if commit.min(matched) > state.commit {
+                    state.commit = commit.min(matched);
+                    self.changed.notify_all();
+                }
+                Ok(RaftMessage::Appended {
+                    term,
+                    success: true,
+                    index: matched,
+                })
+            }
+            RaftMessage::Snapshot {
+                term,
+                leader,
+                index,
+                last_term,
+                offset,
+                data,
+                done,
+            } => {
+                let refused = RaftMessage::Appended {
+                    term: state.term.max(term),
+                    success: false,
+                    index: 0,
+                };
+                if term < state.term {
+                    return Ok(refused);
+                }
+                state.step_down(term)?;
+                state.leader = Some(leader);
+                state.deadline = Instant::now() + jitter(self.election_timeout);
+                // the member has the entries already
+                if index <= state.commit {
+                    state.receiving = None;
+                    return Ok(RaftMessage::Appended {
+                        term,
+                        success: true,
+                        index,
+                    });
+                }
+                if offset > 0 && state.receiving != Some(index) {
+                    return Ok(refused);
+                }
+                if let Err(err) = state.storage.receive(offset, &data) {
+                    warn!("member {} cannot receive a snapshot: {}", self.id, err);
+                    state.receiving = None;
+                    return Ok(refused);
+                }
+                state.receiving = Some(index);
+                if !done {
+                    return Ok(RaftMessage::Appended {
+                        term,
+                        success: true,
+                        index: 0,
+                    });
+                }
+                state.receiving = None;
+                state.compact(RECEIVED, index, last_term)?;
+                info!("member {} installs a snapshot up to {}", self.id, index);
+                state.commit = index;
+                self.changed.notify_all();
+                Ok(RaftMessage::Appended {
+                    term,
+                    success: true,
+                    index,
+                })
+            }
+            // answers are only read by the member that asked
+            RaftMessage::Voted { term, .. } | RaftMessage::Appended { term, .. } => {
+                Ok(RaftMessage::Voted {
+                    term: state.term.max(term),
+                    granted: false,
+                })
+            }
+        }
+    }
+
+    // Sends heartbeats and entries to a member while leading, and asks for its vote while
+    // standing for election.
+    fn replicate(&self, member: &Member) {
+        let mut conn = None;
+        while !self.stopped() {
+            let sent = Instant::now();
+            let message = {
+                let mut state = self.lock();
+                match self.next_message(&mut state, member.id) {
+                    Some(message) => message,
+                    None => {
+                        let _ = self.changed.wait_timeout(state, self.heartbeat).unwrap();
+                        continue;
+                    }
+                }
+            };
+            match self.send(&mut conn, member, message.clone()) {
+                Ok(answer) => self.receive(member.id, message, answer, sent),
+                Err(err) => {
+                    if conn.is_some() {
+                        warn!("member {} at {}: {}", member.id, member.addr, err);
+                    }
+                    conn = None;
+                    sleep(self.heartbeat);
+                }
+            }
+        }
+    }
+
+    fn next_message(&self, state: &mut State, id: u64) -> Option<RaftMessage> {
+        match state.role {
+            RaftRole::Leader => {
+                let (term, commit, last_index) = (state.term, state.commit, state.last_index());
+                let progress = state.peers.get(&id)?;
+                // the member misses entries that were compacted
+                if progress.next <= state.start {
+                    let offset = progress.snapshot.unwrap_or(0);
+                    let (index, last_term, data, done) =
+                        match state.storage.read_snapshot(offset, SNAPSHOT_CHUNK) {
+                            Ok(chunk) => chunk,
+                            Err(err) => {
+                                warn!("member {} cannot read its snapshot: {}", self.id, err);
+                                return None;
+                            }
+                        };
+                    let progress = state.peers.get_mut(&id)?;
+                    progress.snapshot = Some(offset);
+                    progress.sent = Some(Instant::now());
+                    return Some(RaftMessage::Snapshot {
+                        term,
+                        leader: self.id,
+                        index,
+                        last_term,
+                        offset,
+                        data,
+                        done,
+                    });
+                }
+                let due = progress
+                    .sent
+                    .is_none_or(|sent| sent.elapsed() >= self.heartbeat);
+                if progress.next > last_index && !due {
+                    return None;
+                }
+                let prev_index = progress.next - 1;
+                let end = last_index.min(prev_index + MAX_APPEND as u64);
+                let entries = state.entries(prev_index, end).to_vec();
+                let prev_term = state.term_at(prev_index);
+                state.peers.get_mut(&id)?.sent = Some(Instant::now());
+                Some(RaftMessage::Append {
+                    term,
+                    leader: self.id,
+                    prev_index,
+                    prev_term,
+                    entries,
+                    commit,
+                })
+            }
+            RaftRole::Candidate if state.asked.insert(id) => Some(RaftMessage::Vote {
+                term: state.term,
+                candidate: self.id,
+                last_index: state.last_index(),
+                last_term: state.term_at(state.last_index()),
+            }),
+            _ => None,
+        }
+    }
+
+    fn send(
+        &self,
+        conn: &mut Option<Framed<TcpStream>>,
+        member: &Member,
+        message: RaftMessage,
+    ) -> Result<RaftMessage> {
+        let stream = match conn {
+            Some(stream) => stream,
+            None => conn.get_or_insert(connect(
+                &member.addr,
+                self.credentials.as_ref(),
+                Some(self.election_timeout),
+            )?),
+        };
+        Request::Raft(message).write_to(&mut *stream)?;
+        match Response::read_from(stream, Action::Raft)? {
+            Response::Raft(answer) => Ok(answer),
+            Response::Status(status) => Err(Error::new(status, "raft request error")),
+            _ => Err(Error::new(Corruption, "raft request error")),
+        }
+    }
+
+    // `sent` is when the message was taken, before it was sent.
+    fn receive(&self, id: u64, message: RaftMessage, answer: RaftMessage, sent: Instant) {
+        let mut state = self.lock();
+        match (message, answer) {
+            (_, RaftMessage::Voted { term, .. }) | (_, RaftMessage::Appended { term, .. })
+                if term > state.term =>
+            {
+                if let Err(err) = state.step_down(term) {
+                    warn!("member {} cannot store its term: {}", self.id, err);
+                }
+            }
+            (RaftMessage::Vote { term, .. }, RaftMessage::Voted { granted: true, .. })
+                if state.role == RaftRole::Candidate && term == state.term =>
+            {
+                state.votes.insert(id);
+                if state.votes.len() >= self.majority() {
+                    self.lead(&mut state);
+                    self.changed.notify_all();
+                }
+            }
+            (RaftMessage::Append { term, .. }, RaftMessage::Appended { success, index, .. })
+                if state.role == RaftRole::Leader && term == state.term =>
+            {
+                if let Some(progress) = state.peers.get_mut(&id) {
+                    progress.acked = progress.acked.max(Some(sent));
+                    if success {
+                        progress.matched = progress.matched.max(index);
+                        progress.next = index + 1;
+                    } else {
+                        progress.next = (index + 1).min(progress.next - 1).max(1);
+                    }
+                }
+                self.advance_commit(&mut state);
+                // for reads waiting on the acknowledgement
+                self.changed.notify_all();
+            }
+            (
+                RaftMessage::Snapshot {
+                    term, index, data, ..
+                },
+                RaftMessage::Appended {
+                    success,
+                    index: matched,
+                    ..
+                },
+            ) if state.role == RaftRole::Leader && term == state.term => {
+                if let Some(progress) = state.peers.get_mut(&id) {
+                    progress.acked = progress.acked.max(Some(sent));
+                    if success && matched >= index {
+                        progress.matched = progress.matched.max(matched);
+                        progress.next = matched + 1;
+                        progress.snapshot = None;
+                    } else if success {
+                        progress.snapshot =
+                            progress.snapshot.map(|offset| offset + data.len() as u64);
+                    } else {
+                        progress.snapshot = Some(0);
+                    }
+                }
+                self.advance_commit(&mut state);
+                self.changed.notify_all();
+            }
+            _ => (),
+        }
+    }
+
+    fn apply<T: Engine>(&self, engine: &mut T) {
+        let durable = engine.durable();
+        loop {
+            let (first, entries) = {
+                let mut state = self.lock();
+                while state.applied >= state.commit && state.applied >= state.start {
+                    if self.stopped() {
+                        return;
+                    }
+                    state = self.changed.wait_timeout(state, self.heartbeat).unwrap().0;
+                }
+                if state.applied < state.start {
+                    drop(state);
+                    if let Err(err) = self.restore(engine) {
+                        warn!("member {} cannot restore its snapshot: {}", self.id, err);
+                        if self.stopped() {
+                            return;
+                        }
+                        sleep(self.heartbeat);
+                    }
+                    continue;
+                }
+                let entries = state.entries(state.applied, state.commit).to_vec();
+                (state.applied + 1, entries)
+            };
+            let applied = first + entries.len() as u64 - 1;
+            for entry in entries {
+                let result = match entry.event {
+                    Some(Event::Set(key, value)) => engine.set(key, value),
+                    Some(Event::Delete(key)) => engine.delete(key),
+                    None => Ok(()),
+                };
+                if let Err(err) = result {
+                    let err: Error = err.into();
+                    warn!("member {} cannot apply a write: {}", self.id, err);
+                }
+            }
+            // a restarted member resumes after the entries its engine kept
+            let flushed = durable && {
+                let result = engine.flush().map_err(Into::into);
+                if let Err(ref err) = result {
+                    warn!("member {} cannot flush its engine: {}", self.id, err);
+                }
+                result.is_ok()
+            };
+            let compact = {
+                let mut state = self.lock();
+                state.applied = applied;
+                if flushed {
+                    if let Err(err) = state.storage.save_applied(applied) {
+                        warn!("member {} cannot store its applied entry: {}", self.id, err);
+                    }
+                }
+                applied >= state.start + self.log_size
+            };
+            self.changed.notify_all();
+            if compact {
+                if let Err(err) = self.compact(engine, applied) {
+                    warn!("member {} cannot compact its log: {}", self.id, err);
+                }
+            }
+        }
+    }
+
+    // Rebuilds the engine from the snapshot, which holds entries it did not apply.
+    fn restore<T: Engine>(&self, engine: &mut T) -> Result<()> {
+        let (index, entries) = storage::snapshot(&self.dir)?;
+        clear(engine, None, None)?;
+        for entry in entries {
+            let (key, value) = entry?;
+            engine.set(key, value).map_err(Into::into)?;
+        }
+        if engine.durable() {
+            engine.flush().map_err(Into::into)?;
+        }
+        let mut state = self.lock();
+        state.applied = index;
+        state.commit = state.commit.max(index);
+        if engine.durable() {
+            state.storage.save_applied(index)?;
+        }
+        Ok(())
+    }
+
+    // Writes a snapshot of the engine, which applied the entries up to `index`, and drops
+    // them from the log.
+    fn compact<T: Engine>(&self, engine: &T, index: u64) -> Result<()> {
+        let term = {
+            let state = self.lock();
+            if index <= state.start {
+                return Ok(());
+            }
+            state.term_at(index)
+        };
+        let mut scanner = engine.scan(None, None).map_err(Into::into)?;
+        storage::write_snapshot(&self.dir, index, term, scanner.iter())?;
+        let mut state = self.lock();
+        if index > state.start {
+            state.compact(WRITTEN, index, term)?;
+        }
+        Ok(())
+    }
+}
+
+// Writes go through the cluster, reads are served by the engine of the member, so they may
+// miss the latest writes on followers unless the cluster has `linearizable_reads`.
+#[derive(Clone)]
+pub struct Clustered<T: Engine> {
+    engine: T,
+    cluster: Cluster,
+}
+
+impl<T: Engine> Clustered<T> {
+    pub fn new(engine: T, cluster: Cluster) -> Self {
+        Self { engine, cluster }
+    }
+}
+
+impl<T: Engine> Engine for Clustered<T> {
+    type Error = Error;
+
+    fn set(&mut self, key: Key, value: Value) -> Result<()> {
+        self.cluster.propose(Event::Set(key, value))
+    }
+
+    fn get(&self, key: Key) -> Result<Option<Value>> {
+        self.cluster.read_index()?;
+        self.engine.get(key).map_err(Into::into)
+    }
+
+    fn delete(&mut self, key: Key) -> Result<()> {
+        self.cluster.propose(Event::Delete(key))
+    }
+
+    fn scan(
+        &self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Box<dyn Scanner + '_>> {
+        self.cluster.read_index()?;
+        self.engine
+            .scan(lower_bound, upper_bound)
+            .map_err(Into::into)
+    }
+
+    fn watch(
+        &self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Option<Receiver<Event>>> {
+        self.engine
+            .watch(lower_bound, upper_bound)
+            .map_err(Into::into)
+    }
+
+    fn snapshot(&self) -> Result<Option<Box<dyn Scanner + '_>>> {
+        self.cluster.read_index()?;
+        self.engine.snapshot().map_err(Into::into)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.engine.flush().map_err(Into::into)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.engine.close().map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::storage::{self, RECEIVED};
+    use super::{Cluster, ClusterConfig, Member};
+    use bronzedb_protocol::raft::{LogEntry, RaftMessage, RaftRole};
+    use bronzedb_util::types::Event;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("bronzedb-raft-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn member(dir: &Path) -> Cluster {
+        let members = (1..=3)
+            .map(|id| Member {
+                id,
+                addr: format!("127.0.0.1:{}", 9000 + id),
+            })
+            .collect();
+        let mut config = ClusterConfig::new(1, members);
+        config.path = dir.to_string_lossy().into_owned();
+        Cluster::new(config).unwrap()
+    }
+
+    fn handle(cluster: &Cluster, message: RaftMessage) -> RaftMessage {
+        cluster.node.handle(message).unwrap()
+    }
+
+    fn entry(term: u64, key: &str) -> LogEntry {
+        LogEntry {
+            term,
+            event: Some(Event::Delete(key.as_bytes().to_vec().into())),
+        }
+    }
+
+    fn append(term: u64, prev_index: u64, prev_term: u64, entries: Vec<LogEntry>) -> RaftMessage {
+        RaftMessage::Append {
+            term,
+            leader: 2,
+            prev_index,
+            prev_term,
+            entries,
+            commit: 0,
+        }
+    }
+
+    fn vote(term: u64, candidate: u64, last_index: u64, last_term: u64) -> RaftMessage {
+        RaftMessage::Vote {
+            term,
+            candidate,
+            last_index,
+            last_term,
+        }
+    }
+
+    fn voted(term: u64, granted: bool) -> RaftMessage {
+        RaftMessage::Voted { term, granted }
+    }
+
+    fn appended(term: u64, success: bool, index: u64) -> RaftMessage {
+        RaftMessage::Appended {
+            term,
+            success,
+            index,
+        }
+    }
+
+    fn terms(cluster: &Cluster) -> Vec<u64> {
+        let state = cluster.node.lock();
+        state.log.iter().map(|entry| entry.term).collect()
+    }
+
+    #[test]
+    fn votes() {
+        let cluster = member(&dir("votes"));
+        assert_eq!(voted(1, true), handle(&cluster, vote(1, 2, 0, 0)));
+        // one vote per term
+        assert_eq!(voted(1, false), handle(&cluster, vote(1, 3, 0, 0)));
+        assert_eq!(voted(1, true), handle(&cluster, vote(1, 2, 0, 0)));
+        assert_eq!(
+            appended(1, true, 2),
+            handle(
+                &cluster,
+                append(1, 0, 0, vec![entry(1, "a"), entry(1, "b")])
+            )
+        );
+        // candidates with shorter or older logs are refused
+        assert_eq!(voted(2, false), handle(&cluster, vote(2, 3, 1, 1)));
+        assert_eq!(voted(3, false), handle(&cluster, vote(3, 3, 5, 0)));
+        assert_eq!(voted(4, true), handle(&cluster, vote(4, 3, 2, 1)));
+        // stale terms are refused
+        assert_eq!(voted(4, false), handle(&cluster, vote(3, 2, 9, 3)));
+    }
+
+    #[test]
+    fn append_entries() {
+        let cluster = member(&dir("append"));
+        let entries = vec![entry(1, "a"), entry(1, "b"), entry(2, "c")];
+        assert_eq!(
+            appended(2, true, 3),
+            handle(&cluster, append(2, 0, 0, entries))
+        );
+        // a gap, then a mismatch
+        assert_eq!(
+            appended(2, false, 3),
+            handle(&cluster, append(2, 5, 2, Vec::new()))
+        );
+        assert_eq!(
+            appended(2, false, 2),
+            handle(&cluster, append(2, 3, 1, Vec::new()))
+        );
+        // a new leader overwrites the entries it does not have
+        assert_eq!(
+            appended(3, true, 3),
+            handle(&cluster, append(3, 2, 1, vec![entry(3, "d")]))
+        );
+        assert_eq!(vec![1, 1, 3], terms(&cluster));
+        {
+            let state = cluster.node.lock();
+            assert_eq!(Some(2), state.leader);
+            assert_eq!(RaftRole::Follower, state.role);
+        }
+        // repeated entries are kept
+        assert_eq!(
+            appended(3, true, 2),
+            handle(
+                &cluster,
+                append(3, 0, 0, vec![entry(1, "a"), entry(1, "b")])
+            )
+        );
+        assert_eq!(3, cluster.node.lock().log.len());
+        assert_eq!(
+            appended(3, false, 0),
+            handle(&cluster, append(2, 3, 3, Vec::new()))
+        );
+    }
+
+    #[test]
+    fn restart() {
+        let dir = dir("restart");
+        {
+            let cluster = member(&dir);
+            assert_eq!(voted(1, true), handle(&cluster, vote(1, 2, 0, 0)));
+            let entries = vec![entry(1, "a"), entry(1, "b"), entry(1, "c")];
+            handle(&cluster, append(1, 0, 0, entries));
+        }
+        // the vote of the term is kept
+        let cluster = member(&dir);
+        assert_eq!(voted(1, false), handle(&cluster, vote(1, 3, 9, 1)));
+        handle(&cluster, append(2, 1, 1, vec![entry(2, "d")]));
+        assert_eq!(voted(2, true), handle(&cluster, vote(2, 2, 2, 2)));
+        drop(cluster);
+        let cluster = member(&dir);
+        assert_eq!(vec![1, 2], terms(&cluster));
+        assert_eq!(voted(2, false), handle(&cluster, vote(2, 3, 9, 2)));
+        // and so is the log, against which candidates are compared
+        assert_eq!(voted(3, false), handle(&cluster, vote(3, 3, 5, 1)));
+        assert_eq!(2, cluster.node.lock().term_at(2));
+    }
+
+    #[test]
+    fn install_snapshot() {
+        let dir = dir("snapshot");
+        let leader = dir.join("leader");
+        fs::create_dir_all(&leader).unwrap();
+        let entries = (0..100u32).map(|i| Ok((i.to_be_bytes().to_vec().into(), vec![1; 100])));
+        storage::write_snapshot(&leader, 5, 2, entries).unwrap();
+        let data = fs::read(leader.join(storage::WRITTEN)).unwrap();
+        let snapshot = |offset: usize, len: usize| RaftMessage::Snapshot {
+            term: 3,
+            leader: 2,
+            index: 5,
+            last_term: 2,
+            offset: offset as u64,
+            data: data[offset..offset + len].to_vec(),
+            done: offset + len == data.len(),
+        };
+        let cluster = member(&dir.join("follower"));
+        handle(
+            &cluster,
+            append(2, 0, 0, vec![entry(1, "a"), entry(2, "b")]),
+        );
+        let half = data.len() / 2;
+        assert_eq!(appended(3, true, 0), handle(&cluster, snapshot(0, half)));
+        // parts follow each other, or the leader starts over
+        assert_eq!(
+            appended(3, false, 0),
+            handle(&cluster, snapshot(half + 1, data.len() - half - 1))
+        );
+        assert_eq!(
+            appended(3, false, 0),
+            handle(&cluster, snapshot(half, data.len() - half))
+        );
+        assert_eq!(appended(3, true, 0), handle(&cluster, snapshot(0, half)));
+        assert_eq!(
+            appended(3, true, 5),
+            handle(&cluster, snapshot(half, data.len() - half))
+        );
+        assert!(!dir.join("follower").join(RECEIVED).exists());
+        {
+            let state = cluster.node.lock();
+            assert_eq!(
+                (5, 2, 5, 5),
+                (
+                    state.start,
+                    state.start_term,
+                    state.commit,
+                    state.last_index()
+                )
+            );
+        }
+        // entries follow the snapshot, and those in it match
+        assert_eq!(
+            appended(3, true, 6),
+            handle(&cluster, append(3, 5, 2, vec![entry(3, "c")]))
+        );
+        assert_eq!(
+            appended(3, true, 6),
+            handle(
+                &cluster,
+                append(3, 3, 1, vec![entry(2, "x"), entry(2, "y"), entry(3, "c")])
+            )
+        );
+        assert_eq!(
+            appended(3, true, 4),
+            handle(
+                &cluster,
+                append(3, 2, 1, vec![entry(2, "x"), entry(2, "y")])
+            )
+        );
+        drop(cluster);
+        let cluster = member(&dir.join("follower"));
+        let state = cluster.node.lock();
+        assert_eq!(
+            (5, 2, 6),
+            (state.start, state.start_term, state.last_index())
+        );
+        assert_eq!(3, state.term_at(6));
+    }
+}
@@ -0,0 +1,336 @@
+use bronzedb_protocol::backup::{BackupReader, BackupWriter};
+use bronzedb_protocol::raft::LogEntry;
+use bronzedb_util::status::StatusCode::Corruption;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::Entry;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// The files in the directory of a member: `state` holds its term and vote, `log` the entries
+// after the snapshot and `snapshot` the engine up to them. Both of the latter start with the
+// index and term of the last entry in the snapshot. `applied` is the last entry applied to a
+// durable engine.
+const STATE: &str = "state";
+const LOG: &str = "log";
+const SNAPSHOT: &str = "snapshot";
+const APPLIED: &str = "applied";
+// snapshots before they replace the current one
+pub(super) const WRITTEN: &str = "snapshot.tmp";
+pub(super) const RECEIVED: &str = "snapshot.received";
+
+const HEADER_LEN: u64 = 16;
+
+// What a member finds in its directory on start.
+pub(super) struct Stored {
+    pub(super) term: u64,
+    pub(super) voted_for: Option<u64>,
+    // the index and term of the last entry in the snapshot
+    pub(super) start: u64,
+    pub(super) start_term: u64,
+    pub(super) log: Vec<LogEntry>,
+}
+
+// Keeps what a member needs after a restart. Every change is on disk once a method returns,
+// but for entries added with `write`, which are once the file of `log` is synced.
+pub(super) struct Storage {
+    dir: PathBuf,
+    log: File,
+    // where each entry starts in the log file, followed by where the last one ends
+    offsets: Vec<u64>,
+}
+
+impl Storage {
+    pub(super) fn open(dir: impl Into<PathBuf>) -> Result<(Self, Stored)> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let (term, voted_for) = match open(&dir.join(STATE))? {
+            Some(mut file) => {
+                let term = file.read_u64::<BigEndian>()?;
+                let voted_for = match file.read_u8()? {
+                    0 => None,
+                    _ => Some(file.read_u64::<BigEndian>()?),
+                };
+                (term, voted_for)
+            }
+            None => (0, None),
+        };
+        let (snapshot, snapshot_term) = match open(&dir.join(SNAPSHOT))? {
+            Some(mut file) => read_header(&mut file)?,
+            None => (0, 0),
+        };
+        let (storage, mut stored) = match open(&dir.join(LOG))? {
+            Some(file) => Self::read_log(dir, file)?,
+            None => Self::create(dir, 0, 0, Vec::new())?,
+        };
+        stored.term = term;
+        stored.voted_for = voted_for;
+        if snapshot < stored.start {
+            return Err(Error::new(
+                Corruption,
+                "the raft log starts after its snapshot",
+            ));
+        }
+        if snapshot == stored.start {
+            return Ok((storage, stored));
+        }
+        // the log was not compacted after the last snapshot was taken
+        let kept = (snapshot - stored.start) as usize;
+        let log = match stored.log.get(kept.wrapping_sub(1)) {
+            Some(entry) if entry.term == snapshot_term => stored.log.split_off(kept),
+            _ => Vec::new(),
+        };
+        let (storage, mut stored) = Self::create(storage.dir, snapshot, snapshot_term, log)?;
+        stored.term = term;
+        stored.voted_for = voted_for;
+        Ok((storage, stored))
+    }
+
+    // Reads the entries up to the first incomplete one, left by a crash during a write.
+    fn read_log(dir: PathBuf, file: File) -> Result<(Self, Stored)> {
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let (start, start_term) = read_header(&mut reader)?;
+        let mut log = Vec::new();
+        let mut offsets = vec![HEADER_LEN];
+        let mut end = HEADER_LEN;
+        while end < len {
+            match LogEntry::read_from(&mut reader) {
+                Ok(entry) => {
+                    end += entry.write_to(&mut io::sink())? as u64;
+                    offsets.push(end);
+                    log.push(entry);
+                }
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(Error::new(Corruption, err.to_string())),
+            }
+        }
+        let file = OpenOptions::new().append(true).open(dir.join(LOG))?;
+        file.set_len(end)?;
+        let storage = Self {
+            dir,
+            log: file,
+            offsets,
+        };
+        let stored = Stored {
+            term: 0,
+            voted_for: None,
+            start,
+            start_term,
+            log,
+        };
+        Ok((storage, stored))
+    }
+
+    // Writes a new log through a temporary file, so a crash leaves either log.
+    fn create(
+        dir: PathBuf,
+        start: u64,
+        start_term: u64,
+        log: Vec<LogEntry>,
+    ) -> Result<(Self, Stored)> {
+        let temporary = dir.join("log.tmp");
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_u64::<BigEndian>(start)?;
+        writer.write_u64::<BigEndian>(start_term)?;
+        let mut offsets = vec![HEADER_LEN];
+        for entry in &log {
+            let end = offsets[offsets.len() - 1] + entry.write_to(&mut writer)? as u64;
+            offsets.push(end);
+        }
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(&temporary, dir.join(LOG))?;
+        let file = OpenOptions::new().append(true).open(dir.join(LOG))?;
+        let storage = Self {
+            dir,
+            log: file,
+            offsets,
+        };
+        let stored = Stored {
+            term: 0,
+            voted_for: None,
+            start,
+            start_term,
+            log,
+        };
+        Ok((storage, stored))
+    }
+
+    pub(super) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(super) fn save_vote(&self, term: u64, voted_for: Option<u64>) -> Result<()> {
+        let mut state = Vec::with_capacity(17);
+        state.write_u64::<BigEndian>(term)?;
+        match voted_for {
+            Some(id) => {
+                state.write_u8(1)?;
+                state.write_u64::<BigEndian>(id)?;
+            }
+            None => state.write_u8(0)?,
+        }
+        replace(&self.dir, STATE, &state, true)
+    }
+
+    pub(super) fn append(&mut self, entries: &[LogEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.write(entries)?;
+        self.log.sync_data()?;
+        Ok(())
+    }
+
+    pub(super) fn write(&mut self, entries: &[LogEntry]) -> Result<()> {
+        let mut buffer = Vec::new();
+        let mut offsets = Vec::with_capacity(entries.len());
+        let mut end = self.offsets[self.offsets.len() - 1];
+        for entry in entries {
+            end += entry.write_to(&mut buffer)? as u64;
+            offsets.push(end);
+        }
+        self.log.write_all(&buffer)?;
+        self.offsets.extend(offsets);
+        Ok(())
+    }
+
+    // The log file, to be synced without holding the storage.
+    pub(super) fn log(&self) -> Result<File> {
+        Ok(self.log.try_clone()?)
+    }
+
+    // Keeps the first `len` entries after the snapshot.
+    pub(super) fn truncate(&mut self, len: usize) -> Result<()> {
+        self.log.set_len(self.offsets[len])?;
+        self.log.sync_data()?;
+        self.offsets.truncate(len + 1);
+        Ok(())
+    }
+
+    // Makes the snapshot in the file `name` the current one, which holds the entries up to
+    // `start`, and keeps `log` after it.
+    pub(super) fn install(
+        &mut self,
+        name: &str,
+        start: u64,
+        start_term: u64,
+        log: Vec<LogEntry>,
+    ) -> Result<()> {
+        let mut file = File::open(self.dir.join(name))?;
+        if read_header(&mut file)? != (start, start_term) {
+            return Err(Error::new(
+                Corruption,
+                "the snapshot is not the one expected",
+            ));
+        }
+        file.sync_all()?;
+        fs::rename(self.dir.join(name), self.dir.join(SNAPSHOT))?;
+        let dir = self.dir.clone();
+        *self = Self::create(dir, start, start_term, log)?.0;
+        Ok(())
+    }
+
+    // Adds a part of a snapshot sent by the leader, which has to follow the parts before.
+    pub(super) fn receive(&self, offset: u64, data: &[u8]) -> Result<()> {
+        let path = self.dir.join(RECEIVED);
+        let mut file = match offset {
+            0 => File::create(path)?,
+            _ => OpenOptions::new().append(true).open(path)?,
+        };
+        if file.metadata()?.len() != offset {
+            return Err(Error::new(Corruption, "a part of the snapshot is missing"));
+        }
+        file.write_all(data)?;
+        Ok(())
+    }
+
+    // Reads up to `len` bytes of the current snapshot from `offset`, along with the index and
+    // term of its last entry and whether they are the last bytes.
+    pub(super) fn read_snapshot(
+        &self,
+        offset: u64,
+        len: usize,
+    ) -> Result<(u64, u64, Vec<u8>, bool)> {
+        let mut file = File::open(self.dir.join(SNAPSHOT))?;
+        let (index, term) = read_header(&mut file)?;
+        let size = file.metadata()?.len();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut data)?;
+        let done = offset + data.len() as u64 >= size;
+        Ok((index, term, data, done))
+    }
+
+    pub(super) fn applied(&self) -> Result<u64> {
+        match open(&self.dir.join(APPLIED)) {
+            Ok(Some(mut file)) => Ok(file.read_u64::<BigEndian>().unwrap_or(0)),
+            Ok(None) => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Only called once the engine is flushed, so a lost update makes a restarted member
+    // apply a few entries again.
+    pub(super) fn save_applied(&self, applied: u64) -> Result<()> {
+        replace(&self.dir, APPLIED, &applied.to_be_bytes(), false)
+    }
+}
+
+// Writes the entries of an engine that applied the log up to the entry at `index` to a
+// snapshot, which replaces the current one once installed.
+pub(super) fn write_snapshot(
+    dir: &Path,
+    index: u64,
+    term: u64,
+    entries: impl Iterator<Item = Result<Entry>>,
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(dir.join(WRITTEN))?);
+    writer.write_u64::<BigEndian>(index)?;
+    writer.write_u64::<BigEndian>(term)?;
+    let mut writer = BackupWriter::new(writer)?;
+    for entry in entries {
+        let (key, value) = entry?;
+        writer.write(&key, &value)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+// The entries of the current snapshot and the index of the last entry in it.
+pub(super) fn snapshot(dir: &Path) -> Result<(u64, BackupReader<BufReader<File>>)> {
+    let mut reader = BufReader::new(File::open(dir.join(SNAPSHOT))?);
+    let (index, _) = read_header(&mut reader)?;
+    Ok((index, BackupReader::new(reader)?))
+}
+
+fn open(path: &Path) -> Result<Option<File>> {
+    match File::open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn read_header(mut reader: impl Read) -> Result<(u64, u64)> {
+    Ok((
+        reader.read_u64::<BigEndian>()?,
+        reader.read_u64::<BigEndian>()?,
+    ))
+}
+
+// Replaces the file through a temporary one, so a crash leaves either content.
+fn replace(dir: &Path, name: &str, data: &[u8], sync: bool) -> Result<()> {
+    let temporary = dir.join(format!("{}.tmp", name));
+    let mut file = File::create(&temporary)?;
+    file.write_all(data)?;
+    if sync {
+        file.sync_all()?;
+    }
+    fs::rename(&temporary, dir.join(name))?;
+    Ok(())
+}
@@ -0,0 +1,631 @@
+use crate::HEARTBEAT;
+use bronzedb_engine::{Engine, Scanner};
+use bronzedb_protocol::frame::Framed;
+use bronzedb_protocol::replication::{ReplicaState, ReplicationItem, ReplicationStatus, Role};
+use bronzedb_protocol::request::{Action, Credentials, Request};
+use bronzedb_protocol::response::{Response, Streamed};
+use bronzedb_util::status::StatusCode::{self, *};
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Event, Key, Value};
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::iter;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{sleep, Builder};
+use std::time::Duration;
+
+// How long a replica waits before connecting again after losing its primary.
+const RECONNECT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoleConfig {
+    #[default]
+    Standalone,
+    Primary,
+    Replica,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplicationConfig {
+    pub role: RoleConfig,
+    // changes a primary keeps for replicas that fall behind, older ones need a full sync
+    pub log_size: usize,
+    pub primary: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub token: Option<String>,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            role: RoleConfig::Standalone,
+            log_size: 100_000,
+            primary: None,
+            user: None,
+            password: None,
+            token: None,
+        }
+    }
+}
+
+impl ReplicationConfig {
+    pub fn credentials(&self) -> Option<Credentials> {
+        credentials(&self.user, &self.password, &self.token)
+    }
+}
+
+// A password takes precedence over a token.
+pub(crate) fn credentials(
+    user: &Option<String>,
+    password: &Option<String>,
+    token: &Option<String>,
+) -> Option<Credentials> {
+    match (user, password, token) {
+        (Some(user), Some(password), _) => Some(Credentials::Password {
+            user: user.clone(),
+            password: password.clone(),
+        }),
+        (_, _, Some(token)) => Some(Credentials::Token(token.clone())),
+        _ => None,
+    }
+}
+
+// Connects to another server with the native protocol, authenticating if needed.
+pub(crate) fn connect(
+    addr: &str,
+    credentials: Option<&Credentials>,
+    timeout: Option<Duration>,
+) -> Result<Framed<TcpStream>> {
+    let stream = match timeout {
+        Some(timeout) => {
+            let addr = addr
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| Error::new(IOError, format!("cannot resolve {}", addr)))?;
+            TcpStream::connect_timeout(&addr, timeout)?
+        }
+        None => TcpStream::connect(addr)?,
+    };
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    let mut stream = Framed::new(stream);
+    if let Some(credentials) = credentials {
+        Request::Auth(credentials.clone()).write_to(&mut stream)?;
+        expect_ok(
+            Response::read_from(&mut stream, Action::Auth)?,
+            "auth error",
+        )?;
+    }
+    Ok(stream)
+}
+
+#[derive(Clone, Default)]
+pub enum Replication {
+    #[default]
+    Standalone,
+    // the engine of the server must be wrapped in `Logged` with the same log
+    Primary(ChangeLog),
+    // writes are redirected to the primary
+    Replica(Replica),
+}
+
+impl Replication {
+    // The primary of a replica.
+    pub fn primary(&self) -> Option<&str> {
+        match self {
+            Replication::Replica(replica) => Some(replica.primary()),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> ReplicationStatus {
+        match self {
+            Replication::Standalone => ReplicationStatus {
+                role: Role::Standalone,
+                state: ReplicaState::Streaming,
+                primary: None,
+                head: 0,
+                applied: 0,
+                replicas: 0,
+            },
+            Replication::Primary(log) => {
+                let head = log.head();
+                ReplicationStatus {
+                    role: Role::Primary,
+                    state: ReplicaState::Streaming,
+                    primary: None,
+                    head,
+                    applied: head,
+                    replicas: log.replicas() as u32,
+                }
+            }
+            Replication::Replica(replica) => replica.status(),
+        }
+    }
+}
+
+pub(crate) enum Next {
+    Change(u64, Event),
+    // no change within the timeout, with the current head
+    Quiet(u64),
+    // the change is no longer or not yet in the log
+    Lost,
+}
+
+struct Log {
+    // sequence of the first change kept
+    first: u64,
+    changes: VecDeque<Event>,
+    capacity: usize,
+}
+
+impl Log {
+    fn head(&self) -> u64 {
+        self.first + self.changes.len() as u64 - 1
+    }
+}
+
+// The latest changes of a primary, numbered from 1 in the order they were applied.
+#[derive(Clone)]
+pub struct ChangeLog {
+    inner: Arc<(Mutex<Log>, Condvar)>,
+    replicas: Arc<AtomicUsize>,
+}
+
+impl ChangeLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new((
+                Mutex::new(Log {
+                    first: 1,
+                    changes: VecDeque::new(),
+                    capacity: capacity.max(1),
+                }),
+                Condvar::new(),
+            )),
+            replicas: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Log> {
+        self.inner.0.lock().unwrap()
+    }
+
+    // The sequence of the last change, 0 before the first one.
+    pub fn head(&self) -> u64 {
+        self.lock().head()
+    }
+
+    pub fn replicas(&self) -> usize {
+        self.replicas.load(Ordering::SeqCst)
+    }
+
+    fn append(&self, mut log: MutexGuard<'_, Log>, event: Event) {
+        if log.changes.len() == log.capacity {
+            log.changes.pop_front();
+            log.first += 1;
+        }
+        log.changes.push_back(event);
+        self.inner.1.notify_all();
+    }
+
+    // Whether replicas that applied every change up to `seq` can follow the log.
+    pub(crate) fn contains(&self, seq: u64) -> bool {
+        let log = self.lock();
+        seq + 1 >= log.first && seq <= log.head()
+    }
+
+    // Waits up to `timeout` for the change after `seq`.
+    pub(crate) fn next_after(&self, seq: u64, timeout: Duration) -> Next {
+        let mut log = self.lock();
+        if seq == log.head() {
+            log = self.inner.1.wait_timeout(log, timeout).unwrap().0;
+        }
+        if seq + 1 < log.first || seq > log.head() {
+            Next::Lost
+        } else if seq == log.head() {
+            Next::Quiet(seq)
+        } else {
+            let change = log.changes[(seq + 1 - log.first) as usize].clone();
+            Next::Change(seq + 1, change)
+        }
+    }
+
+    // The changes after `seq` as they come, with the head and a pause after every
+    // `HEARTBEAT` without any; ends once the log has moved past the next change.
+    pub(crate) fn changes(
+        &self,
+        after: u64,
+    ) -> impl Iterator<Item = Option<Result<ReplicationItem>>> + '_ {
+        let mut seq = after;
+        let mut quiet = false;
+        iter::from_fn(move || {
+            if quiet {
+                quiet = false;
+                return Some(None);
+            }
+            match self.next_after(seq, HEARTBEAT) {
+                Next::Change(next, event) => {
+                    seq = next;
+                    Some(Some(Ok(ReplicationItem::Change { seq, event })))
+                }
+                Next::Quiet(head) => {
+                    quiet = true;
+                    Some(Some(Ok(ReplicationItem::Head(head))))
+                }
+                Next::Lost => None,
+            }
+        })
+    }
+
+    pub(crate) fn follow(&self) -> Follower {
+        self.replicas.fetch_add(1, Ordering::SeqCst);
+        Follower(self.replicas.clone())
+    }
+}
+
+// Counts a replica while it streams from the primary.
+pub(crate) struct Follower(Arc<AtomicUsize>);
+
+impl Drop for Follower {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Appends every change to the log. Writes hold the log while they are applied, so the log
+// keeps their order, whichever front-end they come from.
+#[derive(Clone)]
+pub struct Logged<T: Engine> {
+    engine: T,
+    log: ChangeLog,
+}
+
+impl<T: Engine> Logged<T> {
+    pub fn new(engine: T, log: ChangeLog) -> Self {
+        Self { engine, log }
+    }
+}
+
+impl<T: Engine> Engine for Logged<T> {
+    type Error = T::Error;
+
+    fn set(&mut self, key: Key, value: Value) -> std::result::Result<(), Self::Error> {
+        let log = self.log.lock();
+        self.engine.set(key.clone(), value.clone())?;
+        self.log.append(log, Event::Set(key, value));
+        Ok(())
+    }
+
+    fn get(&self, key: Key) -> std::result::Result<Option<Value>, Self::Error> {
+        self.engine.get(key)
+    }
+
+    fn delete(&mut self, key: Key) -> std::result::Result<(), Self::Error> {
+        let log = self.log.lock();
+        self.engine.delete(key.clone())?;
+        self.log.append(log, Event::Delete(key));
+        Ok(())
+    }
+
+    fn compare_and_set(
+        &mut self,
+        key: Key,
+        expected: Option<Value>,
+        value: Option<Value>,
+    ) -> std::result::Result<Option<bool>, Self::Error> {
+        let log = self.log.lock();
+        let swapped = self
+            .engine
+            .compare_and_set(key.clone(), expected, value.clone())?;
+        if swapped == Some(true) {
+            let event = match value {
+                Some(value) => Event::Set(key, value),
+                None => Event::Delete(key),
+            };
+            self.log.append(log, event);
+        }
+        Ok(swapped)
+    }
+
+    fn scan(
+        &self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> std::result::Result<Box<dyn Scanner + '_>, Self::Error> {
+        self.engine.scan(lower_bound, upper_bound)
+    }
+
+    fn watch(
+        &self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> std::result::Result<Option<Receiver<Event>>, Self::Error> {
+        self.engine.watch(lower_bound, upper_bound)
+    }
+
+    fn snapshot(&self) -> std::result::Result<Option<Box<dyn Scanner + '_>>, Self::Error> {
+        self.engine.snapshot()
+    }
+
+    fn flush(&mut self) -> std::result::Result<(), Self::Error> {
+        self.engine.flush()
+    }
+
+    fn close(&mut self) -> std::result::Result<(), Self::Error> {
+        self.engine.close()
+    }
+}
+
+// Rejects writes, for the front-ends of a replica; the replica itself writes to the engine.
+#[derive(Clone)]
+pub struct ReadOnly<T: Engine> {
+    engine: T,
+}
+
+impl<T: Engine> ReadOnly<T> {
+    pub fn new(engine: T) -> Self {
+        Self { engine }
+    }
+}
+
+fn read_only() -> Error {
+    Error::new(Redirect, "replicas are read-only")
+}
+
+impl<T: Engine> Engine for ReadOnly<T> {
+    type Error = Error;
+
+    fn set(&mut self, _key: Key, _value: Value) -> Result<()> {
+        Err(read_only())
+    }
+
+    fn get(&self, key: Key) -> Result<Option<Value>> {
+        self.engine.get(key).map_err(Into::into)
+    }
+
+    fn delete(&mut self, _key: Key) -> Result<()> {
+        Err(read_only())
+    }
+
+    fn compare_and_set(
+        &mut self,
+        _key: Key,
+        _expected: Option<Value>,
+        _value: Option<Value>,
+    ) -> Result<Option<bool>> {
+        Err(read_only())
+    }
+
+    fn scan(
+        &self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Box<dyn Scanner + '_>> {
+        self.engine
+            .scan(lower_bound, upper_bound)
+            .map_err(Into::into)
+    }
+
+    fn watch(
+        &self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Option<Receiver<Event>>> {
+        self.engine
+            .watch(lower_bound, upper_bound)
+            .map_err(Into::into)
+    }
+
+    fn snapshot(&self) -> Result<Option<Box<dyn Scanner + '_>>> {
+        self.engine.snapshot().map_err(Into::into)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.engine.flush().map_err(Into::into)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.engine.close().map_err(Into::into)
+    }
+}
+
+struct Progress {
+    state: ReplicaState,
+    head: u64,
+    // `None` until the first full sync completes
+    applied: Option<u64>,
+}
+
+// Follows a primary from a background thread: a full sync first, then its changes.
+#[derive(Clone)]
+pub struct Replica {
+    primary: String,
+    credentials: Option<Credentials>,
+    progress: Arc<Mutex<Progress>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Replica {
+    pub fn new(primary: impl Into<String>) -> Self {
+        Self {
+            primary: primary.into(),
+            credentials: None,
+            progress: Arc::new(Mutex::new(Progress {
+                state: ReplicaState::Connecting,
+                head: 0,
+                applied: None,
+            })),
+            stopped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Needed when the primary has users; the user must be allowed to scan.
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    pub fn primary(&self) -> &str {
+        &self.primary
+    }
+
+    // The data in `engine` is replaced by the primary's on the first sync.
+    pub fn start<T: Engine + Send + 'static>(&self, mut engine: T) -> Result<()> {
+        let replica = self.clone();
+        Builder::new()
+            .name("bronzedb-replica".into())
+            .spawn(move || {
+                while !replica.stopped.load(Ordering::SeqCst) {
+                    if let Err(err) = replica.follow(&mut engine) {
+                        warn!("replication from {}: {}", replica.primary, err);
+                        replica.progress.lock().unwrap().state = ReplicaState::Connecting;
+                        sleep(RECONNECT);
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    // Stops following the primary within a heartbeat.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn status(&self) -> ReplicationStatus {
+        let progress = self.progress.lock().unwrap();
+        let applied = progress.applied.unwrap_or_default();
+        ReplicationStatus {
+            role: Role::Replica,
+            state: progress.state,
+            primary: Some(self.primary.clone()),
+            head: progress.head.max(applied),
+            applied,
+            replicas: 0,
+        }
+    }
+
+    fn follow<T: Engine>(&self, engine: &mut T) -> Result<()> {
+        let mut stream = connect(&self.primary, self.credentials.as_ref(), None)?;
+        info!("replicating from {}", self.primary);
+        while !self.stopped.load(Ordering::SeqCst) {
+            let after = self.progress.lock().unwrap().applied;
+            Request::Replicate { after }.write_to(&mut stream)?;
+            let response = Response::read_from(&mut stream, Action::Replicate)?;
+            expect_ok(response, "replicate request error")?;
+            let mut synced = None;
+            loop {
+                match Response::read_replication(&mut stream)? {
+                    Streamed::Item(ReplicationItem::Reset { head }) => {
+                        info!("full sync from {} at {}", self.primary, head);
+                        self.update(ReplicaState::Syncing, head, None);
+                        clear(engine, None, None)?;
+                        synced = Some(head);
+                    }
+                    Streamed::Item(ReplicationItem::Entry(key, value)) => {
+                        engine.set(key, value).map_err(Into::into)?
+                    }
+                    Streamed::Item(ReplicationItem::Change { seq, event }) => {
+                        match event {
+                            Event::Set(key, value) => engine.set(key, value),
+                            Event::Delete(key) => engine.delete(key),
+                        }
+                        .map_err(Into::into)?;
+                        self.update(ReplicaState::Streaming, seq, Some(seq));
+                    }
+                    Streamed::Item(ReplicationItem::Head(head)) => {
+                        let applied = self.progress.lock().unwrap().applied;
+                        self.update(ReplicaState::Streaming, head, applied);
+                    }
+                    Streamed::Paused if self.stopped.load(Ordering::SeqCst) => {
+                        Request::Cancel.write_to(&mut stream)?;
+                    }
+                    Streamed::Paused => {
+                        Request::More.write_to(&mut stream)?;
+                    }
+                    Streamed::Complete => break,
+                }
+            }
+            // a completed sync is followed by the changes made since
+            if let Some(head) = synced {
+                self.update(ReplicaState::Syncing, head, Some(head));
+            }
+        }
+        Ok(())
+    }
+
+    fn update(&self, state: ReplicaState, head: u64, applied: Option<u64>) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.state = state;
+        progress.head = head;
+        progress.applied = applied;
+    }
+}
+
+pub(crate) fn expect_ok(response: Response, message: &str) -> Result<()> {
+    match response {
+        Response::Status(OK) => Ok(()),
+        Response::Status(status) => Err(Error::new(status, message)),
+        _ => Err(Error::new(StatusCode::Corruption, message)),
+    }
+}
+
+// Deletes the keys between the bounds.
+pub(crate) fn clear<T: Engine>(
+    engine: &mut T,
+    lower_bound: Option<Key>,
+    upper_bound: Option<Key>,
+) -> Result<()> {
+    let keys = {
+        let mut scanner = engine.scan(lower_bound, upper_bound).map_err(Into::into)?;
+        let keys: Result<Vec<Key>> = scanner.iter().map(|entry| Ok(entry?.0)).collect();
+        keys?
+    };
+    for key in keys {
+        engine.delete(key).map_err(Into::into)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChangeLog, Next};
+    use bronzedb_util::types::Event;
+    use std::time::Duration;
+
+    fn set(log: &ChangeLog, key: &[u8]) {
+        log.append(log.lock(), Event::Set(key.to_vec().into(), Vec::new()));
+    }
+
+    #[test]
+    fn truncate() {
+        let log = ChangeLog::new(2);
+        assert_eq!(0, log.head());
+        assert!(log.contains(0));
+        assert!(!log.contains(1));
+        assert!(matches!(
+            log.next_after(0, Duration::from_millis(10)),
+            Next::Quiet(0)
+        ));
+        set(&log, b"a");
+        set(&log, b"b");
+        set(&log, b"c");
+        assert_eq!(3, log.head());
+        assert!(!log.contains(0));
+        assert!(log.contains(1));
+        assert!(matches!(log.next_after(0, Duration::default()), Next::Lost));
+        assert!(matches!(log.next_after(4, Duration::default()), Next::Lost));
+        match log.next_after(1, Duration::default()) {
+            Next::Change(2, event) => assert_eq!(b"b", event.key().as_slice()),
+            _ => panic!("expected the second change"),
+        }
+    }
+}
@@ -0,0 +1,785 @@
+use crate::accept;
+use crate::auth::{self, Permission, User};
+use crate::limit::Limits;
+use crate::listener::{Listener, Socket};
+use crate::shard::{self, Sharding};
+use crate::shutdown::{Shutdown, Tracked};
+use crate::timeout::{Clock, Timed, Timeouts};
+use bronzedb_engine::Engine;
+use bronzedb_protocol::request::Credentials;
+use bronzedb_protocol::{MAX_KEY_LEN, MAX_VALUE_LEN};
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Key, Value};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+
+const MAX_LINE_LEN: u64 = 1 << 16;
+const MAX_ARGS: i64 = 1 << 16;
+const MAX_BULK_LEN: i64 = 1 << 20;
+const DEFAULT_SCAN_COUNT: usize = 10;
+const MAX_CURSORS: usize = 1 << 12;
+
+// Serves the redis serialization protocol (RESP2 and RESP3) on top of any engine.
+pub struct RespServer<T: Engine> {
+    engine: T,
+    users: Arc<Vec<User>>,
+    limits: Limits,
+    timeouts: Timeouts,
+    shutdown: Shutdown,
+    sharding: Option<Sharding>,
+    cursors: Cursors,
+}
+
+impl<T: Engine + Clone + Sync + Send + 'static> RespServer<T> {
+    pub fn new(engine: T) -> Self {
+        Self {
+            engine,
+            users: Arc::new(Vec::new()),
+            limits: Limits::default(),
+            timeouts: Timeouts::default(),
+            shutdown: Shutdown::new(),
+            sharding: None,
+            cursors: Cursors::default(),
+        }
+    }
+
+    pub fn users(mut self, users: Vec<User>) -> Self {
+        self.users = Arc::new(users);
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    // Commands on keys of other shards fail with `WRONGSHARD`; SCAN only yields this shard's keys.
+    pub fn sharding(mut self, sharding: Sharding) -> Self {
+        self.sharding = Some(sharding);
+        self
+    }
+
+    // Returns once the shutdown handle is triggered.
+    pub fn serve<L: Listener>(&mut self, listener: L) -> Result<()> {
+        let engine = self.engine.clone();
+        let users = self.users.clone();
+        let timeouts = self.timeouts;
+        let sharding = self.sharding.clone();
+        let cursors = self.cursors.clone();
+        let handle = move |stream, addr: String, tracked: &Tracked| {
+            let clock = Clock::new(tracked);
+            let result = Timed::new(stream, timeouts, clock.clone())
+                .map_err(Error::from)
+                .and_then(|stream| {
+                    let session = Session::new(&users, sharding.as_ref(), &cursors);
+                    handle_client(stream, engine.clone(), session, &clock)
+                });
+            if let Err(err) = result {
+                warn!("{} from {}", err, addr);
+            }
+            info!("close resp connection from {}", addr);
+        };
+        accept::serve(listener, self.limits, &self.shutdown, handle, reject)
+    }
+}
+
+fn reject(mut stream: impl Write, addr: &str) {
+    warn!("server is busy, reject resp connection from {}", addr);
+    let _ = stream.write_all(b"-ERR max number of clients reached\r\n");
+}
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Array(Vec<Reply>),
+    Map(Vec<(Reply, Reply)>),
+}
+
+impl Reply {
+    fn write_to(&self, writer: &mut impl Write, resp3: bool) -> io::Result<()> {
+        match self {
+            Reply::Simple(status) => write!(writer, "+{}\r\n", status),
+            Reply::Error(message) => write!(writer, "-{}\r\n", message),
+            Reply::Integer(number) => write!(writer, ":{}\r\n", number),
+            Reply::Bulk(data) => {
+                write!(writer, "${}\r\n", data.len())?;
+                writer.write_all(data)?;
+                writer.write_all(b"\r\n")
+            }
+            Reply::Null if resp3 => writer.write_all(b"_\r\n"),
+            Reply::Null => writer.write_all(b"$-1\r\n"),
+            Reply::Array(items) => {
+                write!(writer, "*{}\r\n", items.len())?;
+                items
+                    .iter()
+                    .try_for_each(|item| item.write_to(writer, resp3))
+            }
+            Reply::Map(pairs) => {
+                if resp3 {
+                    write!(writer, "%{}\r\n", pairs.len())?;
+                } else {
+                    write!(writer, "*{}\r\n", pairs.len() * 2)?;
+                }
+                pairs.iter().try_for_each(|(key, value)| {
+                    key.write_to(writer, resp3)?;
+                    value.write_to(writer, resp3)
+                })
+            }
+        }
+    }
+}
+
+fn bulk(data: &str) -> Reply {
+    Reply::Bulk(data.as_bytes().to_vec())
+}
+
+fn syntax_error() -> Reply {
+    Reply::Error("ERR syntax error".to_owned())
+}
+
+fn engine_err(err: impl Into<Error>) -> Reply {
+    Reply::Error(format!("ERR {}", err.into()))
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    let read = reader
+        .by_ref()
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return Err(protocol_error("line is too long or truncated"));
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_number<N: std::str::FromStr>(data: &[u8]) -> Option<N> {
+    std::str::from_utf8(data).ok()?.parse().ok()
+}
+
+fn read_len(reader: &mut impl BufRead, prefix: u8, max: i64) -> io::Result<usize> {
+    let line = read_line(reader)?.ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+    match line.split_first() {
+        Some((first, len)) if *first == prefix => match parse_number::<i64>(len) {
+            Some(len) if (0..=max).contains(&len) => Ok(len as usize),
+            _ => Err(protocol_error("invalid length")),
+        },
+        _ => Err(protocol_error("unexpected type prefix")),
+    }
+}
+
+// Reads a multi bulk command or an inline command; Ok(None) means the peer has closed.
+fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let buf = reader.fill_buf()?;
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    if buf[0] != b'*' {
+        let line = read_line(reader)?.unwrap_or_default();
+        return Ok(Some(
+            line.split(u8::is_ascii_whitespace)
+                .filter(|arg| !arg.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect(),
+        ));
+    }
+    let count = read_len(reader, b'*', MAX_ARGS)?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = read_len(reader, b'$', MAX_BULK_LEN)?;
+        let mut arg = vec![0; len + 2];
+        reader.read_exact(&mut arg)?;
+        if !arg.ends_with(b"\r\n") {
+            return Err(protocol_error("bulk string is not terminated by CRLF"));
+        }
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+// Matches redis style glob patterns: `*`, `?`, `[a-z]`, `[^abc]` and `\` escapes. On a
+// mismatch only the last `*` takes one more byte, so a match is O(pattern * key).
+fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut p, mut k) = (0, 0);
+    let mut star = None;
+    while k < key.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, k));
+            continue;
+        }
+        match glob_step(pattern, p, key[k]) {
+            Some(next) => {
+                p = next;
+                k += 1;
+            }
+            None => match star {
+                Some((after, skipped)) => {
+                    p = after;
+                    k = skipped + 1;
+                    star = Some((after, k));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+// Matches one key byte against the pattern at `p`, giving where the pattern goes on.
+fn glob_step(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+    match pattern.get(p)? {
+        b'?' => Some(p + 1),
+        b'[' => {
+            let end = pattern[p + 1..].iter().position(|c| *c == b']')? + p + 1;
+            if class_match(&pattern[p + 1..end], c) {
+                Some(end + 1)
+            } else {
+                None
+            }
+        }
+        b'\\' if p + 1 < pattern.len() => {
+            if pattern[p + 1] == c {
+                Some(p + 2)
+            } else {
+                None
+            }
+        }
+        first if *first == c => Some(p + 1),
+        _ => None,
+    }
+}
+
+fn class_match(class: &[u8], c: u8) -> bool {
+    let (negated, mut class) = match class.split_first() {
+        Some((b'^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    while let Some((first, rest)) = class.split_first() {
+        match rest {
+            [b'-', last, rest @ ..] => {
+                matched |= (*first.min(last)..=*first.max(last)).contains(&c);
+                class = rest;
+            }
+            _ => {
+                matched |= *first == c;
+                class = rest;
+            }
+        }
+    }
+    matched != negated
+}
+
+// SCAN cursors handed out by a server, each standing for the last key a scan returned, so
+// the scan resumes after it however the keys before it changed. Clients expect an unsigned
+// 64 bit number and may continue a scan on another connection of their pool, so the server
+// numbers the keys and keeps the newest `MAX_CURSORS`; `0` starts and ends a scan.
+#[derive(Clone, Default)]
+struct Cursors(Arc<Mutex<BTreeMap<u64, Key>>>);
+
+impl Cursors {
+    fn save(&self, key: Key) -> u64 {
+        let mut keys = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let cursor = keys.keys().next_back().map_or(1, |last| last + 1);
+        keys.insert(cursor, key);
+        if keys.len() > MAX_CURSORS {
+            keys.pop_first();
+        }
+        cursor
+    }
+
+    fn resume(&self, cursor: u64) -> Option<Key> {
+        let keys = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        keys.get(&cursor).cloned()
+    }
+}
+
+struct Session<'a> {
+    users: &'a [User],
+    user: Option<&'a User>,
+    sharding: Option<&'a Sharding>,
+    cursors: &'a Cursors,
+    resp3: bool,
+    quit: bool,
+}
+
+impl<'a> Session<'a> {
+    fn new(users: &'a [User], sharding: Option<&'a Sharding>, cursors: &'a Cursors) -> Self {
+        Self {
+            users,
+            user: None,
+            sharding,
+            cursors,
+            resp3: false,
+            quit: false,
+        }
+    }
+
+    fn check(&self, permission: Permission, key: Option<&[u8]>) -> std::result::Result<(), Reply> {
+        if auth::permitted(self.users, self.user, permission, key) {
+            return Ok(());
+        }
+        Err(match self.user {
+            Some(user) => Reply::Error(format!(
+                "NOPERM user {} has no permissions to run this command or access this key",
+                user.name
+            )),
+            None => Reply::Error("NOAUTH Authentication required.".to_owned()),
+        })
+    }
+
+    fn route(&self, key: &[u8]) -> std::result::Result<(), Reply> {
+        match self.sharding {
+            Some(sharding) if !sharding.owns(key) => Err(wrong_shard()),
+            _ => Ok(()),
+        }
+    }
+
+    fn owns(&self, key: &[u8]) -> bool {
+        self.sharding.is_none_or(|sharding| sharding.owns(key))
+    }
+
+    fn authenticate(&mut self, credentials: Credentials) -> std::result::Result<(), Reply> {
+        if self.users.is_empty() {
+            return Ok(());
+        }
+        match auth::authenticate(self.users, &credentials) {
+            Some(user) => {
+                self.user = Some(user);
+                Ok(())
+            }
+            None => Err(Reply::Error(
+                "WRONGPASS invalid username-password pair or user is disabled.".to_owned(),
+            )),
+        }
+    }
+}
+
+fn wrong_shard() -> Reply {
+    Reply::Error("WRONGSHARD the key is served by another shard".to_owned())
+}
+
+fn string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).into_owned()
+}
+
+fn key(data: &[u8]) -> std::result::Result<Key, Reply> {
+    if data.len() > MAX_KEY_LEN {
+        return Err(Reply::Error("ERR key is too long".to_owned()));
+    }
+    Ok(data.to_vec().into())
+}
+
+fn handle_client<T: Engine, S: Socket>(
+    stream: Timed<S>,
+    mut engine: T,
+    mut session: Session,
+    clock: &Clock,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        clock.next(!reader.buffer().is_empty());
+        let args = match read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => break Ok(()),
+            Err(ref err) if err.kind() == ErrorKind::InvalidData => {
+                Reply::Error(format!("ERR Protocol error: {}", err))
+                    .write_to(&mut writer, session.resp3)?;
+                writer.flush()?;
+                break Ok(());
+            }
+            Err(err) => break Err(err.into()),
+        };
+        if args.is_empty() {
+            continue;
+        }
+        let reply = execute(&mut engine, &mut session, &args).unwrap_or_else(|reply| reply);
+        reply.write_to(&mut writer, session.resp3)?;
+        // flush once the pipelined commands are drained
+        if session.quit || reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+        if session.quit {
+            break Ok(());
+        }
+    }
+}
+
+fn execute<T: Engine>(
+    engine: &mut T,
+    session: &mut Session,
+    args: &[Vec<u8>],
+) -> std::result::Result<Reply, Reply> {
+    let name = string(&args[0]).to_ascii_uppercase();
+    let args = &args[1..];
+    match (name.as_str(), args.len()) {
+        ("PING", 0) => Ok(Reply::Simple("PONG")),
+        ("PING", 1) => Ok(Reply::Bulk(args[0].clone())),
+        ("QUIT", _) => {
+            session.quit = true;
+            Ok(Reply::Simple("OK"))
+        }
+        ("HELLO", _) => hello(session, args),
+        ("AUTH", 1) => session
+            .authenticate(Credentials::Token(string(&args[0])))
+            .map(|_| Reply::Simple("OK")),
+        ("AUTH", 2) => session
+            .authenticate(Credentials::Password {
+                user: string(&args[0]),
+                password: string(&args[1]),
+            })
+            .map(|_| Reply::Simple("OK")),
+        ("SELECT", 1) if args[0] == b"0" => Ok(Reply::Simple("OK")),
+        ("SELECT", 1) => Err(Reply::Error("ERR DB index is out of range".to_owned())),
+        ("COMMAND", _) => Ok(Reply::Array(Vec::new())),
+        ("CLIENT", _) => Ok(Reply::Simple("OK")),
+        ("GET", 1) => {
+            session.check(Permission::Read, Some(&args[0]))?;
+            session.route(&args[0])?;
+            match engine.get(key(&args[0])?).map_err(engine_err)? {
+                Some(value) => Ok(Reply::Bulk(value)),
+                None => Ok(Reply::Null),
+            }
+        }
+        ("SET", count) if count > 1 => set(engine, session, args),
+        ("DEL", count) if count > 0 => {
+            // refuse the whole command before deleting anything
+            let mut keys = Vec::with_capacity(args.len());
+            for arg in args {
+                session.check(Permission::Delete, Some(arg))?;
+                session.route(arg)?;
+                keys.push(key(arg)?);
+            }
+            let mut deleted = 0;
+            for key in keys {
+                if engine.get(key.clone()).map_err(engine_err)?.is_some() {
+                    shard::write(session.sharding, key, |key| engine.delete(key))
+                        .ok_or_else(wrong_shard)?
+                        .map_err(engine_err)?;
+                    deleted += 1;
+                }
+            }
+            Ok(Reply::Integer(deleted))
+        }
+        ("EXISTS", count) if count > 0 => {
+            let mut existing = 0;
+            for arg in args {
+                session.check(Permission::Read, Some(arg))?;
+                session.route(arg)?;
+                if engine.get(key(arg)?).map_err(engine_err)?.is_some() {
+                    existing += 1;
+                }
+            }
+            Ok(Reply::Integer(existing))
+        }
+        ("MGET", count) if count > 0 => {
+            for arg in args {
+                session.check(Permission::Read, Some(arg))?;
+                session.route(arg)?;
+            }
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+                values.push(match engine.get(key(arg)?).map_err(engine_err)? {
+                    Some(value) => Reply::Bulk(value),
+                    None => Reply::Null,
+                });
+            }
+            Ok(Reply::Array(values))
+        }
+        ("SCAN", count) if count > 0 => scan(engine, session, args),
+        ("PING", _)
+        | ("AUTH", _)
+        | ("SELECT", _)
+        | ("GET", _)
+        | ("SET", _)
+        | ("DEL", _)
+        | ("EXISTS", _)
+        | ("MGET", _)
+        | ("SCAN", _) => Err(Reply::Error(format!(
+            "ERR wrong number of arguments for '{}' command",
+            name.to_ascii_lowercase()
+        ))),
+        _ => Err(Reply::Error(format!(
+            "ERR unknown command '{}'",
+            name.to_ascii_lowercase()
+        ))),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Condition {
+    Always,
+    Missing,
+    Existing,
+}
+
+// Keys do not expire, so the expiry options are refused rather than ignored.
+fn set<T: Engine>(
+    engine: &mut T,
+    session: &Session,
+    args: &[Vec<u8>],
+) -> std::result::Result<Reply, Reply> {
+    let mut condition = Condition::Always;
+    for option in &args[2..] {
+        match option.to_ascii_uppercase().as_slice() {
+            b"NX" if condition == Condition::Always => condition = Condition::Missing,
+            b"XX" if condition == Condition::Always => condition = Condition::Existing,
+            b"EX" | b"PX" | b"EXAT" | b"PXAT" | b"KEEPTTL" => {
+                return Err(Reply::Error(
+                    "ERR keys do not expire, so SET takes no expiry".to_owned(),
+                ))
+            }
+            b"GET" => {
+                return Err(Reply::Error(
+                    "ERR the GET option of SET is not supported".to_owned(),
+                ))
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    session.check(Permission::Write, Some(&args[0]))?;
+    if args[1].len() > MAX_VALUE_LEN {
+        return Err(Reply::Error("ERR value is too long".to_owned()));
+    }
+    let value = args[1].clone();
+    let written = shard::write(session.sharding, key(&args[0])?, |key| {
+        conditional_set(engine, key, value, condition)
+    })
+    .ok_or_else(wrong_shard)?
+    .map_err(engine_err)?;
+    match written {
+        Some(true) => Ok(Reply::Simple("OK")),
+        Some(false) => Ok(Reply::Null),
+        None => Err(Reply::Error(
+            "ERR NX and XX are not supported by the engine".to_owned(),
+        )),
+    }
+}
+
+// Whether the value was set; `None` if the engine cannot compare and set.
+fn conditional_set<T: Engine>(
+    engine: &mut T,
+    key: Key,
+    value: Value,
+    condition: Condition,
+) -> std::result::Result<Option<bool>, T::Error> {
+    match condition {
+        Condition::Always => engine.set(key, value).map(|_| Some(true)),
+        Condition::Missing => engine.compare_and_set(key, None, Some(value)),
+        // a value changed since it was read is read again
+        Condition::Existing => loop {
+            let current = match engine.get(key.clone())? {
+                Some(current) => current,
+                None => return Ok(Some(false)),
+            };
+            match engine.compare_and_set(key.clone(), Some(current), Some(value.clone()))? {
+                Some(false) => continue,
+                swapped => return Ok(swapped),
+            }
+        },
+    }
+}
+
+fn hello(session: &mut Session, args: &[Vec<u8>]) -> std::result::Result<Reply, Reply> {
+    let mut args = args.iter();
+    let resp3 = match args.next().map(Vec::as_slice) {
+        None => session.resp3,
+        Some(b"2") => false,
+        Some(b"3") => true,
+        Some(_) => {
+            return Err(Reply::Error(
+                "NOPROTO unsupported protocol version".to_owned(),
+            ))
+        }
+    };
+    while let Some(option) = args.next() {
+        match (option.to_ascii_uppercase().as_slice(), args.next()) {
+            (b"AUTH", Some(user)) => {
+                let password = args.next().ok_or_else(syntax_error)?;
+                session.authenticate(Credentials::Password {
+                    user: string(user),
+                    password: string(password),
+                })?;
+            }
+            (b"SETNAME", Some(_)) => (),
+            _ => return Err(syntax_error()),
+        }
+    }
+    session.resp3 = resp3;
+    Ok(Reply::Map(vec![
+        (bulk("server"), bulk("bronzedb")),
+        (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+        (bulk("proto"), Reply::Integer(if resp3 { 3 } else { 2 })),
+        (bulk("mode"), bulk("standalone")),
+        (bulk("role"), bulk("master")),
+        (bulk("modules"), Reply::Array(Vec::new())),
+    ]))
+}
+
+fn scan<T: Engine>(
+    engine: &T,
+    session: &Session,
+    args: &[Vec<u8>],
+) -> std::result::Result<Reply, Reply> {
+    session.check(Permission::Scan, None)?;
+    let invalid_cursor = || Reply::Error("ERR invalid cursor".to_owned());
+    let cursor = match parse_number(&args[0]) {
+        Some(0) => None,
+        Some(cursor) => Some(session.cursors.resume(cursor).ok_or_else(invalid_cursor)?),
+        None => return Err(invalid_cursor()),
+    };
+    let mut pattern = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    for option in args[1..].chunks(2) {
+        match (option[0].to_ascii_uppercase().as_slice(), option.get(1)) {
+            (b"MATCH", Some(value)) => pattern = Some(value.as_slice()),
+            (b"COUNT", Some(value)) => {
+                count = parse_number(value)
+                    .filter(|count| *count > 0)
+                    .ok_or_else(syntax_error)?
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    let mut scanner = engine.scan(cursor.clone(), None).map_err(engine_err)?;
+    let entries = auth::visible(session.user, scanner.iter());
+    let mut keys = Vec::new();
+    let mut next = "0".to_owned();
+    let mut last: Option<Key> = None;
+    let mut examined = 0;
+    for result in entries {
+        let (key, _) = result.map_err(engine_err)?;
+        // the lower bound is inclusive, but the cursor's key was already returned
+        if cursor.as_ref() == Some(&key) {
+            continue;
+        }
+        if let Some(last) = last.as_ref().filter(|_| examined == count) {
+            next = session.cursors.save(last.clone()).to_string();
+            break;
+        }
+        // keys of other shards may linger while they are handed over
+        if session.owns(key.as_slice())
+            && pattern.is_none_or(|pattern| glob_match(pattern, key.as_slice()))
+        {
+            keys.push(Reply::Bulk(key.as_slice().to_vec()));
+        }
+        examined += 1;
+        last = Some(key);
+    }
+    Ok(Reply::Array(vec![bulk(&next), Reply::Array(keys)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, read_command, Cursors, Reply, MAX_CURSORS};
+    use bronzedb_protocol::MAX_KEY_LEN;
+    use std::io::Cursor;
+
+    fn encode(reply: Reply, resp3: bool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        reply.write_to(&mut buf, resp3).unwrap();
+        buf
+    }
+
+    #[test]
+    fn multi_bulk_command() {
+        let mut reader = Cursor::new(&b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nva\r\nl\r\n"[..]);
+        let args = read_command(&mut reader).unwrap().unwrap();
+        assert_eq!(
+            vec![b"SET".to_vec(), b"key".to_vec(), b"va\r\nl".to_vec()],
+            args
+        );
+        assert!(read_command(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn inline_command() {
+        let mut reader = Cursor::new(&b"GET  key\r\n"[..]);
+        let args = read_command(&mut reader).unwrap().unwrap();
+        assert_eq!(vec![b"GET".to_vec(), b"key".to_vec()], args);
+    }
+
+    #[test]
+    fn bad_command() {
+        assert!(read_command(&mut Cursor::new(&b"*1\r\n+GET\r\n"[..])).is_err());
+        assert!(read_command(&mut Cursor::new(&b"*1\r\n$-2\r\n"[..])).is_err());
+        assert!(read_command(&mut Cursor::new(&b"*1\r\n$3\r\nGETXX"[..])).is_err());
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(b"$-1\r\n".to_vec(), encode(Reply::Null, false));
+        assert_eq!(b"_\r\n".to_vec(), encode(Reply::Null, true));
+        let map = || Reply::Map(vec![(Reply::Bulk(b"proto".to_vec()), Reply::Integer(3))]);
+        assert_eq!(
+            b"*2\r\n$5\r\nproto\r\n:3\r\n".to_vec(),
+            encode(map(), false)
+        );
+        assert_eq!(b"%1\r\n$5\r\nproto\r\n:3\r\n".to_vec(), encode(map(), true));
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"user:*", b"user:1"));
+        assert!(!glob_match(b"user:*", b"admin:1"));
+        assert!(glob_match(b"h?llo", b"hallo"));
+        assert!(glob_match(b"h[ae]llo", b"hello"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-c]llo", b"hbllo"));
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+        assert!(glob_match(b"a*b*c", b"aXbYbZc"));
+        assert!(!glob_match(b"a*b*c", b"aXbYbZ"));
+        // would backtrack exponentially if every `*` retried its split
+        let pattern = [b"a*".repeat(32), b"b".to_vec()].concat();
+        assert!(!glob_match(&pattern, &[b'a'; 64]));
+    }
+
+    #[test]
+    fn cursor() {
+        let cursors = Cursors::default();
+        let first = cursors.save(b"a".to_vec().into());
+        assert_eq!(1, first);
+        let key = vec![255; MAX_KEY_LEN];
+        let second = cursors.save(key.clone().into());
+        assert_eq!(Some(key.into()), cursors.resume(second));
+        for i in 0..MAX_CURSORS {
+            cursors.save(i.to_string().into_bytes().into());
+        }
+        // the oldest cursors are dropped
+        assert_eq!(None, cursors.resume(first));
+        assert_eq!(None, cursors.resume(second));
+        assert!(cursors.resume(second + 1).is_some());
+    }
+}
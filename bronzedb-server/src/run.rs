@@ -0,0 +1,310 @@
+use crate::auth::hash_password;
+use crate::replication::RoleConfig;
+use crate::{
+    Broker, ChangeLog, Cluster, ClusterConfig, Clustered, HttpServer, Limits, Logged,
+    MemcachedServer, ReadOnly, Replica, Replication, ReplicationConfig, RespServer, Server,
+    Sharding, ShardingConfig, Shutdown, Timeouts, User,
+};
+use bronzedb_engine::Engine;
+use bronzedb_util::status::{Error, Result, StatusCode};
+use log::error;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::net::TcpListener;
+use std::process;
+use std::thread::spawn;
+use std::time::Duration;
+
+// The settings every server binary reads from `Settings.toml`; `engine` holds the ones of its
+// engine, next to the others in the same table.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ServerConfig<E> {
+    pub db_addr: String,
+    pub unix_socket: Option<String>,
+    pub resp_addr: Option<String>,
+    pub http_addr: Option<String>,
+    pub memcached_addr: Option<String>,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub tls_client_ca: Option<String>,
+    pub backup_dir: Option<String>,
+    #[serde(default)]
+    pub users: Vec<User>,
+    // lets users send their credentials over listeners without tls
+    #[serde(default)]
+    pub insecure_auth: bool,
+    #[serde(default)]
+    pub limits: Limits,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+    #[serde(default)]
+    pub replication: ReplicationConfig,
+    pub cluster: Option<ClusterConfig>,
+    pub sharding: Option<ShardingConfig>,
+    #[serde(flatten)]
+    pub engine: E,
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+fn config_error(err: config::ConfigError) -> Error {
+    Error::new(StatusCode::IOError, format!("invalid settings: {}", err))
+}
+
+impl<E: DeserializeOwned> ServerConfig<E> {
+    // Reads `Settings` in the working directory, in any format the config crate knows.
+    pub fn load() -> Result<Self> {
+        let mut settings = config::Config::default();
+        settings
+            .merge(config::File::with_name("Settings"))
+            .map_err(config_error)?;
+        settings.try_into().map_err(config_error)
+    }
+}
+
+// Loads the settings, opens the engine with its own ones and serves it on every configured
+// listener until SIGINT or SIGTERM; the engine is closed once in-flight requests finished.
+// `<binary> hash-password <password>` prints the `password_pbkdf2` of a user instead.
+pub fn run<E, T, F>(open: F) -> Result<()>
+where
+    E: DeserializeOwned,
+    T: Engine + Clone + Send + Sync + 'static,
+    F: FnOnce(&E) -> T,
+{
+    let args = env::args().collect::<Vec<_>>();
+    if let [_, command, password] = args.as_slice() {
+        if command == "hash-password" {
+            println!("{}", hash_password(password));
+            return Ok(());
+        }
+    }
+    let config = ServerConfig::<E>::load()?;
+    check_plaintext_auth(&config)?;
+    let engine = open(&config.engine);
+    let replication = &config.replication;
+    if let Some(ref cluster) = config.cluster {
+        if replication.role != RoleConfig::Standalone {
+            return Err(Error::new(
+                StatusCode::IOError,
+                "cluster members cannot be primaries or replicas",
+            ));
+        }
+        let cluster = Cluster::new(cluster.clone())?;
+        cluster.start(engine.clone())?;
+        let engine = Clustered::new(engine, cluster.clone());
+        return serve(engine, Replication::Standalone, Some(cluster), &config);
+    }
+    match replication.role {
+        RoleConfig::Standalone => serve(engine, Replication::Standalone, None, &config),
+        RoleConfig::Primary => {
+            let log = ChangeLog::new(replication.log_size);
+            let engine = Logged::new(engine, log.clone());
+            serve(engine, Replication::Primary(log), None, &config)
+        }
+        RoleConfig::Replica => {
+            let primary = replication.primary.clone().ok_or_else(|| {
+                Error::new(
+                    StatusCode::IOError,
+                    "a replica needs the address of its primary",
+                )
+            })?;
+            let mut replica = Replica::new(primary);
+            if let Some(credentials) = replication.credentials() {
+                replica = replica.credentials(credentials);
+            }
+            replica.start(engine.clone())?;
+            serve(
+                ReadOnly::new(engine),
+                Replication::Replica(replica),
+                None,
+                &config,
+            )
+        }
+    }
+}
+
+fn serve<T: Engine + Clone + Send + Sync + 'static, E>(
+    mut engine: T,
+    replication: Replication,
+    cluster: Option<Cluster>,
+    config: &ServerConfig<E>,
+) -> Result<()> {
+    let listener = TcpListener::bind(&config.db_addr)?;
+    let sharding = match config.sharding {
+        Some(ref config) => {
+            let sharding = Sharding::open(
+                config.node.as_str(),
+                config.partition_map()?,
+                &config.map_path,
+            )?;
+            Some(match config.credentials() {
+                Some(credentials) => sharding.credentials(credentials),
+                None => sharding,
+            })
+        }
+        None => None,
+    };
+    let shutdown = Shutdown::new();
+    let broker = Broker::new();
+    let handle = shutdown.clone();
+    // a second signal exits at once
+    ctrlc::set_handler(move || {
+        if handle.is_triggered() {
+            process::exit(1);
+        }
+        handle.trigger();
+    })
+    .map_err(|err| Error::new(StatusCode::IOError, err.to_string()))?;
+    #[cfg(unix)]
+    if let Some(ref path) = config.unix_socket {
+        let mut unix = Server::new(engine.clone())
+            .users(config.users.clone())
+            .limits(config.limits)
+            .timeouts(config.timeouts)
+            .shutdown(shutdown.clone())
+            .broker(broker.clone())
+            .replication(replication.clone());
+        if let Some(ref dir) = config.backup_dir {
+            unix = unix.backup_dir(dir);
+        }
+        if let Some(ref cluster) = cluster {
+            unix = unix.cluster(cluster.clone());
+        }
+        if let Some(ref sharding) = sharding {
+            unix = unix.sharding(sharding.clone());
+        }
+        let unix_listener = crate::listener::bind_unix(path)?;
+        spawn_listener("unix", move || unix.serve(unix_listener));
+    }
+    if let Some(ref addr) = config.resp_addr {
+        let mut resp = RespServer::new(engine.clone())
+            .users(config.users.clone())
+            .limits(config.limits)
+            .timeouts(config.timeouts)
+            .shutdown(shutdown.clone());
+        if let Some(ref sharding) = sharding {
+            resp = resp.sharding(sharding.clone());
+        }
+        let resp_listener = TcpListener::bind(addr)?;
+        spawn_listener("resp", move || resp.serve(resp_listener));
+    }
+    if let Some(ref addr) = config.http_addr {
+        let mut http = HttpServer::new(engine.clone())
+            .users(config.users.clone())
+            .limits(config.limits)
+            .timeouts(config.timeouts)
+            .shutdown(shutdown.clone());
+        if let Some(ref sharding) = sharding {
+            http = http.sharding(sharding.clone());
+        }
+        let http_listener = TcpListener::bind(addr)?;
+        spawn_listener("http", move || http.serve(http_listener));
+    }
+    if let Some(ref addr) = config.memcached_addr {
+        let mut memcached = MemcachedServer::new(engine.clone())
+            .users(config.users.clone())
+            .limits(config.limits)
+            .timeouts(config.timeouts)
+            .shutdown(shutdown.clone());
+        if let Some(ref sharding) = sharding {
+            memcached = memcached.sharding(sharding.clone());
+        }
+        let memcached_listener = TcpListener::bind(addr)?;
+        spawn_listener("memcached", move || memcached.serve(memcached_listener));
+    }
+    let server = Server::new(engine.clone())
+        .users(config.users.clone())
+        .limits(config.limits)
+        .timeouts(config.timeouts)
+        .shutdown(shutdown.clone())
+        .broker(broker)
+        .replication(replication.clone());
+    let server = match config.backup_dir {
+        Some(ref dir) => server.backup_dir(dir),
+        None => server,
+    };
+    let server = match cluster {
+        Some(ref cluster) => server.cluster(cluster.clone()),
+        None => server,
+    };
+    let server = match sharding {
+        Some(sharding) => server.sharding(sharding),
+        None => server,
+    };
+    enable_tls(server, config)?.serve(listener)?;
+    shutdown.wait(Duration::from_secs(config.shutdown_timeout));
+    if let Replication::Replica(ref replica) = replication {
+        replica.stop();
+    }
+    if let Some(ref cluster) = cluster {
+        cluster.stop();
+    }
+    engine.close().map_err(Into::into)
+}
+
+// Users send their password or token with every authentication, so it only crosses the
+// network in plaintext once `insecure_auth` allows it: the native listener needs tls, the RESP,
+// HTTP and memcached ones have none. The unix socket stays on the host.
+fn check_plaintext_auth<E>(config: &ServerConfig<E>) -> Result<()> {
+    if config.users.is_empty() || config.insecure_auth {
+        return Ok(());
+    }
+    let plaintext = [
+        ("db_addr", config.tls_cert.is_none()),
+        ("resp_addr", config.resp_addr.is_some()),
+        ("http_addr", config.http_addr.is_some()),
+        ("memcached_addr", config.memcached_addr.is_some()),
+    ];
+    match plaintext.iter().find(|(_, plaintext)| *plaintext) {
+        Some((name, _)) => Err(Error::new(
+            StatusCode::IOError,
+            format!(
+                "users would authenticate on {} in plaintext; configure tls or set insecure_auth",
+                name
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+// Listeners only return on shutdown, unless they cannot be woken up for it.
+fn spawn_listener(name: &'static str, serve: impl FnOnce() -> Result<()> + Send + 'static) {
+    spawn(move || {
+        if let Err(err) = serve() {
+            error!("{} listener failed: {}", name, err);
+        }
+    });
+}
+
+#[cfg(feature = "tls")]
+fn enable_tls<T: Engine + Clone + Send + Sync + 'static, E>(
+    server: Server<T>,
+    config: &ServerConfig<E>,
+) -> Result<Server<T>> {
+    use crate::tls::server_config;
+    match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => {
+            Ok(server.tls(server_config(cert, key, config.tls_client_ca.as_ref())?))
+        }
+        _ => Ok(server),
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+fn enable_tls<T: Engine + Clone + Send + Sync + 'static, E>(
+    server: Server<T>,
+    config: &ServerConfig<E>,
+) -> Result<Server<T>> {
+    match config.tls_cert {
+        Some(_) => Err(Error::new(
+            StatusCode::IOError,
+            "tls is configured, but the server is built without the tls feature",
+        )),
+        None => Ok(server),
+    }
+}
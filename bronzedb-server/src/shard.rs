@@ -0,0 +1,614 @@
+use crate::replication::{clear, connect, credentials, expect_ok};
+use bronzedb_engine::Engine;
+use bronzedb_protocol::replication::ReplicationItem;
+use bronzedb_protocol::request::Request::{self, *};
+use bronzedb_protocol::request::{Action, Credentials};
+use bronzedb_protocol::response::{Response, Streamed};
+use bronzedb_protocol::shard::{
+    predecessor, successor, MigrationCommand, MigrationState, MigrationStatus, PartitionMap, Shard,
+};
+use bronzedb_protocol::SCAN_WINDOW;
+use bronzedb_util::status::StatusCode::*;
+use bronzedb_util::status::{Error, Result};
+use bronzedb_util::types::{Entry, Event, Key};
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread::{sleep, Builder};
+use std::time::{Duration, Instant};
+
+// How long the source of a migration waits for the last changes once it stopped taking
+// writes to the keys.
+const DRAIN: Duration = Duration::from_millis(100);
+
+// How long a new partition map may take to reach another server.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+// `lower` is the first key of the shard, as a string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardConfig {
+    pub lower: String,
+    pub addr: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardingConfig {
+    // the address of this server in the partition map
+    pub node: String,
+    #[serde(default)]
+    pub version: u64,
+    pub shards: Vec<ShardConfig>,
+    // where the server keeps the newest partition map, which replaces `shards` once migrations
+    // moved keys, unless `version` is raised above it
+    #[serde(default = "default_map_path")]
+    pub map_path: String,
+    // credentials to authenticate with the other servers, if they have users
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+fn default_map_path() -> String {
+    "partition.map".to_owned()
+}
+
+impl ShardingConfig {
+    pub fn partition_map(&self) -> Result<PartitionMap> {
+        let shards = self
+            .shards
+            .iter()
+            .map(|shard| Shard {
+                lower: shard.lower.as_bytes().to_vec().into(),
+                addr: shard.addr.clone(),
+            })
+            .collect();
+        PartitionMap::new(self.version, shards)
+    }
+
+    pub fn credentials(&self) -> Option<Credentials> {
+        credentials(&self.user, &self.password, &self.token)
+    }
+}
+
+struct Progress {
+    status: MigrationStatus,
+    aborted: bool,
+}
+
+// The partition map as known to a server, which serves only the keys of the shards at
+// its own address. Every server of a deployment should share the same map.
+#[derive(Clone)]
+pub struct Sharding {
+    node: Arc<str>,
+    map: Arc<RwLock<PartitionMap>>,
+    path: Option<Arc<Path>>,
+    credentials: Option<Credentials>,
+    progress: Arc<Mutex<Progress>>,
+}
+
+impl Sharding {
+    pub fn new(node: impl Into<String>, map: PartitionMap) -> Self {
+        Self {
+            node: node.into().into(),
+            map: Arc::new(RwLock::new(map)),
+            path: None,
+            credentials: None,
+            progress: Arc::new(Mutex::new(Progress {
+                status: MigrationStatus::default(),
+                aborted: false,
+            })),
+        }
+    }
+
+    // Like `new`, but keeps the map in the file at `path`, so a restarted server goes on with
+    // the map of the last migration it took part in. Of the stored map and `map`, the one with
+    // the higher version is served, the stored one if they are the same.
+    pub fn open(
+        node: impl Into<String>,
+        map: PartitionMap,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self> {
+        let path = path.into();
+        let map = match load(&path)? {
+            Some(stored) if stored.version() >= map.version() => stored,
+            _ => {
+                store(&path, &map)?;
+                map
+            }
+        };
+        let mut sharding = Self::new(node, map);
+        sharding.path = Some(path.into());
+        Ok(sharding)
+    }
+
+    // Needed to take over keys when the other servers have users; the user must be allowed
+    // to write and delete any key.
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    pub fn map(&self) -> PartitionMap {
+        self.read().clone()
+    }
+
+    // Replaces the map if `map` is newer; clients learn of it once refused with `WrongShard`.
+    // Waits for the writes in flight, see `write`, and stores the map before serving it.
+    pub fn update(&self, map: PartitionMap) -> Result<bool> {
+        let mut current = self.write();
+        if map.version() <= current.version() {
+            return Ok(false);
+        }
+        self.store(&map)?;
+        *current = map;
+        Ok(true)
+    }
+
+    // Goes back to `previous` after a failed handover, unless the map changed since.
+    fn restore(&self, moved: &PartitionMap, previous: PartitionMap) {
+        let mut current = self.write();
+        if *current != *moved {
+            return;
+        }
+        if let Err(err) = self.store(&previous) {
+            warn!("cannot store the partition map: {}", err);
+        }
+        *current = previous;
+    }
+
+    fn store(&self, map: &PartitionMap) -> Result<()> {
+        match self.path {
+            Some(ref path) => store(path, map),
+            None => Ok(()),
+        }
+    }
+
+    pub fn owns(&self, key: &[u8]) -> bool {
+        self.read().owner(key) == &*self.node
+    }
+
+    // Whether the keys between the bounds are in a single shard of this server.
+    pub fn owns_range(&self, lower_bound: Option<&Key>, upper_bound: Option<&Key>) -> bool {
+        let map = self.read();
+        let first = map.position(lower_bound.map_or(&[][..], |key| key.as_slice()));
+        let last = match upper_bound {
+            Some(key) => map.position(key),
+            None => map.shards().len() - 1,
+        };
+        first == last && map.shards()[first].addr == *self.node
+    }
+
+    // The last migration to this server.
+    pub fn migration(&self) -> MigrationStatus {
+        self.progress().status.clone()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, PartitionMap> {
+        self.map.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, PartitionMap> {
+        self.map.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn progress(&self) -> MutexGuard<'_, Progress> {
+        self.progress.lock().unwrap()
+    }
+
+    // Runs a command sent to the server that should take over the keys.
+    pub(crate) fn migrate<T: Engine + Send + 'static>(
+        &self,
+        command: MigrationCommand,
+        engine: T,
+    ) -> Result<MigrationStatus> {
+        let mut progress = self.progress();
+        let running = matches!(
+            progress.status.state,
+            MigrationState::Copying | MigrationState::CatchingUp
+        );
+        match command {
+            MigrationCommand::Start { .. } if running => {
+                return Err(Error::new(Unavailable, "a migration is running"));
+            }
+            MigrationCommand::Start { lower, upper, rate } => {
+                let map = self.map();
+                let index = map.position(&lower);
+                let source = map.shards()[index].addr.clone();
+                if source == *self.node {
+                    return Err(Error::new(WrongShard, "the keys are on this server"));
+                }
+                let upper =
+                    upper.or_else(|| map.shards().get(index + 1).map(|next| next.lower.clone()));
+                map.split(
+                    &lower,
+                    upper.as_ref().map(|upper| upper.as_slice()),
+                    &self.node,
+                )?;
+                progress.status = MigrationStatus {
+                    state: MigrationState::Copying,
+                    source,
+                    lower,
+                    upper,
+                    rate,
+                    ..MigrationStatus::default()
+                };
+                progress.aborted = false;
+                let sharding = self.clone();
+                Builder::new()
+                    .name("bronzedb-migration".into())
+                    .spawn(move || {
+                        let result = sharding.take_over(engine);
+                        let mut progress = sharding.progress();
+                        match result {
+                            Ok(()) => progress.status.state = MigrationState::Done,
+                            Err(err) => {
+                                warn!("migration from {}: {}", progress.status.source, err);
+                                progress.status.state = MigrationState::Failed;
+                                progress.status.error = Some(err.to_string());
+                            }
+                        }
+                    })?;
+            }
+            MigrationCommand::Throttle(rate) => progress.status.rate = rate,
+            MigrationCommand::Abort => progress.aborted = running,
+            MigrationCommand::Status => (),
+        }
+        Ok(progress.status.clone())
+    }
+
+    // Copies the keys from their server and follows the changes to them until it hands
+    // them over, then passes the new map on to the other servers.
+    fn take_over<T: Engine>(&self, mut engine: T) -> Result<()> {
+        let (source, lower, upper) = {
+            let status = &self.progress().status;
+            (
+                status.source.clone(),
+                status.lower.clone(),
+                status.upper.clone(),
+            )
+        };
+        let last = upper.as_ref().map(|upper| predecessor(upper));
+        // left from an earlier migration away from this server
+        clear(&mut engine, Some(lower.clone()), last)?;
+        let mut stream = connect(&source, self.credentials.as_ref(), None)?;
+        info!("taking over keys from {}", source);
+        Request::Transfer {
+            lower,
+            upper,
+            target: self.node.to_string(),
+        }
+        .write_to(&mut stream)?;
+        expect_ok(
+            Response::read_from(&mut stream, Action::Transfer)?,
+            "transfer request error",
+        )?;
+        self.receive(&mut stream, &mut engine, true)?;
+        if self.progress().aborted {
+            return Err(Error::new(Unavailable, "migration aborted"));
+        }
+        self.progress().status.state = MigrationState::CatchingUp;
+        // the source takes no more writes to the keys and sends the last changes
+        Request::More.write_to(&mut stream)?;
+        expect_ok(
+            Response::read_from(&mut stream, Action::Transfer)?,
+            "transfer request error",
+        )?;
+        self.receive(&mut stream, &mut engine, false)?;
+        let map = match Response::read_from(&mut stream, Action::ShardMap)? {
+            Response::Shards(map) => map,
+            _ => return Err(Error::new(Corruption, "expected the new partition map")),
+        };
+        self.update(map.clone())?;
+        // the source keeps the keys until it learns that they are applied here
+        Request::More.write_to(&mut stream)?;
+        info!(
+            "took over keys from {}, partition map version {}",
+            source,
+            map.version()
+        );
+        // the source as well, in case it missed the acknowledgement and went back to its map
+        let mut addrs: Vec<_> = map
+            .shards()
+            .iter()
+            .map(|shard| shard.addr.as_str())
+            .filter(|&addr| addr != &*self.node)
+            .collect();
+        addrs.sort_unstable();
+        addrs.dedup();
+        for addr in addrs {
+            if let Err(err) = self.publish(addr, &map) {
+                warn!("partition map to {}: {}", addr, err);
+            }
+        }
+        Ok(())
+    }
+
+    fn receive<T: Engine, S: Read + Write>(
+        &self,
+        mut stream: S,
+        engine: &mut T,
+        abortable: bool,
+    ) -> Result<()> {
+        let mut pace = Instant::now();
+        loop {
+            match Response::read_replication(&mut stream)? {
+                Streamed::Item(ReplicationItem::Entry(key, value)) => {
+                    self.throttle(&mut pace)?;
+                    engine.set(key, value).map_err(Into::into)?;
+                    self.progress().status.copied += 1;
+                }
+                Streamed::Item(ReplicationItem::Change { event, .. }) => {
+                    match event {
+                        Event::Set(key, value) => engine.set(key, value),
+                        Event::Delete(key) => engine.delete(key),
+                    }
+                    .map_err(Into::into)?;
+                    let status = &mut self.progress().status;
+                    status.state = MigrationState::CatchingUp;
+                    status.changes += 1;
+                }
+                Streamed::Item(_) => {
+                    return Err(Error::new(Corruption, "unexpected item in a transfer"));
+                }
+                Streamed::Paused if abortable && self.progress().aborted => {
+                    return Err(Error::new(Unavailable, "migration aborted"));
+                }
+                Streamed::Paused => {
+                    Request::More.write_to(&mut stream)?;
+                }
+                Streamed::Complete => return Ok(()),
+            }
+        }
+    }
+
+    // Keeps the copy below the rate of the migration, or gives it up once aborted.
+    fn throttle(&self, pace: &mut Instant) -> Result<()> {
+        let (rate, aborted) = {
+            let progress = self.progress();
+            (progress.status.rate, progress.aborted)
+        };
+        if aborted {
+            return Err(Error::new(Unavailable, "migration aborted"));
+        }
+        if rate > 0 {
+            let now = Instant::now();
+            *pace = (*pace).max(now) + Duration::from_secs(1) / rate;
+            sleep(*pace - now);
+        }
+        Ok(())
+    }
+
+    fn publish(&self, addr: &str, map: &PartitionMap) -> Result<()> {
+        let mut stream = connect(addr, self.credentials.as_ref(), Some(PUBLISH_TIMEOUT))?;
+        Request::UpdateShardMap(map.clone()).write_to(&mut stream)?;
+        let response = Response::read_from(&mut stream, Action::UpdateShardMap)?;
+        expect_ok(response, "update shard map error")
+    }
+
+    // Streams the keys from `lower` up to `upper` to the server taking them over, then the
+    // changes made to them since. Once the target applied them, it stops taking writes to the
+    // keys and sends the last changes and the new map. The keys are dropped once the target
+    // acknowledged the map; if it does not, this server goes back to the old map.
+    pub(crate) fn transfer<T: Engine, S: Read + Write>(
+        &self,
+        engine: &mut T,
+        mut stream: S,
+        lower: Key,
+        upper: Option<Key>,
+        target: &str,
+        mut paused: impl FnMut(),
+    ) -> Result<()> {
+        let map = self.map();
+        let split = map.split(&lower, upper.as_ref().map(|upper| upper.as_slice()), target);
+        let moved = match split {
+            Ok(moved) if map.owner(&lower) == &*self.node && target != &*self.node => moved,
+            _ => {
+                Response::Status(WrongShard).write_to(&mut stream)?;
+                return Ok(());
+            }
+        };
+        let last = upper.as_ref().map(|upper| predecessor(upper));
+        let watched = engine.watch(Some(lower.clone()), last.clone());
+        let receiver = match watched.map_err(Into::into)? {
+            Some(receiver) => receiver,
+            None => {
+                Response::Status(UnknownAction).write_to(&mut stream)?;
+                return Err(Error::new(UnknownAction, "engine cannot watch keys"));
+            }
+        };
+        info!("handing over keys to {}", target);
+        let mut seq = 0;
+        let mut change = |event| {
+            seq += 1;
+            Some(Ok(ReplicationItem::Change { seq, event }))
+        };
+        let entries = pages(&*engine, lower.clone(), last.clone())
+            .map(|entry| Some(entry.map(|(key, value)| ReplicationItem::Entry(key, value))));
+        let backlog = iter::from_fn(|| receiver.try_recv().ok()).map(&mut change);
+        Response::write_replication(entries.chain(backlog), &mut stream, &mut paused)?;
+        // the target applied the copy once it asks for the rest
+        paused();
+        if !matches!(Request::read_from(&mut stream)?, More) {
+            return Err(Error::new(Unavailable, "transfer given up"));
+        }
+        if !self.update(moved.clone())? {
+            return Err(Error::new(
+                Unavailable,
+                "partition map changed during a transfer",
+            ));
+        }
+        let drained = iter::from_fn(|| receiver.recv_timeout(DRAIN).ok()).map(&mut change);
+        if let Err(err) = hand_over(&mut stream, drained, &moved, &mut paused) {
+            self.restore(&moved, map);
+            return Err(err);
+        }
+        drop(receiver);
+        info!("handed over keys to {}", target);
+        clear(engine, Some(lower), last)
+    }
+}
+
+// Sends the last changes and the new map to the target of a transfer, and waits for it to
+// acknowledge them.
+fn hand_over<S: Read + Write>(
+    mut stream: S,
+    drained: impl Iterator<Item = Option<Result<ReplicationItem>>>,
+    moved: &PartitionMap,
+    mut paused: impl FnMut(),
+) -> Result<()> {
+    Response::write_replication(drained, &mut stream, &mut paused)?;
+    Response::Shards(moved.clone()).write_to(&mut stream)?;
+    paused();
+    match Request::read_from(&mut stream)? {
+        More => Ok(()),
+        _ => Err(Error::new(Unavailable, "transfer given up")),
+    }
+}
+
+fn load(path: &Path) -> Result<Option<PartitionMap>> {
+    match File::open(path) {
+        Ok(file) => PartitionMap::read_from(BufReader::new(file)).map(Some),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Replaces the file at once, so a crash leaves either map.
+fn store(path: &Path, map: &PartitionMap) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let mut file = File::create(&temp)?;
+    map.write_to(&mut file)?;
+    file.sync_all()?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+// Scans in pages, so the engine is not held while the entries are sent.
+fn pages<T: Engine>(
+    engine: &T,
+    lower: Key,
+    last: Option<Key>,
+) -> impl Iterator<Item = Result<Entry>> + '_ {
+    let mut next = Some(lower);
+    let mut page = VecDeque::new();
+    iter::from_fn(move || loop {
+        if let Some(entry) = page.pop_front() {
+            return Some(Ok(entry));
+        }
+        let scanned = engine
+            .scan(Some(next.take()?), last.clone())
+            .map_err(Into::into)
+            .and_then(|mut scanner| {
+                scanner
+                    .iter()
+                    .take(SCAN_WINDOW)
+                    .collect::<Result<VecDeque<Entry>>>()
+            });
+        match scanned {
+            Ok(entries) => {
+                if entries.len() == SCAN_WINDOW {
+                    next = entries.back().and_then(|(key, _)| successor(key));
+                }
+                page = entries;
+            }
+            Err(err) => return Some(Err(err)),
+        }
+    })
+}
+
+// Applies a write while the map cannot change, so a handover waits for the writes in flight.
+// `None` if the key is not served here.
+pub(crate) fn write<R>(
+    sharding: Option<&Sharding>,
+    key: Key,
+    write: impl FnOnce(Key) -> R,
+) -> Option<R> {
+    let sharding = match sharding {
+        Some(sharding) => sharding,
+        None => return Some(write(key)),
+    };
+    let map = sharding.read();
+    if map.owner(&key) != &*sharding.node {
+        return None;
+    }
+    Some(write(key))
+}
+
+// Whether a read touches keys the server does not serve; writes are checked by `write`.
+pub(crate) fn misrouted(sharding: Option<&Sharding>, request: &Request) -> bool {
+    let sharding = match sharding {
+        Some(sharding) => sharding,
+        None => return false,
+    };
+    match request {
+        Get(key) => !sharding.owns(key),
+        Scan {
+            lower_bound,
+            upper_bound,
+        }
+        | Watch {
+            lower_bound,
+            upper_bound,
+        } => !sharding.owns_range(lower_bound.as_ref(), upper_bound.as_ref()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{misrouted, write, ShardConfig, Sharding, ShardingConfig};
+    use bronzedb_protocol::request::Request;
+
+    #[test]
+    fn route() {
+        let shard = |lower: &str, addr: &str| ShardConfig {
+            lower: lower.to_owned(),
+            addr: addr.to_owned(),
+        };
+        let config = ShardingConfig {
+            node: "b".to_owned(),
+            version: 1,
+            shards: vec![shard("", "a"), shard("h", "b"), shard("p", "a")],
+            map_path: String::new(),
+            user: None,
+            password: None,
+            token: None,
+        };
+        let sharding = Sharding::new("b", config.partition_map().unwrap());
+        let key = |key: &str| key.as_bytes().to_vec().into();
+        let scan = |lower: &str, upper: &str| Request::Scan {
+            lower_bound: Some(key(lower)),
+            upper_bound: Some(key(upper)),
+        };
+        assert!(sharding.owns(b"h"));
+        assert!(!sharding.owns(b"p"));
+        assert!(misrouted(Some(&sharding), &Request::Get(key("a"))));
+        assert!(!misrouted(Some(&sharding), &Request::Get(key("ozz"))));
+        assert!(!misrouted(Some(&sharding), &scan("i", "o")));
+        assert!(misrouted(Some(&sharding), &scan("i", "q")));
+        assert!(misrouted(
+            Some(&sharding),
+            &Request::Scan {
+                lower_bound: None,
+                upper_bound: Some(key("b")),
+            }
+        ));
+        assert!(!misrouted(None, &Request::Get(key("a"))));
+        assert_eq!(Some(1), write(Some(&sharding), key("i"), |_| 1));
+        assert_eq!(None, write(Some(&sharding), key("a"), |_| 1));
+        assert_eq!(Some(1), write(None, key("a"), |_| 1));
+    }
+}
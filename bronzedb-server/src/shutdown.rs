@@ -0,0 +1,152 @@
+use crate::listener::{Closer, Socket, Waker};
+use std::collections::HashMap;
+use std::io;
+use std::net;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+// A handle to stop servers gracefully, shared by every server it is given to.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    triggered: AtomicBool,
+    state: Mutex<State>,
+    closed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    connections: HashMap<u64, Connection>,
+    wakers: Vec<Waker>,
+}
+
+struct Connection {
+    close: Closer,
+    idle: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.inner.triggered.load(Ordering::SeqCst)
+    }
+
+    // Stops accepting connections and drains them: idle connections are closed at once, busy
+    // ones once they finished their in-flight request, including a scan waiting for its client.
+    pub fn trigger(&self) {
+        let state = self.state();
+        self.inner.triggered.store(true, Ordering::SeqCst);
+        for wake in &state.wakers {
+            wake();
+        }
+        // a connection becoming idle after this sees the trigger by itself; idle ones have
+        // nothing in flight, and closing only their read side does not wake a blocked read
+        for connection in state.connections.values() {
+            if connection.idle.load(Ordering::SeqCst) {
+                (connection.close)(net::Shutdown::Both);
+            }
+        }
+    }
+
+    // Waits for the connections to end, closing those still open after `timeout`.
+    // Returns false if any of them had to be closed.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let (state, result) = self
+            .inner
+            .closed
+            .wait_timeout_while(self.state(), timeout, |state| !state.connections.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+        if result.timed_out() {
+            for connection in state.connections.values() {
+                (connection.close)(net::Shutdown::Both);
+            }
+        }
+        !result.timed_out()
+    }
+
+    pub(crate) fn register(&self, waker: Waker) {
+        let mut state = self.state();
+        if self.is_triggered() {
+            waker();
+        }
+        state.wakers.push(waker);
+    }
+
+    pub(crate) fn track(&self, socket: &impl Socket) -> io::Result<Tracked> {
+        let close = socket.closer()?;
+        let idle = Arc::new(AtomicBool::new(false));
+        let mut state = self.state();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.connections.insert(
+            id,
+            Connection {
+                close,
+                idle: idle.clone(),
+            },
+        );
+        Ok(Tracked {
+            id,
+            idle: Idle {
+                flag: idle,
+                shutdown: self.clone(),
+            },
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Keeps a connection visible to `Shutdown::wait` until dropped.
+pub(crate) struct Tracked {
+    id: u64,
+    idle: Idle,
+}
+
+impl Tracked {
+    pub(crate) fn idle(&self) -> Idle {
+        self.idle.clone()
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let shutdown = &self.idle.shutdown;
+        shutdown.state().connections.remove(&self.id);
+        shutdown.inner.closed.notify_all();
+    }
+}
+
+// Tells the shutdown handle when its connection waits for the next request, the only time
+// it may be closed before the deadline.
+#[derive(Clone)]
+pub(crate) struct Idle {
+    flag: Arc<AtomicBool>,
+    shutdown: Shutdown,
+}
+
+impl Idle {
+    // Returns false if the server is shutting down, so the connection ends instead of waiting.
+    pub(crate) fn enter(&self) -> bool {
+        self.flag.store(true, Ordering::SeqCst);
+        !self.shutdown.is_triggered()
+    }
+
+    pub(crate) fn leave(&self) {
+        self.flag.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::listener::Socket;
+use crate::shutdown::{Idle, Tracked};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::io::{self, ErrorKind, Read, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// All in milliseconds; a missing timeout never expires.
+// `idle` bounds the wait for the next request, `request` the time to receive one once it
+// started, and `write` every blocked write of a response.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    pub idle: Option<u64>,
+    pub request: Option<u64>,
+    pub write: Option<u64>,
+}
+
+fn millis(timeout: Option<u64>) -> Option<Duration> {
+    timeout.map(|ms| Duration::from_millis(ms.max(1)))
+}
+
+fn timed_out(err: io::Error, message: &'static str) -> io::Error {
+    match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => io::Error::new(ErrorKind::TimedOut, message),
+        _ => err,
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Phase {
+    // waiting for the next request
+    Idle,
+    // waiting for the client in the middle of a response, e.g. a scan for its next window
+    Paused,
+    Request(Instant),
+}
+
+// Tells the socket when a request is complete; shared with the `Timed` stream.
+#[derive(Clone)]
+pub(crate) struct Clock {
+    phase: Rc<Cell<Phase>>,
+    idle: Idle,
+}
+
+impl Clock {
+    pub(crate) fn new(tracked: &Tracked) -> Self {
+        Self {
+            phase: Rc::new(Cell::new(Phase::Idle)),
+            idle: tracked.idle(),
+        }
+    }
+
+    pub(crate) fn idle(&self) {
+        self.phase.set(Phase::Idle);
+    }
+
+    // Unlike `idle`, the connection is not closed on shutdown while paused.
+    pub(crate) fn pause(&self) {
+        self.phase.set(Phase::Paused);
+    }
+
+    // Starts the next request, which is idle unless part of it is already buffered, e.g. when
+    // a client pipelines commands.
+    pub(crate) fn next(&self, buffered: bool) {
+        self.phase.set(if buffered {
+            Phase::Request(Instant::now())
+        } else {
+            Phase::Idle
+        });
+    }
+}
+
+pub(crate) struct Timed<S: Socket> {
+    inner: S,
+    clock: Clock,
+    idle: Option<Duration>,
+    request: Option<Duration>,
+    // the read timeout currently set on the socket
+    current: Option<Duration>,
+}
+
+impl<S: Socket> Timed<S> {
+    pub(crate) fn new(inner: S, timeouts: Timeouts, clock: Clock) -> io::Result<Self> {
+        let idle = millis(timeouts.idle);
+        inner.set_read_timeout(idle)?;
+        inner.set_write_timeout(millis(timeouts.write))?;
+        Ok(Self {
+            inner,
+            clock,
+            idle,
+            request: millis(timeouts.request),
+            current: idle,
+        })
+    }
+
+    // Reads and writes of the clone share the socket's timeouts and the clock.
+    pub(crate) fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            inner: self.inner.try_clone()?,
+            clock: self.clock.clone(),
+            idle: self.idle,
+            request: self.request,
+            current: self.current,
+        })
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        if self.current != timeout {
+            self.inner.set_read_timeout(timeout)?;
+            self.current = timeout;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Socket> Read for Timed<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.clock.phase.get() {
+            Phase::Idle => {
+                self.set_read_timeout(self.idle)?;
+                // a draining server ends the connection as if the client closed it
+                if !self.clock.idle.enter() {
+                    return Ok(0);
+                }
+                let read = self.inner.read(buf);
+                self.clock.idle.leave();
+                let read = read.map_err(|err| timed_out(err, "idle timeout"))?;
+                self.clock.phase.set(Phase::Request(Instant::now()));
+                Ok(read)
+            }
+            Phase::Paused => {
+                self.set_read_timeout(self.idle)?;
+                let read = self
+                    .inner
+                    .read(buf)
+                    .map_err(|err| timed_out(err, "idle timeout"))?;
+                self.clock.phase.set(Phase::Request(Instant::now()));
+                Ok(read)
+            }
+            Phase::Request(started) => {
+                // each read may only wait for what is left of the request's time
+                let left = match self.request {
+                    Some(request) => match request.checked_sub(started.elapsed()) {
+                        Some(left) if !left.is_zero() => Some(left),
+                        _ => return Err(io::Error::new(ErrorKind::TimedOut, "request timeout")),
+                    },
+                    None => None,
+                };
+                self.set_read_timeout(left)?;
+                self.inner
+                    .read(buf)
+                    .map_err(|err| timed_out(err, "request timeout"))
+            }
+        }
+    }
+}
+
+impl<S: Socket> Write for Timed<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner
+            .write(buf)
+            .map_err(|err| timed_out(err, "write timeout"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner
+            .flush()
+            .map_err(|err| timed_out(err, "write timeout"))
+    }
+}
@@ -0,0 +1,53 @@
+use bronzedb_util::status::StatusCode::IOError;
+use bronzedb_util::status::{Error, Result};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::Arc;
+
+fn tls_err(err: impl Display) -> Error {
+    Error::new(IOError, format!("tls error: {}", err))
+}
+
+fn load_certs(path: impl AsRef<Path>) -> Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path.as_ref())
+        .map_err(tls_err)?
+        .collect::<std::result::Result<_, _>>()
+        .map_err(tls_err)
+}
+
+// Client certificates are required when `client_ca` is given.
+pub fn server_config(
+    cert: impl AsRef<Path>,
+    key: impl AsRef<Path>,
+    client_ca: Option<impl AsRef<Path>>,
+) -> Result<Arc<ServerConfig>> {
+    let certs = load_certs(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key.as_ref()).map_err(tls_err)?;
+    let builder =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(tls_err)?;
+    let builder = match client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca)? {
+                roots.add(cert).map_err(tls_err)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(
+                Arc::new(roots),
+                Arc::new(rustls::crypto::ring::default_provider()),
+            )
+            .build()
+            .map_err(tls_err)?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(
+        builder.with_single_cert(certs, key).map_err(tls_err)?,
+    ))
+}
@@ -1,8 +1,13 @@
 use bronzedb_engine::{Engine, Scanner};
 use bronzedb_util::status::{Error, StatusCode};
-use bronzedb_util::types::{Entry, Key};
+use bronzedb_util::types::{Entry, Event, Key, Value};
 use sled::Db;
+use std::collections::BTreeMap;
+use std::ops::Bound::{Excluded, Unbounded};
 use std::path;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, Weak};
+use std::thread::Builder;
 
 #[derive(Debug)]
 pub struct EngineError {
@@ -27,17 +32,45 @@ impl From<EngineError> for Error {
     }
 }
 
+// The values that keys had when a snapshot was taken, recorded before their first change
+// after it; `None` for keys that did not exist.
+type Undo = Mutex<BTreeMap<Vec<u8>, Option<Value>>>;
+
 #[derive(Clone)]
 pub struct EngineImpl {
     inner: Db,
+    // writes hold the read lock, so a snapshot starts between them
+    snapshots: Arc<RwLock<Vec<Weak<Undo>>>>,
 }
 
 impl EngineImpl {
     pub fn new(path: impl AsRef<path::Path>) -> Self {
         Self {
             inner: Db::start_default(path.as_ref())
-                .expect(&format!("cannot open db {:?}", path.as_ref())),
+                .unwrap_or_else(|_| panic!("cannot open db {:?}", path.as_ref())),
+            snapshots: Arc::default(),
+        }
+    }
+
+    fn write(&self, key: &Key, write: impl FnOnce(&Db) -> sled::Result<()>) -> sled::Result<()> {
+        let snapshots = self
+            .snapshots
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        for undo in snapshots.iter().filter_map(Weak::upgrade) {
+            self.record(&undo, key)?;
         }
+        write(&self.inner)
+    }
+
+    // Keeps the value of `key` for a snapshot, unless it already has one.
+    fn record(&self, undo: &Undo, key: &Key) -> sled::Result<()> {
+        let mut undo = undo.lock().unwrap_or_else(PoisonError::into_inner);
+        if !undo.contains_key(key.as_slice()) {
+            let value = self.inner.get(key)?.map(|data| data.to_vec());
+            undo.insert(key.to_vec(), value);
+        }
+        Ok(())
     }
 }
 
@@ -45,7 +78,7 @@ impl Engine for EngineImpl {
     type Error = EngineError;
 
     fn set(&mut self, key: Key, value: Vec<u8>) -> Result<(), Self::Error> {
-        self.inner.set(key, value)?;
+        self.write(&key, |db| db.set(key.clone(), value).map(drop))?;
         Ok(())
     }
 
@@ -54,28 +87,42 @@ impl Engine for EngineImpl {
     }
 
     fn delete(&mut self, key: Key) -> Result<(), Self::Error> {
-        self.inner.del(key)?;
+        self.write(&key, |db| db.del(&key).map(drop))?;
         Ok(())
     }
 
+    fn compare_and_set(
+        &mut self,
+        key: Key,
+        expected: Option<Value>,
+        value: Option<Value>,
+    ) -> Result<Option<bool>, Self::Error> {
+        let mut swapped = false;
+        self.write(&key, |db| {
+            swapped = db.cas(&key, expected, value)?.is_ok();
+            Ok(())
+        })?;
+        Ok(Some(swapped))
+    }
+
     fn scan(
         &self,
         lower_bound: Option<Key>,
         upper_bound: Option<Key>,
-    ) -> Result<Box<Scanner + '_>, Self::Error> {
+    ) -> Result<Box<dyn Scanner + '_>, Self::Error> {
         let iter = match lower_bound {
             Some(key) => self.inner.scan(key),
             None => self.inner.iter(),
         };
 
-        let mut entries: Box<Iterator<Item = Result<Entry, Error>>> =
+        let mut entries: Box<dyn Iterator<Item = Result<Entry, Error>>> =
             Box::new(iter.map(|item| -> Result<Entry, Error> {
                 match item {
                     Ok((key, value)) => Ok((key.into(), value.to_vec())),
                     Err(err) => Err(EngineError::from(err).into()),
                 }
             }));
-        if let Some(_) = upper_bound {
+        if upper_bound.is_some() {
             entries = Box::new(entries.filter(move |item| match item {
                 Ok((ref key, _)) => key <= upper_bound.as_ref().unwrap(),
                 _ => true,
@@ -83,20 +130,110 @@ impl Engine for EngineImpl {
         }
         Ok(Box::new(SledScanner::new(entries)))
     }
+
+    // sled subscribers only block, so a thread forwards their events. It ends with the
+    // first event after the receiver is dropped.
+    fn watch(
+        &self,
+        lower_bound: Option<Key>,
+        upper_bound: Option<Key>,
+    ) -> Result<Option<Receiver<Event>>, Self::Error> {
+        let prefix = match (lower_bound.as_ref(), upper_bound.as_ref()) {
+            (Some(lower), Some(upper)) => common_prefix(lower, upper),
+            _ => Vec::new(),
+        };
+        let subscriber = self.inner.watch_prefix(prefix);
+        let (sender, receiver) = channel();
+        Builder::new()
+            .name("bronzedb-watch".to_owned())
+            .spawn(move || {
+                for event in subscriber {
+                    let event = match event {
+                        sled::Event::Set(key, value) | sled::Event::Merge(key, value) => {
+                            Event::Set(key.into(), value.to_vec())
+                        }
+                        sled::Event::Del(key) => Event::Delete(key.into()),
+                    };
+                    let key = event.key();
+                    if lower_bound.as_ref().is_some_and(|lower| key < lower)
+                        || upper_bound.as_ref().is_some_and(|upper| key > upper)
+                    {
+                        continue;
+                    }
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("fail to spawn watch thread");
+        Ok(Some(receiver))
+    }
+
+    // Reads the tree as it goes, taking the values that changed since from the undo log,
+    // which grows with the writes made until the snapshot is dropped.
+    fn snapshot(&self) -> Result<Option<Box<dyn Scanner + '_>>, Self::Error> {
+        let undo = Arc::new(Undo::default());
+        let mut snapshots = self
+            .snapshots
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        snapshots.retain(|undo| undo.strong_count() > 0);
+        snapshots.push(Arc::downgrade(&undo));
+        Ok(Some(Box::new(Snapshot {
+            db: self.inner.clone(),
+            undo,
+            last: None,
+        })))
+    }
+
+    // sled 0.22 has no batch writes, so keys are still set one at a time; only the live
+    // snapshots are looked up once for the batch rather than once per key.
+    fn ingest(&mut self, entries: Vec<Entry>) -> Result<(), Self::Error> {
+        let snapshots = self
+            .snapshots
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let undos: Vec<_> = snapshots.iter().filter_map(Weak::upgrade).collect();
+        for (key, value) in entries {
+            for undo in &undos {
+                self.record(undo, &key)?;
+            }
+            self.inner.set(key, value)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    fn durable(&self) -> bool {
+        true
+    }
+}
+
+fn common_prefix(lower: &[u8], upper: &[u8]) -> Vec<u8> {
+    lower
+        .iter()
+        .zip(upper)
+        .take_while(|(lower, upper)| lower == upper)
+        .map(|(byte, _)| *byte)
+        .collect()
 }
 
 pub struct SledScanner<'a> {
-    iter: Box<Iterator<Item = Result<Entry, Error>> + 'a>,
+    iter: Box<dyn Iterator<Item = Result<Entry, Error>> + 'a>,
 }
 
 impl<'a> SledScanner<'a> {
-    pub fn new(iter: Box<Iterator<Item = Result<Entry, Error>> + 'a>) -> Self {
+    pub fn new(iter: Box<dyn Iterator<Item = Result<Entry, Error>> + 'a>) -> Self {
         Self { iter }
     }
 }
 
 impl Scanner for SledScanner<'_> {
-    fn iter(&mut self) -> Box<Iterator<Item = Result<Entry, Error>> + '_> {
+    fn iter(&mut self) -> Box<dyn Iterator<Item = Result<Entry, Error>> + '_> {
         Box::new(self)
     }
 }
@@ -108,8 +245,137 @@ impl Iterator for SledScanner<'_> {
     }
 }
 
+struct Snapshot {
+    db: Db,
+    undo: Arc<Undo>,
+    last: Option<Vec<u8>>,
+}
+
+impl Snapshot {
+    fn next_entry(&mut self) -> sled::Result<Option<Entry>> {
+        loop {
+            // the tree first: a key changed after it was read is in the undo log by then
+            let current = match self.last {
+                Some(ref last) => self.db.get_gt(last)?,
+                None => self.db.iter().next().transpose()?,
+            };
+            let undo = self.undo.lock().unwrap_or_else(PoisonError::into_inner);
+            let lower = match self.last {
+                Some(ref last) => Excluded(last.as_slice()),
+                None => Unbounded,
+            };
+            let previous = undo.range::<[u8], _>((lower, Unbounded)).next();
+            let (key, value) = match (current, previous) {
+                (Some((key, value)), None) => (key, Some(value.to_vec())),
+                (Some((key, value)), Some((undone, _))) if key < *undone => {
+                    (key, Some(value.to_vec()))
+                }
+                (_, Some((key, value))) => (key.clone(), value.clone()),
+                (None, None) => return Ok(None),
+            };
+            drop(undo);
+            self.last = Some(key.clone());
+            if let Some(value) = value {
+                return Ok(Some((key.into(), value)));
+            }
+        }
+    }
+}
+
+impl Scanner for Snapshot {
+    fn iter(&mut self) -> Box<dyn Iterator<Item = Result<Entry, Error>> + '_> {
+        Box::new(self)
+    }
+}
+
+impl Iterator for Snapshot {
+    type Item = Result<Entry, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry()
+            .map_err(|err| EngineError::from(err).into())
+            .transpose()
+    }
+}
+
 impl Drop for EngineImpl {
     fn drop(&mut self) {
         self.inner.flush().expect("db flush error");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::EngineImpl;
+    use bronzedb_engine::Engine;
+    use bronzedb_util::status::Result;
+    use bronzedb_util::types::{Entry, Key};
+    use std::fs;
+
+    fn key(key: &str) -> Key {
+        key.as_bytes().to_vec().into()
+    }
+
+    #[test]
+    fn snapshot() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("bronzedb-snapshot-{}", std::process::id()));
+        {
+            let mut engine = EngineImpl::new(&path);
+            for name in &["a", "c", "d", "e"] {
+                engine.set(key(name), b"old".to_vec())?;
+            }
+            let mut snapshot = engine.snapshot()?.unwrap();
+            let mut entries = snapshot.iter();
+            assert_eq!(key("a"), entries.next().unwrap()?.0);
+
+            let mut writer = engine.clone();
+            writer.set(key("a"), b"new".to_vec())?;
+            writer.set(key("b"), b"new".to_vec())?;
+            writer.delete(key("c"))?;
+            writer.set(key("d"), b"new".to_vec())?;
+            writer.set(key("f"), b"new".to_vec())?;
+            let rest = entries.collect::<Result<Vec<Entry>>>()?;
+            assert_eq!(
+                vec![
+                    (key("c"), b"old".to_vec()),
+                    (key("d"), b"old".to_vec()),
+                    (key("e"), b"old".to_vec()),
+                ],
+                rest
+            );
+
+            let mut snapshot = engine.snapshot()?.unwrap();
+            let names = snapshot
+                .iter()
+                .map(|entry| entry.map(|(key, _)| key))
+                .collect::<Result<Vec<_>>>()?;
+            assert_eq!(
+                vec![key("a"), key("b"), key("d"), key("e"), key("f")],
+                names
+            );
+        }
+        fs::remove_dir_all(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn ingest() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("bronzedb-ingest-{}", std::process::id()));
+        {
+            let mut engine = EngineImpl::new(&path);
+            engine.set(key("b"), b"old".to_vec())?;
+            let mut snapshot = engine.snapshot()?.unwrap();
+            let mut writer = engine.clone();
+            writer.ingest(vec![
+                (key("a"), b"new".to_vec()),
+                (key("b"), b"new".to_vec()),
+                (key("c"), b"new".to_vec()),
+            ])?;
+            assert_eq!(Some(b"new".to_vec()), engine.get(key("b"))?);
+            assert_eq!(3, engine.scan(None, None)?.iter().count());
+            let entries = snapshot.iter().collect::<Result<Vec<Entry>>>()?;
+            assert_eq!(vec![(key("b"), b"old".to_vec())], entries);
+        }
+        fs::remove_dir_all(&path)?;
+        Ok(())
+    }
+}
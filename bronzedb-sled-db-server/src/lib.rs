@@ -0,0 +1,3 @@
+pub mod engine_impl;
+
+pub use engine_impl::EngineImpl;
@@ -1,6 +1,5 @@
 use std::fmt::{Display, Formatter};
 use std::io;
-use std::u8::MAX;
 
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
 pub enum StatusCode {
@@ -10,7 +9,15 @@ pub enum StatusCode {
     EngineError = 3,
     NotFound = 4,
     Complete = 5,
-    UnknownStatusCode = MAX as isize,
+    Corruption = 6,
+    PermissionDenied = 7,
+    ServerBusy = 8,
+    Timeout = 9,
+    Paused = 10,
+    Redirect = 11,
+    Unavailable = 12,
+    WrongShard = 13,
+    UnknownStatusCode = u8::MAX as isize,
 }
 
 impl From<u8> for StatusCode {
@@ -22,22 +29,38 @@ impl From<u8> for StatusCode {
             3 => StatusCode::EngineError,
             4 => StatusCode::NotFound,
             5 => StatusCode::Complete,
+            6 => StatusCode::Corruption,
+            7 => StatusCode::PermissionDenied,
+            8 => StatusCode::ServerBusy,
+            9 => StatusCode::Timeout,
+            10 => StatusCode::Paused,
+            11 => StatusCode::Redirect,
+            12 => StatusCode::Unavailable,
+            13 => StatusCode::WrongShard,
             _ => StatusCode::UnknownStatusCode,
         }
     }
 }
 
-impl ToString for StatusCode {
-    fn to_string(&self) -> String {
-        match self {
-            StatusCode::OK => "OK".into(),
-            StatusCode::IOError => "IOError".into(),
-            StatusCode::UnknownAction => "UnknownAction".into(),
-            StatusCode::EngineError => "EngineError".into(),
-            StatusCode::NotFound => "NotFound".into(),
-            StatusCode::Complete => "Complete".into(),
-            StatusCode::UnknownStatusCode => "UnknownStatusCode".into(),
-        }
+impl Display for StatusCode {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        f.write_str(match self {
+            StatusCode::OK => "OK",
+            StatusCode::IOError => "IOError",
+            StatusCode::UnknownAction => "UnknownAction",
+            StatusCode::EngineError => "EngineError",
+            StatusCode::NotFound => "NotFound",
+            StatusCode::Complete => "Complete",
+            StatusCode::Corruption => "Corruption",
+            StatusCode::PermissionDenied => "PermissionDenied",
+            StatusCode::ServerBusy => "ServerBusy",
+            StatusCode::Timeout => "Timeout",
+            StatusCode::Paused => "Paused",
+            StatusCode::Redirect => "Redirect",
+            StatusCode::Unavailable => "Unavailable",
+            StatusCode::WrongShard => "WrongShard",
+            StatusCode::UnknownStatusCode => "UnknownStatusCode",
+        })
     }
 }
 
@@ -56,19 +79,32 @@ impl Error {
             message: message.into(),
         }
     }
+
+    pub fn is_timeout(&self) -> bool {
+        self.code == StatusCode::Timeout
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
-        write!(f, "{}: {}", self.code.to_string(), self.message)
+        write!(f, "{}: {}", self.code, self.message)
     }
 }
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
-        Self {
-            code: StatusCode::IOError,
-            message: err.to_string(),
+        match err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<Error>())
+        {
+            Some(inner) => inner.clone(),
+            None => Self {
+                code: match err.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => StatusCode::Timeout,
+                    _ => StatusCode::IOError,
+                },
+                message: err.to_string(),
+            },
         }
     }
 }
@@ -9,6 +9,28 @@ pub type Value = Vec<u8>;
 pub type Entry = (Key, Value);
 pub type EntryRef<'a> = (&'a Key, &'a Value);
 
+// A change to a watched key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Set(Key, Value),
+    Delete(Key),
+}
+
+// A message published to a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub channel: String,
+    pub payload: Value,
+}
+
+impl Event {
+    pub fn key(&self) -> &Key {
+        match self {
+            Event::Set(key, _) | Event::Delete(key) => key,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Key {
     data: Vec<u8>,
@@ -69,7 +91,7 @@ impl PartialOrd for RawKey {
             memcmp(
                 &self.data as *const [u8] as *const c_void,
                 &other.data as *const [u8] as *const c_void,
-                self.len(),
+                self.len().min(other.len()),
             )
         } {
             x if x < 0 => Some(Ordering::Less),
@@ -109,20 +131,23 @@ impl Hash for Key {
 
 #[cfg(test)]
 mod tests {
+    use super::Key;
     use std::cmp::Ordering::*;
     use std::cmp::PartialOrd;
 
     #[test]
     fn key_cmp() {
-        for (former, latter, order) in vec![
+        for (former, latter, order) in [
             ("xixi", "haha", Greater),
             ("haa", "haha", Less),
             ("haha", "haha", Equal),
             ("hah", "haha", Less),
             ("hahah", "haha", Greater),
+            ("k0010", "k", Greater),
+            ("k", "k0010", Less),
         ] {
-            let former_key = former.to_owned().into_bytes();
-            let latter_key = latter.to_owned().into_bytes();
+            let former_key: Key = former.to_owned().into_bytes().into();
+            let latter_key: Key = latter.to_owned().into_bytes().into();
             assert_eq!(order, former_key.partial_cmp(&latter_key).unwrap());
         }
     }
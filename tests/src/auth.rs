@@ -0,0 +1,103 @@
+use crate::serve_local;
+use bronzedb_client::{BronzeConnManager, Credentials};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{Permission, Server, User};
+use bronzedb_util::status::StatusCode::PermissionDenied;
+use bronzedb_util::status::{Error, Result};
+use r2d2::ManageConnection;
+
+fn serve_with_users() -> String {
+    serve_local(Server::new(EngineImpl::default()).users(vec![
+        User::new("admin").password("secret"),
+        User::new("reader")
+            .token("token")
+            .permissions(vec![Permission::Read, Permission::Scan])
+            .prefixes(vec!["public:".to_owned()]),
+    ]))
+}
+
+fn denied<T>(result: Result<T>) -> bool {
+    matches!(
+        result,
+        Err(Error {
+            code: PermissionDenied,
+            ..
+        })
+    )
+}
+
+#[test]
+fn unauthenticated() -> Result<()> {
+    let addr = serve_with_users();
+    let mut conn = BronzeConnManager::new(addr).connect()?;
+    conn.ping()?;
+    assert!(denied(conn.get(b"key".to_vec().into())));
+    assert!(denied(conn.set(b"key".to_vec().into(), b"value".to_vec())));
+    assert!(denied(conn.auth(Credentials::Password {
+        user: "admin".to_owned(),
+        password: "wrong".to_owned(),
+    })));
+    // the connection is still usable after a denied request
+    conn.ping()
+}
+
+#[test]
+fn access_control() -> Result<()> {
+    let addr = serve_with_users();
+    let mut admin = BronzeConnManager::new(addr.clone())
+        .credentials(Credentials::Password {
+            user: "admin".to_owned(),
+            password: "secret".to_owned(),
+        })
+        .connect()?;
+    for key in &["public:a", "public:b", "private:a"] {
+        admin.set(key.as_bytes().to_vec().into(), b"value".to_vec())?;
+    }
+
+    let mut reader = BronzeConnManager::new(addr)
+        .credentials(Credentials::Token("token".to_owned()))
+        .connect()?;
+    assert!(reader.get(b"public:a".to_vec().into())?.is_some());
+    assert!(denied(reader.get(b"private:a".to_vec().into())));
+    assert!(denied(
+        reader.set(b"public:c".to_vec().into(), b"value".to_vec())
+    ));
+    assert!(denied(reader.delete(b"public:a".to_vec().into())));
+    let mut keys = reader
+        .scan(None, None)?
+        .map(|item| item.map(|(key, _)| key.as_slice().to_vec()))
+        .collect::<Result<Vec<_>>>()?;
+    keys.sort();
+    assert_eq!(vec![b"public:a".to_vec(), b"public:b".to_vec()], keys);
+    Ok(())
+}
+
+#[test]
+fn refused_restore() -> Result<()> {
+    let addr = serve_with_users();
+    let mut admin = BronzeConnManager::new(addr.clone())
+        .credentials(Credentials::Password {
+            user: "admin".to_owned(),
+            password: "secret".to_owned(),
+        })
+        .connect()?;
+    admin.set(b"public:a".to_vec().into(), b"value".to_vec())?;
+    let mut backup = Vec::new();
+    admin.backup(&mut backup)?;
+
+    let mut reader = BronzeConnManager::new(addr)
+        .credentials(Credentials::Token("token".to_owned()))
+        .connect()?;
+    assert!(denied(reader.restore(backup.as_slice())));
+    // the backup sent with the refused restore is not read as the next request
+    assert_eq!(
+        Some(b"value".to_vec()),
+        reader.get(b"public:a".to_vec().into())?
+    );
+    assert!(denied(
+        reader.ingest(vec![(b"public:b".to_vec().into(), b"value".to_vec())])
+    ));
+    reader.ping()?;
+    assert!(!reader.is_poisoned());
+    Ok(())
+}
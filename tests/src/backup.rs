@@ -0,0 +1,118 @@
+use crate::serve_local;
+use bronzedb_client::{BackupReader, BronzeConnManager, Connection, Stream};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::Server;
+use bronzedb_util::status::{Result, StatusCode};
+use bronzedb_util::types::Key;
+use r2d2::ManageConnection;
+use std::fs;
+use std::time::Duration;
+
+fn key(i: usize) -> Key {
+    format!("b{:04}", i).into_bytes().into()
+}
+
+fn manager() -> BronzeConnManager {
+    BronzeConnManager::new(serve_local(Server::new(EngineImpl::default())))
+        .read_timeout(Duration::from_secs(5))
+}
+
+fn connect() -> Result<Connection<Stream>> {
+    manager().connect()
+}
+
+#[test]
+fn backup_and_restore() -> Result<()> {
+    let mut source = connect()?;
+    for i in 0..1000 {
+        source.set(key(i), i.to_string().into_bytes())?;
+    }
+    let mut backup = Vec::new();
+    assert_eq!(1000, source.backup(&mut backup)?);
+    // later writes are not in the backup
+    source.delete(key(0))?;
+    assert!(!source.is_poisoned());
+
+    let entries = BackupReader::new(backup.as_slice())?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(1000, entries.len());
+    assert_eq!((key(0), b"0".to_vec()), entries[0]);
+
+    let mut target = connect()?;
+    target.set(key(1), b"stale".to_vec())?;
+    target.set(key(1000), b"kept".to_vec())?;
+    assert_eq!(1000, target.restore(backup.as_slice())?);
+    assert_eq!(Some(b"0".to_vec()), target.get(key(0))?);
+    assert_eq!(Some(b"1".to_vec()), target.get(key(1))?);
+    assert_eq!(Some(b"kept".to_vec()), target.get(key(1000))?);
+
+    // the trailer does not match a damaged backup, which is cut short on the connection
+    let last = backup.len() - 14;
+    backup[last] ^= 1;
+    let err = target.restore(backup.as_slice()).unwrap_err();
+    assert_eq!(StatusCode::Corruption, err.code);
+    assert!(target.is_poisoned());
+    Ok(())
+}
+
+#[test]
+fn backup_file() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("bronzedb-backup-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let manager = || {
+        BronzeConnManager::new(serve_local(
+            Server::new(EngineImpl::default()).backup_dir(&dir),
+        ))
+    };
+    let mut source = manager().connect()?;
+    for i in 0..100 {
+        source.set(key(i), b"value".to_vec())?;
+    }
+    assert_eq!(100, source.backup_file("backup")?);
+
+    let mut target = manager().connect()?;
+    assert_eq!(100, target.restore_file("./backup")?);
+    assert_eq!(Some(b"value".to_vec()), target.get(key(99))?);
+
+    let other_server = manager();
+    let mut other = other_server.connect()?;
+    let err = other.restore_file("missing").unwrap_err();
+    assert_eq!(StatusCode::IOError, err.code);
+    assert!(!other.is_poisoned());
+
+    // paths that leave the backup directory are refused
+    let outside = dir.join("backup");
+    for path in &[
+        outside.to_str().unwrap(),
+        "../backup",
+        "sub/../../backup",
+        "",
+    ] {
+        let err = other.restore_file(*path).unwrap_err();
+        assert_eq!(StatusCode::PermissionDenied, err.code);
+        let err = source.backup_file(*path).unwrap_err();
+        assert_eq!(StatusCode::PermissionDenied, err.code);
+    }
+    assert!(!other.is_poisoned());
+
+    // a damaged file is refused as a whole
+    let path = dir.join("backup");
+    let mut data = fs::read(&path)?;
+    let last = data.len() - 14;
+    data[last] ^= 1;
+    fs::write(&path, data)?;
+    let err = other.restore_file("backup").unwrap_err();
+    assert_eq!(StatusCode::Corruption, err.code);
+    fs::remove_dir_all(&dir)?;
+    assert_eq!(None, other_server.connect()?.get(key(0))?);
+    Ok(())
+}
+
+#[test]
+fn backup_file_without_dir() -> Result<()> {
+    let mut conn = connect()?;
+    let err = conn.backup_file("backup").unwrap_err();
+    assert_eq!(StatusCode::PermissionDenied, err.code);
+    let err = conn.restore_file("backup").unwrap_err();
+    assert_eq!(StatusCode::PermissionDenied, err.code);
+    conn.ping()
+}
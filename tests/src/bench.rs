@@ -0,0 +1,41 @@
+use crate::serve_local;
+use bronzedb_cli::bench::{load, run, Distribution, Workload};
+use bronzedb_client::{BronzeConnManager, Pool, Validation};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::Server;
+use bronzedb_util::status::Result;
+use std::time::Duration;
+
+#[test]
+fn bench() -> Result<()> {
+    let addr = serve_local(Server::new(EngineImpl::default()));
+    let manager = BronzeConnManager::new(addr).validation(Validation::State);
+    let pool = Pool::builder().max_size(2).build(manager).unwrap();
+    for &distribution in &[
+        Distribution::Uniform,
+        Distribution::Zipfian,
+        Distribution::Sequential,
+    ] {
+        let workload = Workload {
+            keys: 1000,
+            key_size: 8,
+            value_size: 32,
+            reads: 2,
+            writes: 1,
+            scans: 1,
+            distribution,
+            threads: 3,
+            duration: Duration::from_millis(300),
+            ..Workload::default()
+        };
+        assert_eq!(1000, load(&pool, &workload)?);
+        let mut reported = 0;
+        let stats = run(&pool, &workload, |done, _| reported = done);
+        assert_eq!(0, stats.errors);
+        assert!(stats.reads.count() > 0 && stats.writes.count() > 0 && stats.scans.count() > 0);
+        assert!(reported > 0 && reported <= stats.total().count());
+    }
+    let mut conn = pool.get().unwrap();
+    assert_eq!(1000, conn.scan(None, None)?.count());
+    Ok(())
+}
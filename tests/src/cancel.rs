@@ -0,0 +1,111 @@
+use crate::serve_local;
+use bronzedb_client::{BronzeConnManager, Options, Pool, SCAN_WINDOW};
+use bronzedb_engine::Engine;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::Server;
+use bronzedb_util::status::{Result, StatusCode};
+use r2d2::ManageConnection;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread::spawn;
+
+const SIZE: usize = 3 * SCAN_WINDOW + 10;
+
+fn serve_filled() -> Result<String> {
+    let mut engine = EngineImpl::default();
+    for i in 0..SIZE {
+        engine.set(i.to_be_bytes().to_vec().into(), i.to_string().into_bytes())?;
+    }
+    Ok(serve_local(Server::new(engine)))
+}
+
+#[test]
+fn cancel_scan() -> Result<()> {
+    let manager = BronzeConnManager::new(serve_filled()?);
+    let mut conn = manager.connect()?;
+    // dropped inside a window, at its end and after more have been asked for
+    for taken in &[1, SCAN_WINDOW, SCAN_WINDOW + 1, 2 * SCAN_WINDOW] {
+        assert_eq!(*taken, conn.scan(None, None)?.take(*taken).count());
+        assert!(!conn.is_poisoned());
+        assert_eq!(
+            Some(b"0".to_vec()),
+            conn.get(0usize.to_be_bytes().to_vec().into())?
+        );
+    }
+    assert_eq!(SIZE, conn.scan(None, None)?.count());
+    Ok(())
+}
+
+#[test]
+fn break_pooled_scan() -> Result<()> {
+    let manager = BronzeConnManager::new(serve_filled()?);
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    for entry in pool.get().unwrap().scan(None, None)? {
+        if entry?.0.as_ref() == 5usize.to_be_bytes() {
+            break;
+        }
+    }
+    let mut conn = pool.get().unwrap();
+    assert!(!conn.is_poisoned());
+    assert_eq!(
+        Some(b"7".to_vec()),
+        conn.get(7usize.to_be_bytes().to_vec().into())?
+    );
+    Ok(())
+}
+
+#[test]
+fn not_negotiated() -> Result<()> {
+    let mut engine = EngineImpl::default();
+    for key in &["a", "b"] {
+        engine.set(key.as_bytes().to_vec().into(), key.as_bytes().to_vec())?;
+    }
+    let addr = serve_local(Server::new(engine).options(Options::default()));
+    let mut conn = BronzeConnManager::new(addr).connect()?;
+    conn.scan(None, None)?.next().unwrap()?;
+    assert!(conn.is_poisoned());
+    Ok(())
+}
+
+// Answers pings like a server from before the handshake, which refuses any other action and
+// closes the connection.
+fn serve_old() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            spawn(move || {
+                let mut action = [0];
+                while stream.read_exact(&mut action).is_ok() {
+                    let ping = action[0] == 1;
+                    let status = if ping {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::UnknownAction
+                    };
+                    if stream.write_all(&[status as u8]).is_err() || !ping {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+#[test]
+fn old_server() -> Result<()> {
+    let addr = serve_old();
+    let mut conn = BronzeConnManager::new(addr.clone()).connect()?;
+    conn.ping()?;
+    assert!(!conn.is_poisoned());
+    // options asked for explicitly are not given up
+    let options = Options {
+        checksum: true,
+        ..Options::default()
+    };
+    let refused = BronzeConnManager::new(addr).options(options).connect();
+    assert_eq!(StatusCode::UnknownAction, refused.err().unwrap().code);
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use crate::serve_local;
+use bronzedb_client::{BronzeConnManager, Client, Options, Retry};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{Server, Timeouts, User};
+use bronzedb_util::status::Result;
+use bronzedb_util::status::StatusCode::{PermissionDenied, Timeout};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+// Accepts connections and holds them without answering; returns the address and the
+// number of accepted connections.
+fn silent_server(close: bool) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    spawn(move || {
+        let mut streams = Vec::new();
+        for stream in listener.incoming() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            if !close {
+                streams.push(stream);
+            }
+        }
+    });
+    (addr, accepted)
+}
+
+fn quick_retry() -> Retry {
+    Retry {
+        attempts: 3,
+        backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(10),
+    }
+}
+
+#[test]
+fn read_timeout() {
+    let (addr, accepted) = silent_server(false);
+    let mut client =
+        Client::new(BronzeConnManager::new(addr).read_timeout(Duration::from_millis(50)))
+            .retry(quick_retry());
+    let err = client.get(b"key".to_vec().into()).unwrap_err();
+    assert!(err.is_timeout());
+    assert_eq!(Timeout, err.code);
+    // every attempt runs on a new connection
+    assert_eq!(3, accepted.load(Ordering::SeqCst));
+}
+
+#[test]
+fn idempotent_retries() {
+    let (addr, accepted) = silent_server(true);
+    // without a handshake the requests are sent before the closed connection shows up
+    let manager = BronzeConnManager::new(addr).options(Options::default());
+    let mut client = Client::new(manager).retry(quick_retry());
+    assert!(client
+        .set(b"key".to_vec().into(), b"value".to_vec())
+        .is_err());
+    assert_eq!(1, accepted.load(Ordering::SeqCst));
+    assert!(client.exists(b"key".to_vec().into()).is_err());
+    assert_eq!(4, accepted.load(Ordering::SeqCst));
+}
+
+#[test]
+fn reconnect() -> Result<()> {
+    let addr = serve_local(Server::new(EngineImpl::default()).timeouts(Timeouts {
+        idle: Some(50),
+        ..Timeouts::default()
+    }));
+    let mut client = Client::new(BronzeConnManager::new(addr)).retry(quick_retry());
+    client.set(b"key".to_vec().into(), b"value".to_vec())?;
+    sleep(Duration::from_millis(200));
+    // the server closed the idle connection
+    assert!(client.exists(b"key".to_vec().into())?);
+    let mut entries = client.scan(None, None)?;
+    assert!(entries.next().is_some());
+    drop(entries);
+    // the unfinished scan is not read as the response of the next request
+    assert_eq!(Some(b"value".to_vec()), client.get(b"key".to_vec().into())?);
+    Ok(())
+}
+
+#[test]
+fn server_errors() {
+    let addr = serve_local(
+        Server::new(EngineImpl::default()).users(vec![User::new("admin").password("secret")]),
+    );
+    let mut client = Client::new(BronzeConnManager::new(addr));
+    let err = client.get(b"key".to_vec().into()).unwrap_err();
+    assert_eq!(PermissionDenied, err.code);
+    assert!(!err.is_timeout());
+}
@@ -0,0 +1,357 @@
+use bronzedb_client::{BronzeConnManager, ClusterStatus, Connection, RaftRole, Stream};
+use bronzedb_engine::Engine;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{Cluster, ClusterConfig, Clustered, Member, Server, Shutdown};
+use bronzedb_sled_db_server::EngineImpl as SledEngine;
+use bronzedb_util::status::{Result, StatusCode};
+use r2d2::ManageConnection;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+struct Node {
+    addr: String,
+    config: ClusterConfig,
+    cluster: Cluster,
+    shutdown: Shutdown,
+    serving: Option<JoinHandle<()>>,
+}
+
+impl Node {
+    fn connect(&self) -> Result<Connection<Stream>> {
+        BronzeConnManager::new(self.addr.as_str())
+            .read_timeout(Duration::from_secs(5))
+            .connect()
+    }
+
+    fn status(&self) -> Result<ClusterStatus> {
+        self.connect()?.cluster_status()
+    }
+
+    fn kill(&self) {
+        self.cluster.stop();
+        self.shutdown.trigger();
+    }
+
+    // Kills the node and waits until it lets go of its engine, so it can be started again.
+    fn stop(&mut self) {
+        self.kill();
+        if let Some(serving) = self.serving.take() {
+            serving.join().unwrap();
+        }
+        sleep(Duration::from_millis(200));
+    }
+}
+
+fn start_node<T: Engine + Clone + Sync + Send + 'static>(
+    config: ClusterConfig,
+    listener: TcpListener,
+    engine: T,
+) -> Result<Node> {
+    let cluster = Cluster::new(config.clone())?;
+    cluster.start(engine.clone())?;
+    let shutdown = Shutdown::new();
+    let mut server = Server::new(Clustered::new(engine, cluster.clone()))
+        .cluster(cluster.clone())
+        .shutdown(shutdown.clone());
+    let addr = listener.local_addr()?.to_string();
+    let serving = spawn(move || server.serve(listener).unwrap());
+    Ok(Node {
+        addr,
+        config,
+        cluster,
+        shutdown,
+        serving: Some(serving),
+    })
+}
+
+// Members keep their raft state in `dir`, by id.
+fn configs(dir: &Path, size: u64) -> Result<Vec<(ClusterConfig, TcpListener)>> {
+    let listeners = (0..size)
+        .map(|_| TcpListener::bind("127.0.0.1:0"))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let members: Vec<_> = listeners
+        .iter()
+        .zip(1..)
+        .map(|(listener, id)| Member {
+            id,
+            addr: listener.local_addr().unwrap().to_string(),
+        })
+        .collect();
+    let configs = listeners
+        .into_iter()
+        .zip(members.iter())
+        .map(|(listener, member)| {
+            let mut config = ClusterConfig::new(member.id, members.clone());
+            config.election_timeout = 150;
+            config.heartbeat = 30;
+            config.write_timeout = 1000;
+            config.path = raft_path(dir, member.id).to_string_lossy().into_owned();
+            (config, listener)
+        });
+    Ok(configs.collect())
+}
+
+fn raft_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(id.to_string()).join("raft")
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bronzedb-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn start_cluster(name: &str, size: u64) -> Result<Vec<Node>> {
+    configs(&temp_dir(name), size)?
+        .into_iter()
+        .map(|(config, listener)| start_node(config, listener, EngineImpl::default()))
+        .collect()
+}
+
+// The port of a stopped node may take a moment to be free again.
+fn rebind(addr: &str) -> Result<TcpListener> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match TcpListener::bind(addr) {
+            Ok(listener) => return Ok(listener),
+            Err(err) if Instant::now() >= deadline => return Err(err.into()),
+            Err(_) => sleep(Duration::from_millis(20)),
+        }
+    }
+}
+
+// Waits until one of the live nodes leads and the others follow it.
+fn leader(nodes: &[Node], live: &[usize]) -> Result<usize> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let statuses = live
+            .iter()
+            .map(|&i| nodes[i].status())
+            .collect::<Result<Vec<_>>>()?;
+        let leaders: Vec<_> = statuses
+            .iter()
+            .zip(live)
+            .filter(|(status, _)| status.role == RaftRole::Leader)
+            .collect();
+        if let [(leader, &i)] = leaders.as_slice() {
+            if statuses.iter().all(|status| {
+                status.term == leader.term && status.leader.as_ref() == Some(&nodes[i].addr)
+            }) {
+                return Ok(i);
+            }
+        }
+        assert!(Instant::now() < deadline, "no leader: {:?}", statuses);
+        sleep(Duration::from_millis(20));
+    }
+}
+
+// Waits until every live node applied the writes committed by the leader.
+fn applied(nodes: &[Node], live: &[usize], leader: usize) -> Result<()> {
+    let commit = nodes[leader].status()?.commit;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    for &i in live {
+        while nodes[i].status()?.applied < commit {
+            assert!(Instant::now() < deadline, "node {} is behind", i);
+            sleep(Duration::from_millis(10));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn replicate_writes() -> Result<()> {
+    let nodes = start_cluster("cluster-replicate", 3)?;
+    let live = [0, 1, 2];
+    let leader = leader(&nodes, &live)?;
+    let mut conn = nodes[leader].connect()?;
+    for i in 0..100u32 {
+        conn.set(i.to_string().into_bytes().into(), i.to_be_bytes().to_vec())?;
+    }
+    conn.delete(b"7".to_vec().into())?;
+    applied(&nodes, &live, leader)?;
+    for node in &nodes {
+        let mut conn = node.connect()?;
+        assert_eq!(99, conn.scan(None, None)?.count());
+        assert_eq!(None, conn.get(b"7".to_vec().into())?);
+        assert_eq!(
+            Some(8u32.to_be_bytes().to_vec()),
+            conn.get(b"8".to_vec().into())?
+        );
+    }
+
+    let follower = (leader + 1) % 3;
+    let mut conn = nodes[follower].connect()?;
+    let err = conn
+        .set(b"name".to_vec().into(), b"Hexi".to_vec())
+        .unwrap_err();
+    assert_eq!(StatusCode::Redirect, err.code);
+    assert_eq!(nodes[leader].addr, err.message);
+    assert!(!conn.is_poisoned());
+    let status = conn.cluster_status()?;
+    assert_eq!(RaftRole::Follower, status.role);
+    assert_eq!(3, status.members);
+    nodes.iter().for_each(Node::kill);
+    Ok(())
+}
+
+// Proposals that come while the leader syncs its log are synced together.
+#[test]
+fn concurrent_writes() -> Result<()> {
+    for &size in &[1, 3] {
+        let nodes = start_cluster(&format!("cluster-concurrent-{}", size), size)?;
+        let live: Vec<_> = (0..size as usize).collect();
+        let leader = leader(&nodes, &live)?;
+        let writers = (0..4u32)
+            .map(|writer| {
+                let mut conn = nodes[leader].connect()?;
+                Ok(spawn(move || {
+                    for i in 0..50u32 {
+                        let key = format!("{}-{}", writer, i).into_bytes();
+                        conn.set(key.into(), i.to_be_bytes().to_vec()).unwrap();
+                    }
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        applied(&nodes, &live, leader)?;
+        for node in &nodes {
+            assert_eq!(200, node.connect()?.scan(None, None)?.count());
+        }
+        nodes.iter().for_each(Node::kill);
+    }
+    Ok(())
+}
+
+#[test]
+fn linearizable_reads() -> Result<()> {
+    let nodes = configs(&temp_dir("cluster-linearizable"), 3)?
+        .into_iter()
+        .map(|(mut config, listener)| {
+            config.linearizable_reads = true;
+            start_node(config, listener, EngineImpl::default())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let leader = leader(&nodes, &[0, 1, 2])?;
+    let mut conn = nodes[leader].connect()?;
+    for i in 0..20u32 {
+        let key = i.to_string().into_bytes();
+        conn.set(key.clone().into(), i.to_be_bytes().to_vec())?;
+        // the leader has applied the write before it reads
+        assert_eq!(Some(i.to_be_bytes().to_vec()), conn.get(key.into())?);
+    }
+    assert_eq!(20, conn.scan(None, None)?.count());
+
+    let follower = (leader + 1) % 3;
+    let mut conn = nodes[follower].connect()?;
+    let err = conn.get(b"0".to_vec().into()).unwrap_err();
+    assert_eq!(StatusCode::Redirect, err.code);
+    assert_eq!(nodes[leader].addr, err.message);
+    let err = conn.scan(None, None).err().unwrap();
+    assert_eq!(StatusCode::Redirect, err.code);
+    assert!(!conn.is_poisoned());
+
+    // a leader cut off from the others cannot confirm it still leads
+    let others: Vec<_> = (0..3).filter(|&i| i != leader).collect();
+    others.iter().for_each(|&i| nodes[i].kill());
+    let err = nodes[leader]
+        .connect()?
+        .get(b"0".to_vec().into())
+        .unwrap_err();
+    assert_eq!(StatusCode::Unavailable, err.code);
+    nodes[leader].kill();
+    Ok(())
+}
+
+#[test]
+fn kill_nodes() -> Result<()> {
+    let nodes = start_cluster("cluster-kill", 3)?;
+    let first = leader(&nodes, &[0, 1, 2])?;
+    nodes[first]
+        .connect()?
+        .set(b"name".to_vec().into(), b"Hexi".to_vec())?;
+    nodes[first].kill();
+
+    let live: Vec<_> = (0..3).filter(|&i| i != first).collect();
+    let second = leader(&nodes, &live)?;
+    assert!(nodes[second].status()?.term > 1);
+    let mut conn = nodes[second].connect()?;
+    assert_eq!(Some(b"Hexi".to_vec()), conn.get(b"name".to_vec().into())?);
+    conn.set(b"name".to_vec().into(), b"Lee".to_vec())?;
+    applied(&nodes, &live, second)?;
+    let follower = live.iter().find(|&&i| i != second).unwrap();
+    assert_eq!(
+        Some(b"Lee".to_vec()),
+        nodes[*follower].connect()?.get(b"name".to_vec().into())?
+    );
+
+    // without a majority, writes are not committed
+    nodes[*follower].kill();
+    let err = conn
+        .set(b"name".to_vec().into(), b"lost".to_vec())
+        .unwrap_err();
+    assert_eq!(StatusCode::Unavailable, err.code);
+    assert_eq!(Some(b"Lee".to_vec()), conn.get(b"name".to_vec().into())?);
+    nodes[second].kill();
+    Ok(())
+}
+
+#[test]
+fn restart_nodes() -> Result<()> {
+    let dir = temp_dir("cluster-restart");
+    let sled = |id: u64| SledEngine::new(dir.join(id.to_string()).join("db"));
+    let mut nodes = configs(&dir, 3)?
+        .into_iter()
+        .map(|(mut config, listener)| {
+            config.log_size = 20;
+            let engine = sled(config.id);
+            start_node(config, listener, engine)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let live = [0, 1, 2];
+    let elected = leader(&nodes, &live)?;
+    let mut conn = nodes[elected].connect()?;
+    let key = |i: u32| i.to_string().into_bytes().into();
+    for i in 0..10 {
+        conn.set(key(i), i.to_be_bytes().to_vec())?;
+    }
+    applied(&nodes, &live, elected)?;
+
+    // a member on sled resumes from the writes it applied, and gets the ones compacted
+    // while it was down from a snapshot
+    let first = (elected + 1) % 3;
+    let before = nodes[first].status()?;
+    nodes[first].stop();
+    for i in 10..100 {
+        conn.set(key(i), i.to_be_bytes().to_vec())?;
+    }
+    let config = nodes[first].config.clone();
+    let engine = sled(config.id);
+    nodes[first] = start_node(config, rebind(&nodes[first].addr)?, engine)?;
+    let status = nodes[first].status()?;
+    assert!(status.term >= before.term);
+    assert!(status.applied >= before.applied, "{:?}", status);
+
+    // a member on the memory engine rebuilds it from the snapshot and the log
+    let second = (elected + 2) % 3;
+    nodes[second].stop();
+    let config = nodes[second].config.clone();
+    let listener = rebind(&nodes[second].addr)?;
+    nodes[second] = start_node(config, listener, EngineImpl::default())?;
+    conn.set(key(100), 100u32.to_be_bytes().to_vec())?;
+
+    let elected = leader(&nodes, &live)?;
+    applied(&nodes, &live, elected)?;
+    for node in &nodes {
+        assert!(raft_path(&dir, node.config.id).join("snapshot").exists());
+        let mut conn = node.connect()?;
+        assert_eq!(101, conn.scan(None, None)?.count());
+        assert_eq!(Some(42u32.to_be_bytes().to_vec()), conn.get(key(42))?);
+    }
+    nodes.iter().for_each(Node::kill);
+    let _ = std::fs::remove_dir_all(dir);
+    Ok(())
+}
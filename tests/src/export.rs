@@ -0,0 +1,120 @@
+use crate::serve_local;
+use bronzedb_cli::checkpoint::{self, Checkpoint};
+use bronzedb_cli::format::Format;
+use bronzedb_cli::transfer::{export, import, Job};
+use bronzedb_client::{BronzeConnManager, Connection, Stream};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::Server;
+use bronzedb_util::status::{Result, StatusCode};
+use bronzedb_util::types::{Entry, Key};
+use r2d2::ManageConnection;
+use std::fs;
+use std::time::Duration;
+
+fn key(i: usize) -> Key {
+    format!("e{:04}", i).into_bytes().into()
+}
+
+fn connect() -> Result<Connection<Stream>> {
+    BronzeConnManager::new(serve_local(Server::new(EngineImpl::default())))
+        .read_timeout(Duration::from_secs(5))
+        .connect()
+}
+
+fn seeded() -> Result<Connection<Stream>> {
+    let mut conn = connect()?;
+    for i in 0..600 {
+        conn.set(key(i), format!("value, \"{}\"\n", i).into_bytes())?;
+    }
+    Ok(conn)
+}
+
+fn entries(conn: &mut Connection<Stream>) -> Result<Vec<Entry>> {
+    conn.scan(None, None)?.collect()
+}
+
+fn job(format: Format, name: &str) -> Job {
+    let path = std::env::temp_dir().join(format!("bronzedb-{}-{}", name, std::process::id()));
+    Job {
+        format,
+        file: Some(path.to_str().unwrap().to_owned()),
+        resume: false,
+        batch: 100,
+    }
+}
+
+#[test]
+fn export_and_import() -> Result<()> {
+    let mut source = seeded()?;
+    for (format, name) in &[
+        (Format::Ndjson, "ndjson"),
+        (Format::Csv, "csv"),
+        (Format::Binary, "binary"),
+    ] {
+        let job = job(*format, name);
+        let mut reported = Vec::new();
+        assert_eq!(
+            600,
+            export(&mut source, &job, None, None, |n| reported.push(n))?
+        );
+        assert_eq!(vec![100, 200, 300, 400, 500, 600], reported);
+        let mut target = connect()?;
+        assert_eq!(600, import(&mut target, &job, |_| ())?);
+        assert_eq!(entries(&mut source)?, entries(&mut target)?, "{}", name);
+
+        let file = job.file.unwrap();
+        assert!(!std::path::Path::new(&checkpoint::path(&file)).exists());
+        fs::remove_file(&file)?;
+    }
+
+    let job = job(Format::Ndjson, "range");
+    let exported = export(&mut source, &job, Some(key(100)), Some(key(149)), |_| ())?;
+    assert_eq!(50, exported);
+    fs::remove_file(job.file.unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn resume() -> Result<()> {
+    let mut source = seeded()?;
+    let full = job(Format::Ndjson, "full");
+    export(&mut source, &full, None, None, |_| ())?;
+    let full = full.file.unwrap();
+    let data = fs::read_to_string(&full)?;
+
+    // an export cut short after 300 entries, and part of the next one
+    let mut partial = job(Format::Ndjson, "partial");
+    let file = partial.file.clone().unwrap();
+    let kept: String = data.split_inclusive('\n').take(300).collect();
+    fs::write(&file, format!("{}{{\"key\":", kept))?;
+    Checkpoint {
+        entries: 300,
+        offset: kept.len() as u64,
+        last: Some(key(299)),
+    }
+    .save(&checkpoint::path(&file))?;
+    partial.resume = true;
+    assert_eq!(600, export(&mut source, &partial, None, None, |_| ())?);
+    assert_eq!(data, fs::read_to_string(&file)?);
+
+    // an import stopped by a damaged entry goes on once it is fixed
+    let broken = format!("{}{{\"broken\"{}", kept, &data[kept.len() + 10..]);
+    fs::write(&file, broken)?;
+    partial.resume = false;
+    let mut target = connect()?;
+    let err = import(&mut target, &partial, |_| ()).unwrap_err();
+    assert_eq!(StatusCode::Corruption, err.code);
+    assert_eq!(
+        Some(300),
+        Checkpoint::load(&checkpoint::path(&file))?.map(|checkpoint| checkpoint.entries)
+    );
+    assert_eq!(None, target.get(key(300))?);
+    fs::write(&file, &data)?;
+    partial.resume = true;
+    assert_eq!(600, import(&mut target, &partial, |_| ())?);
+    assert_eq!(entries(&mut source)?, entries(&mut target)?);
+
+    fs::remove_file(&full)?;
+    fs::remove_file(&file)?;
+    Ok(())
+}
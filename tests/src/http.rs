@@ -0,0 +1,172 @@
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{HttpServer, Permission, User};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::spawn;
+
+fn serve_http(mut server: HttpServer<EngineImpl>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    spawn(move || server.serve(listener).unwrap());
+    addr
+}
+
+// HTTP/1.0 keeps responses unchunked and closes the connection afterwards.
+fn request(addr: SocketAddr, method: &str, path: &str, headers: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\n{}Content-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        headers,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head[9..12].parse().unwrap(), body.to_owned())
+}
+
+#[test]
+fn key_value() {
+    let addr = serve_http(HttpServer::new(EngineImpl::default()));
+    assert_eq!(204, request(addr, "PUT", "/kv/name", "", "value").0);
+    assert_eq!(
+        (200, r#"{"key":"name","value":"value"}"#.to_owned()),
+        request(addr, "GET", "/kv/name", "", "")
+    );
+    assert_eq!(204, request(addr, "DELETE", "/kv/name", "", "").0);
+    assert_eq!(
+        (404, r#"{"error":"not found"}"#.to_owned()),
+        request(addr, "GET", "/kv/name", "", "")
+    );
+    assert_eq!(404, request(addr, "GET", "/nothing", "", "").0);
+    assert_eq!(405, request(addr, "POST", "/kv/name", "", "").0);
+}
+
+#[test]
+fn binary() {
+    let addr = serve_http(HttpServer::new(EngineImpl::default()));
+    // key b"\xff\x00" and value b"\x00\x01\x02"
+    assert_eq!(
+        204,
+        request(addr, "PUT", "/kv/_wA?encoding=base64", "", "AAEC").0
+    );
+    assert_eq!(
+        (200, r#"{"key":"/wA=","value":"AAEC"}"#.to_owned()),
+        request(addr, "GET", "/kv/%2FwA%3D?encoding=base64", "", "")
+    );
+    assert_eq!(422, request(addr, "GET", "/kv/%FF%00", "", "").0);
+    assert_eq!(400, request(addr, "GET", "/kv/_?encoding=base64", "", "").0);
+}
+
+#[test]
+fn scan() {
+    let addr = serve_http(HttpServer::new(EngineImpl::default()));
+    for key in &["a", "b", "c", "d"] {
+        assert_eq!(
+            204,
+            request(addr, "PUT", &format!("/kv/{}", key), "", key).0
+        );
+    }
+    let (status, body) = request(addr, "GET", "/scan?lower=b&upper=c", "", "");
+    assert_eq!(200, status);
+    let mut lines: Vec<&str> = body.lines().collect();
+    lines.sort();
+    assert_eq!(
+        vec![r#"{"key":"b","value":"b"}"#, r#"{"key":"c","value":"c"}"#],
+        lines
+    );
+    let (_, body) = request(addr, "GET", "/scan?limit=3", "", "");
+    assert_eq!(3, body.lines().count());
+}
+
+#[test]
+fn auth() {
+    let addr = serve_http(HttpServer::new(EngineImpl::default()).users(vec![
+        User::new("admin").password("secret"),
+        User::new("reader")
+            .token("token")
+            .permissions(vec![Permission::Read]),
+    ]));
+    // admin:secret
+    let admin = "Authorization: Basic YWRtaW46c2VjcmV0\r\n";
+    let reader = "Authorization: Bearer token\r\n";
+    assert_eq!(401, request(addr, "GET", "/kv/name", "", "").0);
+    assert_eq!(
+        401,
+        request(
+            addr,
+            "GET",
+            "/kv/name",
+            "Authorization: Bearer wrong\r\n",
+            ""
+        )
+        .0
+    );
+    assert_eq!(204, request(addr, "PUT", "/kv/name", admin, "value").0);
+    assert_eq!(200, request(addr, "GET", "/kv/name", reader, "").0);
+    assert_eq!(403, request(addr, "PUT", "/kv/name", reader, "value").0);
+    assert_eq!(403, request(addr, "GET", "/scan", reader, "").0);
+}
+
+fn read_head(reader: &mut BufReader<TcpStream>) -> String {
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        reader.read_line(&mut head).unwrap();
+    }
+    head
+}
+
+#[test]
+fn keep_alive() {
+    let addr = serve_http(HttpServer::new(EngineImpl::default()));
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    // a chunked body, sent once the server is ready for it
+    write!(
+        stream,
+        "PUT /kv/name HTTP/1.1\r\nTransfer-Encoding: chunked\r\nExpect: 100-continue\r\n\r\n"
+    )
+    .unwrap();
+    assert_eq!("HTTP/1.1 100 Continue\r\n\r\n", read_head(&mut reader));
+    write!(stream, "3\r\nval\r\n2;ext=1\r\nue\r\n0\r\n\r\n").unwrap();
+    assert_eq!("HTTP/1.1 204 No Content\r\n\r\n", read_head(&mut reader));
+
+    write!(stream, "GET /kv/name HTTP/1.1\r\n\r\n").unwrap();
+    let head = read_head(&mut reader);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains("Content-Length: 30\r\n"));
+    let mut body = [0; 30];
+    reader.read_exact(&mut body).unwrap();
+    assert_eq!(br#"{"key":"name","value":"value"}"#, &body);
+
+    // without keep-alive the scan ends with the connection
+    write!(stream, "GET /scan HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    let head = read_head(&mut reader);
+    assert!(head.contains("Connection: close\r\n"));
+    let mut body = String::new();
+    reader.read_to_string(&mut body).unwrap();
+    assert_eq!("{\"key\":\"name\",\"value\":\"value\"}\n", body);
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    // otherwise it is chunked
+    write!(stream, "GET /scan HTTP/1.1\r\n\r\n").unwrap();
+    assert!(read_head(&mut reader).contains("Transfer-Encoding: chunked\r\n"));
+    let mut chunks = String::new();
+    while !chunks.ends_with("0\r\n\r\n") {
+        reader.read_line(&mut chunks).unwrap();
+    }
+    assert_eq!(
+        "1f\r\n{\"key\":\"name\",\"value\":\"value\"}\n\r\n0\r\n\r\n",
+        chunks
+    );
+
+    write!(stream, "GET /kv/name HTTP/1.1\r\nContent-Length: x\r\n\r\n").unwrap();
+    assert!(read_head(&mut reader).starts_with("HTTP/1.1 400 Bad Request\r\n"));
+}
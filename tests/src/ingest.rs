@@ -0,0 +1,56 @@
+use crate::serve_local;
+use bronzedb_client::{BronzeConnManager, Client};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::Server;
+use bronzedb_util::status::{Result, StatusCode};
+use bronzedb_util::types::{Entry, Key};
+use r2d2::ManageConnection;
+use std::time::Duration;
+
+fn key(i: usize) -> Key {
+    format!("i{:05}", i).into_bytes().into()
+}
+
+fn manager(addr: &str) -> BronzeConnManager {
+    BronzeConnManager::new(addr).read_timeout(Duration::from_secs(5))
+}
+
+#[test]
+fn ingest() -> Result<()> {
+    let addr = serve_local(Server::new(EngineImpl::default()));
+    let mut conn = manager(&addr).connect()?;
+    conn.set(key(0), b"old".to_vec())?;
+    conn.set(key(5000), b"kept".to_vec())?;
+    let entries = (0..3000).map(|i| (key(i), i.to_string().into_bytes()));
+    assert_eq!(3000, conn.ingest(entries)?);
+    assert!(!conn.is_poisoned());
+    assert_eq!(Some(b"0".to_vec()), conn.get(key(0))?);
+    assert_eq!(Some(b"kept".to_vec()), conn.get(key(5000))?);
+    let scanned = conn.scan(None, None)?.collect::<Result<Vec<Entry>>>()?;
+    assert_eq!(3001, scanned.len());
+    assert_eq!((key(2999), b"2999".to_vec()), scanned[2999]);
+
+    let mut client = Client::new(manager(&addr));
+    let entries = (3000..3100).map(|i| (key(i), Vec::new()));
+    assert_eq!(100, client.ingest(entries)?);
+    assert_eq!(Some(Vec::new()), client.get(key(3099))?);
+    Ok(())
+}
+
+#[test]
+fn unsorted() -> Result<()> {
+    let manager = manager(&serve_local(Server::new(EngineImpl::default())));
+    let mut conn = manager.connect()?;
+    let entries = vec![(key(1), Vec::new()), (key(1), Vec::new())];
+    let err = conn.ingest(entries).unwrap_err();
+    assert_eq!(StatusCode::Corruption, err.code);
+    assert!(conn.is_poisoned());
+
+    let mut conn = manager.connect()?;
+    let entries = vec![(key(2), Vec::new()), (key(1), Vec::new())];
+    assert_eq!(
+        StatusCode::Corruption,
+        conn.ingest(entries).unwrap_err().code
+    );
+    Ok(())
+}
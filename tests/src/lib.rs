@@ -1,14 +1,41 @@
 #![cfg(test)]
-#![feature(duration_float)]
 
 #[macro_use]
 extern crate serde_derive;
 
-use bronzedb_client::{BronzeConnManager, Connection, Pool};
+use bronzedb_client::{BronzeConnManager, Compression, Options, Pool};
+use bronzedb_engine::Engine;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::Server;
 use bronzedb_util::status::Result;
-use std::net::TcpStream;
+use std::net::TcpListener;
+use std::thread::spawn;
 use std::time::Instant;
 
+mod auth;
+mod backup;
+mod bench;
+mod cancel;
+mod client;
+mod cluster;
+mod export;
+mod http;
+mod ingest;
+mod limit;
+mod memcached;
+mod migration;
+mod pool;
+mod pubsub;
+mod replication;
+mod resp;
+mod shard;
+mod shell;
+mod shutdown;
+mod timeout;
+mod tls;
+mod unix;
+mod watch;
+
 #[derive(Serialize, Deserialize, Debug)]
 struct Config {
     pub db_addr: String,
@@ -22,6 +49,13 @@ impl Config {
     }
 }
 
+fn serve_local<T: Engine + Clone + Sync + Send + 'static>(mut server: Server<T>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    spawn(move || server.serve(listener).unwrap());
+    addr
+}
+
 #[test]
 fn single_thread() -> Result<()> {
     let manager = BronzeConnManager::new(Config::new().db_addr);
@@ -188,3 +222,79 @@ fn multi_thread() -> Result<()> {
     }
     Ok(())
 }
+
+fn document(key: &[u8]) -> Vec<u8> {
+    format!(
+        r#"{{"key":"{}","engine":"bronze","tags":[{}]}}"#,
+        String::from_utf8_lossy(key),
+        r#""database","kv""#.repeat(64)
+    )
+    .into_bytes()
+}
+
+fn negotiated(prefix: &str, options: Options) -> Result<()> {
+    const SIZE: u64 = 1_000;
+    let addr = serve_local(Server::new(EngineImpl::default()));
+    let manager = BronzeConnManager::new(addr).options(options);
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    let mut connect = pool.get().unwrap();
+    for i in 0..SIZE {
+        let key = format!("{}{:04}", prefix, i).into_bytes();
+        connect.set(key.clone().into(), document(&key))?;
+    }
+    for i in 0..SIZE {
+        let key = format!("{}{:04}", prefix, i).into_bytes();
+        assert_eq!(document(&key), connect.get(key.into())?.unwrap());
+    }
+    {
+        let lower_key = format!("{}0000", prefix).into_bytes().into();
+        let upper_key = format!("{}{:04}", prefix, SIZE - 1).into_bytes().into();
+        let mut counter = 0;
+        for item in connect.scan(Some(lower_key), Some(upper_key))? {
+            let (key, value) = item?;
+            assert_eq!(document(&key), value);
+            counter += 1;
+        }
+        assert_eq!(SIZE, counter);
+    }
+    for i in 0..SIZE {
+        let key = format!("{}{:04}", prefix, i).into_bytes().into();
+        connect.delete(key)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn checksum() -> Result<()> {
+    negotiated(
+        "checksum",
+        Options {
+            checksum: true,
+            ..Options::default()
+        },
+    )
+}
+
+#[test]
+fn lz4_compression() -> Result<()> {
+    negotiated(
+        "lz4",
+        Options {
+            checksum: true,
+            compression: Compression::Lz4,
+            cancel: true,
+        },
+    )
+}
+
+#[test]
+fn zstd_compression() -> Result<()> {
+    negotiated(
+        "zstd",
+        Options {
+            checksum: false,
+            compression: Compression::Zstd,
+            cancel: true,
+        },
+    )
+}
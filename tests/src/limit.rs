@@ -0,0 +1,147 @@
+use crate::serve_local;
+use bronzedb_client::BronzeConnManager;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::listener::Waker;
+use bronzedb_server::{HttpServer, Limits, Listener, MemcachedServer, RespServer, Server};
+use bronzedb_util::status::Result;
+use bronzedb_util::status::StatusCode::{self, ServerBusy};
+use r2d2::ManageConnection;
+use std::cell::Cell;
+use std::io::{self, Read};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+fn rejection(addr: &str) -> Option<StatusCode> {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let mut status = [0];
+    match stream.read(&mut status) {
+        Ok(1) => Some(status[0].into()),
+        _ => None,
+    }
+}
+
+#[test]
+fn max_connections() -> Result<()> {
+    let addr = serve_local(Server::new(EngineImpl::default()).limits(Limits {
+        max_connections: Some(1),
+        ..Limits::default()
+    }));
+    let manager = BronzeConnManager::new(addr.clone());
+    let mut conn = manager.connect()?;
+    conn.ping()?;
+    assert_eq!(Some(ServerBusy), rejection(&addr));
+    conn.ping()?;
+    drop(conn);
+
+    // the slot is released once the server notices the disconnection
+    for _ in 0..50 {
+        if let Ok(mut conn) = manager.connect() {
+            if conn.ping().is_ok() {
+                return Ok(());
+            }
+        }
+        sleep(Duration::from_millis(20));
+    }
+    panic!("the connection slot is never released");
+}
+
+#[test]
+fn worker_pool() -> Result<()> {
+    let addr = serve_local(Server::new(EngineImpl::default()).limits(Limits {
+        workers: Some(1),
+        queue_size: 1,
+        ..Limits::default()
+    }));
+    let manager = BronzeConnManager::new(addr.clone());
+    let mut conn = manager.connect()?;
+    conn.ping()?;
+    // the second client waits in the queue, the third one is rejected
+    let queued = TcpStream::connect(&addr).unwrap();
+    assert_eq!(Some(ServerBusy), rejection(&addr));
+    drop(queued);
+    conn.set(b"name".to_vec().into(), b"value".to_vec())?;
+    Ok(())
+}
+
+// Fails the first accepts as if the process had run out of file descriptors.
+struct Exhausted {
+    listener: TcpListener,
+    failures: Cell<usize>,
+}
+
+impl Listener for Exhausted {
+    type Stream = TcpStream;
+
+    fn accept(&self) -> io::Result<(TcpStream, String)> {
+        if self.failures.get() > 0 {
+            self.failures.set(self.failures.get() - 1);
+            return Err(io::Error::from_raw_os_error(24));
+        }
+        Listener::accept(&self.listener)
+    }
+
+    fn waker(&self) -> io::Result<Waker> {
+        self.listener.waker()
+    }
+}
+
+#[test]
+fn accept_errors() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let listener = Exhausted {
+        listener,
+        failures: Cell::new(3),
+    };
+    let mut server = Server::new(EngineImpl::default());
+    spawn(move || server.serve(listener).unwrap());
+    BronzeConnManager::new(addr).connect()?.ping()
+}
+
+#[test]
+fn side_listeners() {
+    let limits = Limits {
+        max_connections: Some(1),
+        ..Limits::default()
+    };
+    let serve = |server: Box<dyn FnOnce(TcpListener) + Send>| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        spawn(move || server(listener));
+        addr
+    };
+    let cases = vec![
+        (
+            serve(Box::new(move |listener| {
+                let mut server = RespServer::new(EngineImpl::default()).limits(limits);
+                server.serve(listener).unwrap()
+            })),
+            "-ERR max number of clients reached\r\n",
+        ),
+        (
+            serve(Box::new(move |listener| {
+                let mut server = MemcachedServer::new(EngineImpl::default()).limits(limits);
+                server.serve(listener).unwrap()
+            })),
+            "SERVER_ERROR too many open connections\r\n",
+        ),
+        (
+            serve(Box::new(move |listener| {
+                let mut server = HttpServer::new(EngineImpl::default()).limits(limits);
+                server.serve(listener).unwrap()
+            })),
+            "HTTP/1.1 503 Service Unavailable\r\n",
+        ),
+    ];
+    for (addr, rejection) in cases {
+        let _first = TcpStream::connect(addr).unwrap();
+        let mut second = TcpStream::connect(addr).unwrap();
+        let mut response = String::new();
+        second.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with(rejection), "{}", response);
+    }
+}
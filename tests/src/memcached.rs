@@ -0,0 +1,231 @@
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{MemcachedServer, User};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::spawn;
+
+fn serve_memcached(mut server: MemcachedServer<EngineImpl>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    spawn(move || server.serve(listener).unwrap());
+    addr
+}
+
+fn round_trip(stream: &mut TcpStream, request: &[u8], expected: &[u8]) {
+    stream.write_all(request).unwrap();
+    let mut buf = vec![0; expected.len()];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(expected),
+        String::from_utf8_lossy(&buf)
+    );
+}
+
+#[test]
+fn text() {
+    let addr = serve_memcached(MemcachedServer::new(EngineImpl::default()));
+    let mut stream = TcpStream::connect(addr).unwrap();
+    round_trip(&mut stream, b"set a 0 0 5\r\nhello\r\n", b"STORED\r\n");
+    round_trip(&mut stream, b"add a 0 0 1\r\nx\r\n", b"NOT_STORED\r\n");
+    round_trip(&mut stream, b"replace b 0 0 1\r\nx\r\n", b"NOT_STORED\r\n");
+    round_trip(
+        &mut stream,
+        b"get a b\r\n",
+        b"VALUE a 0 5\r\nhello\r\nEND\r\n",
+    );
+
+    stream.write_all(b"gets a\r\n").unwrap();
+    let mut response = Vec::new();
+    while !response.ends_with(b"END\r\n") {
+        let mut byte = [0];
+        stream.read_exact(&mut byte).unwrap();
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8(response).unwrap();
+    let unique = response.lines().next().unwrap().rsplit(' ').next().unwrap();
+    round_trip(&mut stream, b"cas a 0 0 1 1\r\nx\r\n", b"EXISTS\r\n");
+    round_trip(
+        &mut stream,
+        format!("cas a 0 0 5 {}\r\nworld\r\n", unique).as_bytes(),
+        b"STORED\r\n",
+    );
+    round_trip(&mut stream, b"cas c 0 0 1 1\r\nx\r\n", b"NOT_FOUND\r\n");
+
+    round_trip(&mut stream, b"set n 0 0 2 noreply\r\n10\r\n", b"");
+    round_trip(&mut stream, b"incr n 5\r\n", b"15\r\n");
+    round_trip(&mut stream, b"decr n 20\r\n", b"0\r\n");
+    round_trip(
+        &mut stream,
+        b"incr a 1\r\n",
+        b"CLIENT_ERROR cannot increment or decrement non-numeric value\r\n",
+    );
+    round_trip(&mut stream, b"incr m 1\r\n", b"NOT_FOUND\r\n");
+    round_trip(&mut stream, b"delete a\r\n", b"DELETED\r\n");
+    round_trip(&mut stream, b"delete a\r\n", b"NOT_FOUND\r\n");
+    round_trip(&mut stream, b"flush_all\r\n", b"ERROR\r\n");
+    round_trip(
+        &mut stream,
+        b"set e 0 60 1\r\nx\r\n",
+        b"CLIENT_ERROR items do not expire, exptime must be 0\r\n",
+    );
+    round_trip(&mut stream, b"get e\r\n", b"END\r\n");
+    round_trip(&mut stream, b"set f 42 0 5\r\nhello\r\n", b"STORED\r\n");
+    round_trip(
+        &mut stream,
+        b"incr f 1\r\n",
+        b"CLIENT_ERROR cannot increment or decrement non-numeric value\r\n",
+    );
+    round_trip(
+        &mut stream,
+        b"get f\r\n",
+        b"VALUE f 42 5\r\nhello\r\nEND\r\n",
+    );
+    round_trip(
+        &mut stream,
+        format!("set big 0 0 8192\r\n{}\r\nget n\r\n", "x".repeat(8192)).as_bytes(),
+        b"SERVER_ERROR object too large for cache\r\nVALUE n 0 1\r\n0\r\nEND\r\n",
+    );
+}
+
+fn packet(opcode: u8, extras: &[u8], key: &[u8], value: &[u8], cas: u64) -> Vec<u8> {
+    let mut packet = vec![0x80, opcode];
+    packet.extend(&(key.len() as u16).to_be_bytes());
+    packet.push(extras.len() as u8);
+    packet.extend(&[0, 0, 0]);
+    packet.extend(&((extras.len() + key.len() + value.len()) as u32).to_be_bytes());
+    packet.extend(&0xdead_beef_u32.to_be_bytes());
+    packet.extend(&cas.to_be_bytes());
+    packet.extend(extras);
+    packet.extend(key);
+    packet.extend(value);
+    packet
+}
+
+// Returns the status, cas and body of a binary response.
+fn response(stream: &mut TcpStream) -> (u16, u64, Vec<u8>) {
+    let mut header = [0; 24];
+    stream.read_exact(&mut header).unwrap();
+    assert_eq!(0x81, header[0]);
+    assert_eq!(&0xdead_beef_u32.to_be_bytes(), &header[12..16]);
+    let status = u16::from_be_bytes([header[6], header[7]]);
+    let len = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+    let mut cas = [0; 8];
+    cas.copy_from_slice(&header[16..]);
+    let mut body = vec![0; len as usize];
+    stream.read_exact(&mut body).unwrap();
+    (status, u64::from_be_bytes(cas), body)
+}
+
+#[test]
+fn binary() {
+    let addr = serve_memcached(MemcachedServer::new(EngineImpl::default()));
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let set_extras = [0; 8];
+    stream
+        .write_all(&packet(0x01, &set_extras, b"a", b"hello", 0))
+        .unwrap();
+    let (status, cas, _) = response(&mut stream);
+    assert_eq!(0, status);
+
+    stream.write_all(&packet(0x00, &[], b"a", &[], 0)).unwrap();
+    assert_eq!((0, cas, b"\0\0\0\0hello".to_vec()), response(&mut stream));
+
+    stream
+        .write_all(&packet(0x01, &set_extras, b"a", b"x", cas + 1))
+        .unwrap();
+    assert_eq!(2, response(&mut stream).0);
+
+    // a quiet miss is followed directly by the noop response
+    stream.write_all(&packet(0x09, &[], b"b", &[], 0)).unwrap();
+    stream.write_all(&packet(0x0a, &[], &[], &[], 0)).unwrap();
+    assert_eq!((0, 0, Vec::new()), response(&mut stream));
+
+    let mut incr_extras = Vec::new();
+    incr_extras.extend(&3u64.to_be_bytes());
+    incr_extras.extend(&7u64.to_be_bytes());
+    incr_extras.extend(&0u32.to_be_bytes());
+    stream
+        .write_all(&packet(0x05, &incr_extras, b"n", &[], 0))
+        .unwrap();
+    assert_eq!(7u64.to_be_bytes().to_vec(), response(&mut stream).2);
+    stream
+        .write_all(&packet(0x05, &incr_extras, b"n", &[], 0))
+        .unwrap();
+    assert_eq!(10u64.to_be_bytes().to_vec(), response(&mut stream).2);
+
+    let mut flagged_extras = Vec::new();
+    flagged_extras.extend(&7u32.to_be_bytes());
+    flagged_extras.extend(&0u32.to_be_bytes());
+    stream
+        .write_all(&packet(0x01, &flagged_extras, b"f", b"hi", 0))
+        .unwrap();
+    let (status, cas, _) = response(&mut stream);
+    assert_eq!(0, status);
+    stream.write_all(&packet(0x00, &[], b"f", &[], 0)).unwrap();
+    assert_eq!((0, cas, b"\0\0\0\x07hi".to_vec()), response(&mut stream));
+    let mut expiring_extras = Vec::new();
+    expiring_extras.extend(&0u32.to_be_bytes());
+    expiring_extras.extend(&60u32.to_be_bytes());
+    stream
+        .write_all(&packet(0x01, &expiring_extras, b"e", b"x", 0))
+        .unwrap();
+    assert_eq!(0x83, response(&mut stream).0);
+
+    stream.write_all(&packet(0x04, &[], b"a", &[], 0)).unwrap();
+    assert_eq!(0, response(&mut stream).0);
+    stream.write_all(&packet(0x04, &[], b"a", &[], 0)).unwrap();
+    assert_eq!(1, response(&mut stream).0);
+}
+
+#[test]
+fn sasl() {
+    let addr = serve_memcached(
+        MemcachedServer::new(EngineImpl::default())
+            .users(vec![User::new("admin").password("secret")]),
+    );
+    let mut text = TcpStream::connect(addr).unwrap();
+    round_trip(
+        &mut text,
+        b"get a\r\n",
+        b"CLIENT_ERROR authentication required, use the binary protocol\r\n",
+    );
+    // the text connection is closed
+    assert_eq!(0, text.read(&mut [0]).unwrap());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&packet(0x00, &[], b"a", &[], 0)).unwrap();
+    assert_eq!(0x20, response(&mut stream).0);
+    stream
+        .write_all(&packet(0x21, &[], b"PLAIN", b"\0admin\0wrong", 0))
+        .unwrap();
+    assert_eq!(0x20, response(&mut stream).0);
+    stream
+        .write_all(&packet(0x21, &[], b"PLAIN", b"\0admin\0secret", 0))
+        .unwrap();
+    assert_eq!(0, response(&mut stream).0);
+    stream.write_all(&packet(0x00, &[], b"a", &[], 0)).unwrap();
+    assert_eq!(1, response(&mut stream).0);
+}
+
+#[test]
+fn concurrent_incr() {
+    let addr = serve_memcached(MemcachedServer::new(EngineImpl::default()));
+    let mut stream = TcpStream::connect(addr).unwrap();
+    round_trip(&mut stream, b"set n 0 0 1\r\n0\r\n", b"STORED\r\n");
+    let clients = (0..4)
+        .map(|_| {
+            spawn(move || {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                for _ in 0..100 {
+                    stream.write_all(b"incr n 1 noreply\r\n").unwrap();
+                }
+                round_trip(&mut stream, b"add n 0 0 1\r\nx\r\n", b"NOT_STORED\r\n");
+            })
+        })
+        .collect::<Vec<_>>();
+    for client in clients {
+        client.join().unwrap();
+    }
+    // no increment is lost between connections
+    round_trip(&mut stream, b"get n\r\n", b"VALUE n 0 3\r\n400\r\nEND\r\n");
+}
@@ -0,0 +1,187 @@
+use crate::shard::{key, partition_map, start_shards, start_stored_shards, Node};
+use bronzedb_client::{ClusterClient, MigrationCommand, MigrationState, MigrationStatus};
+use bronzedb_server::Sharding;
+use bronzedb_util::status::{Result, StatusCode};
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+fn start(lower: &str, upper: Option<&str>, rate: u32) -> MigrationCommand {
+    MigrationCommand::Start {
+        lower: lower.as_bytes().to_vec().into(),
+        upper: upper.map(|upper| upper.as_bytes().to_vec().into()),
+        rate,
+    }
+}
+
+fn finished(node: &Node) -> Result<MigrationStatus> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut conn = node.connect()?;
+    loop {
+        let status = conn.migrate(MigrationCommand::Status)?;
+        match status.state {
+            MigrationState::Copying | MigrationState::CatchingUp if Instant::now() < deadline => {
+                sleep(Duration::from_millis(10))
+            }
+            _ => return Ok(status),
+        }
+    }
+}
+
+#[test]
+fn split_shard() -> Result<()> {
+    let nodes = start_shards()?;
+    let seeds = vec![nodes[0].addr.clone()];
+    let mut client = ClusterClient::new(seeds.clone());
+    for i in 0..500 {
+        client.set(key(i).into_bytes().into(), b"old".to_vec())?;
+    }
+
+    // writes go on while the keys from "k" to "n" move to the last server
+    let stop = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let stop = stop.clone();
+        spawn(move || -> Result<usize> {
+            let mut client = ClusterClient::new(seeds);
+            let mut written = 0;
+            while !stop.load(Ordering::SeqCst) {
+                let key = format!("lw{:02}", written % 50);
+                client.set(key.into_bytes().into(), written.to_string().into_bytes())?;
+                written += 1;
+            }
+            Ok(written)
+        })
+    };
+    let status = nodes[2].connect()?.migrate(start("k", Some("n"), 0))?;
+    assert_eq!(nodes[1].addr, status.source);
+    let status = finished(&nodes[2])?;
+    stop.store(true, Ordering::SeqCst);
+    let written = writer.join().unwrap()?;
+    assert_eq!(MigrationState::Done, status.state, "{:?}", status.error);
+    assert!(status.copied > 0);
+
+    for node in &nodes {
+        let map = node.sharding.map();
+        assert_eq!(2, map.version());
+        assert_eq!(nodes[1].addr, map.owner(b"jzz"));
+        assert_eq!(nodes[2].addr, map.owner(b"k"));
+        assert_eq!(nodes[2].addr, map.owner(b"mzz"));
+        assert_eq!(nodes[1].addr, map.owner(b"n"));
+    }
+    // no write was lost on the way
+    for i in 0..written.min(50) {
+        let last = (i..written).step_by(50).next_back().unwrap();
+        let key = format!("lw{:02}", i).into_bytes().into();
+        assert_eq!(Some(last.to_string().into_bytes()), client.get(key)?);
+    }
+    for i in 0..500 {
+        assert_eq!(
+            Some(b"old".to_vec()),
+            client.get(key(i).into_bytes().into())?
+        );
+    }
+    let err = nodes[1]
+        .connect()?
+        .get(b"lw01".to_vec().into())
+        .unwrap_err();
+    assert_eq!(StatusCode::WrongShard, err.code);
+    Ok(())
+}
+
+#[test]
+fn throttle_and_abort() -> Result<()> {
+    let nodes = start_shards()?;
+    let mut client = ClusterClient::new(vec![nodes[0].addr.clone()]);
+    for i in 0..100 {
+        client.set(format!("q{:04}", i).into_bytes().into(), b"value".to_vec())?;
+    }
+    let mut conn = nodes[0].connect()?;
+    let err = conn.migrate(start("a", None, 0)).unwrap_err();
+    assert_eq!(StatusCode::WrongShard, err.code);
+
+    conn.migrate(start("p", None, 10))?;
+    let err = conn.migrate(start("p", None, 10)).unwrap_err();
+    assert_eq!(StatusCode::Unavailable, err.code);
+    let status = conn.migrate(MigrationCommand::Throttle(20))?;
+    assert_eq!(MigrationState::Copying, status.state);
+    assert_eq!(20, status.rate);
+    conn.migrate(MigrationCommand::Abort)?;
+    let status = finished(&nodes[0])?;
+    assert_eq!(MigrationState::Failed, status.state);
+    assert!(status.copied < 100);
+    for node in &nodes {
+        assert_eq!(1, node.sharding.map().version());
+    }
+    assert!(client.exists(b"q0050".to_vec().into())?);
+
+    // the range can move again once given up
+    conn.migrate(start("p", None, 0))?;
+    let status = finished(&nodes[0])?;
+    assert_eq!(MigrationState::Done, status.state, "{:?}", status.error);
+    assert_eq!(100, status.copied);
+    client.refresh()?;
+    let map = client.partition_map()?;
+    assert_eq!(2, map.version());
+    assert_eq!(nodes[0].addr, map.owner(b"q0050"));
+    assert_eq!(
+        Some(b"value".to_vec()),
+        nodes[0].connect()?.get(b"q0050".to_vec().into())?
+    );
+    Ok(())
+}
+
+#[test]
+fn unacknowledged_handover() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("bronzedb-handover-{}", std::process::id()));
+    let nodes = start_stored_shards(&dir)?;
+    let mut client = ClusterClient::new(vec![nodes[0].addr.clone()]);
+    for i in 0..100 {
+        client.set(format!("q{:04}", i).into_bytes().into(), b"value".to_vec())?;
+    }
+    // the first server cannot store the new map, so it never acknowledges it
+    fs::remove_dir_all(dir.join("0"))?;
+    let mut conn = nodes[0].connect()?;
+    conn.migrate(start("p", None, 0))?;
+    let status = finished(&nodes[0])?;
+    assert_eq!(MigrationState::Failed, status.state);
+    // the source goes back to its map and keeps the keys
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while nodes[2].sharding.map().version() != 1 && Instant::now() < deadline {
+        sleep(Duration::from_millis(10));
+    }
+    for node in &nodes {
+        assert_eq!(1, node.sharding.map().version());
+    }
+    let mut source = nodes[2].connect()?;
+    assert_eq!(
+        Some(b"value".to_vec()),
+        source.get(b"q0050".to_vec().into())?
+    );
+    source.set(b"q0050".to_vec().into(), b"new".to_vec())?;
+
+    fs::create_dir_all(dir.join("0"))?;
+    conn.migrate(start("p", None, 0))?;
+    let status = finished(&nodes[0])?;
+    assert_eq!(MigrationState::Done, status.state, "{:?}", status.error);
+    assert_eq!(
+        Some(b"new".to_vec()),
+        nodes[0].connect()?.get(b"q0050".to_vec().into())?
+    );
+    // a restarted source does not claim the keys it handed over
+    let configured = partition_map(
+        1,
+        &[
+            ("", &nodes[0].addr),
+            ("h", &nodes[1].addr),
+            ("p", &nodes[2].addr),
+        ],
+    );
+    let path = dir.join("2").join("partition.map");
+    let map = Sharding::open(nodes[2].addr.as_str(), configured, path)?.map();
+    assert_eq!(2, map.version());
+    assert_eq!(nodes[0].addr, map.owner(b"q0050"));
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
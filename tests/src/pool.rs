@@ -0,0 +1,114 @@
+use crate::serve_local;
+use bronzedb_client::{BronzeConnManager, Options, Pool, Validation};
+use bronzedb_engine::{Engine, Scanner};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{Server, Timeouts};
+use bronzedb_util::status::{Error, Result, StatusCode};
+use bronzedb_util::types::{Key, Value};
+use r2d2::ManageConnection;
+use std::thread::sleep;
+use std::time::Duration;
+
+fn fill(manager: &BronzeConnManager) -> Result<()> {
+    let mut conn = manager.connect()?;
+    for key in &["a", "b", "c"] {
+        conn.set(key.as_bytes().to_vec().into(), key.as_bytes().to_vec())?;
+    }
+    Ok(())
+}
+
+// Fails every request, after which the server closes the connection.
+#[derive(Clone)]
+struct Broken;
+
+impl Engine for Broken {
+    type Error = Error;
+
+    fn set(&mut self, _key: Key, _value: Value) -> Result<()> {
+        Err(Error::new(StatusCode::EngineError, "broken"))
+    }
+
+    fn get(&self, _key: Key) -> Result<Option<Value>> {
+        Err(Error::new(StatusCode::EngineError, "broken"))
+    }
+
+    fn delete(&mut self, _key: Key) -> Result<()> {
+        Err(Error::new(StatusCode::EngineError, "broken"))
+    }
+
+    fn scan(&self, _lower: Option<Key>, _upper: Option<Key>) -> Result<Box<dyn Scanner + '_>> {
+        Err(Error::new(StatusCode::EngineError, "broken"))
+    }
+}
+
+#[test]
+fn poisoned_by_engine_error() -> Result<()> {
+    let manager =
+        BronzeConnManager::new(serve_local(Server::new(Broken))).validation(Validation::State);
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    {
+        let mut conn = pool.get().unwrap();
+        let err = conn.get(b"a".to_vec().into()).unwrap_err();
+        assert_eq!(StatusCode::EngineError, err.code);
+        assert!(conn.is_poisoned());
+    }
+    // the closed connection is not handed out again
+    pool.get().unwrap().ping()
+}
+
+#[test]
+fn poisoned_by_unfinished_scan() -> Result<()> {
+    // without `cancel` an unfinished scan cannot be cleaned up
+    let manager = BronzeConnManager::new(serve_local(Server::new(EngineImpl::default())))
+        .options(Options::default());
+    fill(&manager)?;
+    let mut conn = manager.connect()?;
+    assert_eq!(3, conn.scan(None, None)?.count());
+    assert!(!conn.is_poisoned());
+    conn.scan(None, None)?.next().unwrap()?;
+    assert!(conn.is_poisoned());
+    Ok(())
+}
+
+#[test]
+fn discard_poisoned() -> Result<()> {
+    let manager = BronzeConnManager::new(serve_local(Server::new(EngineImpl::default())))
+        .options(Options::default())
+        .validation(Validation::State);
+    fill(&manager)?;
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    for _ in 0..3 {
+        let mut conn = pool.get().unwrap();
+        let mut scanner = conn.scan(None, None)?;
+        scanner.next().unwrap()?;
+    }
+    let mut conn = pool.get().unwrap();
+    assert!(!conn.is_poisoned());
+    assert_eq!(Some(b"b".to_vec()), conn.get(b"b".to_vec().into())?);
+    Ok(())
+}
+
+#[test]
+fn validation() -> Result<()> {
+    let addr = serve_local(Server::new(EngineImpl::default()).timeouts(Timeouts {
+        idle: Some(50),
+        ..Timeouts::default()
+    }));
+    let ping = Pool::builder()
+        .max_size(1)
+        .build(BronzeConnManager::new(addr.clone()))
+        .unwrap();
+    let state = Pool::builder()
+        .max_size(1)
+        .build(BronzeConnManager::new(addr).validation(Validation::State))
+        .unwrap();
+    ping.get().unwrap().ping()?;
+    state.get().unwrap().ping()?;
+    // the server closes both idle connections
+    sleep(Duration::from_millis(200));
+    ping.get().unwrap().ping()?;
+    // only the failed request reveals the closed connection
+    assert!(state.get().unwrap().ping().is_err());
+    state.get().unwrap().ping()?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::serve_local;
+use bronzedb_client::BronzeConnManager;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{Broker, Server};
+use bronzedb_util::status::Result;
+use bronzedb_util::types::Message;
+use r2d2::ManageConnection;
+use std::time::Duration;
+
+fn manager(addr: String) -> BronzeConnManager {
+    BronzeConnManager::new(addr).read_timeout(Duration::from_secs(5))
+}
+
+fn message(channel: &str, payload: &str) -> Message {
+    Message {
+        channel: channel.to_owned(),
+        payload: payload.as_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn publish_subscribe() -> Result<()> {
+    let manager = manager(serve_local(Server::new(EngineImpl::default())));
+    let mut subscriber = manager.connect()?;
+    let mut publisher = manager.connect()?;
+    // nobody listens yet
+    publisher.publish("news", b"lost".to_vec())?;
+    {
+        let subscription =
+            subscriber.subscribe(vec!["news".to_owned()], vec!["user:*".to_owned()])?;
+        publisher.publish("news", b"hello".to_vec())?;
+        publisher.publish("jobs", b"hiring".to_vec())?;
+        publisher.publish("user:1", b"hi".to_vec())?;
+        let messages = subscription.take(2).collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            vec![message("news", "hello"), message("user:1", "hi")],
+            messages
+        );
+    }
+    assert!(!subscriber.is_poisoned());
+    subscriber.ping()?;
+    // the subscription is gone with the cancelled stream
+    publisher.publish("news", b"again".to_vec())
+}
+
+#[test]
+fn shared_broker() -> Result<()> {
+    let broker = Broker::new();
+    let engine = EngineImpl::default();
+    let first = serve_local(Server::new(engine.clone()).broker(broker.clone()));
+    let second = serve_local(Server::new(engine).broker(broker));
+    let mut subscriber = manager(first).connect()?;
+    let mut subscription = subscriber.subscribe(vec!["news".to_owned()], Vec::new())?;
+    manager(second)
+        .connect()?
+        .publish("news", b"hello".to_vec())?;
+    assert_eq!(message("news", "hello"), subscription.next().unwrap()?);
+    subscription.cancel()
+}
@@ -0,0 +1,131 @@
+use crate::serve_local;
+use bronzedb_client::{BronzeConnManager, Connection, ReplicaState, Role, Stream};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{ChangeLog, Logged, ReadOnly, Replica, Replication, Server};
+use bronzedb_util::status::{Result, StatusCode};
+use r2d2::ManageConnection;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+fn connect(addr: &str) -> Result<Connection<Stream>> {
+    BronzeConnManager::new(addr)
+        .read_timeout(Duration::from_secs(5))
+        .connect()
+}
+
+fn serve_primary(log_size: usize) -> String {
+    let log = ChangeLog::new(log_size);
+    let engine = Logged::new(EngineImpl::default(), log.clone());
+    serve_local(Server::new(engine).replication(Replication::Primary(log)))
+}
+
+fn serve_replica(primary: &str) -> Result<(String, Replica)> {
+    let engine = EngineImpl::default();
+    let replica = Replica::new(primary);
+    replica.start(engine.clone())?;
+    let server =
+        Server::new(ReadOnly::new(engine)).replication(Replication::Replica(replica.clone()));
+    Ok((serve_local(server), replica))
+}
+
+// Waits until the replica applied every change of the primary.
+fn caught_up(primary: &mut Connection<Stream>, replica: &mut Connection<Stream>) -> Result<()> {
+    let head = primary.replication_status()?.head;
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let status = replica.replication_status()?;
+        if status.state == ReplicaState::Streaming && status.applied >= head {
+            assert_eq!(0, status.lag());
+            return Ok(());
+        }
+        assert!(
+            Instant::now() < deadline,
+            "replica is stuck at {:?}",
+            status
+        );
+        sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn replicate() -> Result<()> {
+    let primary_addr = serve_primary(1024);
+    let mut primary = connect(&primary_addr)?;
+    for i in 0..100u32 {
+        primary.set(i.to_string().into_bytes().into(), i.to_be_bytes().to_vec())?;
+    }
+    let (replica_addr, replica) = serve_replica(&primary_addr)?;
+    let mut conn = connect(&replica_addr)?;
+    caught_up(&mut primary, &mut conn)?;
+    assert_eq!(100, conn.scan(None, None)?.count());
+    assert_eq!(
+        Some(7u32.to_be_bytes().to_vec()),
+        conn.get(b"7".to_vec().into())?
+    );
+
+    primary.set(b"name".to_vec().into(), b"Hexi".to_vec())?;
+    primary.delete(b"7".to_vec().into())?;
+    caught_up(&mut primary, &mut conn)?;
+    assert_eq!(Some(b"Hexi".to_vec()), conn.get(b"name".to_vec().into())?);
+    assert_eq!(None, conn.get(b"7".to_vec().into())?);
+
+    let status = primary.replication_status()?;
+    assert_eq!(Role::Primary, status.role);
+    assert_eq!(102, status.head);
+    assert_eq!(1, status.replicas);
+    let status = conn.replication_status()?;
+    assert_eq!(Role::Replica, status.role);
+    assert_eq!(Some(primary_addr.clone()), status.primary);
+    replica.stop();
+    Ok(())
+}
+
+#[test]
+fn redirect_writes() -> Result<()> {
+    let primary_addr = serve_primary(1024);
+    let (replica_addr, replica) = serve_replica(&primary_addr)?;
+    let mut conn = connect(&replica_addr)?;
+    let err = conn
+        .set(b"name".to_vec().into(), b"Hexi".to_vec())
+        .unwrap_err();
+    assert_eq!(StatusCode::Redirect, err.code);
+    assert_eq!(primary_addr, err.message);
+    let err = conn.delete(b"name".to_vec().into()).unwrap_err();
+    assert_eq!(StatusCode::Redirect, err.code);
+    // the connection stays usable
+    assert!(!conn.is_poisoned());
+    assert_eq!(None, conn.get(b"name".to_vec().into())?);
+    replica.stop();
+    Ok(())
+}
+
+#[test]
+fn resync_after_truncation() -> Result<()> {
+    let primary_addr = serve_primary(4);
+    let mut primary = connect(&primary_addr)?;
+    let (replica_addr, replica) = serve_replica(&primary_addr)?;
+    let mut conn = connect(&replica_addr)?;
+    caught_up(&mut primary, &mut conn)?;
+    // more changes than the log keeps, while the replica may be between requests
+    for i in 0..50u32 {
+        primary.set(b"counter".to_vec().into(), i.to_be_bytes().to_vec())?;
+        primary.set(i.to_string().into_bytes().into(), Vec::new())?;
+    }
+    caught_up(&mut primary, &mut conn)?;
+    assert_eq!(
+        Some(49u32.to_be_bytes().to_vec()),
+        conn.get(b"counter".to_vec().into())?
+    );
+    assert_eq!(51, conn.scan(None, None)?.count());
+    replica.stop();
+    Ok(())
+}
+
+#[test]
+fn standalone() -> Result<()> {
+    let mut conn = connect(&serve_local(Server::new(EngineImpl::default())))?;
+    let status = conn.replication_status()?;
+    assert_eq!(Role::Standalone, status.role);
+    assert_eq!(None, status.primary);
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use bronzedb_engine::Engine;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{RespServer, User};
+use bronzedb_sled_db_server::EngineImpl as SledEngine;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::spawn;
+
+fn serve_resp<T: Engine + Clone + Sync + Send + 'static>(mut server: RespServer<T>) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    spawn(move || server.serve(listener).unwrap());
+    TcpStream::connect(addr).unwrap()
+}
+
+fn command(args: &[&str]) -> Vec<u8> {
+    let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        buf.extend(format!("${}\r\n{}\r\n", arg.len(), arg).bytes());
+    }
+    buf
+}
+
+fn assert_reply(stream: &mut TcpStream, expected: &[u8]) {
+    let mut buf = vec![0; expected.len()];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(expected),
+        String::from_utf8_lossy(&buf)
+    );
+}
+
+#[test]
+fn commands() {
+    let mut stream = serve_resp(RespServer::new(EngineImpl::default()));
+    stream.write_all(&command(&["PING"])).unwrap();
+    assert_reply(&mut stream, b"+PONG\r\n");
+    stream.write_all(&command(&["SET", "a", "1"])).unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+    stream.write_all(&command(&["GET", "a"])).unwrap();
+    assert_reply(&mut stream, b"$1\r\n1\r\n");
+    stream.write_all(&command(&["GET", "b"])).unwrap();
+    assert_reply(&mut stream, b"$-1\r\n");
+    stream
+        .write_all(&command(&["EXISTS", "a", "b", "a"]))
+        .unwrap();
+    assert_reply(&mut stream, b":2\r\n");
+    stream.write_all(&command(&["MGET", "a", "b"])).unwrap();
+    assert_reply(&mut stream, b"*2\r\n$1\r\n1\r\n$-1\r\n");
+    stream
+        .write_all(&command(&["SCAN", "0", "MATCH", "a*"]))
+        .unwrap();
+    assert_reply(&mut stream, b"*2\r\n$1\r\n0\r\n*1\r\n$1\r\na\r\n");
+    stream.write_all(&command(&["DEL", "a", "b"])).unwrap();
+    assert_reply(&mut stream, b":1\r\n");
+    stream.write_all(&command(&["FLUSHALL"])).unwrap();
+    assert_reply(&mut stream, b"-ERR unknown command 'flushall'\r\n");
+
+    // inline and pipelined commands
+    stream.write_all(b"SET c 3\r\nGET c\r\n").unwrap();
+    assert_reply(&mut stream, b"+OK\r\n$1\r\n3\r\n");
+
+    stream.write_all(&command(&["HELLO", "3"])).unwrap();
+    assert_reply(
+        &mut stream,
+        concat!(
+            "%6\r\n$6\r\nserver\r\n$8\r\nbronzedb\r\n$7\r\nversion\r\n$5\r\n0.1.0\r\n",
+            "$5\r\nproto\r\n:3\r\n$4\r\nmode\r\n$10\r\nstandalone\r\n",
+            "$4\r\nrole\r\n$6\r\nmaster\r\n$7\r\nmodules\r\n*0\r\n",
+        )
+        .as_bytes(),
+    );
+    stream.write_all(&command(&["GET", "b"])).unwrap();
+    assert_reply(&mut stream, b"_\r\n");
+}
+
+fn assert_set_conditions(mut stream: TcpStream) {
+    stream
+        .write_all(&command(&["SET", "a", "1", "XX"]))
+        .unwrap();
+    assert_reply(&mut stream, b"$-1\r\n");
+    stream
+        .write_all(&command(&["SET", "a", "1", "nx"]))
+        .unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+    stream
+        .write_all(&command(&["SET", "a", "2", "NX"]))
+        .unwrap();
+    assert_reply(&mut stream, b"$-1\r\n");
+    stream
+        .write_all(&command(&["SET", "a", "3", "XX"]))
+        .unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+    stream.write_all(&command(&["GET", "a"])).unwrap();
+    assert_reply(&mut stream, b"$1\r\n3\r\n");
+    stream
+        .write_all(&command(&["SET", "a", "4", "NX", "XX"]))
+        .unwrap();
+    assert_reply(&mut stream, b"-ERR syntax error\r\n");
+    stream
+        .write_all(&command(&["SET", "a", "4", "EX", "10"]))
+        .unwrap();
+    assert_reply(
+        &mut stream,
+        b"-ERR keys do not expire, so SET takes no expiry\r\n",
+    );
+    stream.write_all(&command(&["GET", "a"])).unwrap();
+    assert_reply(&mut stream, b"$1\r\n3\r\n");
+}
+
+#[test]
+fn set_conditions() {
+    assert_set_conditions(serve_resp(RespServer::new(EngineImpl::default())));
+    let path = std::env::temp_dir().join(format!("bronzedb-resp-{}", std::process::id()));
+    assert_set_conditions(serve_resp(RespServer::new(SledEngine::new(&path))));
+    let _ = std::fs::remove_dir_all(path);
+}
+
+fn read_line(stream: &mut TcpStream) -> String {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    while !line.ends_with(b"\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        line.push(byte[0]);
+    }
+    line.truncate(line.len() - 2);
+    String::from_utf8(line).unwrap()
+}
+
+fn scan(stream: &mut TcpStream, cursor: &str) -> (String, Vec<String>) {
+    stream
+        .write_all(&command(&["SCAN", cursor, "COUNT", "10"]))
+        .unwrap();
+    assert_eq!("*2", read_line(stream));
+    read_line(stream);
+    let next = read_line(stream);
+    let count: usize = read_line(stream)[1..].parse().unwrap();
+    let keys = (0..count)
+        .map(|_| {
+            read_line(stream);
+            read_line(stream)
+        })
+        .collect();
+    (next, keys)
+}
+
+#[test]
+fn scan_cursor() {
+    let mut stream = serve_resp(RespServer::new(EngineImpl::default()));
+    for i in 0..25 {
+        stream
+            .write_all(&command(&["SET", &format!("key{:02}", i), "value"]))
+            .unwrap();
+        assert_reply(&mut stream, b"+OK\r\n");
+    }
+    let (cursor, first) = scan(&mut stream, "0");
+    assert_eq!(10, first.len());
+    assert_ne!("0", cursor);
+
+    // deleting keys already returned must not skip the ones after the cursor
+    for key in &first[..5] {
+        stream.write_all(&command(&["DEL", key])).unwrap();
+        assert_reply(&mut stream, b":1\r\n");
+    }
+    let (cursor, second) = scan(&mut stream, &cursor);
+    assert_eq!(10, second.len());
+    // clients parse cursors as unsigned 64 bit numbers and may resume on another connection
+    assert!(cursor.parse::<u64>().is_ok());
+    let mut other = TcpStream::connect(stream.peer_addr().unwrap()).unwrap();
+    let (cursor, third) = scan(&mut other, &cursor);
+    assert_eq!("0", cursor);
+    let keys: Vec<_> = first.into_iter().chain(second).chain(third).collect();
+    let expected: Vec<_> = (0..25).map(|i| format!("key{:02}", i)).collect();
+    assert_eq!(expected, keys);
+
+    stream.write_all(&command(&["SCAN", "12"])).unwrap();
+    assert_reply(&mut stream, b"-ERR invalid cursor\r\n");
+}
+
+#[test]
+fn del_permissions() {
+    let mut stream = serve_resp(RespServer::new(EngineImpl::default()).users(vec![
+        User::new("admin").password("secret"),
+        User::new("app").password("secret").prefixes(vec!["app:".to_owned()]),
+    ]));
+    stream
+        .write_all(&command(&["AUTH", "admin", "secret"]))
+        .unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+    for key in &["app:1", "other"] {
+        stream.write_all(&command(&["SET", key, "1"])).unwrap();
+        assert_reply(&mut stream, b"+OK\r\n");
+    }
+    stream
+        .write_all(&command(&["AUTH", "app", "secret"]))
+        .unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+    stream
+        .write_all(&command(&["DEL", "app:1", "other"]))
+        .unwrap();
+    assert_reply(
+        &mut stream,
+        b"-NOPERM user app has no permissions to run this command or access this key\r\n",
+    );
+    stream.write_all(&command(&["EXISTS", "app:1"])).unwrap();
+    assert_reply(&mut stream, b":1\r\n");
+}
+
+#[test]
+fn auth() {
+    let mut stream = serve_resp(
+        RespServer::new(EngineImpl::default()).users(vec![User::new("admin").password("secret")]),
+    );
+    stream.write_all(&command(&["GET", "a"])).unwrap();
+    assert_reply(&mut stream, b"-NOAUTH Authentication required.\r\n");
+    stream
+        .write_all(&command(&["AUTH", "admin", "wrong"]))
+        .unwrap();
+    assert_reply(
+        &mut stream,
+        b"-WRONGPASS invalid username-password pair or user is disabled.\r\n",
+    );
+    stream
+        .write_all(&command(&["AUTH", "admin", "secret"]))
+        .unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+    stream.write_all(&command(&["GET", "a"])).unwrap();
+    assert_reply(&mut stream, b"$-1\r\n");
+}
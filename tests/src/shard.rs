@@ -0,0 +1,269 @@
+use bronzedb_client::{
+    BronzeConnManager, ClusterClient, Connection, PartitionMap, Shard, Stream, SCAN_WINDOW,
+};
+use bronzedb_engine::Engine;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{HttpServer, MemcachedServer, RespServer, Server, Sharding, Shutdown};
+use bronzedb_util::status::{Result, StatusCode};
+use r2d2::ManageConnection;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::thread::spawn;
+
+pub(crate) struct Node {
+    pub(crate) addr: String,
+    pub(crate) sharding: Sharding,
+    shutdown: Shutdown,
+}
+
+impl Node {
+    pub(crate) fn connect(&self) -> Result<Connection<Stream>> {
+        BronzeConnManager::new(self.addr.as_str()).connect()
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.shutdown.trigger();
+    }
+}
+
+pub(crate) fn partition_map(version: u64, shards: &[(&str, &str)]) -> PartitionMap {
+    let shards = shards
+        .iter()
+        .map(|(lower, addr)| Shard {
+            lower: lower.as_bytes().to_vec().into(),
+            addr: addr.to_string(),
+        })
+        .collect();
+    PartitionMap::new(version, shards).unwrap()
+}
+
+// Three servers, owning the keys before "h", before "p" and the rest.
+pub(crate) fn start_shards() -> Result<Vec<Node>> {
+    serve_shards(|addr, map, _| Ok(Sharding::new(addr, map)))
+}
+
+// Like `start_shards`, with the map of each server kept in a directory of its own in `dir`.
+pub(crate) fn start_stored_shards(dir: &Path) -> Result<Vec<Node>> {
+    serve_shards(|addr, map, index| {
+        let dir = dir.join(index.to_string());
+        fs::create_dir_all(&dir)?;
+        Sharding::open(addr, map, dir.join("partition.map"))
+    })
+}
+
+fn serve_shards(
+    sharding: impl Fn(&str, PartitionMap, usize) -> Result<Sharding>,
+) -> Result<Vec<Node>> {
+    let listeners = (0..3)
+        .map(|_| TcpListener::bind("127.0.0.1:0"))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let addrs: Vec<_> = listeners
+        .iter()
+        .map(|listener| listener.local_addr().unwrap().to_string())
+        .collect();
+    let map = partition_map(1, &[("", &addrs[0]), ("h", &addrs[1]), ("p", &addrs[2])]);
+    let mut nodes = Vec::new();
+    for (index, (listener, addr)) in listeners.into_iter().zip(addrs).enumerate() {
+        let sharding = sharding(&addr, map.clone(), index)?;
+        let shutdown = Shutdown::new();
+        let mut server = Server::new(EngineImpl::default())
+            .sharding(sharding.clone())
+            .shutdown(shutdown.clone());
+        spawn(move || server.serve(listener).unwrap());
+        nodes.push(Node {
+            addr,
+            sharding,
+            shutdown,
+        });
+    }
+    Ok(nodes)
+}
+
+pub(crate) fn key(i: usize) -> String {
+    // spread over the shards
+    format!("{}{:04}", (b'a' + (i % 26) as u8) as char, i)
+}
+
+#[test]
+fn route() -> Result<()> {
+    let nodes = start_shards()?;
+    let mut client = ClusterClient::new(vec![nodes[1].addr.clone()]);
+    for i in 0..100 {
+        client.set(key(i).into_bytes().into(), i.to_string().into_bytes())?;
+    }
+    for i in 0..100 {
+        assert_eq!(
+            Some(i.to_string().into_bytes()),
+            client.get(key(i).into_bytes().into())?
+        );
+    }
+    client.delete(key(7).into_bytes().into())?;
+    assert!(!client.exists(key(7).into_bytes().into())?);
+
+    // every server holds only its own keys and refuses the others
+    let mut conn = nodes[0].connect()?;
+    let err = conn.get(b"hello".to_vec().into()).unwrap_err();
+    assert_eq!(StatusCode::WrongShard, err.code);
+    assert!(!conn.is_poisoned());
+    let err = conn.scan(None, None).map(drop).unwrap_err();
+    assert_eq!(StatusCode::WrongShard, err.code);
+    let map = conn.shard_map()?;
+    assert_eq!(1, map.version());
+    for (i, node) in nodes.iter().enumerate() {
+        let (lower, upper) = map.bounds(i);
+        let entries = node
+            .connect()?
+            .scan(lower, upper)?
+            .collect::<Result<Vec<_>>>()?;
+        assert!(!entries.is_empty());
+        assert!(entries
+            .iter()
+            .all(|(key, _)| map.owner(key) == node.addr.as_str()));
+    }
+    Ok(())
+}
+
+#[test]
+fn stitch_scans() -> Result<()> {
+    let nodes = start_shards()?;
+    let mut client = ClusterClient::new(vec![nodes[0].addr.clone()]);
+    let size = 3 * SCAN_WINDOW + 10;
+    let mut keys: Vec<_> = (0..size).map(key).collect();
+    for key in &keys {
+        client.set(key.as_bytes().to_vec().into(), key.as_bytes().to_vec())?;
+    }
+    keys.sort();
+    let scanned = client
+        .scan(None, None)?
+        .map(|entry| entry.map(|(key, _)| String::from_utf8(key.to_vec()).unwrap()))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(keys, scanned);
+
+    let lower = b"f".to_vec().into();
+    let upper = b"q".to_vec().into();
+    let scanned = client
+        .scan(Some(lower), Some(upper))?
+        .map(|entry| entry.map(|(key, _)| String::from_utf8(key.to_vec()).unwrap()))
+        .collect::<Result<Vec<_>>>()?;
+    let expected: Vec<_> = keys
+        .iter()
+        .filter(|key| key.as_str() >= "f" && key.as_str() <= "q")
+        .cloned()
+        .collect();
+    assert_eq!(expected, scanned);
+
+    // dropped early, the scan leaves the connections usable
+    assert_eq!(10, client.scan(None, None)?.take(10).count());
+    assert!(client.exists(keys[0].as_bytes().to_vec().into())?);
+    Ok(())
+}
+
+#[test]
+fn refresh_map() -> Result<()> {
+    let nodes = start_shards()?;
+    let mut client = ClusterClient::new(vec![nodes[0].addr.clone()]);
+    client.set(b"zoo".to_vec().into(), b"old".to_vec())?;
+    assert_eq!(1, client.partition_map()?.version());
+
+    // the last range moves to the first server
+    let map = partition_map(
+        2,
+        &[
+            ("", &nodes[0].addr),
+            ("h", &nodes[1].addr),
+            ("p", &nodes[0].addr),
+        ],
+    );
+    for node in &nodes {
+        assert!(node.sharding.update(map.clone())?);
+    }
+    assert!(!nodes[0].sharding.update(map.clone())?);
+    client.set(b"zoo".to_vec().into(), b"new".to_vec())?;
+    assert_eq!(2, client.partition_map()?.version());
+    assert_eq!(
+        Some(b"new".to_vec()),
+        nodes[0].connect()?.get(b"zoo".to_vec().into())?
+    );
+    Ok(())
+}
+
+#[test]
+fn stored_map() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("bronzedb-map-{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let map = |version| partition_map(version, &[("", "a"), ("h", "b")]);
+    let sharding = Sharding::open("a", map(1), &path)?;
+    assert_eq!(1, sharding.map().version());
+    assert!(sharding.update(map(2))?);
+
+    // a restarted server goes on with the newest map
+    assert_eq!(2, Sharding::open("a", map(1), &path)?.map().version());
+    assert_eq!(3, Sharding::open("a", map(3), &path)?.map().version());
+    assert_eq!(3, Sharding::open("a", map(2), &path)?.map().version());
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn side_listeners() -> Result<()> {
+    // this server owns the keys before "h"
+    let sharding = Sharding::new("a", partition_map(1, &[("", "a"), ("h", "b")]));
+    let mut engine = EngineImpl::default();
+    engine.set(b"z".to_vec().into(), b"1".to_vec())?;
+    let serve = |server: Box<dyn FnOnce(TcpListener) + Send>| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        spawn(move || server(listener));
+        TcpStream::connect(addr).unwrap()
+    };
+    let round_trip = |stream: &mut TcpStream, request: &str, expected: &str| {
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = vec![0; expected.len()];
+        stream.read_exact(&mut response).unwrap();
+        assert_eq!(expected, String::from_utf8_lossy(&response));
+    };
+
+    let (resp_engine, resp_sharding) = (engine.clone(), sharding.clone());
+    let mut resp = serve(Box::new(move |listener| {
+        let mut server = RespServer::new(resp_engine).sharding(resp_sharding);
+        server.serve(listener).unwrap()
+    }));
+    let wrong_shard = "-WRONGSHARD the key is served by another shard\r\n";
+    round_trip(&mut resp, "GET z\r\n", wrong_shard);
+    round_trip(&mut resp, "SET z 2\r\n", wrong_shard);
+    round_trip(&mut resp, "DEL a z\r\n", wrong_shard);
+    round_trip(&mut resp, "SET a 1\r\n", "+OK\r\n");
+    // the lingering "z" is left out
+    round_trip(
+        &mut resp,
+        "SCAN 0\r\n",
+        "*2\r\n$1\r\n0\r\n*1\r\n$1\r\na\r\n",
+    );
+
+    let (http_engine, http_sharding) = (engine.clone(), sharding.clone());
+    let mut http = serve(Box::new(move |listener| {
+        let mut server = HttpServer::new(http_engine).sharding(http_sharding);
+        server.serve(listener).unwrap()
+    }));
+    round_trip(
+        &mut http,
+        "GET /kv/z HTTP/1.1\r\n\r\n",
+        "HTTP/1.1 421 Misdirected Request\r\n",
+    );
+
+    let mut memcached = serve(Box::new(move |listener| {
+        let mut server = MemcachedServer::new(engine).sharding(sharding);
+        server.serve(listener).unwrap()
+    }));
+    round_trip(
+        &mut memcached,
+        "get z\r\n",
+        "SERVER_ERROR the key is served by another shard\r\n",
+    );
+    round_trip(&mut memcached, "get a\r\n", "VALUE a 0 1\r\n1\r\nEND\r\n");
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use crate::serve_local;
+use bronzedb_cli::shell::{run, Command};
+use bronzedb_client::{BronzeConnManager, Client};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::Server;
+use bronzedb_util::status::Result;
+
+fn execute(client: &mut Client, lines: &[&str]) -> Result<String> {
+    let mut output = Vec::new();
+    for line in lines {
+        let command = Command::parse(line).unwrap().unwrap();
+        run(client, command, &mut output)?;
+    }
+    Ok(String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn commands() -> Result<()> {
+    let addr = serve_local(Server::new(EngineImpl::default()));
+    let mut client = Client::new(BronzeConnManager::new(addr));
+    let output = execute(
+        &mut client,
+        &[
+            "set 'a key' 0x00ff",
+            r#"set b "two\nlines""#,
+            "set c b64:aGk=",
+            "get 'a key'",
+            "get missing",
+            "exists c",
+            "scan",
+            "scan from b limit 1",
+            "delete b",
+            "scan to b",
+            "ping",
+        ],
+    )?;
+    let expected = r#"OK
+OK
+OK
+"\x00\xff"
+(nil)
+true
+"a key" "\x00\xff"
+b "two\nlines"
+c hi
+b "two\nlines"
+OK
+"a key" "\x00\xff"
+PONG
+"#;
+    assert_eq!(expected, output);
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use crate::serve_local;
+use bronzedb_client::{BronzeConnManager, Options};
+use bronzedb_engine::Engine;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{HttpServer, MemcachedServer, RespServer, Server, Shutdown};
+use bronzedb_util::status::Result;
+use r2d2::ManageConnection;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::spawn;
+use std::time::Duration;
+
+#[test]
+fn graceful_shutdown() -> Result<()> {
+    let shutdown = Shutdown::new();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = Server::new(EngineImpl::default()).shutdown(shutdown.clone());
+    let serving = spawn(move || server.serve(listener));
+
+    let manager = BronzeConnManager::new(addr.to_string());
+    let mut idle = manager.connect()?;
+    idle.set(b"name".to_vec().into(), b"value".to_vec())?;
+    let mut busy = manager.connect()?;
+    let mut scanner = busy.scan(None, None)?;
+
+    shutdown.trigger();
+    serving.join().unwrap()?;
+    assert!(TcpStream::connect(addr).is_err());
+    assert!(idle.get(b"name".to_vec().into()).is_err());
+    // the in-flight scan still completes
+    assert_eq!(b"value".to_vec(), scanner.next().unwrap()?.1);
+    assert!(scanner.next().is_none());
+    drop(scanner);
+    assert!(shutdown.wait(Duration::from_secs(5)));
+    Ok(())
+}
+
+#[test]
+fn drain_cancellable_scan() -> Result<()> {
+    let mut engine = EngineImpl::default();
+    // several windows, so the server waits for the client to ask for more
+    let count = 3 * 256 + 1;
+    for i in 0..count as u32 {
+        engine.set(i.to_be_bytes().to_vec().into(), b"value".to_vec())?;
+    }
+    let shutdown = Shutdown::new();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = Server::new(engine).shutdown(shutdown.clone());
+    let serving = spawn(move || server.serve(listener));
+
+    let manager = BronzeConnManager::new(addr.to_string());
+    let mut idle = manager.connect()?;
+    idle.ping()?;
+    let mut busy = manager.connect()?;
+    let mut scanner = busy.scan(None, None)?;
+    scanner.next().unwrap()?;
+
+    shutdown.trigger();
+    serving.join().unwrap()?;
+    assert!(idle.ping().is_err());
+    let rest = scanner.collect::<Result<Vec<_>>>()?;
+    assert_eq!(count - 1, rest.len());
+    assert!(shutdown.wait(Duration::from_secs(5)));
+    Ok(())
+}
+
+#[test]
+fn trigger_before_serve() -> Result<()> {
+    let shutdown = Shutdown::new();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    shutdown.trigger();
+    Server::new(EngineImpl::default())
+        .shutdown(shutdown.clone())
+        .serve(listener)?;
+    assert!(shutdown.wait(Duration::from_millis(10)));
+    assert!(TcpStream::connect(addr).is_err());
+    Ok(())
+}
+
+#[test]
+fn shutdown_deadline() -> Result<()> {
+    let mut engine = EngineImpl::default();
+    for i in 0..5_000u32 {
+        engine.set(i.to_be_bytes().to_vec().into(), vec![0; 4096])?;
+    }
+    let shutdown = Shutdown::new();
+    let addr = serve_local(Server::new(engine).shutdown(shutdown.clone()));
+    // a cancellable scan would pause after a window instead of filling the socket
+    let mut conn = BronzeConnManager::new(addr)
+        .options(Options::default())
+        .connect()?;
+    // the scan is never consumed, so the server blocks on writing it
+    let _scanner = conn.scan(None, None)?;
+    shutdown.trigger();
+    assert!(!shutdown.wait(Duration::from_millis(200)));
+    assert!(shutdown.wait(Duration::from_secs(5)));
+    Ok(())
+}
+
+#[test]
+fn side_listeners() {
+    let shutdown = Shutdown::new();
+    let mut addrs = Vec::new();
+    let mut serving = Vec::new();
+    for protocol in 0..3 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        addrs.push(listener.local_addr().unwrap());
+        let engine = EngineImpl::default();
+        let shutdown = shutdown.clone();
+        serving.push(spawn(move || match protocol {
+            0 => RespServer::new(engine).shutdown(shutdown).serve(listener),
+            1 => HttpServer::new(engine).shutdown(shutdown).serve(listener),
+            _ => MemcachedServer::new(engine)
+                .shutdown(shutdown)
+                .serve(listener),
+        }));
+    }
+    // answered once, so the server has taken the connections
+    let requests: [&[u8]; 3] = [
+        b"PING\r\n",
+        b"GET /nothing HTTP/1.1\r\n\r\n",
+        b"version\r\n",
+    ];
+    let idle: Vec<_> = addrs
+        .iter()
+        .zip(requests.iter())
+        .map(|(addr, request)| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request).unwrap();
+            stream.read_exact(&mut [0]).unwrap();
+            stream
+        })
+        .collect();
+    shutdown.trigger();
+    for serving in serving {
+        serving.join().unwrap().unwrap();
+    }
+    for mut stream in idle {
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    }
+    assert!(shutdown.wait(Duration::from_secs(5)));
+}
@@ -0,0 +1,133 @@
+use crate::serve_local;
+use bronzedb_client::{BronzeConnManager, Options};
+use bronzedb_engine::Engine;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::{MemcachedServer, RespServer, Server, Timeouts};
+use bronzedb_util::status::Result;
+use r2d2::ManageConnection;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::channel;
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+fn serve_with(engine: EngineImpl, timeouts: Timeouts) -> String {
+    serve_local(Server::new(engine).timeouts(timeouts))
+}
+
+#[test]
+fn idle_timeout() -> Result<()> {
+    let addr = serve_with(
+        EngineImpl::default(),
+        Timeouts {
+            idle: Some(100),
+            ..Timeouts::default()
+        },
+    );
+    let mut conn = BronzeConnManager::new(addr).connect()?;
+    conn.ping()?;
+    sleep(Duration::from_millis(50));
+    conn.ping()?;
+    sleep(Duration::from_millis(300));
+    assert!(conn.ping().is_err());
+    Ok(())
+}
+
+#[test]
+fn request_timeout() {
+    let addr = serve_with(
+        EngineImpl::default(),
+        Timeouts {
+            request: Some(100),
+            ..Timeouts::default()
+        },
+    );
+    let mut stream = TcpStream::connect(addr).unwrap();
+    // a set request that never sends its key
+    stream.write_all(&[2]).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut buf = [0];
+    assert!(matches!(stream.read(&mut buf), Ok(0) | Err(_)));
+}
+
+#[test]
+fn request_deadline() {
+    let addr = serve_with(
+        EngineImpl::default(),
+        Timeouts {
+            request: Some(400),
+            ..Timeouts::default()
+        },
+    );
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let started = Instant::now();
+    stream.write_all(&[2]).unwrap();
+    // a late byte of the key length does not restart the timeout
+    sleep(Duration::from_millis(300));
+    stream.write_all(&[0]).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut buf = [0];
+    assert!(matches!(stream.read(&mut buf), Ok(0) | Err(_)));
+    assert!(started.elapsed() < Duration::from_millis(600));
+}
+
+#[test]
+fn write_timeout() -> Result<()> {
+    let mut engine = EngineImpl::default();
+    for i in 0..5_000u32 {
+        engine.set(i.to_be_bytes().to_vec().into(), vec![0; 4096])?;
+    }
+    let addr = serve_with(
+        engine,
+        Timeouts {
+            write: Some(100),
+            ..Timeouts::default()
+        },
+    );
+    // a cancellable scan would pause after a window instead of filling the socket
+    let manager = BronzeConnManager::new(addr).options(Options::default());
+    let mut reader = manager.connect()?;
+    // the scan is never consumed and keeps the engine locked until the write times out
+    let _scanner = reader.scan(None, None)?;
+    let mut writer = manager.connect()?;
+    let (sender, receiver) = channel();
+    spawn(move || sender.send(writer.set(b"key".to_vec().into(), b"value".to_vec())));
+    receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+}
+
+#[test]
+fn side_listeners() {
+    let timeouts = Timeouts {
+        idle: Some(100),
+        request: Some(100),
+        ..Timeouts::default()
+    };
+    let resp = TcpListener::bind("127.0.0.1:0").unwrap();
+    let resp_addr = resp.local_addr().unwrap();
+    let mut server = RespServer::new(EngineImpl::default()).timeouts(timeouts);
+    spawn(move || server.serve(resp).unwrap());
+    let memcached = TcpListener::bind("127.0.0.1:0").unwrap();
+    let memcached_addr = memcached.local_addr().unwrap();
+    let mut server = MemcachedServer::new(EngineImpl::default()).timeouts(timeouts);
+    spawn(move || server.serve(memcached).unwrap());
+
+    let mut idle = TcpStream::connect(resp_addr).unwrap();
+    idle.write_all(b"PING\r\n").unwrap();
+    let mut pong = [0; 7];
+    idle.read_exact(&mut pong).unwrap();
+    assert_eq!(b"+PONG\r\n", &pong);
+    // a client that never finishes its request
+    let mut slow = TcpStream::connect(memcached_addr).unwrap();
+    slow.write_all(b"get ").unwrap();
+
+    let started = Instant::now();
+    for mut stream in [idle, slow] {
+        let mut rest = Vec::new();
+        assert_eq!(0, stream.read_to_end(&mut rest).unwrap());
+    }
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
@@ -0,0 +1,103 @@
+use crate::serve_local;
+use bronzedb_client::tls::client_config;
+use bronzedb_client::BronzeConnManager;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::tls::server_config;
+use bronzedb_server::Server;
+use bronzedb_util::status::Result;
+use r2d2::ManageConnection;
+use rcgen::{
+    BasicConstraints, CertificateParams, CertifiedIssuer, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+};
+use std::fs;
+use std::path::PathBuf;
+
+struct Certs {
+    dir: PathBuf,
+}
+
+impl Certs {
+    fn generate(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("bronzedb-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let server_cert = CertificateParams::new(vec!["localhost".to_owned()])
+            .unwrap()
+            .signed_by(&server_key, &ca)
+            .unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = CertificateParams::new(Vec::new()).unwrap();
+        client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client_cert = client_params.signed_by(&client_key, &ca).unwrap();
+
+        for (file, pem) in [
+            ("ca.pem", ca.pem()),
+            ("server.pem", server_cert.pem()),
+            ("server.key", server_key.serialize_pem()),
+            ("client.pem", client_cert.pem()),
+            ("client.key", client_key.serialize_pem()),
+        ] {
+            fs::write(dir.join(file), pem).unwrap();
+        }
+        Self { dir }
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(file)
+    }
+}
+
+impl Drop for Certs {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn round_trip(manager: BronzeConnManager) -> Result<()> {
+    let mut connect = manager.connect()?;
+    connect.set(b"tls".to_vec().into(), b"encrypted".to_vec())?;
+    assert_eq!(
+        b"encrypted".to_vec(),
+        connect.get(b"tls".to_vec().into())?.unwrap()
+    );
+    let entries = connect.scan(None, None)?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(1, entries.len());
+    connect.delete(b"tls".to_vec().into())?;
+    Ok(())
+}
+
+#[test]
+fn server_auth() -> Result<()> {
+    let certs = Certs::generate("server-auth");
+    let addr = serve_local(Server::new(EngineImpl::new()).tls(server_config(
+        certs.path("server.pem"),
+        certs.path("server.key"),
+        None::<PathBuf>,
+    )?));
+    let config = client_config(certs.path("ca.pem"), None::<(PathBuf, PathBuf)>)?;
+    round_trip(BronzeConnManager::new(addr).tls(config, "localhost"))
+}
+
+#[test]
+fn mutual_auth() -> Result<()> {
+    let certs = Certs::generate("mutual-auth");
+    let addr = serve_local(Server::new(EngineImpl::new()).tls(server_config(
+        certs.path("server.pem"),
+        certs.path("server.key"),
+        Some(certs.path("ca.pem")),
+    )?));
+    let anonymous = client_config(certs.path("ca.pem"), None::<(PathBuf, PathBuf)>)?;
+    assert!(round_trip(BronzeConnManager::new(addr.clone()).tls(anonymous, "localhost")).is_err());
+
+    let identity = client_config(
+        certs.path("ca.pem"),
+        Some((certs.path("client.pem"), certs.path("client.key"))),
+    )?;
+    round_trip(BronzeConnManager::new(addr).tls(identity, "localhost"))
+}
@@ -0,0 +1,37 @@
+#![cfg(unix)]
+
+use bronzedb_client::{BronzeConnManager, Pool};
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::listener::bind_unix;
+use bronzedb_server::Server;
+use bronzedb_util::status::Result;
+use std::fs;
+use std::thread::spawn;
+
+#[test]
+fn unix_socket() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("bronzedb-{}.sock", std::process::id()));
+    let listener = bind_unix(&path)?;
+    // binding again replaces the stale socket file
+    drop(listener);
+    let listener = bind_unix(&path)?;
+    let mut server = Server::new(EngineImpl::default());
+    spawn(move || server.serve(listener).unwrap());
+
+    let manager = BronzeConnManager::new(format!("unix://{}", path.display()));
+    let pool = Pool::builder().max_size(2).build(manager).unwrap();
+    pool.get()
+        .unwrap()
+        .set(b"name".to_vec().into(), b"value".to_vec())?;
+    assert_eq!(
+        Some(b"value".to_vec()),
+        pool.get().unwrap().get(b"name".to_vec().into())?
+    );
+    let mut entries = Vec::new();
+    for item in pool.get().unwrap().scan(None, None)? {
+        entries.push(item?);
+    }
+    assert_eq!(1, entries.len());
+    fs::remove_file(&path)?;
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use crate::serve_local;
+use bronzedb_client::BronzeConnManager;
+use bronzedb_memory_db_server::EngineImpl;
+use bronzedb_server::Server;
+use bronzedb_sled_db_server::EngineImpl as SledEngine;
+use bronzedb_util::status::Result;
+use bronzedb_util::types::{Event, Key};
+use r2d2::ManageConnection;
+use std::thread::sleep;
+use std::time::Duration;
+
+fn key(key: &str) -> Key {
+    key.as_bytes().to_vec().into()
+}
+
+fn manager(addr: String) -> BronzeConnManager {
+    BronzeConnManager::new(addr).read_timeout(Duration::from_secs(5))
+}
+
+#[test]
+fn watch_range() -> Result<()> {
+    let manager = manager(serve_local(Server::new(EngineImpl::default())));
+    let mut watcher = manager.connect()?;
+    let mut writer = manager.connect()?;
+    {
+        let subscription = watcher.watch(Some(key("b")), Some(key("d")))?;
+        for name in &["a", "b", "c", "e"] {
+            writer.set(key(name), name.as_bytes().to_vec())?;
+        }
+        writer.delete(key("c"))?;
+        let events = subscription.take(3).collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            vec![
+                Event::Set(key("b"), b"b".to_vec()),
+                Event::Set(key("c"), b"c".to_vec()),
+                Event::Delete(key("c")),
+            ],
+            events
+        );
+    }
+    assert!(!watcher.is_poisoned());
+    assert_eq!(Some(b"b".to_vec()), watcher.get(key("b"))?);
+    Ok(())
+}
+
+#[test]
+fn quiet_watch() -> Result<()> {
+    let manager = manager(serve_local(Server::new(EngineImpl::default())));
+    let mut watcher = manager.connect()?;
+    let mut writer = manager.connect()?;
+    let mut subscription = watcher.watch_prefix(key("user:"))?;
+    // the watch pauses in the meantime
+    sleep(Duration::from_millis(700));
+    writer.set(key("user"), b"other".to_vec())?;
+    writer.set(key("user:1"), b"Hexi".to_vec())?;
+    assert_eq!(
+        Event::Set(key("user:1"), b"Hexi".to_vec()),
+        subscription.next().unwrap()?
+    );
+    subscription.cancel()?;
+    assert!(!watcher.is_poisoned());
+    watcher.ping()
+}
+
+#[test]
+fn busy_watch() -> Result<()> {
+    const SIZE: usize = 1000;
+    let manager = manager(serve_local(Server::new(EngineImpl::default())));
+    let mut watcher = manager.connect()?;
+    let mut writer = manager.connect()?;
+    let subscription = watcher.watch(None, None)?;
+    for i in 0..SIZE {
+        writer.set(key(&i.to_string()), Vec::new())?;
+    }
+    let events = subscription.take(SIZE).collect::<Result<Vec<_>>>()?;
+    assert_eq!(key(&(SIZE - 1).to_string()), *events[SIZE - 1].key());
+    assert!(!watcher.is_poisoned());
+    watcher.ping()
+}
+
+#[test]
+fn sled_watch() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("bronzedb-watch-{}", std::process::id()));
+    let manager = manager(serve_local(Server::new(SledEngine::new(&path))));
+    let mut watcher = manager.connect()?;
+    let mut writer = manager.connect()?;
+    let mut subscription = watcher.watch_prefix(key("watch:"))?;
+    writer.set(key("watch:name"), b"Hexi".to_vec())?;
+    writer.delete(key("watch:name"))?;
+    assert_eq!(
+        Event::Set(key("watch:name"), b"Hexi".to_vec()),
+        subscription.next().unwrap()?
+    );
+    assert_eq!(
+        Event::Delete(key("watch:name")),
+        subscription.next().unwrap()?
+    );
+    subscription.cancel()?;
+    let _ = std::fs::remove_dir_all(path);
+    Ok(())
+}